@@ -0,0 +1,662 @@
+// (c) ProgramCrafter, 2024
+
+//! Parts of blocks: shard topology of workchains seen by the masterchain, messages imported and
+//! exported by a block; and states of shards.
+
+use tlb_macro::*;
+use crate::ton::cell::{Cell, CellSlice, TlbError};
+use crate::config::ConfigParams;
+use crate::ton::dict::{DictKey, HashmapAugE, HashmapE, InRef};
+use crate::ton::{BinTree, CellDeserialize, CellSerialize, Coins, CurrencyCollection, FixedBits, Int, ParseMode, RawBits};
+
+
+/// `FutureSplitMerge`: split or merge of a shard planned by validators.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_enum_deserializable]
+#[tlb_enum_serializable]
+#[tlb_assert_unsafe(items_prefixes_nonoverlap)]
+pub enum FutureSplitMerge {
+    /// `fsm_none$0`.
+    #[tlb_item_serializable(u 0 1bit)] None{},
+    /// `fsm_split$10 split_utime:uint32 interval:uint32`.
+    #[tlb_item_serializable(u 2 2bit, split_utime, interval)] Split{split_utime: u32, interval: u32},
+    /// `fsm_merge$11 merge_utime:uint32 interval:uint32`.
+    #[tlb_item_serializable(u 3 2bit, merge_utime, interval)] Merge{merge_utime: u32, interval: u32},
+}
+impl Default for FutureSplitMerge {
+    fn default() -> Self {
+        FutureSplitMerge::None{}
+    }
+}
+
+/// `ShardDescr`: latest block of a shard as seen by the masterchain.
+///
+/// Both `shard_descr#b` and `shard_descr_new#a` are read; the latter keeps `fees_collected`
+/// and `funds_created` in a child cell. Writing always produces `shard_descr_new#a`, as
+/// current nodes do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShardDescr {
+    pub seq_no: u32,
+    pub reg_mc_seqno: u32,
+    pub start_lt: u64,
+    pub end_lt: u64,
+    pub root_hash: FixedBits<256>,
+    pub file_hash: FixedBits<256>,
+    pub before_split: bool,
+    pub before_merge: bool,
+    pub want_split: bool,
+    pub want_merge: bool,
+    pub nx_cc_updated: bool,
+    pub next_catchain_seqno: u32,
+    pub next_validator_shard: u64,
+    pub min_ref_mc_seqno: u32,
+    pub gen_utime: u32,
+    pub split_merge_at: FutureSplitMerge,
+    pub fees_collected: CurrencyCollection,
+    pub funds_created: CurrencyCollection,
+}
+
+impl ShardDescr {
+    pub const TAG: u8 = 0xa;
+    pub const TAG_INLINE_FEES: u8 = 0xb;
+}
+
+impl CellSerialize for ShardDescr {
+    fn serialize(&self) -> Vec<String> {
+        let mut result = vec![format!("u {} 4bit", Self::TAG)];
+        result.extend(self.seq_no.serialize());
+        result.extend(self.reg_mc_seqno.serialize());
+        result.extend(self.start_lt.serialize());
+        result.extend(self.end_lt.serialize());
+        result.extend(self.root_hash.serialize());
+        result.extend(self.file_hash.serialize());
+        for flag in [self.before_split, self.before_merge, self.want_split, self.want_merge, self.nx_cc_updated] {
+            result.extend(flag.serialize());
+        }
+        result.push("u 0 3bit".to_owned());             // flags:(## 3) { flags = 0 }
+        result.extend(self.next_catchain_seqno.serialize());
+        result.extend(self.next_validator_shard.serialize());
+        result.extend(self.min_ref_mc_seqno.serialize());
+        result.extend(self.gen_utime.serialize());
+        result.extend(self.split_merge_at.serialize());
+        result.push("ref {".to_owned());
+        result.extend(self.fees_collected.serialize());
+        result.extend(self.funds_created.serialize());
+        result.push("}".to_owned());
+        result
+    }
+}
+
+impl CellDeserialize for ShardDescr {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        let inline_fees = match slice.load_uint(4)? as u8 {
+            Self::TAG => false,
+            Self::TAG_INLINE_FEES => true,
+            _ => return Err(TlbError::UnknownTag {type_name: "ShardDescr"}),
+        };
+        let seq_no = u32::deserialize(slice)?;
+        let reg_mc_seqno = u32::deserialize(slice)?;
+        let start_lt = u64::deserialize(slice)?;
+        let end_lt = u64::deserialize(slice)?;
+        let root_hash = FixedBits::deserialize(slice)?;
+        let file_hash = FixedBits::deserialize(slice)?;
+        let before_split = bool::deserialize(slice)?;
+        let before_merge = bool::deserialize(slice)?;
+        let want_split = bool::deserialize(slice)?;
+        let want_merge = bool::deserialize(slice)?;
+        let nx_cc_updated = bool::deserialize(slice)?;
+        if slice.load_uint(3)? != 0 {
+            return Err(TlbError::InvalidValue);
+        }
+        let next_catchain_seqno = u32::deserialize(slice)?;
+        let next_validator_shard = u64::deserialize(slice)?;
+        let min_ref_mc_seqno = u32::deserialize(slice)?;
+        let gen_utime = u32::deserialize(slice)?;
+        let split_merge_at = FutureSplitMerge::deserialize(slice)?;
+        let (fees_collected, funds_created) = if inline_fees {
+            (CurrencyCollection::deserialize(slice)?, CurrencyCollection::deserialize(slice)?)
+        } else {
+            let mut fees = slice.load_ref_slice()?;
+            let pair = (CurrencyCollection::deserialize(&mut fees)?, CurrencyCollection::deserialize(&mut fees)?);
+            if fees.mode() != Some(ParseMode::Lenient) && !fees.is_empty() {
+                return Err(TlbError::TrailingData);
+            }
+            pair
+        };
+        Ok(ShardDescr {
+            seq_no, reg_mc_seqno, start_lt, end_lt, root_hash, file_hash,
+            before_split, before_merge, want_split, want_merge, nx_cc_updated,
+            next_catchain_seqno, next_validator_shard, min_ref_mc_seqno, gen_utime,
+            split_merge_at, fees_collected, funds_created
+        })
+    }
+    
+    fn prefix_matches(slice: &CellSlice) -> bool {
+        matches!(slice.peek_uint(4), Ok(tag) if tag == Self::TAG as u128 || tag == Self::TAG_INLINE_FEES as u128)
+    }
+}
+
+/// `_ (HashmapE 32 ^(BinTree ShardDescr)) = ShardHashes`: shards of each workchain, keyed
+/// by workchain id. Leaf paths of a tree are shard prefixes, see [`BinTree::leaves`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ShardHashes {
+    pub workchains: Vec<(i32, BinTree<ShardDescr>)>,
+}
+
+impl ShardHashes {
+    /// Shards of `workchain`. Scans workchains one by one, which is cheap for the handful of
+    /// them a network has; the whole dictionary is already parsed when reading.
+    pub fn get(&self, workchain: i32) -> Option<&BinTree<ShardDescr>> {
+        self.workchains.iter().find(|(id, _)| *id == workchain).map(|(_, shards)| shards)
+    }
+    
+    /// Every shard with its workchain and prefix.
+    pub fn shards(&self) -> impl Iterator<Item = (i32, RawBits, &ShardDescr)> {
+        self.workchains.iter().flat_map(|(workchain, shards)| {
+            shards.leaves().into_iter().map(move |(prefix, descr)| (*workchain, prefix, descr))
+        })
+    }
+}
+
+impl CellDeserialize for ShardHashes {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        let dict = HashmapE::<32, BinTree<ShardDescr>, InRef>::deserialize(slice)?;
+        let workchains = dict.iter().map(|entry| {
+            let (key, shards) = entry?;
+            Ok((i32::from_key_bits(&key)?, shards))
+        }).collect::<Result<_, TlbError>>()?;
+        Ok(ShardHashes {workchains})
+    }
+}
+
+
+/// `_ fees:CurrencyCollection create:CurrencyCollection = ShardFeeCreated;`: fees collected
+/// and funds created in shards, per block and summed up in [`ShardFees`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = "_ fees:CurrencyCollection create:CurrencyCollection = ShardFeeCreated;")]
+pub struct ShardFeeCreated {
+    pub fees: CurrencyCollection,
+    pub create: CurrencyCollection,
+}
+
+/// `_ (HashmapAugE 96 ShardFeeCreated ShardFeeCreated) = ShardFees;`: fees of shard blocks
+/// a masterchain block commits, keyed by workchain and shard prefix.
+pub type ShardFees = HashmapAugE<96, ShardFeeCreated, ShardFeeCreated>;
+
+/// `ed25519_signature#5 R:bits256 s:bits256 = CryptoSignatureSimple;`. Chained signatures
+/// are not used by validators and are not read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = "ed25519_signature#5 r:bits256 s:bits256 = CryptoSignature;")]
+pub struct CryptoSignature {
+    pub r: FixedBits<256>,
+    pub s: FixedBits<256>,
+}
+
+/// `sig_pair$_`: signature of the previous block by the validator with short id `node_id_short`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = "sig_pair$_ node_id_short:bits256 sign:CryptoSignature = CryptoSignaturePair;")]
+pub struct CryptoSignaturePair {
+    pub node_id_short: FixedBits<256>,
+    pub sign: CryptoSignature,
+}
+
+/// `masterchain_block_extra#cca5`: what a masterchain block adds to `BlockExtra`, the shards
+/// it commits with their fees and, in key blocks, the new configuration.
+///
+/// Messages `recover_create_msg` and `mint_msg` are kept as their `InMsg` cells.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct McBlockExtra {
+    pub key_block: bool,
+    pub shard_hashes: ShardHashes,
+    pub shard_fees: ShardFees,
+    pub prev_blk_signatures: HashmapE<16, CryptoSignaturePair>,
+    pub recover_create_msg: Option<Cell>,
+    pub mint_msg: Option<Cell>,
+    pub config: Option<ConfigParams>,
+}
+
+impl McBlockExtra {
+    pub const TAG: u16 = 0xcca5;
+}
+
+impl CellDeserialize for McBlockExtra {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        if slice.load_uint(16)? != Self::TAG as u128 {
+            return Err(TlbError::UnknownTag {type_name: "McBlockExtra"});
+        }
+        let key_block = bool::deserialize(slice)?;
+        let shard_hashes = ShardHashes::deserialize(slice)?;
+        let shard_fees = ShardFees::deserialize(slice)?;
+        
+        let mut extra = slice.load_ref_slice()?;
+        let prev_blk_signatures = HashmapE::deserialize(&mut extra)?;
+        let recover_create_msg = if extra.load_bit()? {Some(Cell::clone(extra.load_ref()?))} else {None};
+        let mint_msg = if extra.load_bit()? {Some(Cell::clone(extra.load_ref()?))} else {None};
+        if extra.mode() != Some(ParseMode::Lenient) && !extra.is_empty() {
+            return Err(TlbError::TrailingData);
+        }
+        
+        // config:key_block?ConfigParams
+        let config = if key_block {Some(ConfigParams::deserialize(slice)?)} else {None};
+        Ok(McBlockExtra {key_block, shard_hashes, shard_fees, prev_blk_signatures, recover_create_msg, mint_msg, config})
+    }
+    
+    fn prefix_matches(slice: &CellSlice) -> bool {
+        matches!(slice.peek_uint(16), Ok(tag) if tag == Self::TAG as u128)
+    }
+}
+
+/// `import_fees$_ fees_collected:Grams value_imported:CurrencyCollection = ImportFees;`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = "import_fees$_ fees_collected:Grams value_imported:CurrencyCollection = ImportFees;")]
+pub struct ImportFees {
+    pub fees_collected: Coins,
+    pub value_imported: CurrencyCollection,
+}
+
+/// `_ (HashmapAugE 256 InMsg ImportFees) = InMsgDescr;`: messages a block imports, keyed by
+/// message hash, with fees summed up. `InMsg` descriptions are kept as the leaf cells they are
+/// stored in, from their constructor tag on.
+pub type InMsgDescr = HashmapAugE<256, Cell, ImportFees>;
+
+/// `_ (HashmapAugE 256 OutMsg CurrencyCollection) = OutMsgDescr;`: messages a block exports,
+/// keyed by message hash, with values summed up. `OutMsg` descriptions are kept as the leaf
+/// cells they are stored in, from their constructor tag on.
+pub type OutMsgDescr = HashmapAugE<256, Cell, CurrencyCollection>;
+
+/// `_ enqueued_lt:uint64 out_msg:^MsgEnvelope = EnqueuedMsg;`: message waiting in the queue
+/// of a shard since logical time `enqueued_lt`, kept as its envelope cell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = "_ enqueued_lt:uint64 out_msg:^MsgEnvelope = EnqueuedMsg;")]
+pub struct EnqueuedMsg {
+    pub enqueued_lt: u64,
+    pub out_msg: Cell,
+}
+
+/// `_ (HashmapAugE 352 EnqueuedMsg uint64) = OutMsgQueue;`: messages a shard has yet to
+/// deliver, keyed by next hop workchain and address prefix and message hash, with the
+/// smallest `enqueued_lt` below every edge.
+pub type OutMsgQueue = HashmapAugE<352, EnqueuedMsg, u64>;
+
+/// `processed_upto$_`: the last message of a neighbor's queue processed by a shard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = "processed_upto$_ last_msg_lt:uint64 last_msg_hash:bits256 = ProcessedUpto;")]
+pub struct ProcessedUpto {
+    pub last_msg_lt: u64,
+    pub last_msg_hash: FixedBits<256>,
+}
+
+/// `_ (HashmapE 96 ProcessedUpto) = ProcessedInfo;`: how far queues of neighbors are
+/// processed, keyed by their shard and masterchain block seqno.
+pub type ProcessedInfo = HashmapE<96, ProcessedUpto>;
+
+/// `_ out_queue:OutMsgQueue proc_info:ProcessedInfo ihr_pending:IhrPendingInfo = OutMsgQueueInfo;`
+/// where `ihr_pending` is `HashmapE 320 uint64`, import times of pending IHR messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(out_queue, proc_info, ihr_pending)]
+pub struct OutMsgQueueInfo {
+    pub out_queue: OutMsgQueue,
+    pub proc_info: ProcessedInfo,
+    pub ihr_pending: HashmapE<320, u64>,
+}
+
+/// `shard_ident$00 shard_pfx_bits:(#<= 60) workchain_id:int32 shard_prefix:uint64`: shard of
+/// a workchain. `shard_prefix` holds the first `shard_pfx_bits` bits of account ids in the
+/// shard, the rest of its bits zero.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ShardIdent {
+    pub shard_pfx_bits: u8,
+    pub workchain_id: i32,
+    pub shard_prefix: u64,
+}
+
+impl ShardIdent {
+    pub const MAX_PREFIX_BITS: u8 = 60;
+}
+
+impl CellSerialize for ShardIdent {
+    fn serialize(&self) -> Vec<String> {
+        assert!(self.shard_pfx_bits <= Self::MAX_PREFIX_BITS, "shard prefix is at most 60 bits");
+        // `#<= 60` takes 6 bits
+        let mut result = vec!["u 0 2bit".to_owned(), format!("u {} 6bit", self.shard_pfx_bits)];
+        result.extend(Int::<32>(self.workchain_id as i128).serialize());
+        result.extend(self.shard_prefix.serialize());
+        result
+    }
+}
+
+impl CellDeserialize for ShardIdent {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        if slice.load_uint(2)? != 0 {
+            return Err(TlbError::UnknownTag {type_name: "ShardIdent"});
+        }
+        let shard_pfx_bits = slice.load_uint(6)? as u8;
+        if shard_pfx_bits > Self::MAX_PREFIX_BITS {
+            return Err(TlbError::InvalidValue);
+        }
+        let workchain_id = Int::<32>::deserialize(slice)?.0 as i32;
+        let shard_prefix = u64::deserialize(slice)?;
+        Ok(ShardIdent {shard_pfx_bits, workchain_id, shard_prefix})
+    }
+}
+
+/// `ext_blk_ref$_`: block of the masterchain a shard state refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = "ext_blk_ref$_ end_lt:uint64 seq_no:uint32 root_hash:bits256 file_hash:bits256 = ExtBlkRef;")]
+pub struct ExtBlkRef {
+    pub end_lt: u64,
+    pub seq_no: u32,
+    pub root_hash: FixedBits<256>,
+    pub file_hash: FixedBits<256>,
+}
+
+/// `shard_state#9023afe2`: state of a shard after block `seq_no`, the root of state bags of
+/// cells nodes and liteservers give out.
+///
+/// Big parts are kept as the cells they are stored in, to be parsed only when needed:
+/// `out_msg_queue_info` (see [`ShardStateUnsplit::out_msg_queue`]), `accounts` (`ShardAccounts`), values of
+/// `libraries` (`LibDescr`) and `custom` (`McStateExtra`, present in masterchain states).
+/// Fields of the `^[ ... ]` child cell are read into the state itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShardStateUnsplit {
+    pub global_id: i32,
+    pub shard_id: ShardIdent,
+    pub seq_no: u32,
+    pub vert_seq_no: u32,
+    pub gen_utime: u32,
+    pub gen_lt: u64,
+    pub min_ref_mc_seqno: u32,
+    pub out_msg_queue_info: Cell,
+    pub before_split: bool,
+    pub accounts: Cell,
+    pub overload_history: u64,
+    pub underload_history: u64,
+    pub total_balance: CurrencyCollection,
+    pub total_validator_fees: CurrencyCollection,
+    pub libraries: HashmapE<256, Cell>,
+    pub master_ref: Option<ExtBlkRef>,
+    pub custom: Option<Cell>,
+}
+
+impl ShardStateUnsplit {
+    pub const TAG: u32 = 0x9023afe2;
+    
+    /// Parses `out_msg_queue_info`, the queue of messages waiting to leave the shard.
+    pub fn out_msg_queue(&self) -> Result<OutMsgQueueInfo, TlbError> {
+        OutMsgQueueInfo::from_cell(&self.out_msg_queue_info)
+    }
+}
+
+impl CellSerialize for ShardStateUnsplit {
+    fn serialize(&self) -> Vec<String> {
+        let mut result = vec![format!("u {} 32bit", Self::TAG)];
+        result.extend(Int::<32>(self.global_id as i128).serialize());
+        result.extend(self.shard_id.serialize());
+        result.extend(self.seq_no.serialize());
+        result.extend(self.vert_seq_no.serialize());
+        result.extend(self.gen_utime.serialize());
+        result.extend(self.gen_lt.serialize());
+        result.extend(self.min_ref_mc_seqno.serialize());
+        result.push("ref {".to_owned());
+        result.extend(self.out_msg_queue_info.serialize());
+        result.push("}".to_owned());
+        result.extend(self.before_split.serialize());
+        result.push("ref {".to_owned());
+        result.extend(self.accounts.serialize());
+        result.push("}".to_owned());
+        result.push("ref {".to_owned());
+        result.extend(self.overload_history.serialize());
+        result.extend(self.underload_history.serialize());
+        result.extend(self.total_balance.serialize());
+        result.extend(self.total_validator_fees.serialize());
+        result.extend(self.libraries.serialize());
+        result.extend(self.master_ref.serialize());
+        result.push("}".to_owned());
+        match &self.custom {
+            None => result.push("u 0 1bit".to_owned()),
+            Some(custom) => {
+                result.extend(["u 1 1bit".to_owned(), "ref {".to_owned()]);
+                result.extend(custom.serialize());
+                result.push("}".to_owned());
+            }
+        }
+        result
+    }
+}
+
+impl CellDeserialize for ShardStateUnsplit {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        if slice.load_uint(32)? != Self::TAG as u128 {
+            return Err(TlbError::UnknownTag {type_name: "ShardStateUnsplit"});
+        }
+        let global_id = Int::<32>::deserialize(slice)?.0 as i32;
+        let shard_id = ShardIdent::deserialize(slice)?;
+        let seq_no = u32::deserialize(slice)?;
+        let vert_seq_no = u32::deserialize(slice)?;
+        let gen_utime = u32::deserialize(slice)?;
+        let gen_lt = u64::deserialize(slice)?;
+        let min_ref_mc_seqno = u32::deserialize(slice)?;
+        let out_msg_queue_info = Cell::clone(slice.load_ref()?);
+        let before_split = bool::deserialize(slice)?;
+        let accounts = Cell::clone(slice.load_ref()?);
+        
+        let mut extra = slice.load_ref_slice()?;
+        let overload_history = u64::deserialize(&mut extra)?;
+        let underload_history = u64::deserialize(&mut extra)?;
+        let total_balance = CurrencyCollection::deserialize(&mut extra)?;
+        let total_validator_fees = CurrencyCollection::deserialize(&mut extra)?;
+        let libraries = HashmapE::deserialize(&mut extra)?;
+        let master_ref = Option::<ExtBlkRef>::deserialize(&mut extra)?;
+        if extra.mode() != Some(ParseMode::Lenient) && !extra.is_empty() {
+            return Err(TlbError::TrailingData);
+        }
+        
+        let custom = if slice.load_bit()? {Some(Cell::clone(slice.load_ref()?))} else {None};
+        Ok(ShardStateUnsplit {
+            global_id, shard_id, seq_no, vert_seq_no, gen_utime, gen_lt, min_ref_mc_seqno,
+            out_msg_queue_info, before_split, accounts,
+            overload_history, underload_history, total_balance, total_validator_fees, libraries, master_ref,
+            custom
+        })
+    }
+    
+    fn prefix_matches(slice: &CellSlice) -> bool {
+        matches!(slice.peek_uint(32), Ok(tag) if tag == Self::TAG as u128)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ton::cell::CellBuilder;
+    use crate::ton::Coins;
+    use std::sync::Arc;
+    
+    #[test]
+    fn shard_hashes_list_shards_of_workchains() {
+        let descr = |seq_no| ShardDescr {
+            seq_no, reg_mc_seqno: 100, start_lt: 1000, end_lt: 1001,
+            root_hash: FixedBits(RawBits::new(vec![0x11; 32], 256)), file_hash: FixedBits(RawBits::new(vec![0x22; 32], 256)),
+            before_split: false, before_merge: false, want_split: true, want_merge: false, nx_cc_updated: false,
+            next_catchain_seqno: 3, next_validator_shard: 1 << 63, min_ref_mc_seqno: 99, gen_utime: 1_700_000_000,
+            split_merge_at: FutureSplitMerge::Split{split_utime: 1_700_000_100, interval: 60},
+            fees_collected: Coins(5).into(), funds_created: Coins(0).into(),
+        };
+        let tree = BinTree::Fork(Box::new(BinTree::Leaf(descr(7))), Box::new(BinTree::Leaf(descr(8))));
+        assert_eq!(BinTree::<ShardDescr>::from_cell(&tree.to_cell().unwrap()), Ok(tree.clone()));
+        
+        // the only key -1 as hml_same$11 v=1 n=32, value ^(BinTree ShardDescr)
+        let mut leaf = CellBuilder::new();
+        leaf.store_uint(0b111, 3).unwrap().store_uint(32, 6).unwrap();
+        leaf.store_ref(Arc::new(tree.to_cell().unwrap())).unwrap();
+        let mut dict = CellBuilder::new();
+        dict.store_bit(true).unwrap().store_ref(Arc::new(leaf.build())).unwrap();
+        let hashes = ShardHashes::from_cell(&dict.build()).unwrap();
+        
+        assert_eq!(hashes.get(-1), Some(&tree));
+        assert_eq!(hashes.get(0), None);
+        let shards: Vec<_> = hashes.shards().map(|(workchain, prefix, descr)| (workchain, prefix, descr.seq_no)).collect();
+        assert_eq!(shards, [(-1, RawBits::new(vec![0x00], 1), 7), (-1, RawBits::new(vec![0x80], 1), 8)]);
+    }
+    
+    fn shard_state(out_msg_queue_info: Cell) -> ShardStateUnsplit {
+        let mut accounts = CellBuilder::new();
+        accounts.store_uint(0xacc, 12).unwrap();
+        ShardStateUnsplit {
+            global_id: -239,
+            shard_id: ShardIdent {shard_pfx_bits: 0, workchain_id: 0, shard_prefix: 1 << 63},
+            seq_no: 42, vert_seq_no: 1, gen_utime: 1_700_000_000, gen_lt: 50_000_000_000_001, min_ref_mc_seqno: 40,
+            out_msg_queue_info,
+            before_split: false,
+            accounts: accounts.build(),
+            overload_history: 0, underload_history: u64::MAX,
+            total_balance: Coins(5_000_000_000).into(), total_validator_fees: Coins(7).into(),
+            libraries: HashmapE::default(),
+            master_ref: Some(ExtBlkRef {
+                end_lt: 50_000_000_000_000, seq_no: 41,
+                root_hash: FixedBits(RawBits::new(vec![0x33; 32], 256)), file_hash: FixedBits(RawBits::new(vec![0x44; 32], 256)),
+            }),
+            custom: None,
+        }
+    }
+    
+    #[test]
+    fn shard_states_from_bags_of_cells() {
+        let mut queue = CellBuilder::new();
+        queue.store_uint(0b101, 3).unwrap();
+        let state = shard_state(queue.build());
+        
+        let cell = state.to_cell().unwrap();
+        assert_eq!(&cell.data()[..8], &[0x90, 0x23, 0xaf, 0xe2, 0xff, 0xff, 0xff, 0x11]);
+        assert_eq!((cell.bit_len(), cell.refs().len()), (32 + 32 + 104 + 32 * 3 + 64 + 32 + 1 + 1, 3));
+        let roots = crate::ton::boc::from_boc(&crate::ton::boc::to_boc(&cell, false, true)).unwrap();
+        let parsed = ShardStateUnsplit::from_cell(&roots[0]).unwrap();
+        assert_eq!(parsed, state);
+        assert_eq!(parsed.accounts.bit_len(), 12);
+        
+        let mut custom = CellBuilder::new();
+        custom.store_uint(0xcc, 8).unwrap();
+        let masterchain = ShardStateUnsplit {
+            shard_id: ShardIdent {workchain_id: -1, ..state.shard_id}, custom: Some(custom.build()), ..state
+        };
+        let cell = masterchain.to_cell().unwrap();
+        assert_eq!(cell.refs().len(), 4);
+        assert_eq!(ShardStateUnsplit::from_cell(&cell), Ok(masterchain));
+        
+        let mut wide = CellBuilder::new();
+        wide.store_uint(0, 2).unwrap().store_uint(61, 6).unwrap().store_uint(0, 96).unwrap();
+        assert_eq!(ShardIdent::from_cell(&wide.build()), Err(TlbError::InvalidValue));
+    }
+    
+    #[test]
+    fn masterchain_block_extras() {
+        let fees = ShardFeeCreated {fees: Coins(10).into(), create: Coins(20).into()};
+        let signature = CryptoSignaturePair {
+            node_id_short: FixedBits(RawBits::new(vec![0x77; 32], 256)),
+            sign: CryptoSignature {r: FixedBits(RawBits::new(vec![1; 32], 256)), s: FixedBits(RawBits::new(vec![2; 32], 256))},
+        };
+        let mut signatures = HashmapE::<16, CryptoSignaturePair>::default();
+        signatures.set(&0u16, &signature).unwrap();
+        let mut mint = CellBuilder::new();
+        mint.store_uint(0b100, 3).unwrap();
+        
+        let mut extra = CellBuilder::new();
+        signatures.store(&mut extra).unwrap();
+        extra.store_bit(false).unwrap().store_bit(true).unwrap().store_ref(Arc::new(mint.build())).unwrap();
+        let extra = Arc::new(extra.build());
+        let block = |key_block: bool| {
+            let mut builder = CellBuilder::new();
+            builder.store_uint(0xcca5, 16).unwrap().store_bit(key_block).unwrap();
+            builder.store_bit(false).unwrap();                          // no shards
+            builder.store_bit(false).unwrap();                          // no shard fees, ...
+            fees.store(&mut builder).unwrap();                          // ... summing up to these
+            builder.store_ref(Arc::clone(&extra)).unwrap();
+            if key_block {
+                builder.store_bits(&[0x55; 32], 256).unwrap().store_ref(Arc::new(Cell::default())).unwrap();
+            }
+            builder.build()
+        };
+        
+        let parsed = McBlockExtra::from_cell(&block(false)).unwrap();
+        assert!(parsed.shard_hashes.workchains.is_empty());
+        assert_eq!((parsed.shard_fees.is_empty(), &parsed.shard_fees.extra), (true, &fees));
+        assert_eq!(parsed.prev_blk_signatures.get(&0u16), Ok(Some(signature.clone())));
+        assert_eq!(signature.to_cell().unwrap().bit_len(), 256 + 4 + 512);
+        assert_eq!(parsed.recover_create_msg, None);
+        assert_eq!(parsed.mint_msg.map(|msg| msg.bit_len()), Some(3));
+        assert_eq!(parsed.config, None);
+        
+        let parsed = McBlockExtra::from_cell(&block(true)).unwrap();
+        assert_eq!(parsed.config.map(|config| config.config_addr), Some(FixedBits(RawBits::new(vec![0x55; 32], 256))));
+    }
+    
+    #[test]
+    fn message_descriptors_of_a_block() {
+        // one exported message under key 0x11…11 as hml_long$10 n=256 s=key, then its extra
+        // and the `OutMsg` description
+        let mut leaf = CellBuilder::new();
+        leaf.store_uint(0b10, 2).unwrap().store_uint(256, 9).unwrap().store_bits(&[0x11; 32], 256).unwrap();
+        CurrencyCollection::from(Coins(1_000)).store(&mut leaf).unwrap();
+        leaf.store_uint(0b001, 3).unwrap();                             // msg_export_new$001
+        let mut descr = CellBuilder::new();
+        descr.store_bit(true).unwrap().store_ref(Arc::new(leaf.build())).unwrap();
+        CurrencyCollection::from(Coins(1_000)).store(&mut descr).unwrap();
+        let out_msgs = OutMsgDescr::from_cell(&descr.build()).unwrap();
+        
+        assert_eq!(out_msgs.extra, Coins(1_000).into());
+        let entries = out_msgs.iter().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!((&entries[0].0, &entries[0].1), (&RawBits::new(vec![0x11; 32], 256), &Coins(1_000).into()));
+        assert_eq!((entries[0].2.bit_len(), entries[0].2.data()[0] >> 5), (3, 0b001));
+        
+        let mut descr = CellBuilder::new();
+        descr.store_bit(false).unwrap();
+        ImportFees {fees_collected: Coins(0), value_imported: Coins(0).into()}.store(&mut descr).unwrap();
+        let in_msgs = InMsgDescr::from_cell(&descr.build()).unwrap();
+        assert!(in_msgs.is_empty());
+        assert_eq!(in_msgs.extra.fees_collected, Coins(0));
+    }
+    
+    #[test]
+    fn queues_of_shard_states() {
+        let mut envelope = CellBuilder::new();
+        envelope.store_uint(4, 4).unwrap();
+        let message = EnqueuedMsg {enqueued_lt: 1_000_001, out_msg: envelope.build()};
+        // key: workchain 0, next hop prefix 0x80…, message hash 0x22…22 as hml_long$10 n=352
+        let mut leaf = CellBuilder::new();
+        leaf.store_uint(0b10, 2).unwrap().store_uint(352, 9).unwrap();
+        leaf.store_uint(0, 32).unwrap().store_uint(1 << 63, 64).unwrap().store_bits(&[0x22; 32], 256).unwrap();
+        leaf.store_uint(1_000_001, 64).unwrap();
+        message.store(&mut leaf).unwrap();
+        let mut queue = CellBuilder::new();
+        queue.store_bit(true).unwrap().store_ref(Arc::new(leaf.build())).unwrap().store_uint(1_000_001, 64).unwrap();
+        
+        let mut processed = ProcessedInfo::default();
+        let upto = ProcessedUpto {last_msg_lt: 999, last_msg_hash: FixedBits(RawBits::new(vec![0x33; 32], 256))};
+        processed.set(&FixedBits::<96>(RawBits::new(vec![0; 12], 96)), &upto).unwrap();
+        processed.store(&mut queue).unwrap();
+        queue.store_bit(false).unwrap();
+        let queue = queue.build();
+        
+        let info = OutMsgQueueInfo::from_cell(&queue).unwrap();
+        assert_eq!(info.out_queue.extra, 1_000_001);
+        let entries = info.out_queue.iter().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].0.bit_len(), 352);
+        assert_eq!((entries[0].1, &entries[0].2), (1_000_001, &message));
+        assert_eq!(info.proc_info.iter().map(|entry| entry.unwrap().1).collect::<Vec<_>>(), [upto]);
+        assert!(info.ihr_pending.is_empty());
+        assert_eq!(info.to_cell(), Ok(queue.clone()));
+        
+        let state = ShardStateUnsplit::from_cell(&shard_state(queue).to_cell().unwrap()).unwrap();
+        assert_eq!(state.out_msg_queue(), Ok(info));
+    }
+}
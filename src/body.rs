@@ -0,0 +1,388 @@
+// (c) ProgramCrafter, 2024
+
+//! Dispatching internal message bodies by their 32-bit op-code, as indexers do before decoding
+//! them: handlers get the whole body, op-code included, so types with the op-code as their tag
+//! read it directly. [`detect_interface`] tells the standard a body belongs to by op-code alone,
+//! and [`decode_known_body`] reads bodies of the types in a registry of constructors.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use crate::ton::cell::{Cell, CellSlice, TlbError};
+use crate::bounce::BouncedBody;
+use crate::dedust::NativeSwap;
+use crate::jetton::{ProvideWalletAddress, TakeWalletAddress};
+use crate::sbt::{Destroy, OwnershipProof, ProveOwnership, Revoke};
+use crate::vesting::{AddWhitelist, SendMessage};
+use crate::ton::CellDeserialize;
+
+
+type Handler<'h, R> = Box<dyn Fn(CellSlice<'_>) -> R + 'h>;
+
+/// Handlers of message bodies by op-code, with a default one for other bodies, including ones
+/// shorter than an op-code (e.g. empty bodies of plain transfers).
+///
+/// ```
+/// # use ton_tlb_serialize::body::BodyRouter;
+/// # use ton_tlb_serialize::jetton::ProvideWalletAddress;
+/// # use ton_tlb_serialize::ton::cell::Cell;
+/// let mut router = BodyRouter::new(|_| "other".to_owned());
+/// router.on_body(ProvideWalletAddress::TAG as u32, |request: Result<ProvideWalletAddress, _>| {
+///     format!("{:?}", request.map(|request| request.query_id))
+/// });
+/// assert_eq!(router.route_cell(&Cell::default()), "other");
+/// ```
+pub struct BodyRouter<'h, R> {
+    handlers: HashMap<u32, Handler<'h, R>>,
+    default: Handler<'h, R>,
+}
+
+impl<'h, R> BodyRouter<'h, R> {
+    /// Router with no op-codes, sending every body to `default`.
+    pub fn new(default: impl Fn(CellSlice<'_>) -> R + 'h) -> Self {
+        BodyRouter {handlers: HashMap::new(), default: Box::new(default)}
+    }
+    
+    /// Sends bodies starting with `op` to `handler`, in place of the one set for it before.
+    pub fn on(&mut self, op: u32, handler: impl Fn(CellSlice<'_>) -> R + 'h) -> &mut Self {
+        self.handlers.insert(op, Box::new(handler));
+        self
+    }
+    
+    /// Sends bodies starting with `op` to `handler` as `T`, read from the whole body.
+    pub fn on_body<T: CellDeserialize>(&mut self, op: u32, handler: impl Fn(Result<T, TlbError>) -> R + 'h) -> &mut Self {
+        self.on(op, move |body| handler(T::from_whole_slice(body)))
+    }
+    
+    /// Whether bodies starting with `op` have a handler of their own.
+    pub fn handles(&self, op: u32) -> bool {
+        self.handlers.contains_key(&op)
+    }
+    
+    /// Result of the handler for the op-code `body` starts with, or of the default one.
+    pub fn route(&self, body: CellSlice<'_>) -> R {
+        let handler = body.peek_uint(32).ok().and_then(|op| self.handlers.get(&(op as u32)));
+        handler.unwrap_or(&self.default)(body)
+    }
+    
+    /// [`BodyRouter::route`] for the whole `body` cell.
+    pub fn route_cell(&self, body: &Cell) -> R {
+        self.route(CellSlice::new(body))
+    }
+}
+
+/// Operations of jetton wallets and masters (TEP-74, TEP-89).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JettonOp {
+    /// `transfer#0f8a7ea5`, from the owner to their wallet.
+    Transfer,
+    /// `internal_transfer#178d4519`, between wallets or from the master minting.
+    InternalTransfer,
+    /// `transfer_notification#7362d09c`, from the wallet to its owner on receiving jettons.
+    TransferNotification,
+    /// `burn#595f07bc`, from the owner to their wallet.
+    Burn,
+    /// `burn_notification#7bdd97de`, from the wallet to the master.
+    BurnNotification,
+    /// `provide_wallet_address#2c76b973`, see [`ProvideWalletAddress`].
+    ProvideWalletAddress,
+    /// `take_wallet_address#d1735400`, see [`TakeWalletAddress`].
+    TakeWalletAddress,
+}
+
+/// Operations of NFT items and collections (TEP-62).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NftOp {
+    /// `transfer#5fcc3d14`, from the owner to the item.
+    Transfer,
+    /// `ownership_assigned#05138d91`, from the item to its new owner.
+    OwnershipAssigned,
+    /// `get_static_data#2fcb26a2`, asking the item for its index and collection.
+    GetStaticData,
+    /// `report_static_data#8b771735`, reply to `get_static_data`.
+    ReportStaticData,
+}
+
+/// Operations of soulbound tokens (TEP-85).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SbtOp {
+    /// `prove_ownership#04ded148`, see [`ProveOwnership`].
+    ProveOwnership,
+    /// `ownership_proof#0524c7ae`, see [`OwnershipProof`].
+    OwnershipProof,
+    /// `request_owner#d0c3bfea`, asking the token to report its owner to `dest`.
+    RequestOwner,
+    /// `owner_info#0dd607e3`, reply to `request_owner`.
+    OwnerInfo,
+    /// `destroy#1f04537a`, see [`Destroy`].
+    Destroy,
+    /// `revoke#6f89f5e3`, see [`Revoke`].
+    Revoke,
+}
+
+/// Internal messages to and from wallets. Signed external bodies of wallets v3 and v4 start
+/// with the signature, not with an op-code, so they are not detected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WalletOp {
+    /// Op-code 0: text comment in snake format.
+    Comment,
+    /// `0x2167da4b`: comment encrypted for the receiver.
+    EncryptedComment,
+    /// `0x706c7567` ("plug"): plugin asking wallet v4 for funds.
+    PluginRequestFunds,
+    /// `0x64737472` ("dstr"): plugin removing itself from wallet v4.
+    PluginRemove,
+    /// `0x73696e74` ("sint"): signed request to wallet v5 sent in an internal message.
+    SignedInternal,
+    /// `0x6578746e` ("extn"): request from an extension of wallet v5.
+    ExtensionAction,
+}
+
+/// Standard and operation of a message body, see [`detect_interface`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KnownInterface {
+    Jetton(JettonOp),
+    Nft(NftOp),
+    Sbt(SbtOp),
+    Wallet(WalletOp),
+    /// `excesses#d53276db`: funds left from an operation, returned by jetton wallets, NFT items
+    /// and other contracts alike.
+    Excesses,
+}
+
+const KNOWN_OPS: [(u32, KnownInterface); 24] = [
+    (0x0f8a7ea5, KnownInterface::Jetton(JettonOp::Transfer)),
+    (0x178d4519, KnownInterface::Jetton(JettonOp::InternalTransfer)),
+    (0x7362d09c, KnownInterface::Jetton(JettonOp::TransferNotification)),
+    (0x595f07bc, KnownInterface::Jetton(JettonOp::Burn)),
+    (0x7bdd97de, KnownInterface::Jetton(JettonOp::BurnNotification)),
+    (ProvideWalletAddress::TAG as u32, KnownInterface::Jetton(JettonOp::ProvideWalletAddress)),
+    (TakeWalletAddress::TAG as u32, KnownInterface::Jetton(JettonOp::TakeWalletAddress)),
+    (0x5fcc3d14, KnownInterface::Nft(NftOp::Transfer)),
+    (0x05138d91, KnownInterface::Nft(NftOp::OwnershipAssigned)),
+    (0x2fcb26a2, KnownInterface::Nft(NftOp::GetStaticData)),
+    (0x8b771735, KnownInterface::Nft(NftOp::ReportStaticData)),
+    (ProveOwnership::TAG as u32, KnownInterface::Sbt(SbtOp::ProveOwnership)),
+    (OwnershipProof::TAG as u32, KnownInterface::Sbt(SbtOp::OwnershipProof)),
+    (0xd0c3bfea, KnownInterface::Sbt(SbtOp::RequestOwner)),
+    (0x0dd607e3, KnownInterface::Sbt(SbtOp::OwnerInfo)),
+    (Destroy::TAG as u32, KnownInterface::Sbt(SbtOp::Destroy)),
+    (Revoke::TAG as u32, KnownInterface::Sbt(SbtOp::Revoke)),
+    (0x00000000, KnownInterface::Wallet(WalletOp::Comment)),
+    (0x2167da4b, KnownInterface::Wallet(WalletOp::EncryptedComment)),
+    (0x706c7567, KnownInterface::Wallet(WalletOp::PluginRequestFunds)),
+    (0x64737472, KnownInterface::Wallet(WalletOp::PluginRemove)),
+    (0x73696e74, KnownInterface::Wallet(WalletOp::SignedInternal)),
+    (0x6578746e, KnownInterface::Wallet(WalletOp::ExtensionAction)),
+    (0xd53276db, KnownInterface::Excesses),
+];
+
+impl KnownInterface {
+    /// Op-code bodies of this kind start with.
+    pub fn op(self) -> u32 {
+        KNOWN_OPS.iter().find(|(_, known)| *known == self).expect("every kind has an op-code").0
+    }
+}
+
+/// Standard and operation of `body` by its op-code, without consuming it or checking the rest;
+/// `None` for unknown op-codes and bodies shorter than one.
+pub fn detect_interface(body: &CellSlice) -> Option<KnownInterface> {
+    let op = body.peek_uint(32).ok()? as u32;
+    KNOWN_OPS.iter().find(|(known, _)| *known == op).map(|&(_, known)| known)
+}
+
+/// Body read by [`decode_known_body`], of the type named by [`KnownBody::type_name`] and
+/// recovered with [`downcast_ref`](#method.downcast_ref).
+pub trait KnownBody: Any + Debug {
+    /// Name of the Rust type of the body.
+    fn type_name(&self) -> &'static str;
+}
+
+impl<T: CellDeserialize + Any + Debug> KnownBody for T {
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
+}
+
+impl dyn KnownBody {
+    /// Body as `T`, if it is of that type.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        (self as &dyn Any).downcast_ref()
+    }
+}
+
+/// Entry of a body registry: op-code and the reader of bodies starting with it. Registries are
+/// plain slices, usually built with [`body_constructors!`](crate::body_constructors) from types
+/// with 32-bit tags; types cannot register themselves.
+#[derive(Clone, Copy)]
+pub struct BodyConstructor {
+    pub op: u32,
+    pub read: fn(CellSlice<'_>) -> Result<Box<dyn KnownBody>, TlbError>,
+}
+
+impl BodyConstructor {
+    /// Constructor reading `T` from the whole body for op-code `op`.
+    pub const fn new<T: CellDeserialize + KnownBody>(op: u32) -> Self {
+        fn read<T: CellDeserialize + KnownBody>(body: CellSlice<'_>) -> Result<Box<dyn KnownBody>, TlbError> {
+            Ok(Box::new(T::from_whole_slice(body)?))
+        }
+        BodyConstructor {op, read: read::<T>}
+    }
+    
+    /// Constructor for a type with `TAG` and `TAG_BITS` constants, as given by the annotations;
+    /// fails to compile in constants for tags other than 32-bit.
+    pub const fn tagged<T: CellDeserialize + KnownBody>(tag: u64, tag_bits: usize) -> Self {
+        assert!(tag_bits == 32, "op-codes of message bodies take 32 bits");
+        Self::new::<T>(tag as u32)
+    }
+}
+
+/// Array of [`BodyConstructor`]s of the listed types, by their `TAG` constants or by op-codes
+/// given after `=`: `body_constructors![Destroy, Revoke, BouncedBody = BouncedBody::PREFIX]`.
+#[macro_export]
+macro_rules! body_constructors {
+    (@one $body:ty) => {
+        $crate::body::BodyConstructor::tagged::<$body>(<$body>::TAG, <$body>::TAG_BITS)
+    };
+    (@one $body:ty = $op:expr) => {
+        $crate::body::BodyConstructor::new::<$body>($op)
+    };
+    ($($body:ty $(= $op:expr)?),* $(,)?) => {
+        [$($crate::body_constructors!(@one $body $(= $op)?)),*]
+    };
+}
+
+/// Bodies of this crate's types that start with an op-code.
+pub static KNOWN_BODIES: &[BodyConstructor] = &body_constructors![
+    ProvideWalletAddress, TakeWalletAddress,
+    ProveOwnership, OwnershipProof, Destroy, Revoke,
+    AddWhitelist, SendMessage,
+    NativeSwap,
+    BouncedBody = BouncedBody::PREFIX,
+];
+
+/// [`decode_known_body`] with a registry of one's own, e.g. [`KNOWN_BODIES`] extended with
+/// other types. The first constructor for the op-code is used.
+pub fn decode_body_with(constructors: &[BodyConstructor], body: CellSlice<'_>) -> Option<Result<Box<dyn KnownBody>, TlbError>> {
+    let op = body.peek_uint(32).ok()? as u32;
+    let constructor = constructors.iter().find(|constructor| constructor.op == op)?;
+    Some((constructor.read)(body))
+}
+
+/// Body read as the type of its op-code in [`KNOWN_BODIES`]; `None` if the op-code is not
+/// there, and an error if the body does not hold a value of that type.
+pub fn decode_known_body(body: CellSlice<'_>) -> Option<Result<Box<dyn KnownBody>, TlbError>> {
+    decode_body_with(KNOWN_BODIES, body)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use crate::ton::cell::CellBuilder;
+    use crate::ton::{BodyHeader, CellSerialize, MsgAddress};
+    
+    #[derive(Debug, PartialEq, Eq)]
+    enum Event {
+        Request(Result<u64, TlbError>),
+        Destroy(u64),
+        Other(Option<u32>),
+    }
+    
+    #[test]
+    fn bodies_go_to_handlers_of_their_op() {
+        let seen = RefCell::new(vec![]);
+        let mut router = BodyRouter::new(|body| seen.borrow_mut().push(Event::Other(body.peek_uint(32).ok().map(|op| op as u32))));
+        router.on_body(ProvideWalletAddress::TAG as u32, |request: Result<ProvideWalletAddress, _>| {
+            seen.borrow_mut().push(Event::Request(request.map(|request| request.query_id)))
+        });
+        router.on(Destroy::TAG as u32, |mut body| {
+            let header = BodyHeader::deserialize(&mut body).unwrap();
+            seen.borrow_mut().push(Event::Destroy(header.query_id))
+        });
+        assert!(router.handles(Destroy::TAG as u32));
+        assert!(!router.handles(TakeWalletAddress::TAG as u32));
+        
+        let request = ProvideWalletAddress {query_id: 3, owner_address: MsgAddress::default(), include_address: false};
+        router.route_cell(&request.to_cell().unwrap());
+        router.route_cell(&Destroy {query_id: 9}.to_cell().unwrap());
+        let reply = TakeWalletAddress {query_id: 3, wallet_address: MsgAddress::default(), owner_address: None};
+        router.route_cell(&reply.to_cell().unwrap());
+        router.route_cell(&Cell::default());
+        // known op-code with a body of another type
+        router.route_cell(&BodyHeader {op: ProvideWalletAddress::TAG as u32, query_id: 4}.to_cell().unwrap());
+        let mut short = CellBuilder::new();
+        short.store_uint(0xd1, 8).unwrap();
+        router.route_cell(&short.build());
+        
+        drop(router);
+        assert_eq!(seen.into_inner(), [
+            Event::Request(Ok(3)),
+            Event::Destroy(9),
+            Event::Other(Some(TakeWalletAddress::TAG as u32)),
+            Event::Other(None),
+            Event::Request(Err(TlbError::UnknownTag {type_name: "MsgAddress"})),
+            Event::Other(None),
+        ]);
+    }
+    
+    #[test]
+    fn handlers_are_replaced() {
+        let mut router = BodyRouter::new(|_| 0);
+        router.on(7, |_| 1).on(7, |_| 2);
+        assert_eq!(router.route_cell(&BodyHeader {op: 7, query_id: 0}.to_cell().unwrap()), 2);
+    }
+    
+    #[test]
+    fn interfaces_by_op() {
+        let request = ProvideWalletAddress {query_id: 3, owner_address: MsgAddress::default(), include_address: false};
+        let cell = request.to_cell().unwrap();
+        let slice = CellSlice::new(&cell);
+        assert_eq!(detect_interface(&slice), Some(KnownInterface::Jetton(JettonOp::ProvideWalletAddress)));
+        assert_eq!(slice.remaining_bits(), cell.bit_len());
+        
+        let body = |op| BodyHeader {op, query_id: 0}.to_cell().unwrap();
+        assert_eq!(detect_interface(&CellSlice::new(&body(0x0f8a7ea5))), Some(KnownInterface::Jetton(JettonOp::Transfer)));
+        assert_eq!(detect_interface(&CellSlice::new(&body(0x5fcc3d14))), Some(KnownInterface::Nft(NftOp::Transfer)));
+        assert_eq!(detect_interface(&CellSlice::new(&Destroy {query_id: 1}.to_cell().unwrap())), Some(KnownInterface::Sbt(SbtOp::Destroy)));
+        assert_eq!(detect_interface(&CellSlice::new(&body(0xd53276db))), Some(KnownInterface::Excesses));
+        assert_eq!(detect_interface(&CellSlice::new(&body(0))), Some(KnownInterface::Wallet(WalletOp::Comment)));
+        assert_eq!(detect_interface(&CellSlice::new(&body(0x12345678))), None);
+        assert_eq!(detect_interface(&CellSlice::new(&Cell::default())), None);
+        
+        for (op, known) in KNOWN_OPS {
+            assert_eq!(known.op(), op);
+            assert_eq!(detect_interface(&CellSlice::new(&body(op))), Some(known));
+        }
+    }
+    
+    #[test]
+    fn bodies_of_registered_types() {
+        let request = ProvideWalletAddress {query_id: 3, owner_address: MsgAddress::default(), include_address: true};
+        let decoded = decode_known_body(CellSlice::new(&request.to_cell().unwrap())).unwrap().unwrap();
+        assert_eq!(decoded.type_name(), "ton_tlb_serialize::jetton::ProvideWalletAddress");
+        assert_eq!(decoded.downcast_ref::<ProvideWalletAddress>(), Some(&request));
+        assert_eq!(decoded.downcast_ref::<TakeWalletAddress>(), None);
+        
+        let bounced = BouncedBody::new(&Revoke {query_id: 5}.to_cell().unwrap());
+        let decoded = decode_known_body(CellSlice::new(&bounced.to_cell().unwrap())).unwrap().unwrap();
+        assert_eq!(decoded.downcast_ref::<BouncedBody>(), Some(&bounced));
+        
+        let header = |op| BodyHeader {op, query_id: 0}.to_cell().unwrap();
+        assert_eq!(decode_known_body(CellSlice::new(&header(Destroy::TAG as u32))).unwrap().unwrap().type_name(), "ton_tlb_serialize::sbt::Destroy");
+        assert_eq!(decode_known_body(CellSlice::new(&header(Revoke::TAG as u32 + 1))).map(|body| body.err()), None);
+        assert_eq!(decode_known_body(CellSlice::new(&header(AddWhitelist::TAG as u32))).map(|body| body.err()), Some(Some(TlbError::UnknownTag {type_name: "MsgAddressInt"})));
+        
+        // own registry, with an op-code of this crate read as another type
+        let registry = body_constructors![Destroy, BodyHeader = Revoke::TAG as u32];
+        let decoded = decode_body_with(&registry, CellSlice::new(&Revoke {query_id: 5}.to_cell().unwrap())).unwrap().unwrap();
+        assert_eq!(decoded.downcast_ref(), Some(&BodyHeader {op: Revoke::TAG as u32, query_id: 5}));
+        
+        let mut ops: Vec<_> = KNOWN_BODIES.iter().map(|constructor| constructor.op).collect();
+        ops.sort();
+        ops.dedup();
+        assert_eq!(ops.len(), KNOWN_BODIES.len());
+    }
+}
@@ -0,0 +1,148 @@
+// (c) ProgramCrafter, 2024
+
+//! Bodies of bounced messages, returned to the sender when processing of a message
+//! with `bounce` flag fails.
+
+use crate::ton::cell::{Cell, CellBuilder, CellError, CellSlice, TlbError};
+use crate::ton::{CellDeserialize, CellSerialize, RawBits};
+
+
+/// Bits of the original body kept in a bounced one; references are dropped.
+pub const BOUNCED_BODY_BITS: usize = 256;
+
+/// Bounced body: `0xffffffff` followed by the first 256 bits of the original body
+/// (with `capBounceMsgBody` capability enabled, see [`crate::config::Capabilities`]).
+///
+/// Reading fails with `TlbError::TagMismatch` for bodies that are not bounced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BouncedBody {
+    pub original: RawBits,
+}
+
+impl BouncedBody {
+    pub const PREFIX: u32 = 0xffffffff;
+    
+    /// Body the network bounces message with `original` body back with.
+    pub fn new(original: &Cell) -> Self {
+        let bit_len = original.bit_len().min(BOUNCED_BODY_BITS);
+        let data = CellSlice::new(original).load_bits(bit_len).expect("cell has at least its own bits");
+        BouncedBody {original: RawBits::new(data, bit_len)}
+    }
+    
+    /// Kept part of the original body, to be read by the original message type as far as it goes.
+    pub fn original_cell(&self) -> Cell {
+        self.original.to_cell().expect("256 bits fit into a cell")
+    }
+    
+    /// `op:uint32 query_id:uint64` of the original message, which most contracts start bodies
+    /// with, for finding the message that bounced. `None` if the original body was shorter.
+    pub fn op_and_query_id(&self) -> Option<(u32, u64)> {
+        let cell = self.original_cell();
+        let mut slice = CellSlice::new(&cell);
+        let op = slice.load_uint(32).ok()? as u32;
+        let query_id = slice.load_uint(64).ok()? as u64;
+        Some((op, query_id))
+    }
+}
+
+impl CellSerialize for BouncedBody {
+    fn serialize(&self) -> Vec<String> {
+        assert!(self.original.bit_len() <= BOUNCED_BODY_BITS, "bounced body keeps at most 256 bits of the original");
+        let mut result = vec![format!("u {} 32bit", Self::PREFIX)];
+        result.extend(self.original.serialize());
+        result
+    }
+    
+    fn store(&self, builder: &mut CellBuilder) -> Result<(), CellError> {
+        assert!(self.original.bit_len() <= BOUNCED_BODY_BITS, "bounced body keeps at most 256 bits of the original");
+        builder.store_uint(Self::PREFIX as u128, 32)?;
+        self.original.store(builder)
+    }
+}
+
+impl CellDeserialize for BouncedBody {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        if slice.load_uint(32)? != Self::PREFIX as u128 {
+            return Err(TlbError::TagMismatch);
+        }
+        let bit_len = slice.remaining_bits().min(BOUNCED_BODY_BITS);
+        Ok(BouncedBody {original: RawBits::new(slice.load_bits(bit_len)?, bit_len)})
+    }
+    
+    fn prefix_matches(slice: &CellSlice) -> bool {
+        slice.peek_uint(32) == Ok(Self::PREFIX as u128)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jetton::ProvideWalletAddress;
+    use crate::ton::{Address, BodyHeader, MsgAddress};
+    use std::sync::Arc;
+    
+    fn provide_wallet_address() -> ProvideWalletAddress {
+        let owner: Address = "0:ed1691307050047117b998b561d8de82d31fbf84910ced6eb5fc92e7485ef8a7".parse().unwrap();
+        ProvideWalletAddress {query_id: 77, owner_address: MsgAddress::from(owner), include_address: true}
+    }
+    
+    #[test]
+    fn new_keeps_first_bits_without_refs() {
+        let original = provide_wallet_address().to_cell().unwrap();
+        assert_eq!(original.bit_len(), 32 + 64 + 267 + 1);
+        let bounced = BouncedBody::new(&original);
+        assert_eq!(bounced.original.bit_len(), BOUNCED_BODY_BITS);
+        let kept = bounced.original_cell();
+        assert!((0..BOUNCED_BODY_BITS).all(|i| kept.bit(i) == original.bit(i)));
+        
+        let mut builder = CellBuilder::new();
+        builder.store_uint(0x1234, 16).unwrap();
+        builder.store_ref(Arc::new(original)).unwrap();
+        let short = builder.build();
+        let bounced = BouncedBody::new(&short);
+        assert_eq!(bounced.original, RawBits::new(vec![0x12, 0x34], 16));
+        assert!(bounced.original_cell().refs().is_empty());
+        assert_eq!(bounced.op_and_query_id(), None);
+    }
+    
+    #[test]
+    fn op_and_query_id_of_original() {
+        let bounced = BouncedBody::new(&provide_wallet_address().to_cell().unwrap());
+        assert_eq!(bounced.op_and_query_id(), Some((ProvideWalletAddress::TAG as u32, 77)));
+        
+        let header = BodyHeader {op: 0x0f8a7ea5, query_id: u64::MAX};
+        let bounced = BouncedBody::new(&header.to_cell().unwrap());
+        assert_eq!(bounced.original.bit_len(), 96);
+        assert_eq!(bounced.op_and_query_id(), Some((0x0f8a7ea5, u64::MAX)));
+    }
+    
+    #[test]
+    fn bounced_prefix_is_recognized_and_stripped() {
+        let bounced = BouncedBody::new(&provide_wallet_address().to_cell().unwrap());
+        let cell = bounced.to_cell().unwrap();
+        assert_eq!(cell.bit_len(), 32 + BOUNCED_BODY_BITS);
+        assert!(BouncedBody::prefix_matches(&CellSlice::new(&cell)));
+        assert_eq!(BouncedBody::from_cell(&cell), Ok(bounced.clone()));
+        
+        // the original body is cut inside the owner address, so only its header can be read
+        let kept = bounced.original_cell();
+        let (header, rest) = BodyHeader::from_cell_prefix(&kept).unwrap();
+        assert_eq!(header, BodyHeader {op: ProvideWalletAddress::TAG as u32, query_id: 77});
+        assert_eq!(rest.remaining_bits(), BOUNCED_BODY_BITS - 96);
+        assert_eq!(ProvideWalletAddress::from_cell(&kept), Err(TlbError::NotEnoughBits));
+    }
+    
+    #[test]
+    fn not_a_bounce() {
+        let cell = provide_wallet_address().to_cell().unwrap();
+        assert!(!BouncedBody::prefix_matches(&CellSlice::new(&cell)));
+        assert_eq!(BouncedBody::from_cell(&cell), Err(TlbError::TagMismatch));
+        
+        let mut builder = CellBuilder::new();
+        builder.store_uint(0xffff, 16).unwrap();
+        let short = builder.build();
+        assert!(!BouncedBody::prefix_matches(&CellSlice::new(&short)));
+        assert_eq!(BouncedBody::from_cell(&short), Err(TlbError::NotEnoughBits));
+    }
+}
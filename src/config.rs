@@ -0,0 +1,467 @@
+// (c) ProgramCrafter, 2024
+
+//! Blockchain configuration parameters stored in the masterchain state.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tlb_macro::*;
+use crate::ton::cell::{Cell, CellBuilder, CellError, CellSlice, TlbError};
+use crate::ton::dict::{HashmapE, InRef};
+use crate::ton::{CellDeserialize, CellSerialize, Coins, FixedBits};
+
+
+/// Set of network capabilities enabled by validators, stored as `capabilities:uint64`.
+///
+/// Some of them change what nodes produce and accept (e.g. bounced messages carrying the
+/// original body), so tools replaying network rules should consult them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Capabilities(pub u64);
+
+impl Capabilities {
+    pub const IHR_ENABLED: Capabilities = Capabilities(1);
+    pub const CREATE_STATS_ENABLED: Capabilities = Capabilities(2);
+    /// Bounced messages carry `0xffffffff` and the first 256 bits of the original body.
+    pub const BOUNCE_MSG_BODY: Capabilities = Capabilities(4);
+    pub const REPORT_VERSION: Capabilities = Capabilities(8);
+    pub const SPLIT_MERGE_TRANSACTIONS: Capabilities = Capabilities(16);
+    pub const SHORT_DEQUEUE: Capabilities = Capabilities(32);
+    pub const STORE_OUT_MSG_QUEUE_SIZE: Capabilities = Capabilities(64);
+    pub const MSG_METADATA: Capabilities = Capabilities(128);
+    pub const DEFER_MESSAGES: Capabilities = Capabilities(256);
+    pub const FULL_COLLATED_DATA: Capabilities = Capabilities(512);
+    
+    /// Known flags with their names as used in the reference node (`capIhrEnabled` etc.).
+    pub const NAMED: [(Capabilities, &'static str); 10] = [
+        (Self::IHR_ENABLED, "capIhrEnabled"),
+        (Self::CREATE_STATS_ENABLED, "capCreateStatsEnabled"),
+        (Self::BOUNCE_MSG_BODY, "capBounceMsgBody"),
+        (Self::REPORT_VERSION, "capReportVersion"),
+        (Self::SPLIT_MERGE_TRANSACTIONS, "capSplitMergeTransactions"),
+        (Self::SHORT_DEQUEUE, "capShortDequeue"),
+        (Self::STORE_OUT_MSG_QUEUE_SIZE, "capStoreOutMsgQueueSize"),
+        (Self::MSG_METADATA, "capMsgMetadata"),
+        (Self::DEFER_MESSAGES, "capDeferMessages"),
+        (Self::FULL_COLLATED_DATA, "capFullCollatedData"),
+    ];
+    
+    /// Whether all flags of `other` are set.
+    pub fn contains(self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+    
+    /// Names of the known flags that are set.
+    pub fn names(self) -> impl Iterator<Item = &'static str> {
+        Self::NAMED.into_iter().filter(move |&(flag, _)| self.contains(flag)).map(|(_, name)| name)
+    }
+    
+    /// Set bits that have no name here, e.g. capabilities introduced after this crate.
+    pub fn unknown(self) -> u64 {
+        Self::NAMED.iter().fold(self.0, |rest, (flag, _)| rest & !flag.0)
+    }
+}
+
+impl std::ops::BitOr for Capabilities {
+    type Output = Capabilities;
+    fn bitor(self, rhs: Capabilities) -> Capabilities {
+        Capabilities(self.0 | rhs.0)
+    }
+}
+
+/// Lists flag names separated by ` | `, with unknown bits in hex, e.g.
+/// `capIhrEnabled | capBounceMsgBody | 0x10000`.
+impl std::fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts: Vec<String> = self.names().map(str::to_owned).collect();
+        if self.unknown() != 0 {
+            parts.push(format!("{:#x}", self.unknown()));
+        }
+        if parts.is_empty() {
+            return f.write_str("0");
+        }
+        f.write_str(&parts.join(" | "))
+    }
+}
+
+impl CellSerialize for Capabilities {
+    fn serialize(&self) -> Vec<String> {
+        self.0.serialize()
+    }
+    
+    fn store(&self, builder: &mut CellBuilder) -> Result<(), CellError> {
+        self.0.store(builder)
+    }
+}
+
+impl CellDeserialize for Capabilities {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        Ok(Capabilities(u64::deserialize(slice)?))
+    }
+}
+
+
+/// `_ config_addr:bits256 config:^(Hashmap 32 ^Cell) = ConfigParams;`: address of the config
+/// contract and the parameters, each in its own cell keyed by index. `config` is the root of
+/// the dictionary, which is never empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(config_addr, ^config)]
+pub struct ConfigParams {
+    pub config_addr: FixedBits<256>,
+    pub config: Cell,
+}
+
+impl ConfigParams {
+    /// Cell of parameter `index`, e.g. [`GlobalVersion`] for 8; none if it is not set.
+    pub fn param(&self, index: i32) -> Result<Option<Cell>, TlbError> {
+        HashmapE::<32, Cell, InRef>::with_root(Some(Arc::new(self.config.clone()))).get(&index)
+    }
+}
+
+
+/// Config parameter 8: version of the network protocol and its enabled capabilities.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = "capabilities#c4 version:uint32 capabilities:uint64 = GlobalVersion;")]
+pub struct GlobalVersion {
+    pub version: u32,
+    pub capabilities: Capabilities,
+}
+
+
+/// Entry of config parameter 18: prices for storing a bit or a cell for a second, in 2^-16
+/// nanotons, in effect since `utime_since`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = r#"storage_prices#cc utime_since:uint32 bit_price_ps:uint64 cell_price_ps:uint64
+                               mc_bit_price_ps:uint64 mc_cell_price_ps:uint64 = StoragePrices;"#)]
+pub struct StoragePrices {
+    pub utime_since: u32,
+    pub bit_price_ps: u64,
+    pub cell_price_ps: u64,
+    pub mc_bit_price_ps: u64,
+    pub mc_cell_price_ps: u64,
+}
+
+/// Config parameters 24 (masterchain) and 25 (other workchains): prices for forwarding
+/// messages. Bit and cell prices are in 2^-16 nanotons; `first_frac` and `next_frac` are
+/// parts of the fee, in 2^-16, taken by the first and next hops of the message.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = r#"msg_forward_prices#ea lump_price:uint64 bit_price:uint64 cell_price:uint64
+                               ihr_price_factor:uint32 first_frac:uint16 next_frac:uint16 = MsgForwardPrices;"#)]
+pub struct MsgForwardPrices {
+    pub lump_price: u64,
+    pub bit_price: u64,
+    pub cell_price: u64,
+    pub ihr_price_factor: u32,
+    pub first_frac: u16,
+    pub next_frac: u16,
+}
+
+impl MsgForwardPrices {
+    /// Part of forward fee `fee` left after the first hop takes `first_frac` of it: what
+    /// the `fwd_fee` field of a sent internal message holds.
+    pub fn remaining_after_first_hop(&self, fee: Coins) -> Coins {
+        Coins(fee.0 - ((fee.0 * self.first_frac as u128) >> 16))
+    }
+}
+
+/// Fee for storing the tree of `cell` (e.g. a contract's state) for `duration`, rounded up
+/// to whole nanotons like the storage phase does. Every distinct cell counts, see
+/// [`Cell::stats`].
+pub fn estimate_storage_fee(cell: &Cell, duration: Duration, is_masterchain: bool, prices: &StoragePrices) -> Coins {
+    let stats = cell.stats();
+    let (bit_price, cell_price) = if is_masterchain {
+        (prices.mc_bit_price_ps, prices.mc_cell_price_ps)
+    } else {
+        (prices.bit_price_ps, prices.cell_price_ps)
+    };
+    let per_second = stats.bits as u128 * bit_price as u128 + stats.cells as u128 * cell_price as u128;
+    Coins((per_second * duration.as_secs() as u128).div_ceil(1 << 16))
+}
+
+/// Fee for forwarding the message in `msg_cell`: `lump_price` and the price of its bits
+/// and cells, rounded up to whole nanotons. The root cell of the message is not charged.
+pub fn estimate_fwd_fee(msg_cell: &Cell, prices: &MsgForwardPrices) -> Coins {
+    let stats = msg_cell.stats();
+    let (bits, cells) = ((stats.bits - msg_cell.bit_len()) as u128, (stats.cells - 1) as u128);
+    let price = bits * prices.bit_price as u128 + cells * prices.cell_price as u128;
+    Coins(prices.lump_price as u128 + price.div_ceil(1 << 16))
+}
+
+
+
+/// `ed25519_pubkey#8e81278a pubkey:bits256 = SigPubKey;`: public key validators sign with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = "ed25519_pubkey#8e81278a pubkey:bits256 = SigPubKey;")]
+pub struct SigPubKey {
+    pub pubkey: FixedBits<256>,
+}
+
+/// `ValidatorDescr`: validator of a set with its weight, the share of votes it has.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_enum_deserializable]
+#[tlb_enum_serializable]
+#[tlb_assert_unsafe(items_prefixes_nonoverlap)]
+pub enum ValidatorDescr {
+    /// `validator#53 public_key:SigPubKey weight:uint64`.
+    #[tlb_item_serializable(u 0x53 8bit, public_key, weight)]
+    Validator{public_key: SigPubKey, weight: u64},
+    /// `validator_addr#73 public_key:SigPubKey weight:uint64 adnl_addr:bits256`: with the ADNL
+    /// address the validator is reached at.
+    #[tlb_item_serializable(u 0x73 8bit, public_key, weight, adnl_addr)]
+    ValidatorAddr{public_key: SigPubKey, weight: u64, adnl_addr: FixedBits<256>},
+}
+
+impl ValidatorDescr {
+    pub fn public_key(&self) -> &SigPubKey {
+        match self {
+            ValidatorDescr::Validator {public_key, ..} | ValidatorDescr::ValidatorAddr {public_key, ..} => public_key,
+        }
+    }
+    
+    pub fn weight(&self) -> u64 {
+        match self {
+            ValidatorDescr::Validator {weight, ..} | ValidatorDescr::ValidatorAddr {weight, ..} => *weight,
+        }
+    }
+    
+    pub fn adnl_addr(&self) -> Option<&FixedBits<256>> {
+        match self {
+            ValidatorDescr::Validator {..} => None,
+            ValidatorDescr::ValidatorAddr {adnl_addr, ..} => Some(adnl_addr),
+        }
+    }
+}
+
+/// Config parameters 32, 34 and 36: previous, current and next sets of validators, in effect
+/// from `utime_since` to `utime_until`. The first `main` of `total` validators, keyed by
+/// index, validate the masterchain.
+///
+/// Both `validators#11` and `validators_ext#12` are read; the former keeps a non-empty
+/// list inline and no `total_weight`, which is then summed up. Writing always produces
+/// `validators_ext#12`, as current nodes do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidatorSet {
+    pub utime_since: u32,
+    pub utime_until: u32,
+    pub total: u16,
+    pub main: u16,
+    pub total_weight: u64,
+    pub list: HashmapE<16, ValidatorDescr>,
+}
+
+impl ValidatorSet {
+    pub const TAG: u8 = 0x12;
+    pub const TAG_INLINE_LIST: u8 = 0x11;
+    
+    /// Validators in the order of their indexes.
+    pub fn validators(&self) -> impl Iterator<Item = Result<ValidatorDescr, TlbError>> + '_ {
+        self.list.iter().map(|entry| entry.map(|(_, descr)| descr))
+    }
+}
+
+impl CellSerialize for ValidatorSet {
+    fn serialize(&self) -> Vec<String> {
+        let mut result = vec![format!("u {} 8bit", Self::TAG)];
+        result.extend(self.utime_since.serialize());
+        result.extend(self.utime_until.serialize());
+        result.extend(self.total.serialize());
+        result.extend(self.main.serialize());
+        result.extend(self.total_weight.serialize());
+        result.extend(self.list.serialize());
+        result
+    }
+}
+
+impl CellDeserialize for ValidatorSet {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        let inline_list = match slice.load_uint(8)? as u8 {
+            Self::TAG => false,
+            Self::TAG_INLINE_LIST => true,
+            _ => return Err(TlbError::UnknownTag {type_name: "ValidatorSet"}),
+        };
+        let utime_since = u32::deserialize(slice)?;
+        let utime_until = u32::deserialize(slice)?;
+        let total = u16::deserialize(slice)?;
+        let main = u16::deserialize(slice)?;
+        if main < 1 || main > total {
+            return Err(TlbError::InvalidValue);
+        }
+        if !inline_list {
+            let total_weight = u64::deserialize(slice)?;
+            let list = HashmapE::deserialize(slice)?;
+            return Ok(ValidatorSet {utime_since, utime_until, total, main, total_weight, list});
+        }
+        
+        // `Hashmap 16 ValidatorDescr` takes the rest of the cell: it is the root of the list
+        let mut root = CellBuilder::new();
+        root.store_remaining(slice).expect("rest of a cell fits in a cell");
+        slice.skip_bits(slice.remaining_bits())?;
+        slice.skip_refs(slice.remaining_refs())?;
+        let list = HashmapE::with_root(Some(Arc::new(root.build())));
+        let total_weight = list.iter().map(|entry| entry.map(|(_, descr): (_, ValidatorDescr)| descr.weight()))
+                               .sum::<Result<u64, TlbError>>()?;
+        Ok(ValidatorSet {utime_since, utime_until, total, main, total_weight, list})
+    }
+    
+    fn prefix_matches(slice: &CellSlice) -> bool {
+        matches!(slice.peek_uint(8), Ok(tag) if tag == Self::TAG as u128 || tag == Self::TAG_INLINE_LIST as u128)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ton::{Address, CommonMsgInfo, RawBits};
+    
+    /// Basechain prices of mainnet config parameters 18 and 25.
+    fn storage_prices() -> StoragePrices {
+        StoragePrices {utime_since: 0, bit_price_ps: 1, cell_price_ps: 500, mc_bit_price_ps: 1000, mc_cell_price_ps: 500_000}
+    }
+    
+    fn forward_prices() -> MsgForwardPrices {
+        MsgForwardPrices {
+            lump_price: 400_000, bit_price: 26_214_400, cell_price: 2_621_440_000,
+            ihr_price_factor: 98_304, first_frac: 21_845, next_frac: 21_845,
+        }
+    }
+    
+    #[test]
+    fn capabilities_names_and_unknown_bits() {
+        // mainnet ConfigParam 8 has long been `capabilities#c4 version:4 capabilities:46`
+        let mainnet = Capabilities(46);
+        assert_eq!(
+            mainnet.names().collect::<Vec<_>>(),
+            ["capCreateStatsEnabled", "capBounceMsgBody", "capReportVersion", "capShortDequeue"]
+        );
+        assert!(mainnet.contains(Capabilities::BOUNCE_MSG_BODY));
+        assert!(!mainnet.contains(Capabilities::IHR_ENABLED | Capabilities::BOUNCE_MSG_BODY));
+        assert_eq!(mainnet.unknown(), 0);
+        assert_eq!(mainnet.to_string(), "capCreateStatsEnabled | capBounceMsgBody | capReportVersion | capShortDequeue");
+        
+        let future = Capabilities::IHR_ENABLED | Capabilities(0x10000) | Capabilities(1 << 63);
+        assert_eq!(future.unknown(), 0x8000_0000_0001_0000);
+        assert_eq!(future.to_string(), "capIhrEnabled | 0x8000000000010000");
+        assert_eq!(Capabilities(0).to_string(), "0");
+        assert_eq!(Capabilities(0).names().count(), 0);
+        assert_eq!(Capabilities(0x3ff).names().count(), Capabilities::NAMED.len());
+    }
+    
+    #[test]
+    fn global_version_layout() {
+        let version = GlobalVersion {version: 4, capabilities: Capabilities(46)};
+        let cell = version.to_cell().unwrap();
+        assert_eq!(cell, Cell::parse_fift("x{C400000004000000000000002E}").unwrap());
+        assert_eq!(GlobalVersion::from_cell(&cell), Ok(version));
+    }
+    
+    #[test]
+    fn storage_fee_of_cells() {
+        // a full cell for a year: (1023 + 500) * 31536000 / 2^16 = 732869.2…
+        let mut builder = CellBuilder::new();
+        builder.store_bits(&[0xff; 128], 1023).unwrap();
+        let full = Arc::new(builder.build());
+        let year = Duration::from_secs(365 * 24 * 3600);
+        assert_eq!(estimate_storage_fee(&full, year, false, &storage_prices()), Coins(732_870));
+        // in the masterchain for 2^16 seconds: 1023 * 1000 + 500000, exactly
+        assert_eq!(estimate_storage_fee(&full, Duration::from_secs(65536), true, &storage_prices()), Coins(1_523_000));
+        
+        // a shared child counts once
+        let mut builder = CellBuilder::new();
+        builder.store_ref(Arc::clone(&full)).unwrap().store_ref(full).unwrap();
+        let root = builder.build();
+        // (1023 + 2 * 500) * 31536000 / 2^16 = 973469.9…
+        assert_eq!(estimate_storage_fee(&root, year, false, &storage_prices()), Coins(973_470));
+        
+        let prices = StoragePrices::from_cell(&storage_prices().to_cell().unwrap()).unwrap();
+        assert_eq!(prices, storage_prices());
+    }
+    
+    #[test]
+    fn forward_fee_of_messages() {
+        // a transfer without body: mainnet messages show `fwd_fee` 266669 of 400000
+        let info = CommonMsgInfo::int_msg_info {
+            ihr_disabled: true, bounce: true, bounced: false,
+            src: Address::new(0, [0x11; 32]).into(), dest: Address::new(0, [0x22; 32]).into(),
+            value: Coins(1_000_000_000).into(), ihr_fee: Coins(0), fwd_fee: Coins(0),
+            created_lt: 0, created_at: 0,
+        };
+        let mut builder = CellBuilder::new();
+        info.store(&mut builder).unwrap();
+        builder.store_uint(0, 2).unwrap();
+        let transfer = builder.build();
+        let fee = estimate_fwd_fee(&transfer, &forward_prices());
+        assert_eq!(fee, Coins(400_000));
+        assert_eq!(forward_prices().remaining_after_first_hop(fee), Coins(266_669));
+        
+        // body of 100 bits in a child: 400000 + (100 * 26214400 + 2621440000) / 2^16
+        let mut body = CellBuilder::new();
+        body.store_uint(0, 100).unwrap();
+        let mut builder = CellBuilder::new();
+        info.store(&mut builder).unwrap();
+        builder.store_uint(0b01, 2).unwrap().store_ref(Arc::new(body.build())).unwrap();
+        assert_eq!(estimate_fwd_fee(&builder.build(), &forward_prices()), Coins(480_000));
+        
+        let prices = MsgForwardPrices::from_cell(&forward_prices().to_cell().unwrap()).unwrap();
+        assert_eq!(prices, forward_prices());
+    }
+    
+    fn validator_set() -> ValidatorSet {
+        let key = |byte| SigPubKey {pubkey: FixedBits(RawBits::new(vec![byte; 32], 256))};
+        let mut list = HashmapE::default();
+        list.set(&0u16, &ValidatorDescr::Validator {public_key: key(0xaa), weight: 3}).unwrap();
+        let adnl_addr = FixedBits(RawBits::new(vec![0xcc; 32], 256));
+        list.set(&1u16, &ValidatorDescr::ValidatorAddr {public_key: key(0xbb), weight: 5, adnl_addr}).unwrap();
+        ValidatorSet {utime_since: 1_700_000_000, utime_until: 1_700_065_536, total: 2, main: 1, total_weight: 8, list}
+    }
+    
+    #[test]
+    fn validator_sets_of_both_constructors() {
+        let set = validator_set();
+        let cell = set.to_cell().unwrap();
+        assert_eq!(cell.bit_len(), 8 + 32 + 32 + 16 + 16 + 64 + 1);
+        assert_eq!(&cell.data()[..1], &[0x12]);
+        assert_eq!(ValidatorSet::from_cell(&cell), Ok(set.clone()));
+        
+        let validators = set.validators().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(validators.iter().map(ValidatorDescr::weight).collect::<Vec<_>>(), [3, 5]);
+        assert_eq!(validators[0].adnl_addr(), None);
+        assert_eq!(validators[1].adnl_addr().map(|addr| addr.0.data()[0]), Some(0xcc));
+        assert_eq!(validators[1].public_key().pubkey.0.data()[0], 0xbb);
+        let descr = validators[0].to_cell().unwrap();
+        assert_eq!(&descr.data()[..5], &[0x53, 0x8e, 0x81, 0x27, 0x8a]);
+        assert_eq!(descr.bit_len(), 8 + 32 + 256 + 64);
+        
+        // `validators#11` with the same list inline in place of `total_weight` and `list`
+        let mut builder = CellBuilder::new();
+        builder.store_uint(0x11, 8).unwrap().store_uint(1_700_000_000, 32).unwrap().store_uint(1_700_065_536, 32).unwrap();
+        builder.store_uint(2, 16).unwrap().store_uint(1, 16).unwrap();
+        set.list.root().unwrap().store(&mut builder).unwrap();
+        assert_eq!(ValidatorSet::from_cell(&builder.build()), Ok(set.clone()));
+        
+        let mut builder = CellBuilder::new();
+        builder.store_uint(0x12, 8).unwrap().store_uint(0, 64).unwrap().store_uint(2, 16).unwrap().store_uint(3, 16).unwrap();
+        builder.store_uint(8, 64).unwrap().store_bit(false).unwrap();
+        assert_eq!(ValidatorSet::from_cell(&builder.build()), Err(TlbError::InvalidValue));
+    }
+    
+    #[test]
+    fn config_params_by_index() {
+        let mut params = HashmapE::<32, Cell, InRef>::default();
+        params.set(&8i32, &GlobalVersion {version: 4, capabilities: Capabilities(46)}.to_cell().unwrap()).unwrap();
+        params.set(&-999i32, &Cell::default()).unwrap();
+        let config = ConfigParams {
+            config_addr: FixedBits(RawBits::new(vec![0x55; 32], 256)),
+            config: Cell::clone(params.root().unwrap()),
+        };
+        let config = ConfigParams::from_cell(&config.to_cell().unwrap()).unwrap();
+        
+        let version = config.param(8).unwrap().unwrap();
+        assert_eq!(GlobalVersion::from_cell(&version).unwrap().version, 4);
+        assert_eq!(config.param(-999), Ok(Some(Cell::default())));
+        assert_eq!(config.param(34), Ok(None));
+    }
+}
@@ -0,0 +1,117 @@
+// (c) ProgramCrafter, 2024
+
+//! Message bodies understood by DeDust vaults: swaps through one or several pools.
+//!
+//! Anonymous `_:SwapStep` fields of DeDust schemes are named `step` here.
+
+use tlb_macro::*;
+use crate::ton::cell::Cell;
+use crate::ton::{Coins, MsgAddress, MsgAddressInt};
+
+
+/// `SwapKind`: whether the amount sent (`given_in$0`) or the amount to receive
+/// (`given_out$1`) is fixed. Pools accept only `given_in` so far.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_enum_deserializable]
+#[tlb_enum_serializable]
+#[tlb_tag_bits(1)]
+pub enum SwapKind {
+    #[tlb_item_serializable()] GivenIn{},
+    #[tlb_item_serializable()] GivenOut{},
+}
+impl Default for SwapKind {
+    fn default() -> Self {
+        SwapKind::GivenIn{}
+    }
+}
+
+/// `step#_`: swap in `pool_addr`, optionally followed by the next step of a multi-hop swap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = r#"
+    step#_ pool_addr:MsgAddressInt params:SwapStepParams = SwapStep;
+"#)]
+pub struct SwapStep {
+    pub pool_addr: MsgAddressInt,
+    pub params: SwapStepParams,
+}
+
+/// `step_params#_`: `limit` is the minimal amount to receive (for `given_in`), zero for any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = r#"
+    step_params#_ kind:SwapKind limit:Coins next:(Maybe ^SwapStep) = SwapStepParams;
+"#)]
+pub struct SwapStepParams {
+    pub kind: SwapKind,
+    pub limit: Coins,
+    pub next: Option<Box<SwapStep>>,
+}
+
+/// `swap_params#_`: common parameters of the whole swap. `deadline` is Unix time after which
+/// the swap is rejected, zero for none; payloads are sent with the swapped or returned funds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = r#"
+    swap_params#_ deadline:uint32 recipient_addr:MsgAddressInt referral_addr:MsgAddress
+        fulfill_payload:(Maybe ^Cell) reject_payload:(Maybe ^Cell) = SwapParams;
+"#)]
+pub struct SwapParams {
+    pub deadline: u32,
+    pub recipient_addr: MsgAddressInt,
+    pub referral_addr: MsgAddress,
+    pub fulfill_payload: Option<Cell>,
+    pub reject_payload: Option<Cell>,
+}
+
+/// `swap#ea06185d`, sent with TON to the native vault to swap `amount` of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = r#"
+    swap#ea06185d query_id:uint64 amount:Coins step:SwapStep swap_params:^SwapParams = InMsgBody;
+"#)]
+pub struct NativeSwap {
+    pub query_id: u64,
+    pub amount: Coins,
+    pub step: SwapStep,
+    pub swap_params: SwapParams,
+}
+
+/// `swap#e3a0d482`, sent as `forward_payload` of a jetton transfer to the jetton vault.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = r#"
+    swap#e3a0d482 step:SwapStep swap_params:^SwapParams = ForwardPayload;
+"#)]
+pub struct JettonSwap {
+    pub step: SwapStep,
+    pub swap_params: SwapParams,
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ton::{Address, CellDeserialize, CellSerialize};
+    use crate::ton::cell::CellBuilder;
+    
+    #[test]
+    fn dedust_multi_hop_swap_round_trip() {
+        let mut fulfill = CellBuilder::new();
+        fulfill.store_uint(7, 8).unwrap();
+        let pool = |byte| MsgAddressInt::from(Address::new(0, [byte; 32]));
+        let last = SwapStep {pool_addr: pool(2), params: SwapStepParams {kind: SwapKind::GivenIn{}, limit: Coins(10), next: None}};
+        let swap = JettonSwap {
+            step: SwapStep {pool_addr: pool(1), params: SwapStepParams {kind: SwapKind::GivenIn{}, limit: Coins(0), next: Some(Box::new(last))}},
+            swap_params: SwapParams {
+                deadline: 0, recipient_addr: pool(3), referral_addr: MsgAddress::default(),
+                fulfill_payload: Some(fulfill.build()), reject_payload: None,
+            },
+        };
+        let cell = swap.to_cell().unwrap();
+        // tag, pool, given_in, limit and presence of the next step; swap parameters are in a child cell
+        assert_eq!(cell.bit_len(), 32 + 267 + 1 + 4 + 1);
+        assert_eq!(cell.refs().len(), 2);
+        assert_eq!(JettonSwap::from_cell(&cell), Ok(swap));
+    }
+}
@@ -0,0 +1,154 @@
+// (c) ProgramCrafter, 2024
+
+//! Message bodies of jetton wallet discovery (TEP-89), and addresses of jetton wallets.
+
+use tlb_macro::*;
+use crate::ton::cell::Cell;
+use crate::ton::{Address, CellSerialize, Coins, MsgAddress, MsgAddressInt, StateInit};
+
+
+/// `provide_wallet_address#2c76b973`, asking jetton master for the wallet of `owner_address`.
+/// With `include_address` set, the reply also repeats the owner address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = r#"
+    provide_wallet_address#2c76b973 query_id:uint64 owner_address:MsgAddress include_address:Bool
+        = InternalMsgBody;
+"#)]
+pub struct ProvideWalletAddress {
+    pub query_id: u64,
+    pub owner_address: MsgAddress,
+    pub include_address: bool,
+}
+
+/// `take_wallet_address#d1735400`, reply of jetton master to [`ProvideWalletAddress`]:
+/// the jetton wallet of the owner, `addr_none` if there can be none in this workchain,
+/// and the owner address itself if it was requested.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = r#"
+    take_wallet_address#d1735400 query_id:uint64 wallet_address:MsgAddress
+        owner_address:(Maybe ^MsgAddress) = InternalMsgBody;
+"#)]
+pub struct TakeWalletAddress {
+    pub query_id: u64,
+    pub wallet_address: MsgAddress,
+    pub owner_address: Option<MsgAddress>,
+}
+
+/// `_`: data of the standard jetton wallet (`jetton-wallet.fc` of the reference token
+/// contract), which keeps its own code to deploy wallets of receivers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = r#"
+    jetton_wallet_data#_ balance:Coins owner_address:MsgAddressInt jetton_master_address:MsgAddressInt
+        jetton_wallet_code:^Cell = JettonWalletData;
+"#)]
+pub struct JettonWalletData {
+    pub balance: Coins,
+    pub owner_address: MsgAddressInt,
+    pub jetton_master_address: MsgAddressInt,
+    pub jetton_wallet_code: Cell,
+}
+
+impl JettonWalletData {
+    /// Data of a wallet as deployed by the master: with zero balance.
+    pub fn new(owner: &Address, jetton_master: &Address, jetton_wallet_code: &Cell) -> Self {
+        JettonWalletData {
+            balance: Coins(0),
+            owner_address: owner.clone().into(),
+            jetton_master_address: jetton_master.clone().into(),
+            jetton_wallet_code: jetton_wallet_code.clone(),
+        }
+    }
+    
+    pub fn state_init(&self) -> StateInit {
+        let data = self.to_cell().expect("jetton wallet data fits into a cell");
+        StateInit::new(self.jetton_wallet_code.clone(), data)
+    }
+}
+
+/// Address of the jetton wallet of `owner`, as the master computes it for
+/// [`ProvideWalletAddress`] and transfers: from the initial state of the wallet, in the
+/// workchain of the master. Holds for masters deploying standard wallets with `wallet_code`;
+/// others (e.g. with extra fields in wallet data) give different addresses.
+pub fn jetton_wallet_address(owner: &Address, jetton_master: &Address, wallet_code: &Cell) -> Address {
+    JettonWalletData::new(owner, jetton_master, wallet_code).state_init().address(jetton_master.workchain())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ton::CellDeserialize;
+    use crate::ton::boc::from_boc;
+    use crate::ton::cell::TlbError;
+    use tlb_macro::boc;
+    
+    fn bits_of(cell: &Cell) -> String {
+        (0..cell.bit_len()).map(|i| if cell.bit(i) {'1'} else {'0'}).collect()
+    }
+    
+    /// `addr_std$10 anycast:nothing$0 workchain_id:0 address:bits256` with every byte `byte`.
+    fn addr_bits(byte: u8) -> String {
+        "10".to_owned() + "0" + "00000000" + &format!("{byte:08b}").repeat(32)
+    }
+    
+    #[test]
+    fn tags() {
+        assert_eq!((ProvideWalletAddress::TAG, ProvideWalletAddress::TAG_BITS), (0x2c76b973, 32));
+        assert_eq!((TakeWalletAddress::TAG, TakeWalletAddress::TAG_BITS), (0xd1735400, 32));
+    }
+    
+    #[test]
+    fn provide_wallet_address_layout() {
+        let provide = ProvideWalletAddress {
+            query_id: 3, owner_address: Address::new(0, [0x11; 32]).into(), include_address: true
+        };
+        let cell = provide.to_cell().unwrap();
+        let expected = format!("{:032b}{:064b}", 0x2c76b973u32, 3) + &addr_bits(0x11) + "1";
+        assert_eq!(bits_of(&cell), expected);
+        assert!(cell.refs().is_empty());
+        assert_eq!(ProvideWalletAddress::from_cell(&cell), Ok(provide));
+        
+        let take = TakeWalletAddress {query_id: 3, wallet_address: MsgAddress::default(), owner_address: None};
+        assert_eq!(ProvideWalletAddress::from_cell(&take.to_cell().unwrap()), Err(TlbError::TagMismatch));
+    }
+    
+    #[test]
+    fn take_wallet_address_keeps_owner_in_maybe_ref() {
+        let owner: MsgAddress = Address::new(0, [0x11; 32]).into();
+        let take = TakeWalletAddress {
+            query_id: 3, wallet_address: Address::new(0, [0x22; 32]).into(), owner_address: Some(owner.clone())
+        };
+        let cell = take.to_cell().unwrap();
+        let header = format!("{:032b}{:064b}", 0xd1735400u32, 3) + &addr_bits(0x22);
+        assert_eq!(bits_of(&cell), header.clone() + "1");
+        assert_eq!(cell.refs().len(), 1);
+        assert_eq!(bits_of(&cell.refs()[0]), addr_bits(0x11));
+        assert_eq!(*cell.refs()[0], owner.to_cell().unwrap());
+        assert_eq!(TakeWalletAddress::from_cell(&cell), Ok(take));
+        
+        // no wallet in this workchain: addr_none$00, and no owner requested
+        let none = TakeWalletAddress {query_id: 3, wallet_address: MsgAddress::default(), owner_address: None};
+        let cell = none.to_cell().unwrap();
+        assert_eq!(bits_of(&cell), format!("{:032b}{:064b}", 0xd1735400u32, 3) + "00" + "0");
+        assert!(cell.refs().is_empty());
+        assert_eq!(TakeWalletAddress::from_cell(&cell), Ok(none));
+    }
+    
+    #[test]
+    fn wallet_address_from_state_init() {
+        let code = &from_boc(boc!("te6cckEBAQEAcQAA3v8AIN0gggFMl7ohggEznLqxn3Gw7UTQ0x/THzHXC//jBOCk8mCDCNcYINMf0x/TH/gjE7vyY+1E0NMf0x/T/9FRMrryoVFEuvKiBPkBVBBV+RDyo/gAkyDXSpbTB9QC+wDo0QGkyMsfyx/L/8ntVBC9ba0=")).unwrap()[0];
+        let (owner, master) = (Address::new(0, [0x11; 32]), Address::new(0, [0x22; 32]));
+        let data = JettonWalletData::new(&owner, &master, code);
+        let cell = data.to_cell().unwrap();
+        assert_eq!(bits_of(&cell), "0000".to_owned() + &addr_bits(0x11) + &addr_bits(0x22));
+        assert_eq!(*cell.refs()[0], *code);
+        assert_eq!(JettonWalletData::from_cell(&cell), Ok(data));
+        
+        // hash of `StateInit` computed independently of the crate
+        let address = jetton_wallet_address(&owner, &master, code);
+        assert_eq!(address, "0:eecd98f162fc7ea8a92a0995b033082a58f6a29627facb6de225d739285dde15".parse().unwrap());
+    }
+}
@@ -0,0 +1,24 @@
+// (c) ProgramCrafter, 2024
+
+//! Serialization of Rust types into TON cells and back, following TL-B schemes given in
+//! `#[tlb_serializable]` / `#[tlb_enum_serializable]` attributes from `tlb_macro`.
+//!
+//! Generated code refers to `crate::ton::CellSerialize`, so crates annotating their own types
+//! should bring the module into their root with `use ton_tlb_serialize::ton;`.
+
+pub mod ton;
+pub mod config;
+pub mod block;
+pub mod body;
+pub mod bounce;
+pub mod dedust;
+pub mod jetton;
+pub mod metadata;
+pub mod multisig;
+pub mod nft;
+pub mod sbt;
+pub mod stack;
+pub mod stonfi;
+pub mod testing;
+pub mod vesting;
+pub mod wallet_v4;
@@ -1,82 +1,8 @@
 // (c) ProgramCrafter, 2024
 
 use tlb_macro::*;
-
-
-mod ton {
-    use tlb_macro::*;
-    
-    #[derive(Default)]
-    #[tlb_serializable(__fundamental_varuint16)]
-    pub struct Coins(u128);
-    
-    #[derive(Default)]
-    #[tlb_serializable(u 4 3bit, workchain, hash_high, hash_low)]
-    pub struct Address {
-        workchain: u8,
-        hash_high: u128,
-        hash_low: u128
-    }
-    
-    #[derive(Default)]
-    #[tlb_serializable(grams, u 0 1bit)]
-    pub struct CurrencyCollection {grams: Coins}
-    
-    pub trait CellSerialize {
-        fn serialize(&self) -> Vec<String>;
-    }
-    
-    // Defining serialization on foreign (std) types.
-    impl CellSerialize for u8 {
-        fn serialize(&self) -> Vec<String> {  vec![format!("u {self} 8bit")]  }
-    }
-    impl CellSerialize for u32 {
-        fn serialize(&self) -> Vec<String> {  vec![format!("u {self} 32bit")]  }
-    }
-    impl CellSerialize for u64 {
-        fn serialize(&self) -> Vec<String> {  vec![format!("u {self} 64bit")]  }
-    }
-    impl CellSerialize for u128 {
-        fn serialize(&self) -> Vec<String> {  vec![format!("u {self} 128bit")]  }
-    }
-    impl CellSerialize for bool {
-        fn serialize(&self) -> Vec<String> {
-            vec![format!("u {} 1bit", if *self {1} else {0})]
-        }
-    }
-}
-
-
-#[allow(non_camel_case_types)]
-#[tlb_enum_serializable]
-#[tlb_assert_unsafe(items_prefixes_nonoverlap)]
-// #[repr(u16)]
-enum CommonMsgInfo {
-    #[tlb_item_serializable(u 0 1bit, ihr_disabled, bounce, bounced, src, dest,
-                            value, ihr_fee, fwd_fee, created_lt, created_at)]
-    int_msg_info {
-        ihr_disabled: bool,
-        bounce: bool,
-        bounced: bool,
-        src: ton::Address,
-        dest: ton::Address,
-        value: ton::CurrencyCollection,
-        ihr_fee: ton::Coins,
-        fwd_fee: ton::Coins,
-        created_lt: u64,
-        created_at: u32
-    }
-}
-impl Default for CommonMsgInfo {
-    fn default() -> Self {
-        CommonMsgInfo::int_msg_info {
-            ihr_disabled: true, bounce: true, bounced: false,
-            src: Default::default(), dest: Default::default(),
-            value: Default::default(), ihr_fee: Default::default(),
-            fwd_fee: Default::default(), created_lt: 10001, created_at: 0
-        }
-    }
-}
+use ton_tlb_serialize::{jetton, metadata, sbt, stonfi, ton, vesting, wallet_v4};
+use ton_tlb_serialize::ton::{AccountStatus, CommonMsgInfo};
 
 
 #[tlb_enum_serializable]
@@ -88,10 +14,79 @@ enum Boc {
 
 
 fn main() {
-    use ton::CellSerialize;
+    use ton::{CellDeserialize, CellSerialize};
     
     println!("{:?}", ton::CurrencyCollection::default().serialize());
     println!("{:?}", CommonMsgInfo::default().serialize());
     println!("{:?}", Boc::Normal{}.serialize());
     println!("{:?}", Boc::Empty{}.serialize());
+    println!("{:?}", ton::Int::<257>(-2).serialize());
+    println!("{:#06x}", ton::crc16(b"123456789"));
+    for address in ["-1:3333333333333333333333333333333333333333333333333333333333333333",
+                    "EQDtFpEwcFAEcRe5mLVh2N6C0x-_hJEM7W61_JLnSF74p4q2", "EQDtFpEwcFAEcRe5mLVh2N6C0x-_hJEM7W61_JLnSF74p4q3"] {
+        match address.parse::<ton::Address>() {
+            Ok(address) => println!("{:?}", address.serialize()),
+            Err(err) => println!("{err}"),
+        }
+    }
+    println!("{:?}", ton::RawBits::new(vec![0xde, 0xad, 0xbe, 0xef], 28).serialize());
+    println!("{:?}", stonfi::Swap {
+        token_wallet: ton::Address::new(0, [0x11; 32]), min_out: ton::Coins(1_000_000),
+        to_address: ton::Address::new(0, [0x22; 32]),
+        referral: Some(ton::Address::new(0, [0x33; 32]))
+    }.serialize());
+    println!("{}", CommonMsgInfo::TLB_SCHEME);
+    println!("{}", Boc::TLB_SCHEME);
+    println!("{}", AccountStatus::TLB_SCHEME);
+    println!("{:?}", [AccountStatus::Uninit{}, AccountStatus::Frozen{}, AccountStatus::Active{}, AccountStatus::NonExist{}]
+                        .map(|status| status.serialize()));
+    #[cfg(feature = "json-schema")]
+    println!("{}", CommonMsgInfo::JSON_SCHEMA);
+    println!("{:#x} in {} bits", Boc::NORMAL_TAG, Boc::NORMAL_TAG_BITS);
+    println!("{:?}", CommonMsgInfo::ext_in_msg_info {
+        src: ton::MsgAddressExt::AddrExtern{external_address: ton::VarBits(ton::RawBits::new(vec![0xab], 8))},
+        dest: ton::Address::new(0, [0x55; 32]).into(), import_fee: ton::Coins(0)
+    }.serialize());
+    println!("{:?}", CommonMsgInfo::ext_out_msg_info {
+        src: ton::MsgAddressInt::AddrVar{address: ton::VarAddress {
+            anycast: None, workchain_id: ton::Int(7), address: ton::RawBits::new(vec![0x66; 5], 40)
+        }},
+        dest: ton::MsgAddressExt::default(), created_lt: 1, created_at: 2
+    }.serialize());
+    println!("{:?}", ton::Address::new(0, [0x77; 32])
+                        .with_anycast(ton::Anycast::new(ton::RawBits::new(vec![0xa0], 3))).serialize());
+    println!("{:?}", cell! {
+        u32: 0x5fcc3d14, u64: 0, i8: -1, coins: 50_000_000u128,
+        ton::Address: ton::Address::new(0, [0x88; 32]), u2: 0,
+    });
+    println!("{:02x?}", boc!("te6cckEBAQEAAgAAAEysuc0="));
+    #[cfg(feature = "std-collections")]
+    println!("{:?}", vec![1u8, 2, 3].serialize());
+    let report = CommonMsgInfo::default().size_report().unwrap();
+    println!("{:?}, {} bits and {} cells in total", report.parts, report.total_bits(), report.total_cells());
+    println!("{:?}", ton::IntermediateAddress::Regular{use_dest_bits: ton::Uint(96)}.serialize());
+    println!("{:?}", wallet_v4::InstallPlugin {
+        subwallet_id: 698983191, valid_until: 1700000000, seqno: 5,
+        plugin: wallet_v4::PluginAddress::new(0, [0x99; 32]), amount: ton::Coins(50_000_000), query_id: 0
+    }.serialize());
+    println!("{:?}", jetton::ProvideWalletAddress {
+        query_id: 1, owner_address: ton::Address::new(0, [0xaa; 32]).into(), include_address: true
+    }.serialize());
+    println!("{} {:?}", sbt::Revoke::TLB_SCHEME, sbt::Revoke {query_id: 7}.serialize());
+    println!("{:?}", metadata::OffChainContent {uri: "https://example.com/jetton.json".to_owned()}.serialize());
+    for amount in ["1.5", "0.000001", "12", "0.0000001", "1.", "x"] {
+        match amount.parse::<ton::TokenAmount<6>>() {
+            Ok(amount) => println!("{amount} = {:?}", amount.serialize()),
+            Err(err) => println!("{err}"),
+        }
+    }
+    let cell = CommonMsgInfo::default().to_cell().expect("message info fits into a cell");
+    println!("{} bits: {cell:#}", cell.bit_len());
+    let boc = ton::boc::to_boc(&cell, true, true);
+    println!("{:02x?}", boc);
+    println!("{:?}", ton::boc::from_boc(&boc).map(|roots| roots.len()));
+    let whitelist = vesting::AddWhitelist {query_id: 9, address: "EQDtFpEwcFAEcRe5mLVh2N6C0x-_hJEM7W61_JLnSF74p4q2".parse::<ton::Address>().unwrap().into()};
+    println!("{:?}", vesting::AddWhitelist::from_cell(&whitelist.to_cell().unwrap()));
+    println!("{:?}", ton::Int::<257>::from_cell(&ton::Int::<257>(-2).to_cell().unwrap()).map(|int| int.0));
+    println!("{:?}", vesting::AddWhitelist {query_id: 0, address: ton::Address::new(0, [0x44; 32]).into()}.serialize());
 }
@@ -0,0 +1,297 @@
+// (c) ProgramCrafter, 2024
+
+//! Token metadata content cells (TEP-64).
+
+use crate::ton::cell::{CellSlice, TlbError};
+use crate::ton::dict::{DictError, DictKey, HashmapE, InRef};
+use crate::ton::sha256::Sha256;
+use crate::ton::{CellDeserialize, CellSerialize, FixedBits, RawBits};
+
+
+/// Bytes of URI stored in the first cell: 1023 bits of a cell, minus 8-bit prefix.
+const FIRST_CELL_BYTES: usize = 126;
+/// Bytes of URI stored in each next cell of the snake.
+const CELL_BYTES: usize = 127;
+
+/// Off-chain content: `0x01` prefix followed by URI of the metadata JSON.
+///
+/// URIs longer than 126 bytes continue in a snake: each cell keeps as many bytes as fit
+/// and references the cell with the rest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OffChainContent {
+    pub uri: String,
+}
+
+impl OffChainContent {
+    pub const PREFIX: u8 = 0x01;
+}
+
+impl CellSerialize for OffChainContent {
+    fn serialize(&self) -> Vec<String> {
+        snake(Self::PREFIX, self.uri.as_bytes())
+    }
+}
+
+impl CellDeserialize for OffChainContent {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        if slice.load_uint(8)? != Self::PREFIX as u128 {
+            return Err(TlbError::TagMismatch);
+        }
+        let uri = String::from_utf8(load_snake(slice)?).map_err(|_| TlbError::InvalidValue)?;
+        Ok(OffChainContent {uri})
+    }
+    
+    fn prefix_matches(slice: &CellSlice) -> bool {
+        slice.peek_uint(8) == Ok(Self::PREFIX as u128)
+    }
+}
+
+/// On-chain content: `onchain#00 data:(HashmapE 256 ^ContentData)`, attributes (`name`,
+/// `image`, `decimals`, …) keyed by SHA-256 of their names.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OnChainContent {
+    pub attributes: HashmapE<256, ContentData, InRef>,
+}
+
+impl OnChainContent {
+    pub const PREFIX: u8 = 0x00;
+    
+    pub fn get(&self, name: &str) -> Result<Option<ContentData>, TlbError> {
+        self.attributes.get(&attribute_key(name))
+    }
+    
+    /// Sets attribute `name`; its value picks the encoding, snake or chunks.
+    pub fn set(&mut self, name: &str, value: &ContentData) -> Result<(), DictError> {
+        self.attributes.set(&attribute_key(name), value)
+    }
+}
+
+impl CellSerialize for OnChainContent {
+    fn serialize(&self) -> Vec<String> {
+        let mut result = vec![format!("u {} 8bit", Self::PREFIX)];
+        result.extend(self.attributes.serialize());
+        result
+    }
+}
+
+impl CellDeserialize for OnChainContent {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        if slice.load_uint(8)? != Self::PREFIX as u128 {
+            return Err(TlbError::TagMismatch);
+        }
+        Ok(OnChainContent {attributes: HashmapE::deserialize(slice)?})
+    }
+    
+    fn prefix_matches(slice: &CellSlice) -> bool {
+        slice.peek_uint(8) == Ok(Self::PREFIX as u128)
+    }
+}
+
+fn attribute_key(name: &str) -> FixedBits<256> {
+    let mut hasher = Sha256::new();
+    hasher.update(name.as_bytes());
+    FixedBits(RawBits::new(hasher.finish().to_vec(), 256))
+}
+
+/// `ContentData`: value of an on-chain attribute, `snake#00 data:(SnakeData ~n)` or
+/// `chunks#01 data:ChunkedData`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContentData {
+    /// Bytes continued in a snake of cells.
+    Snake(Vec<u8>),
+    /// `chunked_data#_ data:(HashmapE 32 ^(SnakeData ~0))`: bytes in chunks of up to 127,
+    /// each in a cell of its own keyed by its index; for values too big for a long snake.
+    Chunks(Vec<u8>),
+}
+
+impl ContentData {
+    pub fn bytes(&self) -> &[u8] {
+        match self {
+            ContentData::Snake(bytes) | ContentData::Chunks(bytes) => bytes,
+        }
+    }
+}
+
+impl CellSerialize for ContentData {
+    fn serialize(&self) -> Vec<String> {
+        match self {
+            ContentData::Snake(bytes) => snake(0x00, bytes),
+            ContentData::Chunks(bytes) => {
+                let mut chunks = HashmapE::<32, RawBits, InRef>::default();
+                for (index, chunk) in bytes.chunks(CELL_BYTES).enumerate() {
+                    let chunk = RawBits::new(chunk.to_vec(), chunk.len() * 8);
+                    chunks.set(&(index as u32), &chunk).expect("chunks fit into cells");
+                }
+                let mut result = vec!["u 1 8bit".to_owned()];
+                result.extend(chunks.serialize());
+                result
+            }
+        }
+    }
+}
+
+impl CellDeserialize for ContentData {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        match slice.load_uint(8)? {
+            0x00 => Ok(ContentData::Snake(load_snake(slice)?)),
+            0x01 => {
+                // chunks must be numbered from zero without gaps
+                let chunks = HashmapE::<32, RawBits, InRef>::deserialize(slice)?;
+                let mut bytes = vec![];
+                for (index, entry) in chunks.iter().enumerate() {
+                    let (key, chunk) = entry?;
+                    if u32::from_key_bits(&key)? as usize != index || !chunk.bit_len().is_multiple_of(8) {
+                        return Err(TlbError::InvalidValue);
+                    }
+                    bytes.extend_from_slice(chunk.data());
+                }
+                Ok(ContentData::Chunks(bytes))
+            }
+            _ => Err(TlbError::UnknownTag {type_name: "ContentData"}),
+        }
+    }
+}
+
+/// Commands storing 8-bit `prefix` and `bytes` in a snake: each cell keeps as many bytes
+/// as fit and references the cell with the rest.
+fn snake(prefix: u8, bytes: &[u8]) -> Vec<String> {
+    let (first, mut rest) = bytes.split_at(bytes.len().min(FIRST_CELL_BYTES));
+    let mut result = vec![format!("u {prefix} 8bit")];
+    result.extend(RawBits::new(first.to_vec(), first.len() * 8).serialize());
+    let mut depth = 0;
+    while !rest.is_empty() {
+        let (chunk, tail) = rest.split_at(rest.len().min(CELL_BYTES));
+        result.push("ref {".to_owned());
+        result.extend(RawBits::new(chunk.to_vec(), chunk.len() * 8).serialize());
+        depth += 1;
+        rest = tail;
+    }
+    result.extend(std::iter::repeat_n("}".to_owned(), depth));
+    result
+}
+
+/// Reads bytes of a snake from the rest of `slice` and the cells it continues in.
+fn load_snake(slice: &mut CellSlice) -> Result<Vec<u8>, TlbError> {
+    let mut bytes = load_snake_bytes(slice)?;
+    let mut next = if slice.remaining_refs() > 0 {Some(slice.load_ref()?)} else {None};
+    while let Some(cell) = next {
+        let mut child = CellSlice::new(cell);
+        bytes.extend(load_snake_bytes(&mut child)?);
+        next = if child.remaining_refs() > 0 {Some(child.load_ref()?)} else {None};
+        if child.remaining_refs() > 0 {
+            return Err(TlbError::TrailingData);
+        }
+    }
+    Ok(bytes)
+}
+
+/// Takes all remaining bits of a snake cell, which must be whole bytes.
+fn load_snake_bytes(slice: &mut CellSlice) -> Result<Vec<u8>, TlbError> {
+    let bit_len = slice.remaining_bits();
+    if !bit_len.is_multiple_of(8) {
+        return Err(TlbError::InvalidValue);
+    }
+    slice.load_bits(bit_len)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ton::cell::{Cell, CellBuilder};
+    use std::sync::Arc;
+    
+    fn bytes_cell(bytes: &[u8], child: Option<Cell>) -> Cell {
+        let mut builder = CellBuilder::new();
+        builder.store_bits(bytes, bytes.len() * 8).unwrap();
+        if let Some(child) = child {
+            builder.store_ref(Arc::new(child)).unwrap();
+        }
+        builder.build()
+    }
+    
+    #[test]
+    fn off_chain_content_snake() {
+        let short = OffChainContent {uri: "https://example.org/jetton.json".to_owned()};
+        let cell = short.to_cell().unwrap();
+        assert!(cell.refs().is_empty());
+        assert_eq!(OffChainContent::from_cell(&cell), Ok(short));
+        
+        let long = OffChainContent {uri: "x".repeat(126 + 127 + 1)};
+        let cell = long.to_cell().unwrap();
+        assert_eq!(cell.bit_len(), 8 + 126 * 8);
+        assert_eq!((cell.refs()[0].bit_len(), cell.refs()[0].refs()[0].bit_len()), (127 * 8, 8));
+        assert_eq!(OffChainContent::from_cell(&cell), Ok(long));
+        
+        let mut other_prefix = CellBuilder::new();
+        other_prefix.store_uint(2, 8).unwrap();
+        assert_eq!(OffChainContent::from_cell(&other_prefix.build()), Err(TlbError::TagMismatch));
+    }
+    
+    #[test]
+    fn off_chain_uri_longer_than_a_cell() {
+        let uri = format!("https://example.org/{}.json", "a".repeat(200));
+        let content = OffChainContent {uri: uri.clone()};
+        assert!(uri.len() > CELL_BYTES);
+        
+        let cell = content.to_cell().unwrap();
+        let tail = bytes_cell(&uri.as_bytes()[FIRST_CELL_BYTES..], None);
+        let mut head = vec![OffChainContent::PREFIX];
+        head.extend_from_slice(&uri.as_bytes()[..FIRST_CELL_BYTES]);
+        assert_eq!(cell, bytes_cell(&head, Some(tail)));
+        assert_eq!(OffChainContent::from_cell(&cell), Ok(content));
+        
+        // one byte over the first cell already needs a child
+        let cell = OffChainContent {uri: "y".repeat(FIRST_CELL_BYTES + 1)}.to_cell().unwrap();
+        assert_eq!((cell.refs().len(), cell.refs()[0].bit_len()), (1, 8));
+        let cell = OffChainContent {uri: "y".repeat(FIRST_CELL_BYTES)}.to_cell().unwrap();
+        assert!(cell.refs().is_empty());
+        
+        // characters may be split between cells; the URI is decoded once joined
+        let uri = "é".repeat(100);
+        let cell = OffChainContent {uri: uri.clone()}.to_cell().unwrap();
+        assert_eq!(cell.refs()[0].bit_len(), (200 - FIRST_CELL_BYTES) * 8);
+        assert_eq!(OffChainContent::from_cell(&cell), Ok(OffChainContent {uri}));
+        
+        let invalid = bytes_cell(&[OffChainContent::PREFIX, 0xc3], Some(bytes_cell(&[0x28], None)));
+        assert_eq!(OffChainContent::from_cell(&invalid), Err(TlbError::InvalidValue));
+        let unaligned = bytes_cell(&[OffChainContent::PREFIX], Some(Cell::parse_fift("x{4_}").unwrap()));
+        assert_eq!(OffChainContent::from_cell(&unaligned), Err(TlbError::InvalidValue));
+    }
+    
+    #[test]
+    fn on_chain_content_with_chunks() {
+        let image: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        let chunks = ContentData::Chunks(image.clone()).to_cell().unwrap();
+        assert_eq!(chunks.data(), [0x01, 0x80]);
+        let mut slice = CellSlice::new(&chunks);
+        slice.load_uint(8).unwrap();
+        let read = HashmapE::<32, RawBits, InRef>::deserialize(&mut slice).unwrap();
+        assert_eq!(read.get(&2u32), Ok(Some(RawBits::new(image[254..].to_vec(), 46 * 8))));
+        assert_eq!(ContentData::from_cell(&chunks), Ok(ContentData::Chunks(image.clone())));
+        
+        let mut content = OnChainContent::default();
+        content.set("name", &ContentData::Snake(b"Example Jetton".to_vec())).unwrap();
+        content.set("image_data", &ContentData::Chunks(image.clone())).unwrap();
+        let cell = content.to_cell().unwrap();
+        let name_key = FixedBits(RawBits::new(hex_bytes("82a3537ff0dbce7eec35d69edc3a189ee6f17d82f353a553f9aa96cb0be3ce89"), 256));
+        assert_eq!(attribute_key("name"), name_key);
+        
+        let read = OnChainContent::from_cell(&cell).unwrap();
+        assert_eq!(read.get("name"), Ok(Some(ContentData::Snake(b"Example Jetton".to_vec()))));
+        assert_eq!(read.get("image_data").unwrap().unwrap().bytes(), image);
+        assert_eq!(read.get("symbol"), Ok(None));
+        
+        // chunks must follow each other from zero
+        let mut gap = HashmapE::<32, RawBits, InRef>::default();
+        gap.set(&1u32, &RawBits::new(vec![0xaa], 8)).unwrap();
+        let mut builder = CellBuilder::new();
+        builder.store_uint(1, 8).unwrap();
+        gap.store(&mut builder).unwrap();
+        assert_eq!(ContentData::from_cell(&builder.build()), Err(TlbError::InvalidValue));
+    }
+    
+    fn hex_bytes(hex: &str) -> Vec<u8> {
+        (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+    }
+}
@@ -0,0 +1,171 @@
+// (c) ProgramCrafter, 2024
+
+//! Messages of the multisig wallet v2: signers and proposers create orders, actions numbered
+//! in a dictionary, with `new_order` to the wallet, and signers approve them with `approve`
+//! to the order contract; the wallet runs the actions once enough signers approved.
+
+use std::sync::Arc;
+
+use tlb_macro::*;
+use crate::ton::cell::Cell;
+use crate::ton::dict::{DictError, HashmapE, InRef};
+use crate::ton::{FixedBits, MsgAddressInt, Uint};
+
+
+/// `Action`: what an approved order makes the wallet do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_enum_deserializable]
+#[tlb_enum_serializable]
+#[tlb_assert_unsafe(items_prefixes_nonoverlap)]
+pub enum Action {
+    /// `send_message#f1381e5b mode:uint8 message:^MessageRelaxed`.
+    #[tlb_item_serializable(u 0xf1381e5b 32bit, mode, ^message)]
+    SendMessage{mode: u8, message: Cell},
+    /// `update_multisig_params#1d0cfbd3 threshold:uint8 signers:^(Hashmap 8 MsgAddressInt)
+    /// proposers:(HashmapE 8 MsgAddressInt)`: new approvals needed and lists of signers and
+    /// proposers. `signers` is the root of its dictionary, which is never empty; see
+    /// [`Action::update_params`].
+    #[tlb_item_serializable(u 0x1d0cfbd3 32bit, threshold, ^signers, proposers)]
+    UpdateParams{threshold: u8, signers: Cell, proposers: HashmapE<8, MsgAddressInt>},
+}
+
+impl Action {
+    /// Parameters update with `signers` and `proposers` numbered from 0, as the wallet indexes
+    /// them in `new_order` and `approve`.
+    pub fn update_params(threshold: u8, signers: &[MsgAddressInt], proposers: &[MsgAddressInt]) -> Result<Self, DictError> {
+        assert!(!signers.is_empty(), "multisig needs at least one signer");
+        let signers = address_list(signers)?.root().map(|root| Cell::clone(root)).expect("list is not empty");
+        Ok(Action::UpdateParams {threshold, signers, proposers: address_list(proposers)?})
+    }
+    
+    /// Signers of [`Action::UpdateParams`] as a dictionary; `None` for other actions.
+    pub fn signers(&self) -> Option<HashmapE<8, MsgAddressInt>> {
+        match self {
+            Action::UpdateParams {signers, ..} => Some(HashmapE::with_root(Some(Arc::new(signers.clone())))),
+            Action::SendMessage {..} => None,
+        }
+    }
+}
+
+fn address_list(addresses: &[MsgAddressInt]) -> Result<HashmapE<8, MsgAddressInt>, DictError> {
+    assert!(addresses.len() <= 256, "lists are indexed by 8 bits");
+    let mut list = HashmapE::default();
+    for (index, address) in addresses.iter().enumerate() {
+        list.set(&(index as u8), address)?;
+    }
+    Ok(list)
+}
+
+/// `_ actions:(HashmapE 8 ^Action) = Order`: actions run in the order of their keys.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(actions)]
+pub struct Order {
+    pub actions: HashmapE<8, Action, InRef>,
+}
+
+impl Order {
+    /// Order of `actions`, numbered from 0.
+    pub fn new(actions: &[Action]) -> Result<Self, DictError> {
+        assert!(actions.len() <= 256, "actions are indexed by 8 bits");
+        let mut order = Order::default();
+        for (index, action) in actions.iter().enumerate() {
+            order.actions.set(&(index as u8), action)?;
+        }
+        Ok(order)
+    }
+}
+
+/// `new_order#f718510f`, sent to the wallet by signer or proposer `index` (as `signer` says)
+/// to create order `order_seqno`, which expires at Unix time `expiration_date`. Sending it
+/// as a signer also approves the order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = r#"
+    new_order#f718510f query_id:uint64 order_seqno:uint256 signer:Bool index:uint8
+        expiration_date:uint48 order:^Order = InternalMsgBody;
+"#)]
+pub struct NewOrder {
+    pub query_id: u64,
+    pub order_seqno: FixedBits<256>,
+    pub signer: bool,
+    pub index: u8,
+    pub expiration_date: Uint<48>,
+    pub order: Order,
+}
+
+/// `approve#a762230f`, sent to the order contract by signer `signer_index`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = r#"
+    approve#a762230f query_id:uint64 signer_index:uint8 = InternalMsgBody;
+"#)]
+pub struct Approve {
+    pub query_id: u64,
+    pub signer_index: u8,
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ton::cell::CellBuilder;
+    use crate::ton::{Address, CellDeserialize, CellSerialize, RawBits};
+    
+    fn bits_of(cell: &Cell) -> String {
+        (0..cell.bit_len()).map(|i| if cell.bit(i) {'1'} else {'0'}).collect()
+    }
+    
+    fn address(byte: u8) -> MsgAddressInt {
+        Address::new(0, [byte; 32]).into()
+    }
+    
+    #[test]
+    fn new_order_with_actions() {
+        let mut message = CellBuilder::new();
+        message.store_uint(0x42, 8).unwrap();
+        let send = Action::SendMessage {mode: 3, message: message.build()};
+        let update = Action::update_params(2, &[address(1), address(2), address(3)], &[address(9)]).unwrap();
+        let order = Order::new(&[send.clone(), update.clone()]).unwrap();
+        let new_order = NewOrder {
+            query_id: 7,
+            order_seqno: FixedBits(RawBits::new([[0; 31].as_slice(), &[5]].concat(), 256)),
+            signer: true,
+            index: 1,
+            expiration_date: Uint(1_700_086_400),
+            order: order.clone(),
+        };
+        
+        let cell = new_order.to_cell().unwrap();
+        let seqno = format!("{:0256b}", 5);
+        let expected = format!("{:032b}{:064b}{seqno}1{:08b}{:048b}", 0xf718510fu32, 7, 1, 1_700_086_400u64);
+        assert_eq!(bits_of(&cell), expected);
+        assert_eq!(cell.refs().len(), 1);
+        assert_eq!(NewOrder::from_cell(&cell), Ok(new_order));
+        
+        // actions are numbered in references of the dictionary
+        let actions = &cell.refs()[0];
+        assert_eq!((actions.bit_len(), actions.refs().len()), (1, 1));
+        assert_eq!(order.actions.get(&0u8), Ok(Some(send.clone())));
+        assert_eq!(order.actions.get(&1u8), Ok(Some(update.clone())));
+        assert_eq!(order.actions.get(&2u8), Ok(None));
+        
+        let signers = update.signers().unwrap();
+        assert_eq!(signers.iter().map(|entry| entry.unwrap().1).collect::<Vec<_>>(), [address(1), address(2), address(3)]);
+        assert_eq!(send.signers(), None);
+        let Action::UpdateParams {proposers, ..} = &update else { unreachable!() };
+        assert_eq!(proposers.get(&0u8), Ok(Some(address(9))));
+        let update_cell = update.to_cell().unwrap();
+        assert_eq!(&bits_of(&update_cell)[..40], format!("{:032b}{:08b}", 0x1d0cfbd3u32, 2));
+        assert_eq!(update_cell.refs().len(), 2);
+    }
+    
+    #[test]
+    fn approve_layout() {
+        let approve = Approve {query_id: 7, signer_index: 2};
+        let cell = approve.to_cell().unwrap();
+        assert_eq!(bits_of(&cell), format!("{:032b}{:064b}{:08b}", 0xa762230fu32, 7, 2));
+        assert_eq!(Approve::from_cell(&cell), Ok(approve));
+        assert_eq!(NewOrder::from_cell(&cell).err(), Some(crate::ton::cell::TlbError::TagMismatch));
+    }
+}
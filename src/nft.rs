@@ -0,0 +1,65 @@
+// (c) ProgramCrafter, 2024
+
+//! Addresses of NFT items (TEP-62), for checking item ownership off-chain.
+
+use tlb_macro::*;
+use crate::ton::cell::Cell;
+use crate::ton::{Address, CellSerialize, MsgAddressInt, StateInit};
+
+
+/// `_`: initial data of an item deployed by the standard collection (`nft-collection.fc`).
+/// Owner and content are set by the first message from the collection, so they do not
+/// affect the address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = r#"
+    nft_item_init#_ index:uint64 collection_address:MsgAddressInt = NftItemInit;
+"#)]
+pub struct NftItemInit {
+    pub index: u64,
+    pub collection_address: MsgAddressInt,
+}
+
+impl NftItemInit {
+    pub fn state_init(&self, item_code: &Cell) -> StateInit {
+        let data = self.to_cell().expect("item data fits into a cell");
+        StateInit::new(item_code.clone(), data)
+    }
+}
+
+/// Address of item `index` of `collection`, as `get_nft_address_by_index` of the standard
+/// collection computes it: from the initial state of the item, in the workchain of the
+/// collection. Collections with other item data give different addresses.
+pub fn nft_item_address(collection: &Address, index: u64, item_code: &Cell) -> Address {
+    let init = NftItemInit {index, collection_address: collection.clone().into()};
+    init.state_init(item_code).address(collection.workchain())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ton::CellDeserialize;
+    use crate::ton::boc::from_boc;
+    use tlb_macro::boc;
+    
+    fn bits_of(cell: &Cell) -> String {
+        (0..cell.bit_len()).map(|i| if cell.bit(i) {'1'} else {'0'}).collect()
+    }
+    
+    #[test]
+    fn item_address_from_state_init() {
+        let code = &from_boc(boc!("te6cckEBAQEAcQAA3v8AIN0gggFMl7ohggEznLqxn3Gw7UTQ0x/THzHXC//jBOCk8mCDCNcYINMf0x/TH/gjE7vyY+1E0NMf0x/T/9FRMrryoVFEuvKiBPkBVBBV+RDyo/gAkyDXSpbTB9QC+wDo0QGkyMsfyx/L/8ntVBC9ba0=")).unwrap()[0];
+        let collection = Address::new(0, [0x33; 32]);
+        let init = NftItemInit {index: 5, collection_address: collection.clone().into()};
+        let cell = init.to_cell().unwrap();
+        let addr_bits = "10".to_owned() + "0" + "00000000" + &"00110011".repeat(32);
+        assert_eq!(bits_of(&cell), format!("{:064b}", 5) + &addr_bits);
+        assert_eq!(NftItemInit::from_cell(&cell), Ok(init));
+        
+        // hash of `StateInit` computed independently of the crate
+        let address = nft_item_address(&collection, 5, code);
+        assert_eq!(address, "0:b654ed35879949f6090db89cc5be955c0197d76cc0065cb9b0e4a8c098f65900".parse().unwrap());
+        assert_ne!(nft_item_address(&collection, 6, code), address);
+    }
+}
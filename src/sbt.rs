@@ -0,0 +1,136 @@
+// (c) ProgramCrafter, 2024
+
+//! Message bodies understood by soulbound tokens (TEP-85).
+
+use tlb_macro::*;
+use crate::ton::cell::Cell;
+use crate::ton::{FixedBits, MsgAddress};
+
+
+/// `prove_ownership#04ded148`, sent by the owner to have the token send [`OwnershipProof`]
+/// with `forward_payload` to `dest`, and its content if `with_content` is set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = r#"
+    prove_ownership#04ded148 query_id:uint64 dest:MsgAddress forward_payload:^Cell
+        with_content:Bool = InternalMsgBody;
+"#)]
+pub struct ProveOwnership {
+    pub query_id: u64,
+    pub dest: MsgAddress,
+    pub forward_payload: Cell,
+    pub with_content: bool,
+}
+
+/// `ownership_proof#0524c7ae`, sent by the token in reply to [`ProveOwnership`]. `revoked_at`
+/// is zero for tokens that were not revoked; `item_id` is the 256-bit index of the token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = r#"
+    ownership_proof#0524c7ae query_id:uint64 item_id:uint256 owner:MsgAddress data:^Cell
+        revoked_at:uint64 content:(Maybe ^Cell) = InternalMsgBody;
+"#)]
+pub struct OwnershipProof {
+    pub query_id: u64,
+    pub item_id: FixedBits<256>,
+    pub owner: MsgAddress,
+    pub data: Cell,
+    pub revoked_at: u64,
+    pub content: Option<Cell>,
+}
+
+/// `destroy#1f04537a`, sent by the owner to delete the token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = r#"
+    destroy#1f04537a query_id:uint64 = InternalMsgBody;
+"#)]
+pub struct Destroy {
+    pub query_id: u64,
+}
+
+/// `revoke#6f89f5e3`, sent by the authority to mark the token as revoked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = r#"
+    revoke#6f89f5e3 query_id:uint64 = InternalMsgBody;
+"#)]
+pub struct Revoke {
+    pub query_id: u64,
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ton::{Address, CellDeserialize, CellSerialize, RawBits};
+    use crate::ton::cell::{CellBuilder, TlbError};
+    
+    fn bits_of(cell: &Cell) -> String {
+        (0..cell.bit_len()).map(|i| if cell.bit(i) {'1'} else {'0'}).collect()
+    }
+    
+    fn payload(value: u128) -> Cell {
+        let mut builder = CellBuilder::new();
+        builder.store_uint(value, 32).unwrap();
+        builder.build()
+    }
+    
+    #[test]
+    fn tags() {
+        assert_eq!(ProveOwnership::TAG, 0x04ded148);
+        assert_eq!(OwnershipProof::TAG, 0x0524c7ae);
+        assert_eq!(Destroy::TAG, 0x1f04537a);
+        assert_eq!(Revoke::TAG, 0x6f89f5e3);
+        for tag_bits in [ProveOwnership::TAG_BITS, OwnershipProof::TAG_BITS, Destroy::TAG_BITS, Revoke::TAG_BITS] {
+            assert_eq!(tag_bits, 32);
+        }
+    }
+    
+    #[test]
+    fn prove_ownership_round_trip() {
+        let prove = ProveOwnership {
+            query_id: 1, dest: Address::new(0, [0x44; 32]).into(), forward_payload: payload(0xdead), with_content: true
+        };
+        let cell = prove.to_cell().unwrap();
+        assert_eq!(&bits_of(&cell)[..32], format!("{:032b}", 0x04ded148));
+        assert_eq!(cell.bit_len(), 32 + 64 + 267 + 1);
+        assert_eq!(*cell.refs()[0], payload(0xdead));
+        assert_eq!(ProveOwnership::from_cell(&cell), Ok(prove));
+    }
+    
+    #[test]
+    fn ownership_proof_round_trip() {
+        let mut proof = OwnershipProof {
+            query_id: 1, item_id: FixedBits(RawBits::new(vec![0x07; 32], 256)),
+            owner: Address::new(0, [0x55; 32]).into(), data: payload(0xdead),
+            revoked_at: 0, content: Some(payload(0xc0)),
+        };
+        let cell = proof.to_cell().unwrap();
+        assert_eq!(&bits_of(&cell)[..32], format!("{:032b}", 0x0524c7ae));
+        assert_eq!(cell.bit_len(), 32 + 64 + 256 + 267 + 64 + 1);
+        assert_eq!((cell.refs().len(), &*cell.refs()[1]), (2, &payload(0xc0)));
+        assert_eq!(OwnershipProof::from_cell(&cell), Ok(proof.clone()));
+        
+        proof.revoked_at = 1_700_000_000;
+        proof.content = None;
+        let cell = proof.to_cell().unwrap();
+        assert_eq!(cell.refs().len(), 1);
+        assert!(!cell.bit(cell.bit_len() - 1));
+        assert_eq!(OwnershipProof::from_cell(&cell), Ok(proof));
+    }
+    
+    #[test]
+    fn destroy_and_revoke_differ_by_tag() {
+        let destroy = Destroy {query_id: 0x0102030405060708};
+        let revoke = Revoke {query_id: 0x0102030405060708};
+        let destroy_cell = destroy.to_cell().unwrap();
+        let revoke_cell = revoke.to_cell().unwrap();
+        assert_eq!(bits_of(&destroy_cell), format!("{:032b}{:064b}", 0x1f04537a, 0x0102030405060708u64));
+        assert_eq!(bits_of(&revoke_cell), format!("{:032b}{:064b}", 0x6f89f5e3, 0x0102030405060708u64));
+        assert_eq!(Destroy::from_cell(&destroy_cell), Ok(destroy));
+        assert_eq!(Revoke::from_cell(&revoke_cell), Ok(revoke));
+        assert_eq!(Destroy::from_cell(&revoke_cell), Err(TlbError::TagMismatch));
+        assert_eq!(Revoke::from_cell(&destroy_cell), Err(TlbError::TagMismatch));
+    }
+}
@@ -0,0 +1,401 @@
+// (c) ProgramCrafter, 2024
+
+//! TVM stacks as passed to and returned from get-methods (e.g. liteserver `runSmcMethod`):
+//! `VmStackValue` entries in a `VmStack` list of cells.
+
+use std::sync::Arc;
+
+use crate::ton::boc::{from_boc, BocError};
+use crate::ton::cell::{Cell, CellBuilder, CellError, CellSlice, TlbError};
+use crate::ton::{CellDeserialize, CellSerialize};
+
+
+/// TVM integer: 257-bit two's complement, a sign bit and 256 bits below it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Int257 {
+    negative: bool,
+    low: [u8; 32],
+}
+
+impl Int257 {
+    /// Non-negative integer from 256 bits, most significant first (e.g. a hash).
+    pub fn from_u256(bytes: [u8; 32]) -> Self {
+        Int257 {negative: false, low: bytes}
+    }
+    
+    /// Value as 256 bits, unless it is negative.
+    pub fn to_u256(&self) -> Option<[u8; 32]> {
+        (!self.negative).then_some(self.low)
+    }
+    
+    /// Value, if it fits into `i128`.
+    pub fn to_i128(&self) -> Option<i128> {
+        let fill = if self.negative {0xff} else {0x00};
+        let (high, low) = self.low.split_at(16);
+        let value = i128::from_be_bytes(low.try_into().unwrap());
+        (high.iter().all(|&byte| byte == fill) && (value < 0) == self.negative).then_some(value)
+    }
+    
+    fn fits_i64(&self) -> bool {
+        self.to_i128().is_some_and(|value| i64::try_from(value).is_ok())
+    }
+}
+
+impl From<i128> for Int257 {
+    fn from(value: i128) -> Self {
+        let mut low = [if value < 0 {0xff} else {0x00}; 32];
+        low[16..].copy_from_slice(&value.to_be_bytes());
+        Int257 {negative: value < 0, low}
+    }
+}
+
+/// `VmCellSlice`: part of a cell, bits `st_bits..end_bits` and references `st_ref..end_ref`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VmCellSlice {
+    pub cell: Arc<Cell>,
+    pub st_bits: usize,
+    pub end_bits: usize,
+    pub st_ref: usize,
+    pub end_ref: usize,
+}
+
+impl VmCellSlice {
+    /// Slice of the whole `cell`.
+    pub fn new(cell: Arc<Cell>) -> Self {
+        let (end_bits, end_ref) = (cell.bit_len(), cell.refs().len());
+        VmCellSlice {cell, st_bits: 0, end_bits, st_ref: 0, end_ref}
+    }
+    
+    /// Bits and references of the slice in a cell of their own.
+    pub fn contents(&self) -> Result<Cell, TlbError> {
+        let mut slice = CellSlice::new(&self.cell);
+        slice.skip_bits(self.st_bits)?;
+        let bits = self.end_bits.checked_sub(self.st_bits).ok_or(TlbError::InvalidValue)?;
+        let refs = self.cell.refs().get(self.st_ref..self.end_ref).ok_or(TlbError::NotEnoughRefs)?;
+        let mut builder = CellBuilder::new();
+        builder.store_bits(&slice.load_bits(bits)?, bits).expect("part of a cell fits into a cell");
+        for child in refs {
+            builder.store_ref(Arc::clone(child)).expect("part of a cell fits into a cell");
+        }
+        Ok(builder.build())
+    }
+}
+
+/// `VmStackValue`: entry of a TVM stack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StackValue {
+    /// `vm_stk_null#00`.
+    Null,
+    /// `vm_stk_tinyint#01 value:int64` or `vm_stk_int#0201_ value:int257`; values fitting
+    /// into 64 bits are stored in the short form, as TVM does.
+    Int(Int257),
+    /// `vm_stk_nan#02ff`: result of an integer overflow.
+    NaN,
+    /// `vm_stk_cell#03 cell:^Cell`.
+    Cell(Arc<Cell>),
+    /// `vm_stk_slice#04 _:VmCellSlice`.
+    Slice(VmCellSlice),
+    /// `vm_stk_builder#05 cell:^Cell`: contents of the builder.
+    Builder(Arc<Cell>),
+    /// `vm_stk_cont#06 cont:VmCont`, kept unparsed: the rest of the cell after the tag.
+    Continuation(Cell),
+    /// `vm_stk_tuple#07 len:(## 16) data:(VmTuple len)`.
+    Tuple(Vec<StackValue>),
+}
+
+impl CellSerialize for StackValue {
+    fn serialize(&self) -> Vec<String> {
+        let mut builder = CellBuilder::new();
+        self.store(&mut builder).expect("stack value fits into a cell");
+        builder.build().serialize()
+    }
+    
+    fn store(&self, builder: &mut CellBuilder) -> Result<(), CellError> {
+        match self {
+            StackValue::Null => {
+                builder.store_uint(0x00, 8)?;
+            }
+            StackValue::Int(value) if value.fits_i64() => {
+                let value = value.to_i128().unwrap() as i64;
+                builder.store_uint(0x01, 8)?.store_uint(value as u64 as u128, 64)?;
+            }
+            StackValue::Int(value) => {
+                builder.store_uint(0x0201 >> 1, 15)?.store_bit(value.negative)?.store_bits(&value.low, 256)?;
+            }
+            StackValue::NaN => {
+                builder.store_uint(0x02ff, 16)?;
+            }
+            StackValue::Cell(cell) => {
+                builder.store_uint(0x03, 8)?.store_ref(Arc::clone(cell))?;
+            }
+            StackValue::Slice(slice) => {
+                builder.store_uint(0x04, 8)?.store_ref(Arc::clone(&slice.cell))?;
+                builder.store_uint(slice.st_bits as u128, 10)?.store_uint(slice.end_bits as u128, 10)?;
+                builder.store_uint(slice.st_ref as u128, 3)?.store_uint(slice.end_ref as u128, 3)?;
+            }
+            StackValue::Builder(cell) => {
+                builder.store_uint(0x05, 8)?.store_ref(Arc::clone(cell))?;
+            }
+            StackValue::Continuation(cont) => {
+                builder.store_uint(0x06, 8)?;
+                cont.store(builder)?;
+            }
+            StackValue::Tuple(items) => {
+                assert!(items.len() < 1 << 16, "tuple of {} items is too long", items.len());
+                builder.store_uint(0x07, 8)?.store_uint(items.len() as u128, 16)?;
+                store_tuple(builder, items)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CellDeserialize for StackValue {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        Ok(match slice.load_uint(8)? {
+            0x00 => StackValue::Null,
+            0x01 => StackValue::Int(Int257::from(slice.load_uint(64)? as u64 as i64 as i128)),
+            0x02 => match slice.load_uint(8)? {
+                0xff => StackValue::NaN,
+                // the tag takes 7 bits of this byte, and the sign bit the last one
+                sign @ (0x00 | 0x01) => {
+                    let low = slice.load_bits(256)?.try_into().expect("256 bits are 32 bytes");
+                    StackValue::Int(Int257 {negative: sign == 1, low})
+                }
+                _ => return Err(TlbError::UnknownTag {type_name: "VmStackValue"}),
+            },
+            0x03 => StackValue::Cell(Arc::clone(slice.load_ref()?)),
+            0x04 => {
+                let cell = Arc::clone(slice.load_ref()?);
+                let (st_bits, end_bits) = (slice.load_uint(10)? as usize, slice.load_uint(10)? as usize);
+                let (st_ref, end_ref) = (slice.load_uint(3)? as usize, slice.load_uint(3)? as usize);
+                if st_bits > end_bits || end_bits > cell.bit_len() || st_ref > end_ref || end_ref > cell.refs().len() {
+                    return Err(TlbError::InvalidValue);
+                }
+                StackValue::Slice(VmCellSlice {cell, st_bits, end_bits, st_ref, end_ref})
+            }
+            0x05 => StackValue::Builder(Arc::clone(slice.load_ref()?)),
+            0x06 => StackValue::Continuation(Cell::deserialize(slice)?),
+            0x07 => {
+                let len = slice.load_uint(16)? as usize;
+                StackValue::Tuple(read_tuple(slice, len)?)
+            }
+            _ => return Err(TlbError::UnknownTag {type_name: "VmStackValue"}),
+        })
+    }
+}
+
+/// Stores `VmTuple n`: `vm_tuple_tcons$_ head:(VmTupleRef n-1) tail:^VmStackValue`, or
+/// nothing for an empty tuple.
+fn store_tuple(builder: &mut CellBuilder, items: &[StackValue]) -> Result<(), CellError> {
+    let Some((last, head)) = items.split_last() else { return Ok(()) };
+    store_tuple_ref(builder, head)?;
+    builder.store_ref(Arc::new(last.to_cell()?))?;
+    Ok(())
+}
+
+/// Stores `VmTupleRef n`: nothing, the only item in a reference, or `^(VmTuple n)`.
+fn store_tuple_ref(builder: &mut CellBuilder, items: &[StackValue]) -> Result<(), CellError> {
+    match items {
+        [] => {}
+        [item] => {
+            builder.store_ref(Arc::new(item.to_cell()?))?;
+        }
+        _ => {
+            let mut tuple = CellBuilder::new();
+            store_tuple(&mut tuple, items)?;
+            builder.store_ref(Arc::new(tuple.build()))?;
+        }
+    }
+    Ok(())
+}
+
+fn read_tuple(slice: &mut CellSlice, len: usize) -> Result<Vec<StackValue>, TlbError> {
+    if len == 0 {
+        return Ok(vec![]);
+    }
+    let mut items = read_tuple_ref(slice, len - 1)?;
+    items.push(StackValue::from_ref(slice)?);
+    Ok(items)
+}
+
+fn read_tuple_ref(slice: &mut CellSlice, len: usize) -> Result<Vec<StackValue>, TlbError> {
+    match len {
+        0 => Ok(vec![]),
+        1 => Ok(vec![StackValue::from_ref(slice)?]),
+        _ => {
+            let mut tuple = slice.load_ref_slice()?;
+            let items = read_tuple(&mut tuple, len)?;
+            if !tuple.is_empty() {
+                return Err(TlbError::TrailingData);
+            }
+            Ok(items)
+        }
+    }
+}
+
+/// `vm_stack#_ depth:(## 24) stack:(VmStackList depth)`: entries from the bottom of the
+/// stack to its top. Each `vm_stk_cons#_ rest:^(VmStackList n) tos:VmStackValue` holds the
+/// top entry and refers to the list below it; the bottom one refers to an empty cell.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct VmStack(pub Vec<StackValue>);
+
+impl CellSerialize for VmStack {
+    fn serialize(&self) -> Vec<String> {
+        let mut builder = CellBuilder::new();
+        self.store(&mut builder).expect("stack fits into a cell");
+        builder.build().serialize()
+    }
+    
+    fn store(&self, builder: &mut CellBuilder) -> Result<(), CellError> {
+        assert!(self.0.len() < 1 << 24, "stack of {} entries is too deep", self.0.len());
+        builder.store_uint(self.0.len() as u128, 24)?;
+        let Some((top, below)) = self.0.split_last() else { return Ok(()) };
+        let mut rest = Arc::new(CellBuilder::new().build());
+        for value in below {
+            let mut list = CellBuilder::new();
+            list.store_ref(rest)?;
+            value.store(&mut list)?;
+            rest = Arc::new(list.build());
+        }
+        builder.store_ref(rest)?;
+        top.store(builder)
+    }
+}
+
+impl CellDeserialize for VmStack {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        let depth = slice.load_uint(24)? as usize;
+        if depth == 0 {
+            return Ok(VmStack(vec![]));
+        }
+        // lists are walked in place of recursion, as stacks may be deep
+        let mut rest = slice.load_ref_slice()?;
+        let mut values = vec![StackValue::deserialize(slice)?];
+        for _ in 1..depth {
+            let mut list = rest;
+            rest = list.load_ref_slice()?;
+            values.push(StackValue::from_whole_slice(list)?);
+        }
+        if !rest.is_empty() {
+            return Err(TlbError::TrailingData);
+        }
+        values.reverse();
+        Ok(VmStack(values))
+    }
+}
+
+/// Reason why a bag of cells could not be read as a stack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StackParseError {
+    /// Bytes are not a valid bag of cells.
+    Boc(BocError),
+    /// Bag of cells has no roots or several.
+    NotOneRoot,
+    /// Root is not a `VmStack`.
+    Tlb(TlbError),
+}
+
+impl std::fmt::Display for StackParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StackParseError::Boc(err) => write!(f, "invalid bag of cells: {err}"),
+            StackParseError::NotOneRoot => f.write_str("stack must be the only root of bag of cells"),
+            StackParseError::Tlb(err) => write!(f, "invalid stack: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for StackParseError {}
+
+impl From<BocError> for StackParseError {
+    fn from(err: BocError) -> Self {
+        StackParseError::Boc(err)
+    }
+}
+
+impl From<TlbError> for StackParseError {
+    fn from(err: TlbError) -> Self {
+        StackParseError::Tlb(err)
+    }
+}
+
+/// Entries, bottom first, of the stack serialized in `bytes`: a bag of cells with a
+/// `VmStack` root, as get-method results come from liteservers and toncenter `runGetMethodStd`.
+pub fn parse_stack_boc(bytes: &[u8]) -> Result<Vec<StackValue>, StackParseError> {
+    let [root] = <[Cell; 1]>::try_from(from_boc(bytes)?).map_err(|_| StackParseError::NotOneRoot)?;
+    Ok(VmStack::from_cell(&root)?.0)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ton::boc::to_boc;
+    
+    fn int(value: i128) -> StackValue {
+        StackValue::Int(Int257::from(value))
+    }
+    
+    #[test]
+    fn integers_take_the_shortest_form() {
+        assert_eq!(int(-2).to_cell().unwrap(), Cell::parse_fift("x{01FFFFFFFFFFFFFFFE}").unwrap());
+        assert_eq!(int(i64::MAX as i128 + 1).to_cell().unwrap().bit_len(), 16 + 256);
+        assert_eq!(StackValue::NaN.to_cell().unwrap(), Cell::parse_fift("x{02FF}").unwrap());
+        
+        let hash = StackValue::Int(Int257::from_u256([0xee; 32]));
+        let cell = hash.to_cell().unwrap();
+        assert_eq!(&cell.data()[..3], [0x02, 0x00, 0xee]);
+        for value in [int(-2), int(i64::MIN as i128 - 1), int(i128::MAX), hash, StackValue::NaN] {
+            assert_eq!(StackValue::from_cell(&value.to_cell().unwrap()), Ok(value));
+        }
+        
+        assert_eq!(Int257::from(-5).to_i128(), Some(-5));
+        assert_eq!(Int257::from_u256([0xee; 32]).to_i128(), None);
+        assert_eq!(Int257::from(-1).to_u256(), None);
+        // positive values with the top bit of `i128` set do not fit it
+        let mut low = [0; 32];
+        low[16] = 0x80;
+        assert_eq!(Int257::from_u256(low).to_i128(), None);
+    }
+    
+    #[test]
+    fn stack_lists_and_tuples() {
+        // [5]: depth 1, the value beside the reference to an empty list
+        let stack = VmStack(vec![int(5)]);
+        let cell = stack.to_cell().unwrap();
+        assert_eq!(cell.data(), [0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 5]);
+        assert_eq!(cell.refs()[0].bit_len(), 0);
+        
+        let leaf = Arc::new(Cell::parse_fift("x{ABCD}").unwrap());
+        let slice = VmCellSlice {cell: Arc::clone(&leaf), st_bits: 4, end_bits: 12, st_ref: 0, end_ref: 0};
+        assert_eq!(slice.contents(), Ok(Cell::parse_fift("x{BC}").unwrap()));
+        let tuple = StackValue::Tuple(vec![int(1), StackValue::Null, StackValue::Cell(Arc::clone(&leaf)), int(3)]);
+        let stack = VmStack(vec![StackValue::Slice(slice), tuple, StackValue::Builder(leaf), StackValue::Tuple(vec![])]);
+        let cell = stack.to_cell().unwrap();
+        assert_eq!(cell.data()[..3], [0, 0, 4]);
+        assert_eq!(VmStack::from_cell(&cell), Ok(stack));
+        
+        // tuple of 4: head is ^(VmTuple 3), whose head is ^(VmTuple 2) of two references
+        let tuple = StackValue::Tuple((1..=4).map(int).collect()).to_cell().unwrap();
+        assert_eq!(tuple.refs().len(), 2);
+        assert_eq!(tuple.refs()[0].refs()[0].refs().len(), 2);
+        
+        let mut shallow = CellBuilder::new();
+        shallow.store_uint(2, 24).unwrap().store_ref(Arc::new(Cell::default())).unwrap();
+        int(5).store(&mut shallow).unwrap();
+        assert_eq!(VmStack::from_cell(&shallow.build()), Err(TlbError::NotEnoughRefs));
+    }
+    
+    #[test]
+    fn stacks_from_bags_of_cells() {
+        let inner = StackValue::Tuple(vec![StackValue::NaN, int(-7)]);
+        let leaf = Arc::new(Cell::parse_fift("x{F00D_}").unwrap());
+        let slice = StackValue::Slice(VmCellSlice::new(Arc::new(Cell::parse_fift("x{12}\n x{34}").unwrap())));
+        let values = vec![StackValue::Cell(leaf), StackValue::Tuple(vec![inner, slice, int(1 << 70)]), StackValue::Null];
+        let boc = to_boc(&VmStack(values.clone()).to_cell().unwrap(), false, true);
+        assert_eq!(parse_stack_boc(&boc), Ok(values));
+        assert_eq!(parse_stack_boc(&to_boc(&Cell::default(), false, false)), Err(StackParseError::Tlb(TlbError::NotEnoughBits)));
+        assert_eq!(parse_stack_boc(&boc[..boc.len() - 1]).map_err(|err| matches!(err, StackParseError::Boc(_))), Err(true));
+    }
+}
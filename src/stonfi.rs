@@ -0,0 +1,69 @@
+// (c) ProgramCrafter, 2024
+
+//! Message bodies understood by the STON.fi DEX router (v1).
+
+use tlb_macro::*;
+use crate::ton::{Address, Coins};
+
+
+/// `swap#25938561`, sent as `forward_payload` of a jetton transfer to the router.
+///
+/// `token_wallet` is the router's jetton wallet for the asked token, `to_address`
+/// receives the swapped tokens. `referral` is stored as a presence bit, then the address if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(u 0x25938561 32bit, token_wallet, min_out, to_address, referral)]
+pub struct Swap {
+    pub token_wallet: Address,
+    pub min_out: Coins,
+    pub to_address: Address,
+    pub referral: Option<Address>,
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ton::{CellDeserialize, CellSerialize};
+    use crate::ton::cell::Cell;
+    
+    const WALLET: &str = "0:ed1691307050047117b998b561d8de82d31fbf84910ced6eb5fc92e7485ef8a7";
+    const RECEIVER: &str = "-1:3333333333333333333333333333333333333333333333333333333333333333";
+    
+    fn bits_of(cell: &Cell) -> String {
+        (0..cell.bit_len()).map(|i| if cell.bit(i) {'1'} else {'0'}).collect()
+    }
+    
+    /// `addr_std$10 anycast:nothing$0 workchain_id:int8 address:bits256`, spelled out by hand.
+    fn addr_std_bits(workchain: u8, hash: &str) -> String {
+        let hash: String = hash.chars().map(|c| format!("{:04b}", c.to_digit(16).unwrap())).collect();
+        format!("100{workchain:08b}{hash}")
+    }
+    
+    #[test]
+    fn swap_payload_layout() {
+        let swap = Swap {
+            token_wallet: WALLET.parse().unwrap(),
+            min_out: Coins(1_000_000),
+            to_address: RECEIVER.parse().unwrap(),
+            referral: None,
+        };
+        let cell = swap.to_cell().unwrap();
+        let expected = [
+            "00100101100100111000010101100001".to_owned(),         // op 0x25938561
+            addr_std_bits(0, &WALLET[2..]),
+            "0011".to_owned() + "000011110100001001000000",         // 3-byte Coins 0x0f4240
+            addr_std_bits(0xff, &RECEIVER[3..]),
+            "0".to_owned(),                                         // no referral
+        ].concat();
+        assert_eq!(bits_of(&cell), expected);
+        assert_eq!(cell.bit_len(), 32 + 267 + 4 + 24 + 267 + 1);
+        assert!(cell.refs().is_empty());
+        assert_eq!(Swap::from_cell(&cell), Ok(swap.clone()));
+        
+        let with_referral = Swap {referral: Some(WALLET.parse().unwrap()), ..swap};
+        let cell = with_referral.to_cell().unwrap();
+        assert_eq!(bits_of(&cell), expected[..expected.len() - 1].to_owned() + "1" + &addr_std_bits(0, &WALLET[2..]));
+        assert_eq!(Swap::from_cell(&cell), Ok(with_referral));
+    }
+}
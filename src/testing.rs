@@ -0,0 +1,326 @@
+// (c) ProgramCrafter, 2024
+
+//! Test support: snapshots of serialized values, see [`assert_boc_snapshot!`], and
+//! conformance vectors, TL-B values with the bags of cells they serialize to, so that custom
+//! types and other implementations can be checked against the same data.
+//!
+//! Vectors are stored as text, one block of `key: value` lines per vector, blocks separated
+//! by blank lines; `#` starts a comment line.
+//!
+//! ```text
+//! name: global_version
+//! type: GlobalVersion
+//! value: u 196 8bit; u 4 32bit; u 46 64bit
+//! boc: b5ee9c72…
+//! hash: 1d3c…
+//! ```
+//!
+//! `value` describes the cell in commands of [`CellSerialize::serialize`] separated by `;`,
+//! `boc` is the expected bag of cells in hex and `hash`, optional, the representation hash
+//! of its root.
+
+use std::path::Path;
+
+use crate::ton::boc::{from_boc, to_boc, BocError};
+use crate::ton::cell::{Cell, CellBuilder, CellError};
+use crate::ton::{CellDeserialize, CellSerialize};
+
+
+/// Vectors shipped with this crate, for types it defines.
+pub const BUNDLED_VECTORS: &str = include_str!("../conformance/vectors.txt");
+
+/// Reason why conformance vectors could not be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VectorError {
+    /// Line is not a `key: value` pair of a known key, or repeats a key of its vector.
+    Syntax {line: usize},
+    /// `boc` or `hash` is not valid hex, or the hash is not 32 bytes long.
+    InvalidHex {line: usize},
+    /// Vector starting at `line` lacks a required key.
+    MissingKey {line: usize, key: &'static str},
+}
+
+impl std::fmt::Display for VectorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VectorError::Syntax {line} => write!(f, "line {line}: expected `key: value` of a known key"),
+            VectorError::InvalidHex {line} => write!(f, "line {line}: invalid hex"),
+            VectorError::MissingKey {line, key} => write!(f, "vector at line {line} has no `{key}`"),
+        }
+    }
+}
+
+impl std::error::Error for VectorError {}
+
+/// Way in which a value or a vector fails a conformance check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    /// Expected bag of cells cannot be read, or has other than one root.
+    InvalidBoc(Option<BocError>),
+    /// Value description does not build the root of the bag of cells.
+    Description,
+    /// Root of the bag of cells has another hash than the vector states.
+    Hash,
+    /// Value serializes into another cell; both are given in Fift notation.
+    Serialized {actual: String, expected: String},
+    /// Expected cell does not read back as the value.
+    Deserialized,
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mismatch::InvalidBoc(Some(error)) => write!(f, "expected bag of cells is invalid: {error}"),
+            Mismatch::InvalidBoc(None) => f.write_str("expected bag of cells must have one root"),
+            Mismatch::Description => f.write_str("value description differs from the bag of cells"),
+            Mismatch::Hash => f.write_str("bag of cells has another root hash than stated"),
+            Mismatch::Serialized {actual, expected} => write!(f, "value serializes to\n{actual}\ninstead of\n{expected}"),
+            Mismatch::Deserialized => f.write_str("expected cell reads as another value"),
+        }
+    }
+}
+
+impl std::error::Error for Mismatch {}
+
+/// TL-B value with the bag of cells it serializes to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConformanceVector {
+    pub name: String,
+    /// TL-B type of the value, for readers; not checked.
+    pub type_name: String,
+    /// Commands building the value, as returned by [`CellSerialize::serialize`].
+    pub commands: Vec<String>,
+    pub boc: Vec<u8>,
+    pub hash: Option<[u8; 32]>,
+}
+
+impl ConformanceVector {
+    /// Root of the expected bag of cells, checked against the value description and hash.
+    pub fn expected_cell(&self) -> Result<Cell, Mismatch> {
+        let roots = from_boc(&self.boc).map_err(|error| Mismatch::InvalidBoc(Some(error)))?;
+        let [root] = <[Cell; 1]>::try_from(roots).map_err(|_| Mismatch::InvalidBoc(None))?;
+        let mut builder = CellBuilder::new();
+        if builder.store_commands(&self.commands).is_err() || builder.build() != root {
+            return Err(Mismatch::Description);
+        }
+        if self.hash.is_some_and(|hash| hash != root.repr_hash()) {
+            return Err(Mismatch::Hash);
+        }
+        Ok(root)
+    }
+    
+    /// Checks that `value` serializes into the expected cell.
+    pub fn check(&self, value: &impl CellSerialize) -> Result<(), Mismatch> {
+        let expected = self.expected_cell()?;
+        let actual = value.to_cell().map_err(|_| Mismatch::Description)?;
+        if actual != expected {
+            return Err(Mismatch::Serialized {actual: format!("{actual:#}"), expected: format!("{expected:#}")});
+        }
+        Ok(())
+    }
+    
+    /// Checks that `value` serializes into the expected cell, and the cell reads back as it.
+    pub fn check_round_trip<T: CellSerialize + CellDeserialize + PartialEq>(&self, value: &T) -> Result<(), Mismatch> {
+        self.check(value)?;
+        match T::from_cell(&self.expected_cell()?) {
+            Ok(read) if read == *value => Ok(()),
+            _ => Err(Mismatch::Deserialized),
+        }
+    }
+}
+
+/// Reason why a snapshot does not hold.
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// Snapshot file could not be read or written.
+    Io(std::io::Error),
+    /// Value cannot be serialized.
+    Cell(CellError),
+    /// Snapshot is not a bag of cells with one root, or the value serializes into another cell.
+    Mismatch(Mismatch),
+}
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "snapshot I/O failed: {error}"),
+            SnapshotError::Cell(error) => write!(f, "value cannot be serialized: {error}"),
+            SnapshotError::Mismatch(mismatch) => mismatch.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(error: std::io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+/// Checks that `value` serializes into the root of the bag of cells at `path`. If there is
+/// no file yet, writes it instead; delete the file to record a changed value.
+pub fn check_boc_snapshot(value: &impl CellSerialize, path: &Path) -> Result<(), SnapshotError> {
+    let actual = value.to_cell().map_err(SnapshotError::Cell)?;
+    if !path.exists() {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, to_boc(&actual, false, true))?;
+        return Ok(());
+    }
+    let roots = from_boc(&std::fs::read(path)?).map_err(|error| SnapshotError::Mismatch(Mismatch::InvalidBoc(Some(error))))?;
+    match roots.as_slice() {
+        [expected] if *expected == actual => Ok(()),
+        [expected] => Err(SnapshotError::Mismatch(Mismatch::Serialized {
+            actual: format!("{actual:#}"), expected: format!("{expected:#}"),
+        })),
+        _ => Err(SnapshotError::Mismatch(Mismatch::InvalidBoc(None))),
+    }
+}
+
+/// Asserts that a value serializes as in snapshot file `$file`, kept in `snapshots/` of the
+/// calling crate; see [`testing::check_boc_snapshot`](crate::testing::check_boc_snapshot).
+/// The first run writes the file, to be committed with the test.
+///
+/// ```ignore
+/// assert_boc_snapshot!(GlobalVersion {version: 4, capabilities: Capabilities(46)}, "global_version.boc");
+/// ```
+#[macro_export]
+macro_rules! assert_boc_snapshot {
+    ($value:expr, $file:expr) => {{
+        let path = ::std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("snapshots").join($file);
+        if let ::std::result::Result::Err(error) = $crate::testing::check_boc_snapshot(&$value, &path) {
+            panic!("snapshot {} does not hold: {error}", path.display());
+        }
+    }};
+}
+
+/// Reads conformance vectors in the format described in the [module](self) docs.
+pub fn parse_vectors(text: &str) -> Result<Vec<ConformanceVector>, VectorError> {
+    let mut vectors = vec![];
+    let mut block: Vec<(usize, &str, &str)> = vec![];
+    let lines = text.lines().enumerate().map(|(index, line)| (index + 1, line.trim()));
+    for (number, line) in lines.chain([(0, "")]) {
+        if line.starts_with('#') {
+            continue;
+        }
+        if !line.is_empty() {
+            let (key, value) = line.split_once(':').ok_or(VectorError::Syntax {line: number})?;
+            let key = key.trim();
+            if block.iter().any(|&(_, seen, _)| seen == key) {
+                return Err(VectorError::Syntax {line: number});
+            }
+            block.push((number, key, value.trim()));
+            continue;
+        }
+        if let Some(&(start, _, _)) = block.first() {
+            vectors.push(parse_vector(start, &block)?);
+            block.clear();
+        }
+    }
+    Ok(vectors)
+}
+
+fn parse_vector(start: usize, block: &[(usize, &str, &str)]) -> Result<ConformanceVector, VectorError> {
+    let field = |key: &'static str| block.iter().find(|&&(_, seen, _)| seen == key).map(|&(line, _, value)| (line, value));
+    let required = |key| field(key).ok_or(VectorError::MissingKey {line: start, key});
+    if let Some(&(line, _, _)) = block.iter().find(|(_, key, _)| !["name", "type", "value", "boc", "hash"].contains(key)) {
+        return Err(VectorError::Syntax {line});
+    }
+    let (boc_line, boc) = required("boc")?;
+    let hash = match field("hash") {
+        Some((line, hash)) => {
+            let bytes = hex_bytes(hash).ok_or(VectorError::InvalidHex {line})?;
+            Some(bytes.try_into().map_err(|_| VectorError::InvalidHex {line})?)
+        }
+        None => None,
+    };
+    Ok(ConformanceVector {
+        name: required("name")?.1.to_owned(),
+        type_name: required("type")?.1.to_owned(),
+        commands: required("value")?.1.split(';').map(|command| command.trim().to_owned()).collect(),
+        boc: hex_bytes(boc).ok_or(VectorError::InvalidHex {line: boc_line})?,
+        hash,
+    })
+}
+
+fn hex_bytes(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Capabilities, GlobalVersion, StoragePrices};
+    use crate::ton::dict::HashmapE;
+    use crate::ton::{Address, MsgAddressInt};
+    
+    fn bundled(name: &str) -> ConformanceVector {
+        let vectors = parse_vectors(BUNDLED_VECTORS).unwrap();
+        vectors.into_iter().find(|vector| vector.name == name).unwrap()
+    }
+    
+    #[test]
+    fn bundled_vectors_hold() {
+        let version = GlobalVersion {version: 4, capabilities: Capabilities(46)};
+        assert_eq!(bundled("global_version").check_round_trip(&version), Ok(()));
+        let address = MsgAddressInt::from(Address::new(-1, [0x33; 32]));
+        assert_eq!(bundled("addr_std").check_round_trip(&address), Ok(()));
+        let prices = StoragePrices {utime_since: 0, bit_price_ps: 1, cell_price_ps: 500, mc_bit_price_ps: 1000, mc_cell_price_ps: 500_000};
+        assert_eq!(bundled("storage_prices").check_round_trip(&prices), Ok(()));
+        let mut dict = HashmapE::<8, u8>::default();
+        for (key, value) in [(0x01u8, 0xaa), (0x81, 0xbb), (0x83, 0xcc)] {
+            dict.set(&key, &value).unwrap();
+        }
+        assert_eq!(bundled("hashmap_e_8").check_round_trip(&dict), Ok(()));
+        
+        let other = GlobalVersion {version: 5, capabilities: Capabilities(46)};
+        assert!(matches!(bundled("global_version").check(&other), Err(Mismatch::Serialized {..})));
+    }
+    
+    #[test]
+    fn snapshots_are_written_then_compared() {
+        let dir = std::env::temp_dir().join(format!("tlb-snapshot-{}", std::process::id()));
+        let path = dir.join("nested").join("version.boc");
+        let version = GlobalVersion {version: 4, capabilities: Capabilities(46)};
+        check_boc_snapshot(&version, &path).unwrap();
+        assert_eq!(from_boc(&std::fs::read(&path).unwrap()), Ok(vec![version.to_cell().unwrap()]));
+        check_boc_snapshot(&version, &path).unwrap();
+        
+        let changed = GlobalVersion {version: 5, ..version};
+        let error = check_boc_snapshot(&changed, &path).unwrap_err();
+        assert!(matches!(error, SnapshotError::Mismatch(Mismatch::Serialized {..})));
+        std::fs::write(&path, b"not a boc").unwrap();
+        assert!(matches!(check_boc_snapshot(&version, &path), Err(SnapshotError::Mismatch(Mismatch::InvalidBoc(_)))));
+        std::fs::remove_dir_all(&dir).unwrap();
+        
+        // kept with the crate
+        crate::assert_boc_snapshot!(version, "global_version.boc");
+    }
+    
+    #[test]
+    fn vectors_are_checked() {
+        let text = "# one vector\nname: bit\ntype: Bool\nvalue: u 1 1bit\nboc: b5ee9c72410101010003000001c08ee9b6b6\n";
+        let [vector] = <[ConformanceVector; 1]>::try_from(parse_vectors(text).unwrap()).unwrap();
+        assert_eq!(vector.commands, ["u 1 1bit"]);
+        assert_eq!(vector.check_round_trip(&true), Ok(()));
+        assert!(matches!(vector.check(&false), Err(Mismatch::Serialized {..})));
+        
+        let wrong_description = ConformanceVector {commands: vec!["u 0 1bit".to_owned()], ..vector.clone()};
+        assert_eq!(wrong_description.check(&true), Err(Mismatch::Description));
+        let wrong_hash = ConformanceVector {hash: Some([0; 32]), ..vector.clone()};
+        assert_eq!(wrong_hash.check(&true), Err(Mismatch::Hash));
+        let broken = ConformanceVector {boc: vec![0xb5, 0xee, 0x9c, 0x73, 0, 0], ..vector};
+        assert_eq!(broken.check(&true), Err(Mismatch::InvalidBoc(Some(BocError::InvalidMagic))));
+        
+        assert_eq!(parse_vectors("name: a\nname: b\n"), Err(VectorError::Syntax {line: 2}));
+        assert_eq!(parse_vectors("name: a\ncolor: red\n"), Err(VectorError::Syntax {line: 2}));
+        assert_eq!(parse_vectors("\nname: a\ntype: T\nvalue: u 1 1bit\n"), Err(VectorError::MissingKey {line: 2, key: "boc"}));
+        assert_eq!(parse_vectors("name: a\ntype: T\nvalue: \nboc: b5e\n"), Err(VectorError::InvalidHex {line: 4}));
+    }
+}
@@ -0,0 +1,1888 @@
+// (c) ProgramCrafter, 2024
+
+//! Basic TON types: integers, coins, addresses and message headers, together with the
+//! [`CellSerialize`] trait code generated by `tlb_macro` relies on and [`cell::Cell`]s
+//! values are stored into.
+
+use tlb_macro::*;
+
+pub mod boc;
+pub mod cell;
+mod crc32c;
+pub mod dict;
+#[cfg(feature = "ed25519")]
+pub mod ed25519;
+pub mod proof;
+pub(crate) mod sha256;
+pub mod store;
+use cell::{Cell, CellBuilder, CellError, CellSlice, TlbError};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[tlb_deserializable]
+#[tlb_serializable(__fundamental_varuint16)]
+pub struct Coins(pub u128);
+
+/// Amount of token with `DECIMALS` digits after the point, e.g. `TokenAmount<9>` for TON and
+/// most jettons or `TokenAmount<6>` for USDT. Holds the raw integer stored on-chain as `Coins`,
+/// and is parsed from and formatted to decimal strings like `"1.5"`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TokenAmount<const DECIMALS: u32>(pub u128);
+
+/// Reason why a string could not be parsed into [`TokenAmount`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenAmountParseError {
+    /// Not a non-negative decimal number like `12` or `0.05` (`1.` and `.5` are not accepted).
+    InvalidNumber,
+    /// More digits after the point than the token has decimals.
+    TooManyDecimals,
+    /// Raw amount does not fit into 128 bits.
+    Overflow,
+}
+
+impl std::fmt::Display for TokenAmountParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TokenAmountParseError::InvalidNumber => "token amount must be a non-negative decimal number",
+            TokenAmountParseError::TooManyDecimals => "token amount has more decimal places than the token",
+            TokenAmountParseError::Overflow => "token amount is too large",
+        })
+    }
+}
+
+impl std::error::Error for TokenAmountParseError {}
+
+impl<const DECIMALS: u32> TokenAmount<DECIMALS> {
+    fn unit() -> u128 {
+        10u128.checked_pow(DECIMALS).expect("TokenAmount cannot have more than 38 decimals")
+    }
+}
+
+impl<const DECIMALS: u32> std::str::FromStr for TokenAmount<DECIMALS> {
+    type Err = TokenAmountParseError;
+    
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        // "1." and ".5" are rejected: a point must have digits on both sides
+        let (whole, fraction) = match text.split_once('.') {
+            Some((_, "")) => return Err(TokenAmountParseError::InvalidNumber),
+            Some(parts) => parts,
+            None => (text, ""),
+        };
+        let is_number = |digits: &str| digits.bytes().all(|c| c.is_ascii_digit());
+        if whole.is_empty() || !is_number(whole) || !is_number(fraction) {
+            return Err(TokenAmountParseError::InvalidNumber);
+        }
+        if fraction.len() > DECIMALS as usize {
+            return Err(TokenAmountParseError::TooManyDecimals);
+        }
+        
+        let whole: u128 = whole.parse().map_err(|_| TokenAmountParseError::Overflow)?;
+        let fraction: u128 = format!("{fraction:0<width$}", width = DECIMALS as usize).parse().unwrap_or(0);
+        whole.checked_mul(Self::unit()).and_then(|raw| raw.checked_add(fraction))
+            .map(TokenAmount).ok_or(TokenAmountParseError::Overflow)
+    }
+}
+
+impl<const DECIMALS: u32> std::fmt::Display for TokenAmount<DECIMALS> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (whole, fraction) = (self.0 / Self::unit(), self.0 % Self::unit());
+        if fraction == 0 {
+            return write!(f, "{whole}");
+        }
+        let fraction = format!("{fraction:0width$}", width = DECIMALS as usize);
+        write!(f, "{whole}.{}", fraction.trim_end_matches('0'))
+    }
+}
+
+impl<const DECIMALS: u32> From<TokenAmount<DECIMALS>> for Coins {
+    fn from(amount: TokenAmount<DECIMALS>) -> Self {
+        Coins(amount.0)
+    }
+}
+
+impl<const DECIMALS: u32> CellSerialize for TokenAmount<DECIMALS> {
+    fn serialize(&self) -> Vec<String> {
+        Coins(self.0).serialize()
+    }
+}
+
+impl<const DECIMALS: u32> CellDeserialize for TokenAmount<DECIMALS> {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        Ok(TokenAmount(Coins::deserialize(slice)?.0))
+    }
+}
+
+/// `addr_std$10`: address of a contract in a workchain, by hash of its initial state.
+///
+/// Parsed from and formatted to raw form `0:83df…`; see [`FriendlyAddress`] for the
+/// base64 user-friendly form.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+#[tlb_deserializable]
+#[tlb_serializable(u 2 2bit, anycast, workchain, hash_high, hash_low)]
+pub struct Address {
+    anycast: Option<Anycast>,
+    workchain: u8,
+    hash_high: u128,
+    hash_low: u128
+}
+
+impl Address {
+    pub fn new(workchain: i8, hash: [u8; 32]) -> Self {
+        let (high, low) = hash.split_at(16);
+        Address {
+            anycast: None,
+            workchain: workchain as u8,
+            hash_high: u128::from_be_bytes(high.try_into().unwrap()),
+            hash_low: u128::from_be_bytes(low.try_into().unwrap())
+        }
+    }
+    
+    pub fn with_anycast(self, anycast: Anycast) -> Self {
+        Address {anycast: Some(anycast), ..self}
+    }
+    
+    pub fn anycast(&self) -> Option<&Anycast> {
+        self.anycast.as_ref()
+    }
+    
+    pub fn workchain(&self) -> i8 {
+        self.workchain as i8
+    }
+    
+    pub fn hash(&self) -> [u8; 32] {
+        let mut hash = [0u8; 32];
+        hash[..16].copy_from_slice(&self.hash_high.to_be_bytes());
+        hash[16..].copy_from_slice(&self.hash_low.to_be_bytes());
+        hash
+    }
+}
+
+/// Raw form: workchain and hash in lowercase hex, like `-1:3333…`. Anycast is not shown.
+impl std::fmt::Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{:032x}{:032x}", self.workchain(), self.hash_high, self.hash_low)
+    }
+}
+
+/// `anycast_info$_ depth:(#<= 30) { depth >= 1 } rewrite_pfx:(bits depth)`: prefix that
+/// replaces the first `depth` bits of the address when routing messages.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Anycast {
+    rewrite_pfx: RawBits
+}
+
+impl Anycast {
+    pub fn new(rewrite_pfx: RawBits) -> Self {
+        assert!((1..=30).contains(&rewrite_pfx.bit_len()), "anycast depth must be in 1..=30 bits");
+        Anycast {rewrite_pfx}
+    }
+    
+    pub fn rewrite_pfx(&self) -> &RawBits {
+        &self.rewrite_pfx
+    }
+}
+
+impl CellSerialize for Anycast {
+    fn serialize(&self) -> Vec<String> {
+        // `#<= 30` takes 5 bits
+        VarBits::<5>(self.rewrite_pfx.clone()).serialize()
+    }
+}
+
+impl CellDeserialize for Anycast {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        let VarBits(rewrite_pfx) = VarBits::<5>::deserialize(slice)?;
+        if !(1..=30).contains(&rewrite_pfx.bit_len()) {
+            return Err(TlbError::InvalidValue);
+        }
+        Ok(Anycast {rewrite_pfx})
+    }
+}
+
+/// Reason why a string could not be parsed into [`Address`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressParseError {
+    /// Neither raw `workchain:hex` form nor 48-character user-friendly form.
+    UnknownFormat,
+    /// Workchain of raw form is not an 8-bit signed integer.
+    InvalidWorkchain,
+    /// Hash of raw form is not 64 hexadecimal digits.
+    InvalidHash,
+    /// User-friendly form contains characters outside of base64 and base64url alphabets.
+    InvalidBase64,
+    /// User-friendly form has flags byte other than bounceable/non-bounceable (and testnet bit).
+    InvalidFlags,
+    /// CRC16 of user-friendly form does not match its contents.
+    ChecksumMismatch,
+}
+
+impl std::fmt::Display for AddressParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            AddressParseError::UnknownFormat => "address is neither in raw nor in user-friendly form",
+            AddressParseError::InvalidWorkchain => "invalid workchain in raw address",
+            AddressParseError::InvalidHash => "hash in raw address must be 64 hex digits",
+            AddressParseError::InvalidBase64 => "invalid base64 in user-friendly address",
+            AddressParseError::InvalidFlags => "unknown flags in user-friendly address",
+            AddressParseError::ChecksumMismatch => "checksum mismatch in user-friendly address",
+        })
+    }
+}
+
+impl std::error::Error for AddressParseError {}
+
+/// Decodes 48 characters of base64 or base64url (alphabets may be mixed) into 36 bytes.
+fn decode_friendly(text: &str) -> Option<[u8; 36]> {
+    let mut result = [0u8; 36];
+    for (chunk, out) in text.as_bytes().chunks(4).zip(result.chunks_mut(3)) {
+        let mut acc: u32 = 0;
+        for c in chunk {
+            let sextet = match c {
+                b'A'..=b'Z' => c - b'A',
+                b'a'..=b'z' => c - b'a' + 26,
+                b'0'..=b'9' => c - b'0' + 52,
+                b'+' | b'-' => 62,
+                b'/' | b'_' => 63,
+                _ => return None,
+            };
+            acc = (acc << 6) | sextet as u32;
+        }
+        out.copy_from_slice(&acc.to_be_bytes()[1..]);
+    }
+    Some(result)
+}
+
+/// Encodes 36 bytes into 48 characters of base64url.
+fn encode_friendly(data: &[u8; 36]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+    data.chunks(3).flat_map(|chunk| {
+        let acc = u32::from_be_bytes([0, chunk[0], chunk[1], chunk[2]]);
+        (0..4).rev().map(move |i| ALPHABET[(acc >> (6 * i)) as usize & 63] as char)
+    }).collect()
+}
+
+/// Address in user-friendly form: base64 of flags, workchain, hash and CRC16 of them, like
+/// `EQDtFpEw…`. Flags tell wallets whether the contract expects bounceable messages, and
+/// whether the address is meant for testnet only (`kQ…`, `0Q…`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FriendlyAddress {
+    pub address: Address,
+    pub bounceable: bool,
+    pub testnet: bool,
+}
+
+impl FriendlyAddress {
+    const BOUNCEABLE_TAG: u8 = 0x11;
+    const NON_BOUNCEABLE_TAG: u8 = 0x51;
+    const TESTNET_FLAG: u8 = 0x80;
+}
+
+impl std::str::FromStr for FriendlyAddress {
+    type Err = AddressParseError;
+    
+    /// Parses 48-character form (`EQ…`, `UQ…`, `kQ…`, `0Q…`) written in either base64 alphabet.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        if text.len() != 48 {
+            return Err(AddressParseError::UnknownFormat);
+        }
+        let data = decode_friendly(text).ok_or(AddressParseError::InvalidBase64)?;
+        let testnet = data[0] & Self::TESTNET_FLAG != 0;
+        let bounceable = match data[0] & !Self::TESTNET_FLAG {
+            Self::BOUNCEABLE_TAG => true,
+            Self::NON_BOUNCEABLE_TAG => false,
+            _ => return Err(AddressParseError::InvalidFlags),
+        };
+        if crc16(&data[..34]) != u16::from_be_bytes([data[34], data[35]]) {
+            return Err(AddressParseError::ChecksumMismatch);
+        }
+        let address = Address::new(data[1] as i8, data[2..34].try_into().unwrap());
+        Ok(FriendlyAddress {address, bounceable, testnet})
+    }
+}
+
+/// Formats with base64url alphabet, as wallets show addresses. Anycast cannot be represented
+/// in this form and is left out.
+impl std::fmt::Display for FriendlyAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut data = [0u8; 36];
+        data[0] = if self.bounceable {Self::BOUNCEABLE_TAG} else {Self::NON_BOUNCEABLE_TAG};
+        if self.testnet {
+            data[0] |= Self::TESTNET_FLAG;
+        }
+        data[1] = self.address.workchain;
+        data[2..34].copy_from_slice(&self.address.hash());
+        let crc = crc16(&data[..34]);
+        data[34..].copy_from_slice(&crc.to_be_bytes());
+        f.write_str(&encode_friendly(&data))
+    }
+}
+
+impl std::str::FromStr for Address {
+    type Err = AddressParseError;
+    
+    /// Parses raw form (`0:83df…`, `-1:3333…`) or 48-character user-friendly form
+    /// (`EQ…`, `UQ…`, either base64 alphabet); flags of the latter are validated, and
+    /// can be kept by parsing [`FriendlyAddress`] instead.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        if let Some((workchain, hash)) = text.split_once(':') {
+            let workchain: i8 = workchain.parse().map_err(|_| AddressParseError::InvalidWorkchain)?;
+            if hash.len() != 64 || !hash.bytes().all(|c| c.is_ascii_hexdigit()) {
+                return Err(AddressParseError::InvalidHash);
+            }
+            let mut bytes = [0u8; 32];
+            for (i, byte) in bytes.iter_mut().enumerate() {
+                *byte = u8::from_str_radix(&hash[2 * i..2 * i + 2], 16).unwrap();
+            }
+            return Ok(Address::new(workchain, bytes));
+        }
+        Ok(text.parse::<FriendlyAddress>()?.address)
+    }
+}
+
+/// CRC16-XMODEM checksum, as stored in the last two bytes of user-friendly addresses.
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {(crc << 1) ^ 0x1021} else {crc << 1};
+        }
+    }
+    crc
+}
+
+/// `currencies$_ grams:Grams other:ExtraCurrencyCollection`, with no extra currencies:
+/// their dictionary is always stored empty, and reading a non-empty one fails with
+/// `TlbError::TagMismatch`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(grams, u 0 1bit)]
+pub struct CurrencyCollection {grams: Coins}
+
+impl CurrencyCollection {
+    pub fn new(grams: Coins) -> Self {
+        CurrencyCollection {grams}
+    }
+    
+    /// Amount of nanotons.
+    pub fn grams(&self) -> Coins {
+        self.grams
+    }
+}
+
+impl From<Coins> for CurrencyCollection {
+    fn from(grams: Coins) -> Self {
+        CurrencyCollection {grams}
+    }
+}
+
+pub trait CellSerialize {
+    fn serialize(&self) -> Vec<String>;
+    
+    /// Stores value into cell under construction. Default implementation executes
+    /// commands returned by [`CellSerialize::serialize`].
+    fn store(&self, builder: &mut CellBuilder) -> Result<(), CellError> {
+        builder.store_commands(&self.serialize())?;
+        Ok(())
+    }
+    
+    /// Bits the value stores into the cell itself, not counting child cells: a lower estimate
+    /// for reserving builder capacity up front, zero where unknown. Generated `store`
+    /// reserves the sum over fields before storing them.
+    fn bit_len_hint(&self) -> usize {
+        0
+    }
+    
+    /// Serializes value into a standalone cell.
+    fn to_cell(&self) -> Result<Cell, CellError> {
+        let mut builder = CellBuilder::new();
+        self.store(&mut builder)?;
+        Ok(builder.build())
+    }
+}
+
+/// Treatment of bits and references left in a cell after the value read from it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Leftovers are an error, `TlbError::TrailingData`: for validating data.
+    #[default]
+    Strict,
+    /// Leftovers are ignored: for data that newer versions of contracts may extend.
+    Lenient,
+}
+
+pub trait CellDeserialize: Sized {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError>;
+    
+    /// Whether `slice` starts with constructor tag of this type, checked without consuming it.
+    /// Enums with non-overlapping prefixes pick their variant by it. Types that do not begin
+    /// with a tag accept any slice.
+    fn prefix_matches(_slice: &CellSlice) -> bool {
+        true
+    }
+    
+    /// Whether [`CellDeserialize::from_cell`] rejects data left after the value; types mark
+    /// themselves lenient with `#[tlb_lenient]`.
+    const PARSE_MODE: ParseMode = ParseMode::Strict;
+    
+    /// Reads value from the whole cell, failing if anything is left unread, unless the type
+    /// is lenient (see [`CellDeserialize::PARSE_MODE`]). Values in child cells follow their
+    /// own types' modes.
+    fn from_cell(cell: &Cell) -> Result<Self, TlbError> {
+        Self::from_whole_slice(CellSlice::new(cell))
+    }
+    
+    /// Reads value from the whole cell in the given mode, regardless of the type's own.
+    /// The mode also applies to every value in child cells, e.g. `^X` fields.
+    fn from_cell_with(cell: &Cell, mode: ParseMode) -> Result<Self, TlbError> {
+        Self::from_whole_slice(CellSlice::with_mode(cell, mode))
+    }
+    
+    /// Reads value from the next child cell of `slice`, as `^X` fields are read: the child
+    /// must hold exactly the value, in the mode of `slice` if it has one.
+    fn from_ref(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        Self::from_whole_slice(slice.load_ref_slice()?)
+    }
+    
+    /// Reads value from all of `slice`, checking leftovers in the mode of the slice or, if it
+    /// has none, of the type.
+    fn from_whole_slice(mut slice: CellSlice) -> Result<Self, TlbError> {
+        let value = Self::deserialize(&mut slice)?;
+        if slice.mode().unwrap_or(Self::PARSE_MODE) == ParseMode::Strict && !slice.is_empty() {
+            return Err(TlbError::TrailingData);
+        }
+        Ok(value)
+    }
+    
+    /// Reads value from the start of the cell, returning the rest unread: e.g. only
+    /// [`BodyHeader`] of a message, to filter messages cheaply before decoding them fully.
+    fn from_cell_prefix(cell: &Cell) -> Result<(Self, CellSlice<'_>), TlbError> {
+        let mut slice = CellSlice::new(cell);
+        let value = Self::deserialize(&mut slice)?;
+        Ok((value, slice))
+    }
+}
+
+/// `op:uint32 query_id:uint64` most internal message bodies start with; read it with
+/// [`CellDeserialize::from_cell_prefix`] to get the rest of the body unparsed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[tlb_deserializable]
+#[tlb_serializable(op, query_id)]
+pub struct BodyHeader {
+    pub op: u32,
+    pub query_id: u64,
+}
+
+/// Bits and child cells taken by each top-level part of a serialized value, in storage order,
+/// as returned by generated `size_report()` methods. Constants are named by their TL-B tag,
+/// like `#7258a69b`. Parts stored in child cells take no bits of the cell itself, but count
+/// every distinct cell of their subtree.
+#[derive(Debug, Default)]
+pub struct SizeReport {
+    pub parts: Vec<(&'static str, usize, usize)>,
+    measured_bits: usize,
+    measured_refs: usize,
+}
+
+impl SizeReport {
+    /// Records what was stored into `builder` since the previous part as `part`: its bits,
+    /// and cells of the new references counted by [`Cell::stats`].
+    pub fn push(&mut self, part: &'static str, builder: &CellBuilder) {
+        let bits = builder.bit_len() - self.measured_bits;
+        let cells = builder.refs()[self.measured_refs..].iter().map(|child| child.stats().cells).sum();
+        self.measured_bits = builder.bit_len();
+        self.measured_refs = builder.refs().len();
+        self.parts.push((part, bits, cells));
+    }
+    
+    pub fn total_bits(&self) -> usize {
+        self.parts.iter().map(|(_, bits, _)| bits).sum()
+    }
+    
+    /// Cells of the whole value, including the root one, as counted by forward fees.
+    pub fn total_cells(&self) -> usize {
+        1 + self.parts.iter().map(|(_, _, cells)| cells).sum::<usize>()
+    }
+}
+
+// Defining serialization on foreign (std) types.
+impl CellDeserialize for u8 {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {  Ok(slice.load_uint(8)? as u8)  }
+}
+impl CellSerialize for u8 {
+    fn serialize(&self) -> Vec<String> {  vec![format!("u {self} 8bit")]  }
+    fn bit_len_hint(&self) -> usize {  8  }
+    fn store(&self, builder: &mut CellBuilder) -> Result<(), CellError> {
+        builder.store_uint(*self as u128, 8)?;
+        Ok(())
+    }
+}
+impl CellDeserialize for u16 {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {  Ok(slice.load_uint(16)? as u16)  }
+}
+impl CellSerialize for u16 {
+    fn serialize(&self) -> Vec<String> {  vec![format!("u {self} 16bit")]  }
+    fn bit_len_hint(&self) -> usize {  16  }
+    fn store(&self, builder: &mut CellBuilder) -> Result<(), CellError> {
+        builder.store_uint(*self as u128, 16)?;
+        Ok(())
+    }
+}
+impl CellDeserialize for u32 {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {  Ok(slice.load_uint(32)? as u32)  }
+}
+impl CellSerialize for u32 {
+    fn serialize(&self) -> Vec<String> {  vec![format!("u {self} 32bit")]  }
+    fn bit_len_hint(&self) -> usize {  32  }
+    fn store(&self, builder: &mut CellBuilder) -> Result<(), CellError> {
+        builder.store_uint(*self as u128, 32)?;
+        Ok(())
+    }
+}
+impl CellDeserialize for u64 {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {  Ok(slice.load_uint(64)? as u64)  }
+}
+impl CellSerialize for u64 {
+    fn serialize(&self) -> Vec<String> {  vec![format!("u {self} 64bit")]  }
+    fn bit_len_hint(&self) -> usize {  64  }
+    fn store(&self, builder: &mut CellBuilder) -> Result<(), CellError> {
+        builder.store_uint(*self as u128, 64)?;
+        Ok(())
+    }
+}
+impl CellDeserialize for u128 {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {  slice.load_uint(128)  }
+}
+impl CellSerialize for u128 {
+    fn serialize(&self) -> Vec<String> {  vec![format!("u {self} 128bit")]  }
+    fn bit_len_hint(&self) -> usize {  128  }
+    fn store(&self, builder: &mut CellBuilder) -> Result<(), CellError> {
+        builder.store_uint(*self, 128)?;
+        Ok(())
+    }
+}
+impl CellDeserialize for bool {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {  slice.load_bit()  }
+}
+impl CellSerialize for bool {
+    fn serialize(&self) -> Vec<String> {
+        vec![format!("u {} 1bit", if *self {1} else {0})]
+    }
+    fn store(&self, builder: &mut CellBuilder) -> Result<(), CellError> {
+        builder.store_bit(*self)?;
+        Ok(())
+    }
+    fn bit_len_hint(&self) -> usize {
+        1
+    }
+}
+
+/// `Maybe X`: presence bit, then the value if any (e.g. `anycast:(Maybe Anycast)` of addresses).
+impl<T: CellSerialize> CellSerialize for Option<T> {
+    fn serialize(&self) -> Vec<String> {
+        match self {
+            None => vec!["u 0 1bit".to_owned()],
+            Some(value) => {
+                let mut result = vec!["u 1 1bit".to_owned()];
+                result.extend(value.serialize());
+                result
+            },
+        }
+    }
+    
+    fn store(&self, builder: &mut CellBuilder) -> Result<(), CellError> {
+        builder.store_bit(self.is_some())?;
+        if let Some(value) = self {
+            value.store(builder)?;
+        }
+        Ok(())
+    }
+    
+    fn bit_len_hint(&self) -> usize {
+        1 + self.as_ref().map_or(0, T::bit_len_hint)
+    }
+}
+
+impl<T: CellDeserialize> CellDeserialize for Option<T> {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        if slice.load_bit()? {
+            Ok(Some(T::deserialize(slice)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Boxed value is stored as the value itself, for recursive types like `next:(Maybe ^SwapStep)`.
+impl<T: CellSerialize> CellSerialize for Box<T> {
+    fn serialize(&self) -> Vec<String> {
+        T::serialize(self)
+    }
+    
+    fn store(&self, builder: &mut CellBuilder) -> Result<(), CellError> {
+        T::store(self, builder)
+    }
+    
+    fn bit_len_hint(&self) -> usize {
+        T::bit_len_hint(self)
+    }
+}
+
+impl<T: CellDeserialize> CellDeserialize for Box<T> {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        Ok(Box::new(T::deserialize(slice)?))
+    }
+    
+    fn prefix_matches(slice: &CellSlice) -> bool {
+        T::prefix_matches(slice)
+    }
+}
+
+/// Counted array `count:uint32 items:(count * T)`, with all items in the same cell.
+/// Opinionated encoding for prototypes; real schemes usually need a dedicated type.
+#[cfg(feature = "std-collections")]
+impl<T: CellSerialize> CellSerialize for Vec<T> {
+    fn serialize(&self) -> Vec<String> {
+        let count = u32::try_from(self.len()).expect("Vec is too long for 32-bit count");
+        let mut result = count.serialize();
+        for item in self {
+            result.extend(item.serialize());
+        }
+        result
+    }
+}
+
+#[cfg(feature = "std-collections")]
+impl<T: CellDeserialize> CellDeserialize for Vec<T> {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        let count = u32::deserialize(slice)?;
+        (0..count).map(|_| T::deserialize(slice)).collect()
+    }
+}
+
+/// Unsigned integer `uintN` or `## N` stored in `N` bits, for widths without a Rust type
+/// (e.g. `Uint<7>` for `#<= 96`).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Uint<const N: usize>(pub u128);
+
+impl<const N: usize> CellSerialize for Uint<N> {
+    fn serialize(&self) -> Vec<String> {
+        assert!((1..=128).contains(&N), "Uint<{N}> width must be in 1..=128 bits");
+        assert!(N == 128 || self.0 >> N == 0, "Uint<{N}> overflow");
+        vec![format!("u {} {N}bit", self.0)]
+    }
+    
+    fn bit_len_hint(&self) -> usize {
+        N
+    }
+}
+
+impl<const N: usize> CellDeserialize for Uint<N> {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        assert!((1..=128).contains(&N), "Uint<{N}> width must be in 1..=128 bits");
+        Ok(Uint(slice.load_uint(N)?))
+    }
+}
+
+/// Signed integer `intN` stored in `N` bits using two's complement (e.g. `Int<257>`).
+///
+/// Value is held in `i128`, so types wider than 128 bits only cover `-2^127..2^127`:
+/// reading a stored value outside of this range fails with `TlbError::InvalidValue`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Int<const N: usize>(pub i128);
+
+impl<const N: usize> CellSerialize for Int<N> {
+    fn serialize(&self) -> Vec<String> {
+        assert!((1..=257).contains(&N), "Int<{N}> width must be in 1..=257 bits");
+        let value = self.0;
+        if N < 128 {
+            let half = 1i128 << (N - 1);
+            assert!(-half <= value && value < half, "Int<{N}> overflow");
+        }
+        
+        // Bits above the low 128 only repeat the sign; emitting them in chunks keeps every
+        // command within u128 range.
+        let mut result = vec![];
+        let mut sign_bits = N.saturating_sub(128);
+        while sign_bits > 0 {
+            let chunk = sign_bits.min(128);
+            let fill = if value < 0 {u128::MAX >> (128 - chunk)} else {0};
+            result.push(format!("u {fill} {chunk}bit"));
+            sign_bits -= chunk;
+        }
+        
+        let low_bits = N.min(128);
+        let mask = if low_bits == 128 {u128::MAX} else {(1u128 << low_bits) - 1};
+        result.push(format!("u {} {low_bits}bit", value as u128 & mask));
+        result
+    }
+    
+    fn bit_len_hint(&self) -> usize {
+        N
+    }
+}
+
+impl<const N: usize> CellDeserialize for Int<N> {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        assert!((1..=257).contains(&N), "Int<{N}> width must be in 1..=257 bits");
+        let mut sign_fills = vec![];
+        let mut sign_bits = N.saturating_sub(128);
+        while sign_bits > 0 {
+            let chunk = sign_bits.min(128);
+            sign_fills.push((slice.load_uint(chunk)?, u128::MAX >> (128 - chunk)));
+            sign_bits -= chunk;
+        }
+        
+        let low_bits = N.min(128);
+        let low = slice.load_uint(low_bits)?;
+        let negative = low >> (low_bits - 1) == 1;
+        let value = if negative && low_bits < 128 {(low | (u128::MAX << low_bits)) as i128} else {low as i128};
+        // bits above the low 128 must only repeat the sign, or value does not fit into i128
+        if sign_fills.iter().any(|&(fill, ones)| fill != if negative {ones} else {0}) {
+            return Err(TlbError::InvalidValue);
+        }
+        Ok(Int(value))
+    }
+}
+
+/// Opaque bit string copied into the cell verbatim, for payloads whose inner format
+/// is of no interest. The first `bit_len` bits of `data` are stored, most significant first.
+///
+/// Reading takes all remaining bits of the slice (references are left for following fields);
+/// use [`FixedBits`] for bit strings of known width.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct RawBits {
+    data: Vec<u8>,
+    bit_len: usize
+}
+
+impl RawBits {
+    /// Bits past `bit_len` are dropped, so equal bit strings compare equal.
+    pub fn new(mut data: Vec<u8>, bit_len: usize) -> Self {
+        assert!(bit_len <= data.len() * 8, "RawBits data holds fewer than {bit_len} bits");
+        data.truncate(bit_len.div_ceil(8));
+        if !bit_len.is_multiple_of(8) {
+            *data.last_mut().unwrap() &= 0xff << (8 - bit_len % 8);
+        }
+        RawBits {data, bit_len}
+    }
+    
+    /// Bytes holding the bits, most significant first; bits of the last byte past `bit_len` are zero.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+    
+    pub fn bit_len(&self) -> usize {
+        self.bit_len
+    }
+    
+    pub fn bit(&self, index: usize) -> bool {
+        assert!(index < self.bit_len, "bit {index} is out of {} bits", self.bit_len);
+        (self.data[index / 8] >> (7 - index % 8)) & 1 == 1
+    }
+}
+
+/// Collects bits in order, e.g. a path in a tree or a dictionary key.
+impl FromIterator<bool> for RawBits {
+    fn from_iter<I: IntoIterator<Item = bool>>(iter: I) -> Self {
+        let mut data = vec![];
+        let mut bit_len = 0;
+        for bit in iter {
+            if bit_len % 8 == 0 {
+                data.push(0);
+            }
+            if bit {
+                data[bit_len / 8] |= 0x80 >> (bit_len % 8);
+            }
+            bit_len += 1;
+        }
+        RawBits {data, bit_len}
+    }
+}
+
+impl CellSerialize for RawBits {
+    fn serialize(&self) -> Vec<String> {
+        (0..self.bit_len).step_by(128).map(|start| {
+            let len = (self.bit_len - start).min(128);
+            let value = (start..start + len).fold(0u128, |acc, i| (acc << 1) | self.bit(i) as u128);
+            format!("u {value} {len}bit")
+        }).collect()
+    }
+    
+    fn store(&self, builder: &mut CellBuilder) -> Result<(), CellError> {
+        builder.store_bits(&self.data, self.bit_len)?;
+        Ok(())
+    }
+    
+    fn bit_len_hint(&self) -> usize {
+        self.bit_len
+    }
+}
+
+impl CellDeserialize for RawBits {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        let bit_len = slice.remaining_bits();
+        Ok(RawBits::new(slice.load_bits(bit_len)?, bit_len))
+    }
+}
+
+/// Bit string of exactly `N` bits, as TL-B `bits N` (e.g. `FixedBits<256>` for `bits256`).
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct FixedBits<const N: usize>(pub RawBits);
+
+impl<const N: usize> CellSerialize for FixedBits<N> {
+    fn serialize(&self) -> Vec<String> {
+        assert!(self.0.bit_len() == N, "FixedBits<{N}> holds {} bits", self.0.bit_len());
+        self.0.serialize()
+    }
+    
+    fn store(&self, builder: &mut CellBuilder) -> Result<(), CellError> {
+        assert!(self.0.bit_len() == N, "FixedBits<{N}> holds {} bits", self.0.bit_len());
+        self.0.store(builder)
+    }
+    
+    fn bit_len_hint(&self) -> usize {
+        N
+    }
+}
+
+impl<const N: usize> CellDeserialize for FixedBits<N> {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        Ok(FixedBits(RawBits::new(slice.load_bits(N)?, N)))
+    }
+}
+
+/// Bit string preceded by its length in `LEN_BITS` bits, as `len:(## 9) bits:(bits len)`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct VarBits<const LEN_BITS: usize>(pub RawBits);
+
+impl<const LEN_BITS: usize> CellSerialize for VarBits<LEN_BITS> {
+    fn serialize(&self) -> Vec<String> {
+        let len = self.0.bit_len();
+        assert!(len >> LEN_BITS == 0, "VarBits<{LEN_BITS}> cannot hold {len} bits");
+        let mut result = vec![format!("u {len} {LEN_BITS}bit")];
+        result.extend(self.0.serialize());
+        result
+    }
+    
+    fn bit_len_hint(&self) -> usize {
+        LEN_BITS + self.0.bit_len()
+    }
+}
+
+impl<const LEN_BITS: usize> CellDeserialize for VarBits<LEN_BITS> {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        let len = slice.load_uint(LEN_BITS)? as usize;
+        Ok(VarBits(RawBits::new(slice.load_bits(len)?, len)))
+    }
+}
+
+/// Payload of `addr_var`: workchain with non-standard address length. Length of `address`
+/// goes before `workchain_id`, so it is not a plain [`VarBits`].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct VarAddress {
+    pub anycast: Option<Anycast>,
+    pub workchain_id: Int<32>,
+    pub address: RawBits
+}
+
+impl CellSerialize for VarAddress {
+    fn serialize(&self) -> Vec<String> {
+        let len = self.address.bit_len();
+        assert!(len >> 9 == 0, "addr_var cannot hold {len} bits");
+        let mut result = self.anycast.serialize();
+        result.push(format!("u {len} 9bit"));
+        result.extend(self.workchain_id.serialize());
+        result.extend(self.address.serialize());
+        result
+    }
+}
+
+impl CellDeserialize for VarAddress {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        let anycast = Option::<Anycast>::deserialize(slice)?;
+        let len = slice.load_uint(9)? as usize;
+        let workchain_id = Int::<32>::deserialize(slice)?;
+        let address = RawBits::new(slice.load_bits(len)?, len);
+        Ok(VarAddress {anycast, workchain_id, address})
+    }
+}
+
+/// Arbitrary cell whose contents are not interpreted, as in `body:^Cell`. Reading takes
+/// the rest of the slice, all bits and references.
+///
+/// As a field type, `Cell` holds pre-built data the crate has no type for, e.g. contract
+/// code compiled elsewhere: `^code` in a scheme stores it as a child cell, a bare `code`
+/// appends its bits and references to the current cell (so it must be the last field to
+/// read back). Either way the cell is written as is, so its hash does not change.
+impl CellSerialize for Cell {
+    fn serialize(&self) -> Vec<String> {
+        let mut result = RawBits::new(self.data().to_vec(), self.bit_len()).serialize();
+        for child in self.refs() {
+            result.push("ref {".to_owned());
+            result.extend(child.serialize());
+            result.push("}".to_owned());
+        }
+        result
+    }
+    
+    fn store(&self, builder: &mut CellBuilder) -> Result<(), CellError> {
+        builder.store_bits(self.data(), self.bit_len())?;
+        for child in self.refs() {
+            builder.store_ref(child.clone())?;
+        }
+        Ok(())
+    }
+    
+    fn bit_len_hint(&self) -> usize {
+        self.bit_len()
+    }
+}
+
+impl CellDeserialize for Cell {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        let mut builder = CellBuilder::new();
+        builder.store_remaining(slice).expect("rest of a cell fits into a cell");
+        slice.skip_bits(slice.remaining_bits())?;
+        slice.skip_refs(slice.remaining_refs())?;
+        Ok(builder.build())
+    }
+}
+
+/// Value kept serialized: the cell it was read from, deserialized only on demand. Writing
+/// stores the same cell back, so hashes of a big structure stay stable when only some of its
+/// parts are decoded and replaced. Meant for `^field`s; a bare one takes the rest of the cell,
+/// like [`Cell`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lazy<T> {
+    cell: Cell,
+    _value: std::marker::PhantomData<T>,
+}
+
+impl<T> Lazy<T> {
+    pub fn cell(&self) -> &Cell {
+        &self.cell
+    }
+    
+    /// Representation hash of the stored cell, computed when the cell was built.
+    pub fn hash(&self) -> [u8; 32] {
+        self.cell.repr_hash()
+    }
+}
+
+impl<T> From<Cell> for Lazy<T> {
+    fn from(cell: Cell) -> Self {
+        Lazy {cell, _value: std::marker::PhantomData}
+    }
+}
+
+impl<T: CellSerialize> Lazy<T> {
+    /// Serializes `value` right away.
+    pub fn new(value: &T) -> Result<Self, CellError> {
+        Ok(value.to_cell()?.into())
+    }
+    
+    /// Replaces the stored cell with serialized `value`.
+    pub fn set(&mut self, value: &T) -> Result<(), CellError> {
+        self.cell = value.to_cell()?;
+        Ok(())
+    }
+}
+
+impl<T: CellDeserialize> Lazy<T> {
+    /// Deserializes the value from the whole cell, see [`CellDeserialize::from_cell`].
+    pub fn load(&self) -> Result<T, TlbError> {
+        T::from_cell(&self.cell)
+    }
+    
+    /// Deserializes the value in the given mode, see [`CellDeserialize::from_cell_with`].
+    pub fn load_with(&self, mode: ParseMode) -> Result<T, TlbError> {
+        T::from_cell_with(&self.cell, mode)
+    }
+}
+
+impl<T> CellSerialize for Lazy<T> {
+    fn serialize(&self) -> Vec<String> {
+        self.cell.serialize()
+    }
+    
+    fn store(&self, builder: &mut CellBuilder) -> Result<(), CellError> {
+        self.cell.store(builder)
+    }
+}
+
+impl<T> CellDeserialize for Lazy<T> {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        Ok(Cell::deserialize(slice)?.into())
+    }
+}
+
+/// `BinTree X`: binary tree with values in leaves, each fork keeping its subtrees in
+/// child cells. Used for shard topology, where path to a leaf is the shard prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BinTree<T> {
+    /// `bt_leaf$0 leaf:X`.
+    Leaf(T),
+    /// `bt_fork$1 left:^(BinTree X) right:^(BinTree X)`.
+    Fork(Box<BinTree<T>>, Box<BinTree<T>>),
+}
+
+impl<T> BinTree<T> {
+    /// Leaves from left to right, each with its path from the root: `0` for left, `1` for right.
+    pub fn leaves(&self) -> Vec<(RawBits, &T)> {
+        fn walk<'t, T>(tree: &'t BinTree<T>, path: &mut Vec<bool>, result: &mut Vec<(RawBits, &'t T)>) {
+            match tree {
+                BinTree::Leaf(leaf) => result.push((path.iter().copied().collect(), leaf)),
+                BinTree::Fork(left, right) => {
+                    for (bit, subtree) in [(false, left), (true, right)] {
+                        path.push(bit);
+                        walk(subtree, path, result);
+                        path.pop();
+                    }
+                },
+            }
+        }
+        let mut result = vec![];
+        walk(self, &mut vec![], &mut result);
+        result
+    }
+}
+
+impl<T: CellSerialize> CellSerialize for BinTree<T> {
+    fn serialize(&self) -> Vec<String> {
+        match self {
+            BinTree::Leaf(leaf) => {
+                let mut result = vec!["u 0 1bit".to_owned()];
+                result.extend(leaf.serialize());
+                result
+            },
+            BinTree::Fork(left, right) => {
+                let mut result = vec!["u 1 1bit".to_owned()];
+                for subtree in [left, right] {
+                    result.push("ref {".to_owned());
+                    result.extend(subtree.serialize());
+                    result.push("}".to_owned());
+                }
+                result
+            },
+        }
+    }
+}
+
+impl<T: CellDeserialize> CellDeserialize for BinTree<T> {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        if slice.load_bit()? {
+            let left = BinTree::from_ref(slice)?;
+            let right = BinTree::from_ref(slice)?;
+            Ok(BinTree::Fork(Box::new(left), Box::new(right)))
+        } else {
+            Ok(BinTree::Leaf(T::deserialize(slice)?))
+        }
+    }
+}
+
+/// `MsgAddressInt`: address of a smart contract, source or destination of internal messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_enum_deserializable]
+#[tlb_enum_serializable]
+#[tlb_assert_unsafe(items_prefixes_nonoverlap)]
+pub enum MsgAddressInt {
+    /// `addr_std$10`, tag is stored by [`Address`] itself.
+    #[tlb_item_serializable(address)] AddrStd{address: Address},
+    /// `addr_var$11`.
+    #[tlb_item_serializable(u 3 2bit, address)] AddrVar{address: VarAddress},
+}
+impl Default for MsgAddressInt {
+    fn default() -> Self {
+        MsgAddressInt::AddrStd{address: Address::default()}
+    }
+}
+impl From<Address> for MsgAddressInt {
+    fn from(address: Address) -> Self {
+        MsgAddressInt::AddrStd{address}
+    }
+}
+
+/// `MsgAddressExt`: address outside of TON, source of inbound external messages
+/// and destination of outbound ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_enum_deserializable]
+#[tlb_enum_serializable]
+#[tlb_tag_bits(2)]
+pub enum MsgAddressExt {
+    /// `addr_none$00`.
+    #[tlb_item_serializable()] AddrNone{},
+    /// `addr_extern$01 len:(## 9) external_address:(bits len)`.
+    #[tlb_item_serializable(external_address)] AddrExtern{external_address: VarBits<9>},
+}
+impl Default for MsgAddressExt {
+    fn default() -> Self {
+        MsgAddressExt::AddrNone{}
+    }
+}
+
+/// `MsgAddress`: either kind of address; constructors of both kinds have distinct tags.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_enum_deserializable]
+#[tlb_enum_serializable]
+#[tlb_assert_unsafe(items_prefixes_nonoverlap)]
+pub enum MsgAddress {
+    #[tlb_item_serializable(address)] Int{address: MsgAddressInt},
+    #[tlb_item_serializable(address)] Ext{address: MsgAddressExt},
+}
+impl Default for MsgAddress {
+    fn default() -> Self {
+        MsgAddress::Ext{address: MsgAddressExt::default()}
+    }
+}
+impl From<Address> for MsgAddress {
+    fn from(address: Address) -> Self {
+        MsgAddress::Int{address: address.into()}
+    }
+}
+
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_enum_deserializable]
+#[tlb_enum_serializable]
+#[tlb_assert_unsafe(items_prefixes_nonoverlap)]
+// #[repr(u16)]
+pub enum CommonMsgInfo {
+    #[tlb_item_serializable(u 0 1bit,               // int_msg_info$0
+                            ihr_disabled, bounce, bounced,
+                            src, dest,
+                            value, ihr_fee, fwd_fee,
+                            created_lt, created_at)]
+    int_msg_info {
+        ihr_disabled: bool,
+        bounce: bool,
+        bounced: bool,
+        src: MsgAddressInt,
+        dest: MsgAddressInt,
+        value: CurrencyCollection,
+        ihr_fee: Coins,
+        fwd_fee: Coins,
+        created_lt: u64,
+        created_at: u32
+    },
+    #[tlb_item_serializable(u 2 2bit,               // ext_in_msg_info$10
+                            src, dest, import_fee)]
+    ext_in_msg_info {
+        src: MsgAddressExt,
+        dest: MsgAddressInt,
+        import_fee: Coins
+    },
+    #[tlb_item_serializable(u 3 2bit,               // ext_out_msg_info$11
+                            src, dest,
+                            created_lt, created_at)]
+    ext_out_msg_info {
+        src: MsgAddressInt,
+        dest: MsgAddressExt,
+        created_lt: u64,
+        created_at: u32
+    }
+}
+impl Default for CommonMsgInfo {
+    fn default() -> Self {
+        CommonMsgInfo::int_msg_info {
+            ihr_disabled: true, bounce: true, bounced: false,
+            src: Default::default(), dest: Default::default(),
+            value: Default::default(), ihr_fee: Default::default(),
+            fwd_fee: Default::default(), created_lt: 10001, created_at: 0
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_enum_deserializable]
+#[tlb_enum_serializable]
+#[tlb_tag_bits(2)]
+pub enum AccountStatus {
+    #[tlb_item_serializable()] Uninit{},
+    #[tlb_item_serializable()] Frozen{},
+    #[tlb_item_serializable()] Active{},
+    #[tlb_item_serializable()] NonExist{},
+}
+
+
+/// `IntermediateAddress`: position of a message on its route between shards.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_enum_deserializable]
+#[tlb_enum_serializable]
+#[tlb_assert_unsafe(items_prefixes_nonoverlap)]
+pub enum IntermediateAddress {
+    /// `interm_addr_regular$0 use_dest_bits:(#<= 96)`: first bits of destination already reached.
+    #[tlb_item_serializable(u 0 1bit, use_dest_bits)] Regular{use_dest_bits: Uint<7>},
+    /// `interm_addr_simple$10 workchain_id:int8 addr_pfx:uint64`.
+    #[tlb_item_serializable(u 2 2bit, workchain_id, addr_pfx)] Simple{workchain_id: Int<8>, addr_pfx: u64},
+    /// `interm_addr_ext$11 workchain_id:int32 addr_pfx:uint64`.
+    #[tlb_item_serializable(u 3 2bit, workchain_id, addr_pfx)] Ext{workchain_id: Int<32>, addr_pfx: u64},
+}
+
+/// `tick_tock$_ tick:Bool tock:Bool`: whether a special account is run at the start and
+/// at the end of each masterchain block.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(tick, tock)]
+pub struct TickTock {
+    pub tick: bool,
+    pub tock: bool,
+}
+
+/// `StateInit`: code and data a contract is deployed with. Its hash is the address of the
+/// contract, see [`StateInit::address`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(split_depth, special, ^code, ^data, ^library)]
+pub struct StateInit {
+    pub split_depth: Option<Uint<5>>,
+    pub special: Option<TickTock>,
+    pub code: Option<Cell>,
+    pub data: Option<Cell>,
+    pub library: Option<Cell>,
+}
+
+impl StateInit {
+    /// State of an ordinary contract: just code and data.
+    pub fn new(code: Cell, data: Cell) -> Self {
+        StateInit {code: Some(code), data: Some(data), ..Default::default()}
+    }
+    
+    /// Address of the contract deployed with this state into `workchain`.
+    pub fn address(&self, workchain: i8) -> Address {
+        let cell = self.to_cell().expect("StateInit takes 11 bits and 3 references at most");
+        Address::new(workchain, cell.repr_hash())
+    }
+}
+
+/// Address of the contract with given `code` and initial `data` in `workchain`, as wallets
+/// and jetton masters compute it before deploying.
+pub fn derive_address(workchain: i8, code: &Cell, data: &Cell) -> Address {
+    StateInit::new(code.clone(), data.clone()).address(workchain)
+}
+
+/// Message in transit, as stored in outbound message queues and message descriptors of blocks:
+/// routing state of the message and the message itself in a child cell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = r#"
+    msg_envelope#4 cur_addr:IntermediateAddress next_addr:IntermediateAddress
+                   fwd_fee_remaining:Grams msg:^(Message Any) = MsgEnvelope;
+"#)]
+pub struct MsgEnvelope {
+    pub cur_addr: IntermediateAddress,
+    pub next_addr: IntermediateAddress,
+    pub fwd_fee_remaining: Coins,
+    pub msg: Cell,
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn cell_of(values: &[&dyn CellSerialize]) -> Cell {
+        let mut builder = CellBuilder::new();
+        for value in values {
+            value.store(&mut builder).unwrap();
+        }
+        builder.build()
+    }
+    
+    fn bits_of(cell: &Cell) -> String {
+        (0..cell.bit_len()).map(|i| if cell.bit(i) {'1'} else {'0'}).collect()
+    }
+    
+    const FRIENDLY: &str = "EQDtFpEwcFAEcRe5mLVh2N6C0x-_hJEM7W61_JLnSF74p4q2";
+    const RAW: &str = "0:ed1691307050047117b998b561d8de82d31fbf84910ced6eb5fc92e7485ef8a7";
+    
+    #[test]
+    fn friendly_address_round_trip() {
+        let friendly: FriendlyAddress = FRIENDLY.parse().unwrap();
+        assert!(friendly.bounceable && !friendly.testnet);
+        assert_eq!(friendly.address.to_string(), RAW);
+        assert_eq!(friendly.to_string(), FRIENDLY);
+        assert_eq!(RAW.parse::<Address>(), Ok(friendly.address.clone()));
+        assert_eq!(FRIENDLY.replace('-', "+").replace('_', "/").parse::<Address>(), Ok(friendly.address.clone()));
+        
+        let non_bounceable = FriendlyAddress {bounceable: false, ..friendly.clone()};
+        assert_eq!(non_bounceable.to_string(), "UQDtFpEwcFAEcRe5mLVh2N6C0x-_hJEM7W61_JLnSF74p9dz");
+        assert_eq!(non_bounceable.to_string().parse(), Ok(non_bounceable));
+        
+        for (bounceable, text) in [(true, "kQDtFpEwcFAEcRe5mLVh2N6C0x-_hJEM7W61_JLnSF74pzE8"),
+                                   (false, "0QDtFpEwcFAEcRe5mLVh2N6C0x-_hJEM7W61_JLnSF74p2z5")] {
+            let testnet = FriendlyAddress {bounceable, testnet: true, ..friendly.clone()};
+            assert_eq!(testnet.to_string(), text);
+            assert_eq!(text.parse(), Ok(testnet));
+        }
+    }
+    
+    #[test]
+    fn crc16_xmodem_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+        assert_eq!(crc16(b""), 0);
+    }
+    
+    #[test]
+    fn token_amounts_parse_and_format() {
+        assert_eq!("1.5".parse(), Ok(TokenAmount::<6>(1_500_000)));
+        assert_eq!("0.00000001".parse(), Ok(TokenAmount::<8>(1)));
+        assert_eq!("12".parse(), Ok(TokenAmount::<9>(12_000_000_000)));
+        assert_eq!("007.050".parse(), Ok(TokenAmount::<9>(7_050_000_000)));
+        
+        assert_eq!("0.0000001".parse::<TokenAmount<6>>(), Err(TokenAmountParseError::TooManyDecimals));
+        assert_eq!("1.0000000000".parse::<TokenAmount<9>>(), Err(TokenAmountParseError::TooManyDecimals));
+        for invalid in ["1.", ".5", "", ".", "-1", "1.2.3", "1e9", " 1", "0x10"] {
+            assert_eq!(invalid.parse::<TokenAmount<9>>(), Err(TokenAmountParseError::InvalidNumber), "{invalid:?}");
+        }
+        assert_eq!(u128::MAX.to_string().parse::<TokenAmount<9>>(), Err(TokenAmountParseError::Overflow));
+        assert_eq!("1".repeat(40).parse::<TokenAmount<0>>(), Err(TokenAmountParseError::Overflow));
+        assert_eq!((u128::MAX / 1_000_000).to_string().parse::<TokenAmount<6>>(), Ok(TokenAmount((u128::MAX / 1_000_000) * 1_000_000)));
+        
+        assert_eq!(TokenAmount::<6>(1_500_000).to_string(), "1.5");
+        assert_eq!(TokenAmount::<8>(1).to_string(), "0.00000001");
+        assert_eq!(TokenAmount::<9>(12_000_000_000).to_string(), "12");
+        assert_eq!(TokenAmount::<9>(0).to_string(), "0");
+        assert_eq!(Coins::from(TokenAmount::<9>(5)), Coins(5));
+    }
+    
+    #[test]
+    fn address_parse_errors() {
+        let corrupted = FRIENDLY.replace("p4q2", "p4q3");
+        assert_eq!(corrupted.parse::<Address>(), Err(AddressParseError::ChecksumMismatch));
+        assert_eq!(FRIENDLY.replacen('E', "A", 1).parse::<Address>(), Err(AddressParseError::InvalidFlags));
+        assert_eq!(FRIENDLY.replace('-', "!").parse::<Address>(), Err(AddressParseError::InvalidBase64));
+        assert_eq!(FRIENDLY[1..].parse::<Address>(), Err(AddressParseError::UnknownFormat));
+        assert_eq!(RAW.replace("0:", "128:").parse::<Address>(), Err(AddressParseError::InvalidWorkchain));
+        assert_eq!(RAW[..60].parse::<Address>(), Err(AddressParseError::InvalidHash));
+        
+        let masterchain: Address = format!("-1:{}", "33".repeat(32)).parse().unwrap();
+        assert_eq!((masterchain.workchain(), masterchain.hash()), (-1, [0x33; 32]));
+        assert_eq!(masterchain.to_string(), format!("-1:{}", "33".repeat(32)));
+    }
+    
+    #[test]
+    fn int_boundaries_round_trip() {
+        for value in [-128, -1, 0, 127] {
+            let cell = Int::<8>(value).to_cell().unwrap();
+            assert_eq!(Int::<8>::from_cell(&cell).unwrap().0, value);
+        }
+        assert_eq!(bits_of(&Int::<8>(-128).to_cell().unwrap()), "10000000");
+        assert_eq!(bits_of(&Int::<8>(127).to_cell().unwrap()), "01111111");
+        
+        for value in [i128::MIN, -1, 0, i128::MAX] {
+            let cell = Int::<128>(value).to_cell().unwrap();
+            assert_eq!(Int::<128>::from_cell(&cell).unwrap().0, value);
+            let cell = Int::<257>(value).to_cell().unwrap();
+            assert_eq!(cell.bit_len(), 257);
+            assert_eq!(Int::<257>::from_cell(&cell).unwrap().0, value);
+        }
+    }
+    
+    #[test]
+    fn int257_sign_extension() {
+        assert_eq!(bits_of(&Int::<257>(-1).to_cell().unwrap()), "1".repeat(257));
+        assert_eq!(bits_of(&Int::<257>(1).to_cell().unwrap()), format!("{}1", "0".repeat(256)));
+    }
+    
+    #[test]
+    fn int257_out_of_i128_range() {
+        // 2^127 is a valid int257, but does not fit into i128
+        let mut builder = CellBuilder::new();
+        builder.store_uint(0, 1).unwrap().store_uint(0, 128).unwrap().store_uint(1 << 127, 128).unwrap();
+        let cell = builder.build();
+        assert_eq!(Int::<257>::from_cell(&cell).err(), Some(TlbError::InvalidValue));
+    }
+    
+    #[test]
+    fn raw_bits_take_remaining_bits() {
+        let cell = cell_of(&[&RawBits::new(vec![0xde, 0xad, 0xbe, 0xef], 28)]);
+        let bits = RawBits::from_cell(&cell).unwrap();
+        assert_eq!(bits, RawBits::new(vec![0xde, 0xad, 0xbe, 0xe0], 28));
+        assert_eq!(bits.data(), [0xde, 0xad, 0xbe, 0xe0]);
+        assert!(bits.bit(0) && !bits.bit(2));
+        
+        let mut slice = CellSlice::new(&cell);
+        assert_eq!(FixedBits::<12>::deserialize(&mut slice).unwrap().0, RawBits::new(vec![0xde, 0xa0], 12));
+        assert_eq!(RawBits::deserialize(&mut slice).unwrap().bit_len(), 16);
+        assert_eq!(FixedBits::<29>::from_cell(&cell).err(), Some(TlbError::NotEnoughBits));
+    }
+    
+    #[test]
+    fn address_of_state_init() {
+        let code = &boc::from_boc(boc!("te6cckEBAQEAcQAA3v8AIN0gggFMl7ohggEznLqxn3Gw7UTQ0x/THzHXC//jBOCk8mCDCNcYINMf0x/TH/gjE7vyY+1E0NMf0x/T/9FRMrryoVFEuvKiBPkBVBBV+RDyo/gAkyDXSpbTB9QC+wDo0QGkyMsfyx/L/8ntVBC9ba0=")).unwrap()[0];
+        // wallet v3r2 data: seqno, subwallet id and public key
+        let data = cell_of(&[&0u32, &698983191u32, &FixedBits::<256>(RawBits::new(vec![0x11; 32], 256))]);
+        let state_init = StateInit::new(code.clone(), data.clone());
+        let cell = state_init.to_cell().unwrap();
+        assert_eq!((bits_of(&cell), cell.refs().len()), ("00110".to_owned(), 2));
+        assert_eq!(StateInit::from_cell(&cell), Ok(state_init.clone()));
+        
+        let address = derive_address(0, code, &data);
+        assert_eq!(address, state_init.address(0));
+        assert_eq!(address, "0:40b9b19c392dfda4249e67be0ebcfe92907eef8a7a5f783e71841ad11e91a4cd".parse().unwrap());
+        assert_eq!(derive_address(-1, code, &data).hash(), address.hash());
+        
+        let special = StateInit {split_depth: Some(Uint(4)), special: Some(TickTock {tick: true, tock: false}), ..state_init};
+        let cell = special.to_cell().unwrap();
+        assert_eq!(bits_of(&cell), "1".to_owned() + "00100" + "1" + "10" + "110");
+        assert_eq!(StateInit::from_cell(&cell), Ok(special));
+    }
+    
+    #[test]
+    fn msg_envelope_round_trip() {
+        let envelope = MsgEnvelope {
+            cur_addr: IntermediateAddress::Regular {use_dest_bits: Uint(0)},
+            next_addr: IntermediateAddress::Simple {workchain_id: Int(-1), addr_pfx: 1 << 63},
+            fwd_fee_remaining: Coins(666_672),
+            msg: cell_of(&[&CommonMsgInfo::default()]),
+        };
+        let cell = envelope.to_cell().unwrap();
+        assert_eq!(&bits_of(&cell)[..4], "0100");
+        assert_eq!(cell.bit_len(), 4 + 8 + 2 + 8 + 64 + 4 + 24);
+        assert_eq!(*cell.refs()[0], envelope.msg);
+        assert_eq!(MsgEnvelope::from_cell(&cell), Ok(envelope));
+    }
+    
+    #[test]
+    fn struct_round_trip_and_errors() {
+        use crate::vesting::{AddWhitelist, SendMessage};
+        
+        let whitelist = AddWhitelist {query_id: 9, address: FRIENDLY.parse::<Address>().unwrap().into()};
+        let cell = whitelist.to_cell().unwrap();
+        assert_eq!(AddWhitelist::from_cell(&cell), Ok(whitelist.clone()));
+        assert_eq!(AddWhitelist::from_cell(&cell_of(&[&0x7258a69cu32, &9u64])), Err(TlbError::TagMismatch));
+        // addr_std$10 without anycast and workchain, but with no hash
+        let truncated = cell_of(&[&0x7258a69bu32, &9u64, &Uint::<3>(0b100), &0u8]);
+        assert_eq!(AddWhitelist::from_cell(&truncated), Err(TlbError::NotEnoughBits));
+        assert_eq!(AddWhitelist::from_cell(&cell_of(&[&whitelist, &true])), Err(TlbError::TrailingData));
+        
+        let mut slice = CellSlice::new(&cell);
+        assert_eq!(AddWhitelist::deserialize(&mut slice), Ok(whitelist));
+        assert!(slice.is_empty());
+        
+        let without_message = cell_of(&[&0xa7733acdu32, &1u64, &3u8]);
+        assert_eq!(SendMessage::from_cell(&without_message), Err(TlbError::NotEnoughRefs));
+    }
+    
+    #[test]
+    fn message_info_round_trip() {
+        let internal = CommonMsgInfo::int_msg_info {
+            ihr_disabled: true, bounce: false, bounced: true,
+            src: Address::new(-1, [0x11; 32]).into(), dest: RAW.parse::<Address>().unwrap().into(),
+            value: Coins(1_000_000_000).into(), ihr_fee: Coins(0), fwd_fee: Coins(666_672),
+            created_lt: 47_000_000_000_001, created_at: 1_700_000_000
+        };
+        let external = CommonMsgInfo::ext_in_msg_info {
+            src: MsgAddressExt::default(), dest: Address::new(0, [0x22; 32]).into(), import_fee: Coins(5)
+        };
+        for info in [CommonMsgInfo::default(), internal, external] {
+            assert_eq!(CommonMsgInfo::from_cell(&info.to_cell().unwrap()), Ok(info));
+        }
+    }
+    
+    #[test]
+    fn peek_tag_routes_bodies() {
+        use crate::vesting::{AddWhitelist, SendMessage};
+        
+        let cell = SendMessage {query_id: 1, send_mode: 3, message: Cell::default()}.to_cell().unwrap();
+        let slice = CellSlice::new(&cell);
+        assert_eq!(AddWhitelist::peek_tag(&slice), Ok(SendMessage::TAG));
+        assert_eq!((SendMessage::TAG, SendMessage::TAG_BITS), (0xa7733acd, 32));
+        assert_eq!(slice.remaining_bits(), 32 + 64 + 8);
+        
+        let status = cell_of(&[&AccountStatus::Frozen{}]);
+        assert_eq!(AccountStatus::peek_tag(&CellSlice::new(&status)), Ok(AccountStatus::FROZEN_TAG));
+        assert_eq!(AccountStatus::peek_tag(&CellSlice::new(&Cell::default())), Err(TlbError::NotEnoughBits));
+    }
+    
+    #[test]
+    fn partial_parse_leaves_rest_of_body() {
+        use crate::vesting::AddWhitelist;
+        
+        let address = MsgAddressInt::from(Address::new(0, [0x55; 32]));
+        let cell = AddWhitelist {query_id: 12, address: address.clone()}.to_cell().unwrap();
+        let (header, mut rest) = BodyHeader::from_cell_prefix(&cell).unwrap();
+        assert_eq!(header, BodyHeader {op: AddWhitelist::TAG as u32, query_id: 12});
+        assert_eq!(rest.remaining_bits(), 267);
+        assert_eq!(MsgAddressInt::deserialize(&mut rest), Ok(address));
+        assert_eq!(BodyHeader::from_cell_prefix(&cell_of(&[&1u32])).err(), Some(TlbError::NotEnoughBits));
+    }
+    
+    #[tlb_deserializable]
+    #[tlb_lenient]
+    #[tlb_serializable(u 0x7258a69b 32bit, query_id)]
+    struct WhitelistQuery {
+        query_id: u64,
+    }
+    
+    #[test]
+    fn lenient_parsing_ignores_rest() {
+        use crate::vesting::AddWhitelist;
+        
+        let cell = AddWhitelist {query_id: 12, address: Address::new(0, [0x55; 32]).into()}.to_cell().unwrap();
+        assert_eq!(WhitelistQuery::from_cell(&cell).map(|query| query.query_id), Ok(12));
+        assert_eq!(WhitelistQuery::from_cell_with(&cell, ParseMode::Strict).err(), Some(TlbError::TrailingData));
+        
+        let extended = cell_of(&[&cell, &true]);
+        assert_eq!(AddWhitelist::from_cell(&extended).err(), Some(TlbError::TrailingData));
+        assert!(AddWhitelist::from_cell_with(&extended, ParseMode::Lenient).is_ok());
+    }
+    
+    #[tlb_deserializable]
+    #[tlb_serializable(^query, ^header)]
+    struct QueryHolder {
+        query: WhitelistQuery,
+        header: Option<BodyHeader>,
+    }
+    
+    #[tlb_deserializable]
+    #[tlb_serializable(^holder)]
+    struct HolderRef {
+        holder: QueryHolder,
+    }
+    
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[tlb]
+    #[tlb_tag_bits(2)]
+    enum Direction {
+        #[tlb_item_serializable()] Incoming{},
+        #[tlb_item_serializable(^header)] Outgoing{header: BodyHeader},
+    }
+    
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[tlb(scheme = r#"
+        transfer_log#5ee1 direction:Direction amount:Coins comment:(Maybe ^Cell) = TransferLog;
+    "#)]
+    #[tlb_lenient]
+    struct TransferLog {
+        direction: Direction,
+        amount: Coins,
+        comment: Option<Cell>,
+    }
+    
+    #[test]
+    fn single_attribute_reads_what_it_writes() {
+        let logs = [
+            TransferLog {direction: Direction::Incoming{}, amount: Coins(0), comment: None},
+            TransferLog {
+                direction: Direction::Outgoing{header: BodyHeader {op: 0x0f8a7ea5, query_id: 9}},
+                amount: Coins(1_000_000_000),
+                comment: Some(cell_of(&[&0u32, &0x6869u16])),
+            },
+        ];
+        for log in logs {
+            let cell = log.to_cell().unwrap();
+            assert_eq!(cell.data()[..2], [0x5e, 0xe1]);
+            assert_eq!(TransferLog::from_cell(&cell), Ok(log));
+        }
+        assert_eq!(TransferLog::TAG, 0x5ee1);
+        assert!(TransferLog::TLB_SCHEME.starts_with("transfer_log#5ee1"));
+        assert_eq!(Direction::from_cell(&Direction::Incoming{}.to_cell().unwrap()), Ok(Direction::Incoming{}));
+        
+        // lenient as placed after the attribute
+        let log = TransferLog {direction: Direction::Incoming{}, amount: Coins(5), comment: None};
+        assert!(TransferLog::from_cell(&cell_of(&[&log.to_cell().unwrap(), &true])).is_ok());
+        assert_eq!(TransferLog::from_cell(&cell_of(&[&0x5ee1u16, &0xc0u8])).err(), Some(TlbError::UnknownTag {type_name: "Direction"}));
+    }
+    
+    #[test]
+    fn bit_len_hints_of_fields() {
+        let header = BodyHeader {op: 1, query_id: 2};
+        assert_eq!(header.bit_len_hint(), 96);
+        let address = Address::new(-1, [0x33; 32]);
+        assert_eq!(address.bit_len_hint(), address.to_cell().unwrap().bit_len());
+        for coins in [Coins(0), Coins(255), Coins(256), Coins(u128::MAX >> 8)] {
+            assert_eq!(coins.bit_len_hint(), coins.to_cell().unwrap().bit_len());
+        }
+        
+        // enums have no hints, and child cells take no bits but the presence one
+        let log = TransferLog {direction: Direction::Incoming{}, amount: Coins(1000), comment: Some(Cell::default())};
+        assert_eq!(log.bit_len_hint(), 16 + 4 + 16 + 1);
+        assert_eq!(log.to_cell().unwrap().bit_len(), 16 + 2 + 4 + 16 + 1);
+    }
+    
+    #[test]
+    fn parse_mode_reaches_child_cells() {
+        use crate::vesting::AddWhitelist;
+        use std::sync::Arc;
+        
+        let holder = |query: Cell, header: Cell| {
+            let mut builder = CellBuilder::new();
+            builder.store_ref(Arc::new(query)).unwrap();
+            builder.store_bit(true).unwrap().store_ref(Arc::new(header)).unwrap();
+            builder.build()
+        };
+        let query = AddWhitelist {query_id: 12, address: Address::new(0, [0x55; 32]).into()}.to_cell().unwrap();
+        let header = BodyHeader {op: 1, query_id: 2}.to_cell().unwrap();
+        let extended_header = cell_of(&[&header, &true]);
+        
+        // children follow their own modes: lenient query, strict header
+        let cell = holder(query.clone(), header.clone());
+        assert_eq!(QueryHolder::from_cell(&cell).map(|holder| holder.query.query_id), Ok(12));
+        assert_eq!(QueryHolder::from_cell_with(&cell, ParseMode::Strict).err(), Some(TlbError::TrailingData));
+        
+        let cell = holder(cell_of(&[&WhitelistQuery {query_id: 12}]), extended_header);
+        assert_eq!(QueryHolder::from_cell(&cell).err(), Some(TlbError::TrailingData));
+        let parsed = QueryHolder::from_cell_with(&cell, ParseMode::Lenient).unwrap();
+        assert_eq!(parsed.header, Some(BodyHeader {op: 1, query_id: 2}));
+        
+        // the forced mode is inherited by grandchildren as well
+        let wrap = |child: Cell| {
+            let mut builder = CellBuilder::new();
+            builder.store_ref(Arc::new(child)).unwrap();
+            builder.build()
+        };
+        let wrapped = wrap(holder(query, header));
+        assert!(HolderRef::from_cell(&wrapped).is_ok());
+        assert_eq!(HolderRef::from_cell_with(&wrapped, ParseMode::Strict).err(), Some(TlbError::TrailingData));
+        let wrapped = wrap(cell);
+        assert_eq!(HolderRef::from_cell(&wrapped).err(), Some(TlbError::TrailingData));
+        assert!(HolderRef::from_cell_with(&wrapped, ParseMode::Lenient).is_ok());
+    }
+    
+    #[test]
+    fn tagged_enum_dispatch() {
+        for status in [AccountStatus::Uninit{}, AccountStatus::Frozen{}, AccountStatus::Active{}, AccountStatus::NonExist{}] {
+            let cell = status.to_cell().unwrap();
+            assert!(AccountStatus::prefix_matches(&CellSlice::new(&cell)));
+            assert_eq!(AccountStatus::from_cell(&cell).unwrap().serialize(), status.serialize());
+        }
+        let external = MsgAddressExt::AddrExtern{external_address: VarBits(RawBits::new(vec![0xab], 8))};
+        let parsed = MsgAddressExt::from_cell(&external.to_cell().unwrap()).unwrap();
+        assert!(matches!(parsed, MsgAddressExt::AddrExtern{external_address: VarBits(ref bits)} if bits.data() == [0xab]));
+    }
+    
+    #[tlb_enum_deserializable]
+    #[tlb_enum_serializable]
+    #[tlb_tag_bits(8)]
+    enum Command {
+        #[tlb_item_serializable(amount)] Deposit{amount: Coins},
+        #[tlb(other)]
+        #[tlb_item_serializable(tag, rest)] Unknown{tag: Uint<8>, rest: Cell},
+        #[tlb_item_serializable()] Withdraw{},
+    }
+    
+    #[test]
+    fn fallback_variant_keeps_unknown_data() {
+        assert_eq!((Command::DEPOSIT_TAG, Command::WITHDRAW_TAG), (0, 1));
+        assert!(!Command::TLB_SCHEME.contains("Unknown"));
+        
+        let cell = Command::Withdraw{}.to_cell().unwrap();
+        assert!(matches!(Command::from_cell(&cell), Ok(Command::Withdraw{})));
+        
+        let cell = cell_of(&[&Uint::<8>(7), &Uint::<16>(0xabcd)]);
+        let parsed = Command::from_cell(&cell).unwrap();
+        assert!(matches!(parsed, Command::Unknown{tag: Uint(7), ref rest} if rest.bit_len() == 16));
+        assert_eq!(parsed.to_cell().unwrap(), cell);
+        assert_eq!(Command::peek_tag(&CellSlice::new(&cell)), Ok(7));
+        
+        assert_eq!(Command::from_cell(&Cell::default()).err(), Some(TlbError::NotEnoughBits));
+    }
+    
+    #[test]
+    fn nonoverlap_enum_dispatch() {
+        let address = Address::new(0, [0x11; 32]);
+        let cell = MsgAddress::from(address).to_cell().unwrap();
+        let slice = CellSlice::new(&cell);
+        assert!(MsgAddressInt::prefix_matches(&slice) && !MsgAddressExt::prefix_matches(&slice));
+        assert_eq!(slice.remaining_bits(), 267);
+        assert!(matches!(MsgAddress::from_cell(&cell), Ok(MsgAddress::Int{address: MsgAddressInt::AddrStd{..}})));
+        
+        let cell = MsgAddress::default().to_cell().unwrap();
+        assert!(matches!(MsgAddress::from_cell(&cell), Ok(MsgAddress::Ext{address: MsgAddressExt::AddrNone{}})));
+        
+        assert_eq!((CommonMsgInfo::INT_MSG_INFO_TAG, CommonMsgInfo::INT_MSG_INFO_TAG_BITS), (0, 1));
+        assert_eq!((CommonMsgInfo::EXT_OUT_MSG_INFO_TAG, CommonMsgInfo::EXT_OUT_MSG_INFO_TAG_BITS), (3, 2));
+        assert_eq!((MsgAddressInt::ADDR_VAR_TAG, MsgAddressInt::ADDR_VAR_TAG_BITS), (3, 2));
+        
+        let cell = cell_of(&[&Uint::<2>(0)]);
+        assert_eq!(MsgAddressInt::from_cell(&cell).err(), Some(TlbError::UnknownTag {type_name: "MsgAddressInt"}));
+    }
+    
+    #[test]
+    fn nonoverlap_enum_keeps_errors_of_chosen_variant() {
+        // int_msg_info$0 with non-empty extra currencies, which `CurrencyCollection` rejects
+        let mut builder = CellBuilder::new();
+        builder.store_uint(0b0100, 4).unwrap();
+        Address::default().store(&mut builder).unwrap();
+        Address::default().store(&mut builder).unwrap();
+        Coins(5).store(&mut builder).unwrap();
+        builder.store_bit(true).unwrap();
+        let cell = builder.build();
+        assert_eq!(CommonMsgInfo::from_cell(&cell).err(), Some(TlbError::TagMismatch));
+        
+        let info = CommonMsgInfo::default();
+        let parsed = CommonMsgInfo::from_cell(&info.to_cell().unwrap()).unwrap();
+        assert_eq!(parsed.serialize(), info.serialize());
+    }
+    
+    #[tlb_deserializable]
+    #[tlb_serializable(flag, ^payload)]
+    struct MaybeRef {
+        flag: bool,
+        payload: Option<Cell>,
+    }
+    
+    #[tlb_deserializable]
+    #[tlb_serializable(scheme = "maybe_ref$_ flag:Bool payload:(Maybe ^Cell) = MaybeRef;")]
+    struct MaybeRefText {
+        flag: bool,
+        payload: Option<Cell>,
+    }
+    
+    #[test]
+    fn maybe_ref_keeps_presence_bit_inline() {
+        let payload = cell_of(&[&0xabcdu32]);
+        let value = MaybeRef {flag: true, payload: Some(payload.clone())};
+        let cell = value.to_cell().unwrap();
+        assert_eq!(bits_of(&cell), "11");
+        assert_eq!(*cell.refs()[0], payload);
+        assert_eq!(Cell::from_legacy_commands(&value.serialize()), Ok(cell.clone()));
+        assert_eq!(value.size_report().unwrap().parts, [("flag", 1, 0), ("^payload", 1, 1)]);
+        assert_eq!(value.size_report().unwrap().total_cells(), 2);
+        
+        let parsed = MaybeRefText::from_cell(&cell).unwrap();
+        assert!(parsed.flag && parsed.payload == Some(payload));
+        
+        let cell = MaybeRef {flag: false, payload: None}.to_cell().unwrap();
+        assert_eq!((bits_of(&cell), cell.refs().len()), ("00".to_owned(), 0));
+        assert!(MaybeRefText::from_cell(&cell).unwrap().payload.is_none());
+        
+        assert_eq!(MaybeRef::TLB_SCHEME, "_ flag:bool payload:(Maybe ^Cell) = MaybeRef;");
+        assert_eq!(MaybeRefText::TLB_SCHEME, "maybe_ref$_ flag:Bool payload:(Maybe ^Cell) = MaybeRef;");
+    }
+    
+    #[tlb_deserializable]
+    #[tlb_serializable(u 0b00110 5bit, ^code, ^data)]
+    struct CodeAndData {
+        code: Cell,
+        data: Cell,
+    }
+    
+    #[tlb_deserializable]
+    #[tlb_serializable(u 1 8bit, ^header)]
+    struct LazyHeader {
+        header: Lazy<BodyHeader>,
+    }
+    
+    #[test]
+    fn lazy_value_keeps_its_cell() {
+        use std::sync::Arc;
+        
+        // header with bits this version does not know about
+        let mut child = CellBuilder::new();
+        BodyHeader {op: 0x0f8a7ea5, query_id: 9}.store(&mut child).unwrap();
+        child.store_uint(0b101, 3).unwrap();
+        let child = child.build();
+        let mut outer = CellBuilder::new();
+        outer.store_uint(1, 8).unwrap().store_ref(Arc::new(child.clone())).unwrap();
+        let outer = outer.build();
+        
+        let lazy = LazyHeader::from_cell(&outer).unwrap();
+        assert_eq!(lazy.header.hash(), child.repr_hash());
+        assert_eq!(lazy.header.load(), Err(TlbError::TrailingData));
+        assert_eq!(lazy.header.load_with(ParseMode::Lenient), Ok(BodyHeader {op: 0x0f8a7ea5, query_id: 9}));
+        // written back unchanged, extra bits included
+        assert_eq!(lazy.to_cell().unwrap().repr_hash(), outer.repr_hash());
+        
+        let mut edited = lazy;
+        edited.header.set(&BodyHeader {op: 1, query_id: 2}).unwrap();
+        let reread = LazyHeader::from_cell(&edited.to_cell().unwrap()).unwrap();
+        assert_eq!(reread.header.load(), Ok(BodyHeader {op: 1, query_id: 2}));
+        assert_eq!(reread.header, Lazy::new(&BodyHeader {op: 1, query_id: 2}).unwrap());
+    }
+    
+    #[test]
+    fn prebuilt_code_cell_round_trips_unchanged() {
+        use std::sync::Arc;
+        
+        // two-level code with a shared library-like child, as a compiler would emit it
+        let code = Cell::parse_fift("x{FF00F4A413F4BCF2C80B}").unwrap();
+        let shared = Arc::new(Cell::parse_fift("x{D4}").unwrap());
+        let mut builder = CellBuilder::new();
+        code.store(&mut builder).unwrap();
+        builder.store_ref(shared.clone()).unwrap().store_ref(shared).unwrap();
+        let code = builder.build();
+        let data = cell_of(&[&0u32, &698983191u32]);
+        
+        let value = CodeAndData {code: code.clone(), data: data.clone()};
+        let cell = value.to_cell().unwrap();
+        assert_eq!(bits_of(&cell), "00110");
+        assert_eq!((*cell.refs()[0] == code, *cell.refs()[1] == data), (true, true));
+        assert_eq!(boc::to_boc(&cell.refs()[0], false, false), boc::to_boc(&code, false, false));
+        
+        let parsed = CodeAndData::from_cell(&cell).unwrap();
+        assert!(parsed.code == code && parsed.data == data);
+        assert_eq!(parsed.code.stats(), code.stats());
+        assert_eq!(parsed.to_cell(), Ok(cell));
+    }
+    
+    #[test]
+    fn size_report_measures_stored_parts() {
+        use std::sync::Arc;
+        
+        let shared = Arc::new(cell_of(&[&7u8]));
+        let mut msg = CellBuilder::new();
+        msg.store_ref(shared.clone()).unwrap().store_ref(shared).unwrap();
+        let envelope = MsgEnvelope {
+            cur_addr: IntermediateAddress::Regular {use_dest_bits: Uint(0)},
+            next_addr: IntermediateAddress::Simple {workchain_id: Int(-1), addr_pfx: 1 << 63},
+            fwd_fee_remaining: Coins(666_672),
+            msg: msg.build(),
+        };
+        let report = envelope.size_report().unwrap();
+        // the message cell and the child it references twice
+        assert_eq!(report.parts, [("#4", 4, 0), ("cur_addr", 8, 0), ("next_addr", 74, 0),
+                                  ("fwd_fee_remaining", 28, 0), ("^msg", 0, 2)]);
+        assert_eq!(report.total_bits(), envelope.to_cell().unwrap().bit_len());
+        assert_eq!(report.total_cells(), envelope.to_cell().unwrap().stats().cells);
+        
+        let report = IntermediateAddress::Ext {workchain_id: Int(0), addr_pfx: 0}.size_report().unwrap();
+        assert_eq!(report.parts, [("$11", 2, 0), ("workchain_id", 32, 0), ("addr_pfx", 64, 0)]);
+        assert_eq!(Coins(1).size_report().unwrap().parts, [("(VarUInteger 16)", 12, 0)]);
+    }
+    
+    #[cfg(feature = "json-schema")]
+    #[test]
+    fn json_schema_describes_hand_written_types_inline() {
+        assert!(Address::JSON_SCHEMA.contains(r#""anycast":{"anyOf":[{"type":"string","pattern":"^[01]*$","minLength":1,"maxLength":30}"#));
+        assert!(!MsgAddressInt::JSON_SCHEMA.contains("$defs/VarAddress"));
+        assert!(!MsgAddressExt::JSON_SCHEMA.contains("$defs/VarBits"));
+        assert!(!MaybeRef::JSON_SCHEMA.contains("$defs/Cell"));
+    }
+    
+    #[test]
+    fn cell_macro_types_literals_by_width() {
+        let query_id = 7u64;
+        let cell = cell! {
+            u32: 0xffffffff, u64: query_id, u2: 3, i64: -0x7fffffffffffffff, i8: -1, coins: 5_000_000_000,
+        }.unwrap();
+        let expected = cell_of(&[&u32::MAX, &7u64, &Uint::<2>(3), &Int::<64>(-0x7fffffffffffffff), &Int::<8>(-1),
+                                 &Coins(5_000_000_000)]);
+        assert_eq!(cell, expected);
+    }
+    
+    #[test]
+    fn cell_macro_builds_child_cells() {
+        let body = cell! {u32: 0x5fcc3d14}.unwrap();
+        let cell = cell! {u8: 1, ref: body.clone(), ref: Coins(3)}.unwrap();
+        assert_eq!(cell.bit_len(), 8);
+        assert_eq!((&*cell.refs()[0], &*cell.refs()[1]), (&body, &Coins(3).to_cell().unwrap()));
+        assert_eq!(cell! {Int<257>: Int(-1), Int<257>: Int(-1), Int<257>: Int(-1), Int<257>: Int(-1)},
+                   Err(CellError::BitsOverflow));
+    }
+    
+    #[test]
+    #[should_panic(expected = "value does not fit in 2 bits")]
+    fn cell_macro_checks_width() {
+        let _ = cell! {u2: 4};
+    }
+    
+    #[test]
+    #[should_panic(expected = "Int<8> overflow")]
+    fn int_overflow_panics() {
+        Int::<8>(128).serialize();
+    }
+}
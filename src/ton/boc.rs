@@ -0,0 +1,594 @@
+// (c) ProgramCrafter, 2024
+
+//! Bag-of-cells encoding, the format cells are sent to the network in.
+//!
+//! Encoder emits generic `serialized_boc#b5ee9c72`; decoder also accepts the older
+//! indexed formats `serialized_boc_idx#68ff65f3` and `serialized_boc_idx_crc32c#acc3a728`.
+//!
+//! Cells left out of a bag are stored as absent: by hashes and depths alone. They are read
+//! into [`CellType::Absent`] placeholders, so parents keep their hashes. Diffs between trees
+//! are bags like this, see [`to_boc_diff`].
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use super::cell::{Cell, CellBuilder, CellType};
+use super::crc32c::crc32c_software;
+
+
+/// Reason why bytes could not be decoded as bag of cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BocError {
+    /// Data does not start with any of BOC magic numbers.
+    InvalidMagic,
+    /// Data ends before the structure described by header.
+    Truncated,
+    /// Header has invalid sizes or counts (including count of absent cells), or refers
+    /// to missing cells.
+    InvalidHeader,
+    /// Cell has invalid descriptor or completion tag, or refers to a cell not following it.
+    InvalidCell,
+    /// Exotic cell (pruned branch, library reference, Merkle proof or update) has unknown
+    /// type or data not matching its type.
+    ExoticCell,
+    /// CRC32C stored in BOC does not match its contents.
+    ChecksumMismatch,
+    /// Data continues after the end of BOC.
+    TrailingData,
+    /// Diff refers to a cell the tree it is applied to does not have.
+    MissingCell,
+}
+
+impl std::fmt::Display for BocError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            BocError::InvalidMagic => "data is not a bag of cells",
+            BocError::Truncated => "bag of cells is truncated",
+            BocError::InvalidHeader => "invalid bag of cells header",
+            BocError::InvalidCell => "invalid cell in bag of cells",
+            BocError::ExoticCell => "invalid exotic cell in bag of cells",
+            BocError::ChecksumMismatch => "checksum mismatch in bag of cells",
+            BocError::TrailingData => "extra data after bag of cells",
+            BocError::MissingCell => "diff refers to a cell missing from the base tree",
+        })
+    }
+}
+
+impl std::error::Error for BocError {}
+
+
+/// CRC32C (Castagnoli) checksum, as stored at the end of BOC. Uses CRC instructions of
+/// SSE 4.2 or ARMv8 if the processor has them, which matters for block-sized bags.
+pub fn crc32c(data: &[u8]) -> u32 {
+    #[cfg(target_arch = "x86_64")]
+    if std::arch::is_x86_feature_detected!("sse4.2") {
+        // SAFETY: instructions are supported, as just checked
+        return unsafe {crc32c_sse42(data)};
+    }
+    #[cfg(target_arch = "aarch64")]
+    if std::arch::is_aarch64_feature_detected!("crc") {
+        // SAFETY: instructions are supported, as just checked
+        return unsafe {crc32c_armv8(data)};
+    }
+    crc32c_software(data)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.2")]
+unsafe fn crc32c_sse42(data: &[u8]) -> u32 {
+    use std::arch::x86_64::{_mm_crc32_u64, _mm_crc32_u8};
+    
+    let mut chunks = data.chunks_exact(8);
+    let mut crc = !0u32 as u64;
+    for chunk in &mut chunks {
+        crc = _mm_crc32_u64(crc, u64::from_le_bytes(chunk.try_into().unwrap()));
+    }
+    let mut crc = crc as u32;
+    for &byte in chunks.remainder() {
+        crc = _mm_crc32_u8(crc, byte);
+    }
+    !crc
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "crc")]
+unsafe fn crc32c_armv8(data: &[u8]) -> u32 {
+    use std::arch::aarch64::{__crc32cb, __crc32cd};
+    
+    let mut chunks = data.chunks_exact(8);
+    let mut crc = !0u32;
+    for chunk in &mut chunks {
+        crc = __crc32cd(crc, u64::from_le_bytes(chunk.try_into().unwrap()));
+    }
+    for &byte in chunks.remainder() {
+        crc = __crc32cb(crc, byte);
+    }
+    !crc
+}
+
+/// Lists cells reachable from `root` so that every cell comes before its children.
+/// Cells shared through the same `Arc` are listed once.
+fn order_cells(root: &Cell) -> Vec<&Cell> {
+    fn visit<'a>(cell: &'a Cell, visited: &mut HashMap<*const Cell, ()>, post_order: &mut Vec<&'a Cell>) {
+        for child in cell.refs() {
+            if visited.insert(Arc::as_ptr(child), ()).is_none() {
+                visit(child, visited, post_order);
+            }
+        }
+        post_order.push(cell);
+    }
+    
+    let mut post_order = vec![];
+    visit(root, &mut HashMap::new(), &mut post_order);
+    post_order.reverse();
+    post_order
+}
+
+/// Minimal number of bytes holding `value`, but at least one.
+fn bytes_for(value: usize) -> usize {
+    ((usize::BITS - value.leading_zeros()) as usize).div_ceil(8).max(1)
+}
+
+fn push_uint(out: &mut Vec<u8>, value: usize, bytes: usize) {
+    out.extend_from_slice(&value.to_be_bytes()[size_of::<usize>() - bytes..]);
+}
+
+/// Encodes cell tree with single root into BOC, optionally with index of cell offsets
+/// and CRC32C checksum.
+pub fn to_boc(root: &Cell, has_idx: bool, has_crc32c: bool) -> Vec<u8> {
+    let cells = order_cells(root);
+    let indices: HashMap<*const Cell, usize> = cells.iter().enumerate()
+        .map(|(index, &cell)| (cell as *const Cell, index))
+        .collect();
+    let size = bytes_for(cells.len());
+    
+    let mut cell_data = vec![];
+    let mut offsets = vec![];
+    let mut absent = 0;
+    for cell in &cells {
+        cell_data.extend_from_slice(&cell.descriptors());
+        if cell.cell_type() == CellType::Absent {
+            absent += 1;
+            let levels = (0..=cell.level()).filter(|&level| level == 0 || cell.level_mask() >> (level - 1) & 1 == 1);
+            cell_data.extend(levels.clone().flat_map(|level| cell.hash(level)));
+            cell_data.extend(levels.flat_map(|level| cell.depth(level).to_be_bytes()));
+        }
+        cell_data.extend(cell.padded_data());
+        for child in cell.refs() {
+            push_uint(&mut cell_data, indices[&Arc::as_ptr(child)], size);
+        }
+        offsets.push(cell_data.len());
+    }
+    let off_bytes = bytes_for(cell_data.len());
+    
+    let mut out = vec![0xb5, 0xee, 0x9c, 0x72];
+    out.push((has_idx as u8) << 7 | (has_crc32c as u8) << 6 | size as u8);
+    out.push(off_bytes as u8);
+    push_uint(&mut out, cells.len(), size);
+    push_uint(&mut out, 1, size);                   // roots
+    push_uint(&mut out, absent, size);
+    push_uint(&mut out, cell_data.len(), off_bytes);
+    push_uint(&mut out, 0, size);                   // root index
+    if has_idx {
+        for offset in offsets {
+            push_uint(&mut out, offset, off_bytes);
+        }
+    }
+    out.extend(cell_data);
+    if has_crc32c {
+        let crc = crc32c(&out);
+        out.extend_from_slice(&crc.to_le_bytes());
+    }
+    out
+}
+
+/// [`to_boc`] with CRC32C and without index, in base64 with padding: the form HTTP APIs take
+/// bags of cells in, e.g. `boc` of toncenter `sendBoc`.
+pub fn to_boc_base64(root: &Cell) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let boc = to_boc(root, false, true);
+    let mut out = String::with_capacity(boc.len().div_ceil(3) * 4);
+    for chunk in boc.chunks(3) {
+        let acc = chunk.iter().enumerate().fold(0u32, |acc, (i, &byte)| acc | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            out.push(if i <= chunk.len() {ALPHABET[(acc >> (18 - 6 * i)) as usize & 63] as char} else {'='});
+        }
+    }
+    out
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], BocError> {
+        let end = self.pos.checked_add(count).filter(|&end| end <= self.data.len()).ok_or(BocError::Truncated)?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+    
+    fn uint(&mut self, size: usize) -> Result<usize, BocError> {
+        Ok(self.bytes(size)?.iter().fold(0, |acc, &b| (acc << 8) | b as usize))
+    }
+}
+
+/// Cell as stored in BOC: descriptor, data with its length in bits and indices of children;
+/// or level mask, hashes and depths of an absent cell.
+enum RawCell<'a> {
+    Present(usize, &'a [u8], usize, Vec<usize>),
+    Absent(u8, Vec<[u8; 32]>, Vec<u16>),
+}
+
+/// Decodes bag of cells, returning its roots in order. Absent cells are returned as
+/// placeholders, see [`Cell::absent`].
+pub fn from_boc(data: &[u8]) -> Result<Vec<Cell>, BocError> {
+    let mut reader = Reader {data, pos: 0};
+    let (has_idx, has_crc32c, size, off_bytes, has_root_list) = match reader.bytes(4)? {
+        [0xb5, 0xee, 0x9c, 0x72] => {
+            let flags = reader.uint(1)?;
+            (flags & 0x80 != 0, flags & 0x40 != 0, flags & 7, reader.uint(1)?, true)
+        },
+        [0x68, 0xff, 0x65, 0xf3] => (true, false, reader.uint(1)?, reader.uint(1)?, false),
+        [0xac, 0xc3, 0xa7, 0x28] => (true, true, reader.uint(1)?, reader.uint(1)?, false),
+        _ => return Err(BocError::InvalidMagic),
+    };
+    if !(1..=4).contains(&size) || !(1..=8).contains(&off_bytes) {
+        return Err(BocError::InvalidHeader);
+    }
+    
+    let cell_count = reader.uint(size)?;
+    let root_count = reader.uint(size)?;
+    let absent = reader.uint(size)?;
+    let tot_cells_size = reader.uint(off_bytes)?;
+    if root_count == 0 || root_count > cell_count || absent > cell_count || (!has_root_list && root_count != 1) {
+        return Err(BocError::InvalidHeader);
+    }
+    let roots = if has_root_list {
+        (0..root_count).map(|_| reader.uint(size)).collect::<Result<Vec<_>, _>>()?
+    } else {
+        vec![0]
+    };
+    if roots.iter().any(|&root| root >= cell_count) {
+        return Err(BocError::InvalidHeader);
+    }
+    if has_idx {
+        // offsets (and cache bits in them) are of no use when reading all cells sequentially
+        reader.bytes(cell_count.checked_mul(off_bytes).ok_or(BocError::InvalidHeader)?)?;
+    }
+    
+    // Cells refer only to following ones, so they are built from the end
+    let cells_start = reader.pos;
+    let mut raw_cells = Vec::with_capacity(cell_count.min(data.len()));
+    let mut absent_found = 0;
+    for index in 0..cell_count {
+        let descriptor = reader.bytes(2)?;
+        let (d1, d2) = (descriptor[0] as usize, descriptor[1] as usize);
+        let is_absent = d1 & 7 == 7;
+        if (d1 & 7 > 4 && !is_absent) || (is_absent && (d1 & 0x18 != 0x10 || d2 != 0)) {
+            return Err(BocError::InvalidCell);
+        }
+        let mut stored = None;
+        if d1 & 16 != 0 {
+            // stored hashes and depths, one per significant level; only absent cells need them
+            let hash_count = (d1 >> 5).count_ones() as usize + 1;
+            let hashes = reader.bytes(hash_count * 32)?.chunks(32).map(|hash| hash.try_into().unwrap()).collect();
+            let depths = reader.bytes(hash_count * 2)?.chunks(2).map(|depth| u16::from_be_bytes(depth.try_into().unwrap())).collect();
+            stored = Some((hashes, depths));
+        }
+        if is_absent {
+            absent_found += 1;
+            let (hashes, depths) = stored.unwrap();
+            raw_cells.push(RawCell::Absent((d1 >> 5) as u8, hashes, depths));
+            continue;
+        }
+        
+        let cell_data = reader.bytes(d2.div_ceil(2))?;
+        let bit_len = match cell_data.last() {
+            Some(0) if d2 % 2 == 1 => return Err(BocError::InvalidCell),
+            Some(&last) if d2 % 2 == 1 => cell_data.len() * 8 - 1 - last.trailing_zeros() as usize,
+            _ => cell_data.len() * 8,
+        };
+        let refs = (0..d1 & 7).map(|_| reader.uint(size)).collect::<Result<Vec<_>, _>>()?;
+        if refs.iter().any(|&child| child <= index || child >= cell_count) {
+            return Err(BocError::InvalidCell);
+        }
+        raw_cells.push(RawCell::Present(d1, cell_data, bit_len, refs));
+    }
+    if reader.pos - cells_start != tot_cells_size || absent_found != absent {
+        return Err(BocError::InvalidHeader);
+    }
+    
+    if has_crc32c {
+        let expected = crc32c(&data[..reader.pos]);
+        let stored = u32::from_le_bytes(reader.bytes(4)?.try_into().unwrap());
+        if stored != expected {
+            return Err(BocError::ChecksumMismatch);
+        }
+    }
+    if reader.pos != data.len() {
+        return Err(BocError::TrailingData);
+    }
+    
+    let mut cells: Vec<Option<Arc<Cell>>> = vec![None; cell_count];
+    for (index, raw_cell) in raw_cells.into_iter().enumerate().rev() {
+        let (d1, cell_data, bit_len, refs) = match raw_cell {
+            RawCell::Present(d1, cell_data, bit_len, refs) => (d1, cell_data, bit_len, refs),
+            RawCell::Absent(level_mask, hashes, depths) => {
+                cells[index] = Some(Arc::new(Cell::absent_at_levels(level_mask, hashes, depths)));
+                continue;
+            },
+        };
+        let mut builder = CellBuilder::new();
+        builder.store_bits(cell_data, bit_len).map_err(|_| BocError::InvalidCell)?;
+        for child in refs {
+            let child = cells[child].clone().expect("children are built before parents");
+            builder.store_ref(child).map_err(|_| BocError::InvalidCell)?;
+        }
+        let cell = if d1 & 8 != 0 {
+            builder.build_exotic().map_err(|_| BocError::ExoticCell)?
+        } else {
+            builder.build()
+        };
+        if cell.level_mask() as usize != d1 >> 5 {
+            return Err(BocError::InvalidCell);
+        }
+        cells[index] = Some(Arc::new(cell));
+    }
+    Ok(roots.into_iter().map(|root| Cell::clone(cells[root].as_ref().unwrap())).collect())
+}
+
+
+/// Encodes the changes turning tree `old` into tree `new`: BOC of `new` where subtrees also
+/// present in `old` are absent cells. Only cells on paths to changed data are stored, so
+/// after a small change to a large state the diff is small; [`apply_boc_diff`] restores
+/// `new` from it and `old`.
+pub fn to_boc_diff(old: &Cell, new: &Cell) -> Vec<u8> {
+    fn replace(cell: &Arc<Cell>, old: &HashSet<[u8; 32]>, done: &mut HashMap<*const Cell, Arc<Cell>>) -> Arc<Cell> {
+        if let Some(replaced) = done.get(&Arc::as_ptr(cell)) {
+            return replaced.clone();
+        }
+        let replaced = if old.contains(&cell.repr_hash()) {
+            Arc::new(cell.to_absent())
+        } else {
+            let refs = cell.refs().iter().map(|child| replace(child, old, done)).collect();
+            Arc::new(cell.with_refs(refs))
+        };
+        done.insert(Arc::as_ptr(cell), replaced.clone());
+        replaced
+    }
+    
+    let old = order_cells(old).into_iter().map(Cell::repr_hash).collect();
+    to_boc(&replace(&Arc::new(new.clone()), &old, &mut HashMap::new()), false, true)
+}
+
+/// Applies `diff` made by [`to_boc_diff`] to `old`, returning the new tree. Fails with
+/// [`BocError::MissingCell`] if `old` is not the tree the diff was made against (or has
+/// no cell the diff needs from it).
+pub fn apply_boc_diff(old: &Cell, diff: &[u8]) -> Result<Cell, BocError> {
+    fn restore(cell: &Arc<Cell>, old: &HashMap<[u8; 32], Arc<Cell>>,
+               done: &mut HashMap<*const Cell, Arc<Cell>>) -> Result<Arc<Cell>, BocError> {
+        if let Some(restored) = done.get(&Arc::as_ptr(cell)) {
+            return Ok(restored.clone());
+        }
+        let restored = if cell.cell_type() == CellType::Absent {
+            old.get(&cell.repr_hash()).ok_or(BocError::MissingCell)?.clone()
+        } else {
+            let refs = cell.refs().iter().map(|child| restore(child, old, done)).collect::<Result<_, _>>()?;
+            Arc::new(cell.with_refs(refs))
+        };
+        done.insert(Arc::as_ptr(cell), restored.clone());
+        Ok(restored)
+    }
+    
+    let mut index = HashMap::new();
+    let mut pending = vec![Arc::new(old.clone())];
+    while let Some(cell) = pending.pop() {
+        pending.extend(cell.refs().iter().cloned());
+        index.entry(cell.repr_hash()).or_insert(cell);
+    }
+    let new = from_boc(diff)?.pop().ok_or(BocError::InvalidHeader)?;
+    let restored = restore(&Arc::new(new), &index, &mut HashMap::new())?;
+    Ok(Cell::clone(&restored))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tlb_macro::boc;
+    
+    fn tree_with_shared_child() -> Cell {
+        let mut leaf = CellBuilder::new();
+        leaf.store_uint(0xabc, 12).unwrap();
+        let leaf = Arc::new(leaf.build());
+        let mut root = CellBuilder::new();
+        root.store_uint(1, 1).unwrap().store_ref(leaf.clone()).unwrap().store_ref(leaf).unwrap();
+        root.build()
+    }
+    
+    /// Rewrites generic BOC with index into `serialized_boc_idx` form, which has no flags byte
+    /// and no root list.
+    fn to_indexed(generic: &[u8], magic: [u8; 4]) -> Vec<u8> {
+        let size = (generic[4] & 7) as usize;
+        let off_bytes = generic[5] as usize;
+        let root_list = 6 + 3 * size + off_bytes;
+        let has_crc32c = generic[4] & 0x40 != 0;
+        let end = generic.len() - if has_crc32c {4} else {0};
+        
+        let mut out = magic.to_vec();
+        out.push(size as u8);
+        out.extend_from_slice(&generic[5..root_list]);
+        out.extend_from_slice(&generic[root_list + size..end]);
+        if has_crc32c {
+            let crc = crc32c(&out);
+            out.extend_from_slice(&crc.to_le_bytes());
+        }
+        out
+    }
+    
+    #[test]
+    fn crc32c_matches_software_fallback() {
+        assert_eq!(crc32c(b"123456789"), 0xe3069283);
+        let data: Vec<u8> = (0..100u32).map(|i| (i * 37 % 251) as u8).collect();
+        for len in 0..data.len() {
+            assert_eq!(crc32c(&data[..len]), crc32c_software(&data[..len]), "{len} bytes");
+        }
+    }
+    
+    #[test]
+    fn known_empty_cell() {
+        assert_eq!(to_boc(&Cell::default(), false, true), boc!("te6cckEBAQEAAgAAAEysuc0="));
+        assert_eq!(from_boc(boc!("te6cckEBAQEAAgAAAEysuc0=")), Ok(vec![Cell::default()]));
+        assert_eq!(to_boc_base64(&Cell::default()), "te6cckEBAQEAAgAAAEysuc0=");
+    }
+    
+    #[test]
+    fn round_trip_lists_shared_cells_once() {
+        let root = tree_with_shared_child();
+        for (has_idx, has_crc32c) in [(false, false), (true, false), (false, true), (true, true)] {
+            let boc = to_boc(&root, has_idx, has_crc32c);
+            assert_eq!(boc[6], 2, "cell count");
+            assert_eq!(from_boc(&boc), Ok(vec![root.clone()]));
+        }
+    }
+    
+    #[test]
+    fn reads_indexed_formats() {
+        let root = tree_with_shared_child();
+        let idx = to_indexed(&to_boc(&root, true, false), [0x68, 0xff, 0x65, 0xf3]);
+        assert_eq!(from_boc(&idx), Ok(vec![root.clone()]));
+        let idx_crc32c = to_indexed(&to_boc(&root, true, true), [0xac, 0xc3, 0xa7, 0x28]);
+        assert_eq!(from_boc(&idx_crc32c), Ok(vec![root]));
+    }
+    
+    #[test]
+    fn rejects_damaged_boc() {
+        let boc = to_boc(&tree_with_shared_child(), false, true);
+        for len in [0, 3, 7, boc.len() - 1] {
+            assert_eq!(from_boc(&boc[..len]), Err(BocError::Truncated), "truncated to {len} bytes");
+        }
+        let mut bad_crc = boc.clone();
+        *bad_crc.last_mut().unwrap() ^= 1;
+        assert_eq!(from_boc(&bad_crc), Err(BocError::ChecksumMismatch));
+        let mut extended = boc.clone();
+        extended.push(0);
+        assert_eq!(from_boc(&extended), Err(BocError::TrailingData));
+        assert_eq!(from_boc(&[0xde, 0xad, 0xbe, 0xef, 0, 0]), Err(BocError::InvalidMagic));
+    }
+    
+    #[test]
+    fn round_trip_of_merkle_proof() {
+        let leaf = tree_with_shared_child().refs()[0].clone();
+        let mut pruned = CellBuilder::new();
+        pruned.store_uint(0x0101, 16).unwrap().store_bits(&leaf.repr_hash(), 256).unwrap().store_uint(0, 16).unwrap();
+        let pruned = Arc::new(pruned.build_exotic().unwrap());
+        let mut partial = CellBuilder::new();
+        partial.store_uint(1, 1).unwrap().store_ref(pruned.clone()).unwrap().store_ref(leaf).unwrap();
+        let partial = Arc::new(partial.build());
+        let mut proof = CellBuilder::new();
+        proof.store_uint(3, 8).unwrap().store_bits(&partial.hash(0), 256).unwrap();
+        proof.store_uint(partial.depth(0) as u128, 16).unwrap().store_ref(partial.clone()).unwrap();
+        let proof = proof.build_exotic().unwrap();
+        
+        let boc = to_boc(&proof, false, true);
+        // proof, partial tree (level 1), pruned branch (exotic, level 1) and leaf
+        assert_eq!(boc[6], 4, "cell count");
+        assert_eq!(&boc[11..13], [0x09, 70]);
+        assert_eq!(from_boc(&boc), Ok(vec![proof.clone()]));
+        assert_eq!(from_boc(&boc).unwrap()[0].refs()[0].hash(0), tree_with_shared_child().repr_hash());
+        
+        // pruned branch without its exotic flag, with a level mask it does not have,
+        // or of unknown exotic type
+        let damages = [(0, 0x20, BocError::InvalidCell), (0, 0x48, BocError::InvalidCell),
+                       (0, 0x08, BocError::InvalidCell), (2, 0x05, BocError::ExoticCell)];
+        for (offset, byte, error) in damages {
+            let mut damaged = to_boc(&proof, false, false);
+            // pruned branch is listed last, taking 2 + 36 bytes
+            let pruned_at = damaged.len() - 38;
+            assert_eq!(damaged[pruned_at..pruned_at + 4], [0x28, 72, 1, 1]);
+            damaged[pruned_at + offset] = byte;
+            assert_eq!(from_boc(&damaged), Err(error), "{byte:#x} at {offset}");
+        }
+    }
+    
+    #[test]
+    fn absent_cells_keep_hashes_of_parents() {
+        let original = tree_with_shared_child();
+        let leaf = &original.refs()[0];
+        let absent = Arc::new(Cell::absent(leaf.repr_hash(), leaf.repr_depth()));
+        let mut root = CellBuilder::new();
+        root.store_uint(1, 1).unwrap().store_ref(absent.clone()).unwrap().store_ref(absent).unwrap();
+        let root = root.build();
+        assert_eq!(root.repr_hash(), original.repr_hash());
+        
+        let boc = to_boc(&root, false, false);
+        assert_eq!(boc[8], 1, "absent count");
+        // absent cell is listed last: 7 references and hashes flag, no data, hash and depth
+        let absent_at = boc.len() - 36;
+        assert_eq!(boc[absent_at..absent_at + 2], [0x17, 0]);
+        assert_eq!(boc[absent_at + 2..absent_at + 34], leaf.repr_hash());
+        assert_eq!(boc[absent_at + 34..], [0, 0]);
+        
+        let decoded = from_boc(&boc).unwrap();
+        assert_eq!(decoded, vec![root.clone()]);
+        assert_eq!(decoded[0].refs()[0].cell_type(), CellType::Absent);
+        assert_eq!(decoded[0].repr_hash(), original.repr_hash());
+        
+        // count in header must match cells, which must carry hashes and no data
+        let mut wrong_count = boc.clone();
+        wrong_count[8] = 0;
+        assert_eq!(from_boc(&wrong_count), Err(BocError::InvalidHeader));
+        let mut without_hashes = boc.clone();
+        without_hashes[absent_at] = 0x07;
+        assert_eq!(from_boc(&without_hashes), Err(BocError::InvalidCell));
+    }
+    
+    #[test]
+    fn diff_stores_changed_cells_only() {
+        // root over eight leaves of 100 bytes in two levels of forks
+        let tree = |changed: u128| {
+            let leaf = |value| {
+                let mut leaf = CellBuilder::new();
+                leaf.store_uint(value, 32).unwrap().store_bits(&[0x55; 96], 768).unwrap();
+                Arc::new(leaf.build())
+            };
+            let fork = |refs: Vec<Arc<Cell>>| {
+                let mut fork = CellBuilder::new();
+                for child in refs {
+                    fork.store_ref(child).unwrap();
+                }
+                Arc::new(fork.build())
+            };
+            let forks = (0..2).map(|i| fork((0..4).map(|j| leaf(if i * 4 + j == 5 {changed} else {i * 4 + j})).collect()));
+            Cell::clone(&fork(forks.collect()))
+        };
+        let (old, new) = (tree(5), tree(500));
+        
+        let diff = to_boc_diff(&old, &new);
+        // root, changed fork and leaf; unchanged fork and three leaves absent
+        assert_eq!(diff[6], 7, "cell count");
+        assert_eq!(diff[8], 4, "absent count");
+        assert!(diff.len() < to_boc(&new, false, true).len());
+        assert_eq!(apply_boc_diff(&old, &diff), Ok(new.clone()));
+        
+        // nothing changed: the root itself is absent
+        let same = to_boc_diff(&old, &old);
+        assert_eq!((same[6], same[8]), (1, 1));
+        assert_eq!(apply_boc_diff(&old, &same), Ok(old.clone()));
+        
+        // base missing the unchanged parts
+        assert_eq!(apply_boc_diff(&new.refs()[1], &diff), Err(BocError::MissingCell));
+    }
+    
+    #[test]
+    fn rejects_references_to_previous_cells() {
+        // two cells of one reference each, the second referring back to the first
+        let cyclic = [0xb5, 0xee, 0x9c, 0x72, 0x01, 0x01, 2, 1, 0, 6, 0, 1, 0, 1, 1, 0, 0];
+        assert_eq!(from_boc(&cyclic), Err(BocError::InvalidCell));
+        let self_referring = [0xb5, 0xee, 0x9c, 0x72, 0x01, 0x01, 1, 1, 0, 3, 0, 1, 0, 0];
+        assert_eq!(from_boc(&self_referring), Err(BocError::InvalidCell));
+    }
+}
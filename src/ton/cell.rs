@@ -0,0 +1,1049 @@
+// (c) ProgramCrafter, 2024
+
+//! Bit-level cells: up to 1023 data bits and up to 4 references to other cells.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::ParseMode;
+use super::sha256::Sha256;
+use super::store::MemoryCellStore;
+
+
+pub const MAX_BITS: usize = 1023;
+pub const MAX_REFS: usize = 4;
+
+/// Reason why value could not be stored into [`CellBuilder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellError {
+    /// Cell would have more than 1023 data bits.
+    BitsOverflow,
+    /// Cell would have more than 4 references.
+    RefsOverflow,
+    /// Exotic cell has unknown type, or its data does not match its type and references.
+    InvalidExotic,
+}
+
+impl std::fmt::Display for CellError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CellError::BitsOverflow => "cell cannot hold more than 1023 bits",
+            CellError::RefsOverflow => "cell cannot hold more than 4 references",
+            CellError::InvalidExotic => "invalid exotic cell",
+        })
+    }
+}
+
+impl std::error::Error for CellError {}
+
+
+/// Kind of a cell. Exotic cells tell their type by the first byte of data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CellType {
+    Ordinary,
+    /// `1`, level mask, then hashes and depths of the removed subtree at lower levels.
+    PrunedBranch,
+    /// `2` and hash of a library cell, to be found by the hash at runtime.
+    LibraryReference,
+    /// `3`, level-0 hash and depth of the only child, whose subtrees may be pruned.
+    MerkleProof,
+    /// `4`, level-0 hashes and depths of both children: the old and the new state.
+    MerkleUpdate,
+    /// Cell left out of a bag of cells, known only by its hashes and depths; it has no data
+    /// or references to read.
+    Absent,
+}
+
+/// Immutable cell. Data bits are stored most significant first; bits of the last byte
+/// past `bit_len` are zero. Hashes and depths are computed once, when the cell is built.
+///
+/// Cells below a pruned branch have level above 0: they have a hash for each level in
+/// their level mask, see [`Cell::hash`].
+#[derive(Clone, PartialEq, Eq)]
+pub struct Cell {
+    data: Vec<u8>,
+    bit_len: usize,
+    refs: Vec<Arc<Cell>>,
+    cell_type: CellType,
+    level_mask: u8,
+    /// One per significant level, from level 0 to the representation one.
+    hashes: Vec<[u8; 32]>,
+    depths: Vec<u16>
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        CellBuilder::new().build()
+    }
+}
+
+impl std::fmt::Debug for Cell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cell")
+            .field("data", &self.data)
+            .field("bit_len", &self.bit_len)
+            .field("refs", &self.refs)
+            .finish()
+    }
+}
+
+impl Cell {
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+    
+    pub fn bit_len(&self) -> usize {
+        self.bit_len
+    }
+    
+    pub fn refs(&self) -> &[Arc<Cell>] {
+        &self.refs
+    }
+    
+    pub fn bit(&self, index: usize) -> bool {
+        assert!(index < self.bit_len, "bit {index} is out of {} cell bits", self.bit_len);
+        (self.data[index / 8] >> (7 - index % 8)) & 1 == 1
+    }
+    
+    /// Placeholder for a cell with representation hash `hash` and depth `depth`, standing
+    /// for it in hashes of parents. Bags of cells list such cells as absent.
+    pub fn absent(hash: [u8; 32], depth: u16) -> Cell {
+        Cell::absent_at_levels(0, vec![hash], vec![depth])
+    }
+    
+    /// Placeholder with hashes and depths for each significant level of `level_mask`.
+    pub(crate) fn absent_at_levels(level_mask: u8, hashes: Vec<[u8; 32]>, depths: Vec<u16>) -> Cell {
+        assert_eq!(hashes.len(), level_mask.count_ones() as usize + 1, "one hash per significant level");
+        assert_eq!(depths.len(), hashes.len(), "one depth per significant level");
+        Cell {
+            data: vec![], bit_len: 0, refs: vec![], cell_type: CellType::Absent,
+            level_mask, hashes, depths
+        }
+    }
+    
+    /// Placeholder with the hashes and depths of this cell.
+    pub(crate) fn to_absent(&self) -> Cell {
+        Cell::absent_at_levels(self.level_mask, self.hashes.clone(), self.depths.clone())
+    }
+    
+    /// The same cell with `refs` in place of its references. They must have the same hashes
+    /// as the current ones (e.g. be copies of them, or placeholders), so hashes are kept.
+    pub(crate) fn with_refs(&self, refs: Vec<Arc<Cell>>) -> Cell {
+        assert!(refs.len() == self.refs.len() && refs.iter().zip(&self.refs).all(|(new, old)| new.hashes == old.hashes),
+                "references are replaced by cells with the same hashes");
+        Cell {refs, ..self.clone()}
+    }
+    
+    pub fn is_exotic(&self) -> bool {
+        !matches!(self.cell_type, CellType::Ordinary | CellType::Absent)
+    }
+    
+    pub fn cell_type(&self) -> CellType {
+        self.cell_type
+    }
+    
+    /// Levels of pruned branches below the cell (bit `i - 1` for level `i`), shifted down
+    /// by one by each Merkle proof or update on the way.
+    pub fn level_mask(&self) -> u8 {
+        self.level_mask
+    }
+    
+    /// Highest level in the level mask; 0 for cells with no pruned branches below.
+    pub fn level(&self) -> u8 {
+        (u8::BITS - self.level_mask.leading_zeros()) as u8
+    }
+    
+    /// Hash of the cell at `level` (levels above the cell's own give the representation hash).
+    /// At level 0, pruned branches stand for the subtrees they replaced, so a tree with parts
+    /// pruned has the level-0 hash of the original one.
+    pub fn hash(&self, level: u8) -> [u8; 32] {
+        self.hashes[self.hash_index(level)]
+    }
+    
+    /// Depth of the cell at `level`, counted as for [`Cell::hash`].
+    pub fn depth(&self, level: u8) -> u16 {
+        self.depths[self.hash_index(level)]
+    }
+    
+    fn hash_index(&self, level: u8) -> usize {
+        (self.level_mask & ((1u8 << level.min(3)) - 1)).count_ones() as usize
+    }
+    
+    /// Representation hash: SHA-256 of descriptor bytes, data with completion tag, and depths
+    /// and hashes of children. Identifies the whole tree; contract addresses, signatures and
+    /// proofs are built on it.
+    pub fn repr_hash(&self) -> [u8; 32] {
+        *self.hashes.last().unwrap()
+    }
+    
+    /// Longest chain of references below the cell, as used in hashes.
+    pub fn repr_depth(&self) -> u16 {
+        *self.depths.last().unwrap()
+    }
+    
+    /// Descriptor bytes `d1 d2`: number of references, exotic flag and level mask; and length
+    /// of data in half-bytes rounded as by the completion tag. Absent cells have 7 for number
+    /// of references and the flag of stored hashes set, with no data.
+    pub(crate) fn descriptors(&self) -> [u8; 2] {
+        if self.cell_type == CellType::Absent {
+            return [7 | 16 | self.level_mask << 5, 0];
+        }
+        self.descriptors_at(self.level_mask)
+    }
+    
+    fn descriptors_at(&self, level_mask: u8) -> [u8; 2] {
+        let d1 = self.refs.len() as u8 | (self.is_exotic() as u8) << 3 | level_mask << 5;
+        [d1, (self.bit_len / 8 + self.bit_len.div_ceil(8)) as u8]
+    }
+    
+    /// Data padded to whole bytes with completion tag, if it does not end on a byte.
+    pub(crate) fn padded_data(&self) -> Vec<u8> {
+        let mut data = self.data.clone();
+        if !self.bit_len.is_multiple_of(8) {
+            data[self.bit_len / 8] |= 0x80 >> (self.bit_len % 8);
+        }
+        data
+    }
+    
+    /// Counts distinct cells of the tree, their bits and references, and its depth, to check
+    /// payloads against TVM limits (like `max_msg_cells` and `max_msg_bits`) before sending.
+    pub fn stats(&self) -> CellStats {
+        fn visit(cell: &Cell, depths: &mut HashMap<*const Cell, usize>, stats: &mut CellStats) -> usize {
+            stats.cells += 1;
+            stats.bits += cell.bit_len;
+            stats.refs += cell.refs.len();
+            let mut depth = 0;
+            for child in &cell.refs {
+                let child_depth = match depths.get(&Arc::as_ptr(child)) {
+                    Some(&child_depth) => child_depth,
+                    None => {
+                        let child_depth = visit(child, depths, stats);
+                        depths.insert(Arc::as_ptr(child), child_depth);
+                        child_depth
+                    },
+                };
+                depth = depth.max(child_depth + 1);
+            }
+            depth
+        }
+        
+        let mut stats = CellStats::default();
+        stats.depth = visit(self, &mut HashMap::new(), &mut stats);
+        stats
+    }
+    
+    /// Parses cell tree in Fift notation, as printed by `{:#}`: one cell per line, children
+    /// indented by one space more than their parent. A line may hold several `x{...}` (hex,
+    /// with `_` marking completed last digit) and `b{...}` (binary) literals, whose bits are
+    /// concatenated. Common indentation of all lines is ignored.
+    pub fn parse_fift(text: &str) -> Result<Cell, FiftParseError> {
+        let lines: Vec<&str> = text.lines().filter(|line| !line.trim().is_empty()).collect();
+        let base = lines.first().map_or(0, |line| line.len() - line.trim_start_matches(' ').len());
+        let nodes = lines.iter().map(|line| {
+            let indent = line.len() - line.trim_start_matches(' ').len();
+            let depth = indent.checked_sub(base).ok_or(FiftParseError::InvalidIndent)?;
+            let bits = line.split_whitespace().map(parse_fift_literal).collect::<Result<Vec<_>, _>>()?;
+            Ok((depth, bits.concat()))
+        }).collect::<Result<Vec<_>, FiftParseError>>()?;
+        
+        fn build(nodes: &[(usize, Vec<bool>)], pos: &mut usize, depth: usize) -> Result<Cell, FiftParseError> {
+            let mut builder = CellBuilder::new();
+            for &bit in &nodes[*pos].1 {
+                builder.store_bit(bit).map_err(FiftParseError::Overflow)?;
+            }
+            *pos += 1;
+            while nodes.get(*pos).is_some_and(|(child_depth, _)| *child_depth == depth + 1) {
+                let child = build(nodes, pos, depth + 1)?;
+                builder.store_ref(Arc::new(child)).map_err(FiftParseError::Overflow)?;
+            }
+            Ok(builder.build())
+        }
+        
+        if nodes.is_empty() {
+            return Err(FiftParseError::InvalidLiteral);
+        }
+        let mut pos = 0;
+        let root = build(&nodes, &mut pos, 0)?;
+        if pos != nodes.len() {
+            return Err(FiftParseError::InvalidIndent);
+        }
+        Ok(root)
+    }
+    
+    /// Builds cell from store commands returned by `CellSerialize::serialize`, for code that
+    /// still passes them around. See [`CellBuilder::store_commands`].
+    pub fn from_legacy_commands(commands: &[String]) -> Result<Cell, CellError> {
+        let mut builder = CellBuilder::new();
+        builder.store_commands(commands)?;
+        Ok(builder.build())
+    }
+}
+
+/// Bits of single `x{...}` or `b{...}` literal.
+fn parse_fift_literal(literal: &str) -> Result<Vec<bool>, FiftParseError> {
+    let invalid = FiftParseError::InvalidLiteral;
+    if let Some(binary) = literal.strip_prefix("b{").and_then(|rest| rest.strip_suffix('}')) {
+        return binary.chars().map(|c| match c {
+            '0' => Ok(false),
+            '1' => Ok(true),
+            _ => Err(invalid),
+        }).collect();
+    }
+    let hex = literal.strip_prefix("x{").and_then(|rest| rest.strip_suffix('}')).ok_or(invalid)?;
+    let (digits, completed) = match hex.strip_suffix('_') {
+        Some(digits) => (digits, true),
+        None => (hex, false),
+    };
+    let mut bits = vec![];
+    for c in digits.chars() {
+        let digit = c.to_digit(16).ok_or(invalid)?;
+        bits.extend((0..4).rev().map(|i| (digit >> i) & 1 == 1));
+    }
+    if completed {
+        // completion tag: last 1 bit and zeros after it
+        let end = bits.iter().rposition(|&bit| bit).ok_or(invalid)?;
+        bits.truncate(end);
+    }
+    Ok(bits)
+}
+
+/// Reason why text could not be parsed by [`Cell::parse_fift`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FiftParseError {
+    /// Line holds something other than `x{...}` and `b{...}` literals, or there are no lines.
+    InvalidLiteral,
+    /// Line is indented less than the first one, or deeper than a child of the previous line.
+    InvalidIndent,
+    /// Literals of a cell take more bits than it holds, or it has too many children.
+    Overflow(CellError),
+}
+
+impl std::fmt::Display for FiftParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FiftParseError::InvalidLiteral => f.write_str("expected x{...} or b{...} cell literals"),
+            FiftParseError::InvalidIndent => f.write_str("indentation does not form a cell tree"),
+            FiftParseError::Overflow(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for FiftParseError {}
+
+/// Fift notation `x{...}`: data in hex; if it does not end on a hex digit, the last one is
+/// completed by a 1 bit and zeros and marked with `_`. Alternate form `{:#}` also prints the
+/// whole tree, each child on its own line, indented by one space per level.
+impl std::fmt::Display for Cell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn write_data(cell: &Cell, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let nibble = |i: usize| if i.is_multiple_of(2) {cell.data[i / 2] >> 4} else {cell.data[i / 2] & 0xf};
+            f.write_str("x{")?;
+            for i in 0..cell.bit_len / 4 {
+                write!(f, "{:X}", nibble(i))?;
+            }
+            let rest = cell.bit_len % 4;
+            if rest != 0 {
+                write!(f, "{:X}_", nibble(cell.bit_len / 4) | (0x8 >> rest))?;
+            }
+            f.write_str("}")
+        }
+        
+        fn write_tree(cell: &Cell, indent: usize, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:indent$}", "")?;
+            write_data(cell, f)?;
+            for child in &cell.refs {
+                writeln!(f)?;
+                write_tree(child, indent + 1, f)?;
+            }
+            Ok(())
+        }
+        
+        if f.alternate() {
+            write_tree(self, 0, f)
+        } else {
+            write_data(self, f)
+        }
+    }
+}
+
+/// Size of a cell tree, as returned by [`Cell::stats`]. Cells shared through the same `Arc`
+/// are counted once, like in bags of cells.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CellStats {
+    /// Distinct cells, including the root.
+    pub cells: usize,
+    /// Data bits of distinct cells.
+    pub bits: usize,
+    /// References stored in distinct cells.
+    pub refs: usize,
+    /// Longest chain of references from the root; zero for a cell without references.
+    pub depth: usize,
+}
+
+
+/// Accumulates bits and references of a cell under construction.
+#[derive(Debug, Default)]
+pub struct CellBuilder {
+    data: Vec<u8>,
+    bit_len: usize,
+    refs: Vec<Arc<Cell>>
+}
+
+impl CellBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    pub fn bit_len(&self) -> usize {
+        self.bit_len
+    }
+    
+    pub fn refs(&self) -> &[Arc<Cell>] {
+        &self.refs
+    }
+    
+    /// Reserves room for `bits` more bits and `refs` more references, up to the limits of
+    /// a cell, so that storing them does not reallocate.
+    pub fn reserve(&mut self, bits: usize, refs: usize) {
+        let bytes = (self.bit_len + bits).min(MAX_BITS).div_ceil(8);
+        self.data.reserve(bytes.saturating_sub(self.data.len()));
+        self.refs.reserve((self.refs.len() + refs).min(MAX_REFS) - self.refs.len());
+    }
+    
+    pub fn store_bit(&mut self, bit: bool) -> Result<&mut Self, CellError> {
+        if self.bit_len == MAX_BITS {
+            return Err(CellError::BitsOverflow);
+        }
+        if self.bit_len.is_multiple_of(8) {
+            self.data.push(0);
+        }
+        if bit {
+            self.data[self.bit_len / 8] |= 0x80 >> (self.bit_len % 8);
+        }
+        self.bit_len += 1;
+        Ok(self)
+    }
+    
+    /// Stores `bits` lowest bits of `value`, most significant first.
+    pub fn store_uint(&mut self, value: u128, bits: usize) -> Result<&mut Self, CellError> {
+        assert!(bits <= 128 && (bits == 128 || value >> bits == 0), "{value} does not fit in {bits} bits");
+        if self.bit_len + bits > MAX_BITS {
+            return Err(CellError::BitsOverflow);
+        }
+        for i in (0..bits).rev() {
+            self.store_bit((value >> i) & 1 == 1)?;
+        }
+        Ok(self)
+    }
+    
+    /// Stores first `bit_len` bits of `data`, most significant first.
+    pub fn store_bits(&mut self, data: &[u8], bit_len: usize) -> Result<&mut Self, CellError> {
+        assert!(bit_len <= data.len() * 8, "data holds fewer than {bit_len} bits");
+        if self.bit_len + bit_len > MAX_BITS {
+            return Err(CellError::BitsOverflow);
+        }
+        for i in 0..bit_len {
+            self.store_bit((data[i / 8] >> (7 - i % 8)) & 1 == 1)?;
+        }
+        Ok(self)
+    }
+    
+    pub fn store_ref(&mut self, cell: Arc<Cell>) -> Result<&mut Self, CellError> {
+        if self.refs.len() == MAX_REFS {
+            return Err(CellError::RefsOverflow);
+        }
+        self.refs.push(cell);
+        Ok(self)
+    }
+    
+    /// Stores bits and references left in `slice`, without consuming it. Parts of slices
+    /// are concatenated by storing them one after another.
+    pub fn store_remaining(&mut self, slice: &CellSlice) -> Result<&mut Self, CellError> {
+        if self.bit_len + slice.remaining_bits() > MAX_BITS {
+            return Err(CellError::BitsOverflow);
+        }
+        if self.refs.len() + slice.remaining_refs() > MAX_REFS {
+            return Err(CellError::RefsOverflow);
+        }
+        let mut slice = slice.clone();
+        while let Ok(bit) = slice.load_bit() {
+            self.store_bit(bit)?;
+        }
+        while let Ok(child) = slice.load_ref() {
+            self.store_ref(child.clone())?;
+        }
+        Ok(self)
+    }
+    
+    /// Executes textual store command `u VALUE WIDTHbit` produced by `CellSerialize::serialize`.
+    fn store_command(&mut self, command: &str) -> Result<&mut Self, CellError> {
+        let parsed = command.strip_prefix("u ")
+            .and_then(|rest| rest.strip_suffix("bit"))
+            .and_then(|rest| rest.split_once(' '))
+            .and_then(|(value, bits)| Some((value.parse().ok()?, bits.parse().ok()?)));
+        let Some((value, bits)) = parsed else {
+            panic!("malformed store command `{command}`");
+        };
+        self.store_uint(value, bits)
+    }
+    
+    /// Executes store commands returned by `CellSerialize::serialize` in order. Commands between
+    /// `ref {` and matching `}` are stored into a new cell, which becomes the next reference of this one.
+    ///
+    /// # Panics
+    ///
+    /// Panics on malformed commands or unbalanced `ref {` blocks.
+    pub fn store_commands(&mut self, commands: &[String]) -> Result<&mut Self, CellError> {
+        let mut i = 0;
+        while i < commands.len() {
+            if commands[i] != "ref {" {
+                self.store_command(&commands[i])?;
+                i += 1;
+                continue;
+            }
+            
+            let mut depth = 0;
+            let mut end = i;
+            loop {
+                match commands.get(end).map(String::as_str) {
+                    Some("ref {") => depth += 1,
+                    Some("}") => depth -= 1,
+                    Some(_) => {},
+                    None => panic!("store command `ref {{` is not closed"),
+                }
+                if depth == 0 {break;}
+                end += 1;
+            }
+            let mut child = CellBuilder::new();
+            child.store_commands(&commands[i + 1..end])?;
+            self.store_ref(Arc::new(child.build()))?;
+            i = end + 1;
+        }
+        Ok(self)
+    }
+    
+    pub fn build(self) -> Cell {
+        self.finish(false).expect("ordinary cells are always valid")
+    }
+    
+    /// Builds cell, returning the copy kept in `store` if it has one (the cell is added
+    /// otherwise), so that equal subtrees of large structures share memory.
+    pub fn build_interned(self, store: &mut MemoryCellStore) -> Arc<Cell> {
+        store.intern(self.build())
+    }
+    
+    /// Builds exotic cell, checking that data is valid for its type (the first byte).
+    pub fn build_exotic(self) -> Result<Cell, CellError> {
+        self.finish(true)
+    }
+    
+    fn finish(self, exotic: bool) -> Result<Cell, CellError> {
+        let mut cell = Cell {
+            data: self.data, bit_len: self.bit_len, refs: self.refs, cell_type: CellType::Ordinary,
+            level_mask: 0, hashes: vec![], depths: vec![]
+        };
+        let children_mask = cell.refs.iter().fold(0, |mask, child| mask | child.level_mask);
+        let invalid = CellError::InvalidExotic;
+        let (level_mask, child_level_shift) = match exotic.then(|| cell.data.first().copied()) {
+            None => (children_mask, 0),
+            Some(Some(1)) => {
+                cell.cell_type = CellType::PrunedBranch;
+                let level_mask = *cell.data.get(1).ok_or(invalid)?;
+                let stored = level_mask.count_ones() as usize;
+                if !(1..=7).contains(&level_mask) || cell.bit_len != 16 + stored * (256 + 16) || !cell.refs.is_empty() {
+                    return Err(invalid);
+                }
+                (level_mask, 0)
+            },
+            Some(Some(2)) if cell.bit_len == 8 + 256 && cell.refs.is_empty() => {
+                cell.cell_type = CellType::LibraryReference;
+                (0, 0)
+            },
+            Some(Some(3)) if cell.bit_len == 8 + 256 + 16 && cell.refs.len() == 1 => {
+                cell.cell_type = CellType::MerkleProof;
+                (children_mask >> 1, 1)
+            },
+            Some(Some(4)) if cell.bit_len == 8 + 2 * (256 + 16) && cell.refs.len() == 2 => {
+                cell.cell_type = CellType::MerkleUpdate;
+                (children_mask >> 1, 1)
+            },
+            Some(_) => return Err(invalid),
+        };
+        cell.level_mask = level_mask;
+        
+        let pruned = cell.cell_type() == CellType::PrunedBranch;
+        if pruned {
+            // hashes and depths of the lower levels are the stored ones
+            let stored = level_mask.count_ones() as usize;
+            for i in 0..stored {
+                cell.hashes.push(cell.data[2 + 32 * i..][..32].try_into().unwrap());
+                let depth = &cell.data[2 + 32 * stored + 2 * i..][..2];
+                cell.depths.push(u16::from_be_bytes(depth.try_into().unwrap()));
+            }
+        }
+        for level in 0..=cell.level() {
+            let significant = level == 0 || level_mask >> (level - 1) & 1 == 1;
+            if !significant || (pruned && level != cell.level()) {
+                continue;
+            }
+            let child_level = level + child_level_shift;
+            let mut hasher = Sha256::new();
+            hasher.update(&cell.descriptors_at(level_mask & ((1 << level) - 1)));
+            match cell.hashes.last() {
+                // higher levels hash the previous hash instead of data
+                Some(previous) if !pruned => hasher.update(previous),
+                _ => hasher.update(&cell.padded_data()),
+            }
+            let mut depth = 0u16;
+            for child in &cell.refs {
+                let child_depth = child.depth(child_level);
+                depth = depth.max(child_depth.checked_add(1).expect("cell tree is at most 65535 levels deep"));
+                hasher.update(&child_depth.to_be_bytes());
+            }
+            for child in &cell.refs {
+                hasher.update(&child.hash(child_level));
+            }
+            cell.hashes.push(hasher.finish());
+            cell.depths.push(depth);
+        }
+        
+        if matches!(cell.cell_type(), CellType::MerkleProof | CellType::MerkleUpdate) {
+            // proofs keep level-0 hashes and depths of their children, which must match
+            for (i, child) in cell.refs.iter().enumerate() {
+                let hash_at = 8 + 256 * i;
+                let depth_at = 8 + 256 * cell.refs.len() + 16 * i;
+                let mut slice = CellSlice::new(&cell);
+                slice.skip_bits(hash_at).unwrap();
+                let hash = slice.load_bits(256).unwrap();
+                slice.skip_bits(depth_at - hash_at - 256).unwrap();
+                if hash != child.hash(0) || slice.load_uint(16).unwrap() as u16 != child.depth(0) {
+                    return Err(invalid);
+                }
+            }
+        }
+        Ok(cell)
+    }}
+
+
+/// Reason why value could not be read from [`CellSlice`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlbError {
+    /// Slice has fewer bits left than the value takes.
+    NotEnoughBits,
+    /// Slice has fewer references left than the value takes.
+    NotEnoughRefs,
+    /// Constant or constructor tag differs from the scheme.
+    TagMismatch,
+    /// No constructor of enum `type_name` starts with the stored tag.
+    UnknownTag {type_name: &'static str},
+    /// Stored value is out of range of the Rust type.
+    InvalidValue,
+    /// Data is left in the cell after reading the value.
+    TrailingData,
+    /// Cell is pruned from a Merkle proof or absent from a bag of cells; only its hash is known.
+    Pruned,
+}
+
+impl std::fmt::Display for TlbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlbError::NotEnoughBits => f.write_str("cell has not enough bits"),
+            TlbError::NotEnoughRefs => f.write_str("cell has not enough references"),
+            TlbError::TagMismatch => f.write_str("tag does not match the scheme"),
+            TlbError::UnknownTag {type_name} => write!(f, "no constructor of `{type_name}` matches stored tag"),
+            TlbError::InvalidValue => f.write_str("stored value is out of range"),
+            TlbError::TrailingData => f.write_str("cell has data after the value"),
+            TlbError::Pruned => f.write_str("cell is pruned, its data is unknown"),
+        }
+    }
+}
+
+impl std::error::Error for TlbError {}
+
+
+/// Position inside a cell saved by [`CellSlice::checkpoint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SliceCheckpoint {
+    bit_pos: usize,
+    ref_pos: usize
+}
+
+/// Reading position inside a cell: bits and references not yet loaded, up to the end of
+/// the cell or of the part [`CellSlice::split_at`] cut off.
+///
+/// A slice may carry [`ParseMode`] forced by the caller, which slices of its child cells
+/// inherit; without one, every value follows its type's own mode.
+#[derive(Debug, Clone)]
+pub struct CellSlice<'a> {
+    cell: &'a Cell,
+    bit_pos: usize,
+    bit_end: usize,
+    ref_pos: usize,
+    ref_end: usize,
+    mode: Option<ParseMode>
+}
+
+impl<'a> CellSlice<'a> {
+    pub fn new(cell: &'a Cell) -> Self {
+        CellSlice {cell, bit_pos: 0, bit_end: cell.bit_len(), ref_pos: 0, ref_end: cell.refs().len(), mode: None}
+    }
+    
+    /// Slice of the whole cell, reading it and all cells below in `mode`.
+    pub fn with_mode(cell: &'a Cell, mode: ParseMode) -> Self {
+        CellSlice {mode: Some(mode), ..CellSlice::new(cell)}
+    }
+    
+    /// Mode forced on values read from this slice, if any.
+    pub fn mode(&self) -> Option<ParseMode> {
+        self.mode
+    }
+    
+    pub fn remaining_bits(&self) -> usize {
+        self.bit_end - self.bit_pos
+    }
+    
+    pub fn remaining_refs(&self) -> usize {
+        self.ref_end - self.ref_pos
+    }
+    
+    /// Splits remaining data after `bits` bits. The first part has no references; all of
+    /// them stay in the second one, as when a contract cuts a body after its header.
+    pub fn split_at(&self, bits: usize) -> Result<(CellSlice<'a>, CellSlice<'a>), TlbError> {
+        if self.remaining_bits() < bits {
+            return Err(TlbError::NotEnoughBits);
+        }
+        let head = CellSlice {bit_end: self.bit_pos + bits, ref_end: self.ref_pos, ..self.clone()};
+        let tail = CellSlice {bit_pos: self.bit_pos + bits, ..self.clone()};
+        Ok((head, tail))
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.remaining_bits() == 0 && self.remaining_refs() == 0
+    }
+    
+    pub fn skip_bits(&mut self, bits: usize) -> Result<(), TlbError> {
+        if self.remaining_bits() < bits {
+            return Err(TlbError::NotEnoughBits);
+        }
+        self.bit_pos += bits;
+        Ok(())
+    }
+    
+    pub fn skip_refs(&mut self, refs: usize) -> Result<(), TlbError> {
+        if self.remaining_refs() < refs {
+            return Err(TlbError::NotEnoughRefs);
+        }
+        self.ref_pos += refs;
+        Ok(())
+    }
+    
+    /// Saves reading position, to return to it with [`CellSlice::rewind`] (e.g. when retrying
+    /// the same data as another type).
+    pub fn checkpoint(&self) -> SliceCheckpoint {
+        SliceCheckpoint {bit_pos: self.bit_pos, ref_pos: self.ref_pos}
+    }
+    
+    /// Returns to position saved by `checkpoint` of this slice.
+    pub fn rewind(&mut self, checkpoint: SliceCheckpoint) {
+        assert!(checkpoint.bit_pos <= self.bit_end && checkpoint.ref_pos <= self.ref_end,
+                "checkpoint is not of this slice");
+        self.bit_pos = checkpoint.bit_pos;
+        self.ref_pos = checkpoint.ref_pos;
+    }
+    
+    pub fn load_bit(&mut self) -> Result<bool, TlbError> {
+        if self.remaining_bits() == 0 {
+            return Err(TlbError::NotEnoughBits);
+        }
+        self.bit_pos += 1;
+        Ok(self.cell.bit(self.bit_pos - 1))
+    }
+    
+    /// Loads unsigned integer of `bits` bits, most significant first.
+    pub fn load_uint(&mut self, bits: usize) -> Result<u128, TlbError> {
+        assert!(bits <= 128, "cannot load more than 128 bits into integer");
+        if self.remaining_bits() < bits {
+            return Err(TlbError::NotEnoughBits);
+        }
+        (0..bits).try_fold(0u128, |acc, _| Ok((acc << 1) | self.load_bit()? as u128))
+    }
+    
+    /// Reads unsigned integer of `bits` bits like [`CellSlice::load_uint`], without consuming it.
+    pub fn peek_uint(&self, bits: usize) -> Result<u128, TlbError> {
+        self.clone().load_uint(bits)
+    }
+    
+    /// Loads `bit_len` bits, packed most significant first into bytes.
+    pub fn load_bits(&mut self, bit_len: usize) -> Result<Vec<u8>, TlbError> {
+        if self.remaining_bits() < bit_len {
+            return Err(TlbError::NotEnoughBits);
+        }
+        let mut data = vec![0u8; bit_len.div_ceil(8)];
+        for i in 0..bit_len {
+            if self.load_bit()? {
+                data[i / 8] |= 0x80 >> (i % 8);
+            }
+        }
+        Ok(data)
+    }
+    
+    pub fn load_ref(&mut self) -> Result<&'a Arc<Cell>, TlbError> {
+        if self.remaining_refs() == 0 {
+            return Err(TlbError::NotEnoughRefs);
+        }
+        self.ref_pos += 1;
+        Ok(&self.cell.refs()[self.ref_pos - 1])
+    }
+    
+    /// Slice of the next child cell, keeping the mode of this slice.
+    pub fn load_ref_slice(&mut self) -> Result<CellSlice<'a>, TlbError> {
+        let child = self.load_ref()?;
+        Ok(CellSlice {mode: self.mode, ..CellSlice::new(child)})
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn cell(value: u128, bits: usize, refs: &[&Arc<Cell>]) -> Cell {
+        let mut builder = CellBuilder::new();
+        builder.store_uint(value, bits).unwrap();
+        for child in refs {
+            builder.store_ref(Arc::clone(child)).unwrap();
+        }
+        builder.build()
+    }
+    
+    #[test]
+    fn stats_count_shared_cells_once() {
+        let leaf = Arc::new(cell(0xff, 8, &[]));
+        let middle = Arc::new(cell(1, 3, &[&leaf]));
+        let root = cell(0, 1, &[&middle, &leaf, &leaf]);
+        assert_eq!(root.stats(), CellStats {cells: 3, bits: 12, refs: 4, depth: 2});
+        assert_eq!(Cell::default().stats(), CellStats {cells: 1, bits: 0, refs: 0, depth: 0});
+    }
+    
+    #[test]
+    fn reserved_room_is_not_reallocated() {
+        let mut builder = CellBuilder::new();
+        builder.store_uint(1, 3).unwrap();
+        builder.reserve(1020, 4);
+        let (data, refs) = (builder.data.as_ptr(), builder.refs.as_ptr());
+        builder.store_uint(u128::MAX, 128).unwrap().store_bits(&[0xaa; 112], 892).unwrap();
+        for _ in 0..MAX_REFS {
+            builder.store_ref(Arc::new(Cell::default())).unwrap();
+        }
+        assert_eq!((builder.data.as_ptr(), builder.refs.as_ptr()), (data, refs));
+        
+        // room beyond the limits of a cell is not reserved
+        let mut builder = CellBuilder::new();
+        builder.reserve(usize::MAX / 2, 100);
+        assert!(builder.data.capacity() >= 128 && builder.refs.capacity() >= MAX_REFS);
+        assert!(builder.refs.capacity() < 100);
+    }
+    
+    fn hex(hash: [u8; 32]) -> String {
+        hash.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+    
+    #[test]
+    fn repr_hash_of_known_cells() {
+        assert_eq!(hex(Cell::default().repr_hash()), "96a296d224f285c67bee93c30f8a309157f0daa35dc5b87e410b78630a09cfc7");
+        
+        // code of wallet v3r2, whose hash explorers show for such wallets
+        let boc = tlb_macro::boc!("te6cckEBAQEAcQAA3v8AIN0gggFMl7ohggEznLqxn3Gw7UTQ0x/THzHXC//jBOCk8mCDCNcYINMf0x/TH/gjE7vyY+1E0NMf0x/T/9FRMrryoVFEuvKiBPkBVBBV+RDyo/gAkyDXSpbTB9QC+wDo0QGkyMsfyx/L/8ntVBC9ba0=");
+        let code = &super::super::boc::from_boc(boc).unwrap()[0];
+        assert_eq!(hex(code.repr_hash()), "84dafa449f98a6987789ba232358072bc0f76dc4524002a5d0918b9a75d2d599");
+        assert_eq!(code.repr_depth(), 0);
+    }
+    
+    #[test]
+    fn repr_hash_depends_on_whole_tree() {
+        let leaf = Arc::new(cell(0xff, 8, &[]));
+        let other_leaf = Arc::new(cell(0xfe, 8, &[]));
+        let middle = Arc::new(cell(1, 3, &[&leaf]));
+        let root = cell(0, 1, &[&middle, &leaf]);
+        assert_eq!((root.repr_depth(), middle.repr_depth(), leaf.repr_depth()), (2, 1, 0));
+        
+        // equal trees hash equally whether or not children are shared
+        let copy = cell(0, 1, &[&Arc::new(cell(1, 3, &[&Arc::new(cell(0xff, 8, &[]))])), &Arc::new(cell(0xff, 8, &[]))]);
+        assert_eq!(copy.repr_hash(), root.repr_hash());
+        assert_ne!(cell(0, 1, &[&Arc::new(cell(1, 3, &[&other_leaf])), &leaf]).repr_hash(), root.repr_hash());
+        assert_ne!(cell(0, 1, &[&leaf, &middle]).repr_hash(), root.repr_hash());
+        // completion tag tells `0` from `00`
+        assert_ne!(cell(0, 1, &[]).repr_hash(), cell(0, 2, &[]).repr_hash());
+    }
+    
+    /// Pruned branch standing for `cell` at `level`, as a Merkle proof `level` deep keeps it.
+    fn pruned(cell: &Cell, level: u8) -> Cell {
+        let level_mask = cell.level_mask() | 1 << (level - 1);
+        let mut builder = CellBuilder::new();
+        builder.store_uint(1, 8).unwrap().store_uint(level_mask as u128, 8).unwrap();
+        let levels: Vec<u8> = (0..level).filter(|&i| i == 0 || level_mask >> (i - 1) & 1 == 1).collect();
+        for &i in &levels {
+            builder.store_bits(&cell.hash(i), 256).unwrap();
+        }
+        for &i in &levels {
+            builder.store_uint(cell.depth(i) as u128, 16).unwrap();
+        }
+        builder.build_exotic().unwrap()
+    }
+    
+    fn merkle_proof(root: &Arc<Cell>) -> Result<Cell, CellError> {
+        let mut builder = CellBuilder::new();
+        builder.store_uint(3, 8).unwrap().store_bits(&root.hash(0), 256).unwrap();
+        builder.store_uint(root.depth(0) as u128, 16).unwrap().store_ref(root.clone()).unwrap();
+        builder.build_exotic()
+    }
+    
+    #[test]
+    fn pruned_tree_keeps_level_zero_hash() {
+        let leaf = Arc::new(cell(0xff, 8, &[]));
+        let middle = Arc::new(cell(1, 3, &[&leaf]));
+        let root = cell(0, 1, &[&middle, &leaf]);
+        
+        let pruned_middle = Arc::new(pruned(&middle, 1));
+        assert_eq!(pruned_middle.cell_type(), CellType::PrunedBranch);
+        assert_eq!((pruned_middle.level_mask(), pruned_middle.level()), (1, 1));
+        assert_eq!((pruned_middle.hash(0), pruned_middle.depth(0)), (middle.repr_hash(), 1));
+        assert_eq!((pruned_middle.repr_depth(), pruned_middle.descriptors()), (0, [0b0010_1000, 2 * 36]));
+        
+        let partial = Arc::new(cell(0, 1, &[&pruned_middle, &leaf]));
+        assert_eq!((partial.level_mask(), partial.cell_type()), (1, CellType::Ordinary));
+        assert_eq!((partial.hash(0), partial.depth(0)), (root.repr_hash(), root.repr_depth()));
+        assert_ne!(partial.repr_hash(), root.repr_hash());
+        assert_eq!(partial.hash(1), partial.repr_hash());
+        assert_eq!(partial.hash(3), partial.repr_hash());
+        assert_eq!(partial.repr_depth(), 1);
+        
+        // the proof is of level 0 again, and refers to the original hash
+        let proof = merkle_proof(&partial).unwrap();
+        assert_eq!((proof.cell_type(), proof.level_mask(), proof.repr_depth()), (CellType::MerkleProof, 0, 2));
+        let mut slice = CellSlice::new(&proof);
+        slice.skip_bits(8).unwrap();
+        assert_eq!(slice.load_bits(256).unwrap(), root.repr_hash());
+    }
+    
+    #[test]
+    fn hashes_of_every_significant_level() {
+        let leaf = Arc::new(cell(0xff, 8, &[]));
+        // pruned at level 1, then what is left pruned again by an outer proof at level 2
+        let inner = Arc::new(cell(1, 3, &[&Arc::new(pruned(&leaf, 1))]));
+        let twice = pruned(&inner, 2);
+        assert_eq!((twice.level_mask(), twice.level()), (0b11, 2));
+        assert_eq!(twice.bit_len(), 16 + 2 * (256 + 16));
+        assert_eq!((twice.hash(0), twice.hash(1)), (inner.hash(0), inner.hash(1)));
+        assert_eq!((twice.depth(0), twice.depth(1)), (inner.depth(0), inner.depth(1)));
+        assert_ne!(twice.hash(2), twice.hash(1));
+        
+        let mixed = cell(0, 0, &[&Arc::new(twice), &Arc::new(pruned(&leaf, 3))]);
+        assert_eq!((mixed.level_mask(), mixed.level()), (0b111, 3));
+        let hashes: Vec<_> = (0..4).map(|level| mixed.hash(level)).collect();
+        assert!(hashes.iter().enumerate().all(|(i, hash)| !hashes[..i].contains(hash)));
+    }
+    
+    #[test]
+    fn exotic_cells_are_checked() {
+        let leaf = Arc::new(cell(0xff, 8, &[]));
+        let mut library = CellBuilder::new();
+        library.store_uint(2, 8).unwrap().store_bits(&leaf.repr_hash(), 256).unwrap();
+        let library = library.build_exotic().unwrap();
+        assert_eq!((library.cell_type(), library.level()), (CellType::LibraryReference, 0));
+        assert!(library.is_exotic() && !leaf.is_exotic());
+        
+        let mut wrong_hash = CellBuilder::new();
+        wrong_hash.store_uint(3, 8).unwrap().store_uint(0, 256 - 128).unwrap().store_uint(0, 128).unwrap();
+        wrong_hash.store_uint(leaf.repr_depth() as u128, 16).unwrap().store_ref(leaf.clone()).unwrap();
+        assert_eq!(wrong_hash.build_exotic(), Err(CellError::InvalidExotic));
+        assert!(merkle_proof(&leaf).is_ok());
+        
+        // unknown type, pruned branches of no levels or without hashes, short library, no type
+        for (value, bits) in [(5, 8), (1, 8), (0x0100, 16), (0x0101, 16), (2, 8), (0, 0)] {
+            let mut builder = CellBuilder::new();
+            builder.store_uint(value, bits).unwrap();
+            assert_eq!(builder.build_exotic(), Err(CellError::InvalidExotic), "{value:x}");
+        }
+    }
+    
+    #[test]
+    fn slice_skips_and_rewinds() {
+        let leaf = Arc::new(cell(0, 0, &[]));
+        let root = cell(0xabcd, 16, &[&leaf, &leaf]);
+        let mut slice = CellSlice::new(&root);
+        slice.skip_bits(4).unwrap();
+        let checkpoint = slice.checkpoint();
+        assert_eq!(slice.load_uint(4), Ok(0xb));
+        slice.skip_refs(1).unwrap();
+        assert_eq!((slice.skip_bits(9), slice.skip_refs(2)), (Err(TlbError::NotEnoughBits), Err(TlbError::NotEnoughRefs)));
+        
+        slice.rewind(checkpoint);
+        assert_eq!((slice.remaining_bits(), slice.remaining_refs()), (12, 2));
+        assert_eq!(slice.load_uint(12), Ok(0xbcd));
+        slice.skip_refs(2).unwrap();
+        assert!(slice.is_empty());
+    }
+    
+    #[test]
+    fn slices_split_and_concatenate() {
+        let leaf = Arc::new(cell(1, 1, &[]));
+        let body = cell(0x12345678, 32, &[&leaf]);
+        let (header, payload) = CellSlice::new(&body).split_at(12).unwrap();
+        assert_eq!((header.remaining_bits(), header.remaining_refs()), (12, 0));
+        assert_eq!(header.peek_uint(12), Ok(0x123));
+        assert_eq!(header.clone().load_uint(13), Err(TlbError::NotEnoughBits));
+        assert_eq!((payload.remaining_bits(), payload.remaining_refs()), (20, 1));
+        assert!(CellSlice::new(&body).split_at(33).is_err());
+        
+        let mut swapped = CellBuilder::new();
+        swapped.store_remaining(&payload).unwrap().store_remaining(&header).unwrap();
+        assert_eq!(swapped.build(), cell(0x45678123, 32, &[&leaf]));
+        
+        let mut full = CellBuilder::new();
+        full.store_bits(&[0; 127], 1010).unwrap();
+        assert_eq!(full.store_remaining(&payload).err(), Some(CellError::BitsOverflow));
+    }
+    
+    #[test]
+    fn display_in_fift_notation() {
+        assert_eq!(cell(0xabcd, 16, &[]).to_string(), "x{ABCD}");
+        assert_eq!(cell(1, 1, &[]).to_string(), "x{C_}");
+        assert_eq!(cell(0, 1, &[]).to_string(), "x{4_}");
+        assert_eq!(cell(0b1011_0100, 8, &[]).to_string(), "x{B4}");
+        assert_eq!(cell(0b1011010, 7, &[]).to_string(), "x{B5_}");
+        assert_eq!(Cell::default().to_string(), "x{}");
+        
+        let leaf = Arc::new(cell(0xf, 4, &[]));
+        let middle = Arc::new(cell(0, 0, &[&leaf]));
+        let root = cell(0x12, 8, &[&middle, &leaf]);
+        assert_eq!(root.to_string(), "x{12}");
+        assert_eq!(format!("{root:#}"), "x{12}\n x{}\n  x{F}\n x{F}");
+    }
+    
+    #[test]
+    fn parse_fift_literals() {
+        assert_eq!(Cell::parse_fift("x{ABCD}"), Ok(cell(0xabcd, 16, &[])));
+        assert_eq!(Cell::parse_fift("x{C_}"), Ok(cell(1, 1, &[])));
+        assert_eq!(Cell::parse_fift("x{B5_}"), Ok(cell(0b1011010, 7, &[])));
+        assert_eq!(Cell::parse_fift("b{101} x{F}"), Ok(cell(0b101_1111, 7, &[])));
+        
+        let leaf = Arc::new(cell(0xf, 4, &[]));
+        let middle = Arc::new(cell(0, 0, &[&leaf]));
+        let root = cell(0x12, 8, &[&middle, &leaf]);
+        assert_eq!(Cell::parse_fift(&format!("{root:#}")), Ok(root.clone()));
+        assert_eq!(Cell::parse_fift("
+            x{12}
+             x{}
+              x{F}
+             x{F}
+        "), Ok(root));
+        
+        assert_eq!(Cell::parse_fift("x{12}\n  x{F}"), Err(FiftParseError::InvalidIndent));
+        assert_eq!(Cell::parse_fift("x{12}\nx{F}"), Err(FiftParseError::InvalidIndent));
+        assert_eq!(Cell::parse_fift("x{0_}"), Err(FiftParseError::InvalidLiteral));
+        assert_eq!(Cell::parse_fift("x{G}"), Err(FiftParseError::InvalidLiteral));
+        assert_eq!(Cell::parse_fift(""), Err(FiftParseError::InvalidLiteral));
+        assert_eq!(Cell::parse_fift(&["x{FF}"; 128].join(" ")), Err(FiftParseError::Overflow(CellError::BitsOverflow)));
+    }
+}
@@ -0,0 +1,18 @@
+// (c) ProgramCrafter, 2024
+
+//! Bitwise CRC32C (Castagnoli), as stored at the end of bags of cells.
+//!
+//! `tlb_macro` includes this file by `#[path]` to check `boc!()` literals, so both crates
+//! compute the checksum the same way.
+
+
+pub fn crc32c_software(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {(crc >> 1) ^ 0x82f63b78} else {crc >> 1};
+        }
+    }
+    !crc
+}
@@ -0,0 +1,1000 @@
+// (c) ProgramCrafter, 2024
+
+//! Dictionaries: `HashmapE n X`, binary tries with `n`-bit keys where each edge is labeled
+//! with the common part of keys below it, and `HashmapAugE n X Y`, which also keep a `Y`
+//! aggregated over every subtree.
+//!
+//! Dictionaries are used in place of their cells: lookups and scans walk the trie, and changes
+//! rebuild only the edges on the way to the key, sharing the rest with the previous version.
+
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use super::cell::{Cell, CellBuilder, CellError, CellSlice, CellType, TlbError};
+use super::proof::merkle_proof;
+use super::{CellDeserialize, CellSerialize, FixedBits, MsgAddressInt, RawBits, Uint};
+
+
+/// Typed dictionary key, stored as `BITS` key bits; `HashmapE n X` takes keys with `BITS`
+/// equal to `n`. Integers are stored in two's complement, so iteration order of negative
+/// keys follows their unsigned form.
+pub trait DictKey: Sized {
+    const BITS: usize;
+    
+    /// Key bits, `BITS` of them; none if this value has no such form (e.g. an anycast
+    /// address), so it is never found in a dictionary.
+    fn to_key_bits(&self) -> Option<RawBits>;
+    
+    /// Key from `BITS` key bits, e.g. as given by dictionary scans.
+    fn from_key_bits(bits: &RawBits) -> Result<Self, TlbError>;
+}
+
+macro_rules! int_dict_keys {
+    ($($int:ty),*) => {$(
+        impl DictKey for $int {
+            const BITS: usize = <$int>::BITS as usize;
+            
+            fn to_key_bits(&self) -> Option<RawBits> {
+                Some(RawBits::new(self.to_be_bytes().to_vec(), <Self as DictKey>::BITS))
+            }
+            
+            fn from_key_bits(bits: &RawBits) -> Result<Self, TlbError> {
+                Ok(<$int>::from_be_bytes(bits.data().try_into().map_err(|_| TlbError::InvalidValue)?))
+            }
+        }
+    )*};
+}
+
+int_dict_keys!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl<const N: usize> DictKey for Uint<N> {
+    const BITS: usize = N;
+    
+    fn to_key_bits(&self) -> Option<RawBits> {
+        assert!((1..=128).contains(&N) && (N == 128 || self.0 >> N == 0), "Uint<{N}> overflow");
+        Some((0..N).rev().map(|i| (self.0 >> i) & 1 == 1).collect())
+    }
+    
+    fn from_key_bits(bits: &RawBits) -> Result<Self, TlbError> {
+        Uint::from_cell(&bits.to_cell().map_err(|_| TlbError::InvalidValue)?)
+    }
+}
+
+impl<const N: usize> DictKey for FixedBits<N> {
+    const BITS: usize = N;
+    
+    fn to_key_bits(&self) -> Option<RawBits> {
+        (self.0.bit_len() == N).then(|| self.0.clone())
+    }
+    
+    fn from_key_bits(bits: &RawBits) -> Result<Self, TlbError> {
+        if bits.bit_len() != N {
+            return Err(TlbError::InvalidValue);
+        }
+        Ok(FixedBits(bits.clone()))
+    }
+}
+
+/// Accounts of a shard are keyed by `addr_std` without anycast, 267 bits.
+impl DictKey for MsgAddressInt {
+    const BITS: usize = 267;
+    
+    fn to_key_bits(&self) -> Option<RawBits> {
+        match self {
+            MsgAddressInt::AddrStd{address} if address.anycast().is_none() => {
+                let cell = self.to_cell().ok()?;
+                Some(RawBits::new(cell.data().to_vec(), cell.bit_len()))
+            }
+            _ => None,
+        }
+    }
+    
+    fn from_key_bits(bits: &RawBits) -> Result<Self, TlbError> {
+        MsgAddressInt::from_cell(&bits.to_cell().map_err(|_| TlbError::InvalidValue)?)
+    }
+}
+
+
+/// Reason why a dictionary could not be changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DictError {
+    /// Edge on the way to the key is malformed.
+    Malformed(TlbError),
+    /// Cell could not be built: an edge with the value does not fit, or the dictionary has
+    /// pruned cells already.
+    Cell(CellError),
+    /// Key has no key bits, see [`DictKey::to_key_bits`].
+    InvalidKey,
+}
+
+impl std::fmt::Display for DictError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DictError::Malformed(error) => write!(f, "dictionary is malformed: {error}"),
+            DictError::Cell(error) => write!(f, "dictionary cell cannot be built: {error}"),
+            DictError::InvalidKey => f.write_str("key cannot be stored in a dictionary"),
+        }
+    }
+}
+
+impl std::error::Error for DictError {}
+
+impl From<TlbError> for DictError {
+    fn from(error: TlbError) -> Self {
+        DictError::Malformed(error)
+    }
+}
+
+impl From<CellError> for DictError {
+    fn from(error: CellError) -> Self {
+        DictError::Cell(error)
+    }
+}
+
+
+/// Where values of a dictionary are stored: in the rest of leaf cells, or in their references.
+pub trait ValuePlacement {
+    fn read<T: CellDeserialize>(slice: &mut CellSlice) -> Result<T, TlbError>;
+    
+    fn store<T: CellSerialize>(value: &T, builder: &mut CellBuilder) -> Result<(), CellError>;
+}
+
+/// Values in the rest of leaf cells, as in `HashmapE n X`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Inline;
+
+impl ValuePlacement for Inline {
+    fn read<T: CellDeserialize>(slice: &mut CellSlice) -> Result<T, TlbError> {
+        T::deserialize(slice)
+    }
+    
+    fn store<T: CellSerialize>(value: &T, builder: &mut CellBuilder) -> Result<(), CellError> {
+        value.store(builder)
+    }
+}
+
+/// Values in the only reference of leaf cells, as in `HashmapE n ^X`: for values too big
+/// to share a cell with the label.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct InRef;
+
+impl ValuePlacement for InRef {
+    fn read<T: CellDeserialize>(slice: &mut CellSlice) -> Result<T, TlbError> {
+        T::from_ref(slice)
+    }
+    
+    fn store<T: CellSerialize>(value: &T, builder: &mut CellBuilder) -> Result<(), CellError> {
+        builder.store_ref(Arc::new(value.to_cell()?))?;
+        Ok(())
+    }
+}
+
+
+/// `HashmapE n X`: `hme_empty$0` or `hme_root$1 root:^(Hashmap n X)`. Keeps the root cell and
+/// walks it on access, so reading a dictionary reads none of its values, and lookups and scans
+/// visit only the edges on their way. Values are placed as `P` tells: [`Inline`] in the rest
+/// of leaf cells by default, or [`InRef`] for `HashmapE n ^X`.
+///
+/// Keys are ordered as unsigned `N`-bit numbers. A malformed trie is reported by the access
+/// reaching the malformed edge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashmapE<const N: usize, T, P = Inline> {
+    root: Option<Arc<Cell>>,
+    _value: PhantomData<(T, P)>,
+}
+
+impl<const N: usize, T, P> HashmapE<N, T, P> {
+    /// Root `Hashmap n X` cell; none for an empty dictionary.
+    pub fn root(&self) -> Option<&Arc<Cell>> {
+        self.root.as_ref()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+    
+    /// Smallest key, found by following left references.
+    pub fn min_key<K: DictKey>(&self) -> Result<Option<K>, TlbError> {
+        self.outermost_key(false)
+    }
+    
+    /// Largest key, found by following right references.
+    pub fn max_key<K: DictKey>(&self) -> Result<Option<K>, TlbError> {
+        self.outermost_key(true)
+    }
+    
+    fn outermost_key<K: DictKey>(&self, side: bool) -> Result<Option<K>, TlbError> {
+        const { assert!(K::BITS == N, "key width differs from the dictionary's") };
+        let Some(mut cell) = self.root.as_deref() else { return Ok(None) };
+        let mut key = Vec::with_capacity(N);
+        loop {
+            let mut slice = edge_slice(cell)?;
+            key.extend(read_label(&mut slice, N - key.len())?);
+            if key.len() == N {
+                return K::from_key_bits(&key.into_iter().collect()).map(Some);
+            }
+            cell = read_fork(&mut slice)?[side as usize].as_ref();
+            key.push(side);
+        }
+    }
+}
+
+impl<const N: usize, T: CellDeserialize, P: ValuePlacement> HashmapE<N, T, P> {
+    /// Value under `key`, found by following its bits from the root.
+    pub fn get<K: DictKey>(&self, key: &K) -> Result<Option<T>, TlbError> {
+        const { assert!(K::BITS == N, "key width differs from the dictionary's") };
+        let (Some(mut cell), Some(key)) = (self.root.as_deref(), key.to_key_bits()) else { return Ok(None) };
+        let mut pos = 0;
+        loop {
+            let mut slice = edge_slice(cell)?;
+            let label = read_label(&mut slice, N - pos)?;
+            if label.iter().enumerate().any(|(i, &bit)| bit != key.bit(pos + i)) {
+                return Ok(None);
+            }
+            pos += label.len();
+            if pos == N {
+                return read_leaf::<T, P>(slice).map(Some);
+            }
+            cell = read_fork(&mut slice)?[key.bit(pos) as usize].as_ref();
+            pos += 1;
+        }
+    }
+    
+    /// Entries in order of keys.
+    pub fn iter(&self) -> Iter<'_, T, P> {
+        Iter::new(self.root.as_deref(), N, Bounds::default())
+    }
+    
+    /// Entries whose keys start with `prefix`, in order of keys; other subtrees are skipped.
+    pub fn prefix(&self, prefix: &RawBits) -> Iter<'_, T, P> {
+        assert!(prefix.bit_len() <= N, "prefix is longer than {N}-bit keys");
+        Iter::new(self.root.as_deref(), N, Bounds {prefix: bits(prefix), ..Bounds::default()})
+    }
+    
+    /// Entries with keys from `from` inclusive to `to` exclusive, in order of keys; subtrees
+    /// out of the range are skipped.
+    ///
+    /// # Panics
+    /// If a bound has no key bits, see [`DictKey::to_key_bits`].
+    pub fn range<K: DictKey>(&self, from: &K, to: &K) -> Iter<'_, T, P> {
+        const { assert!(K::BITS == N, "key width differs from the dictionary's") };
+        let bound = |key: &K| bits(&key.to_key_bits().expect("range bounds have key bits"));
+        let bounds = Bounds {from: Some(bound(from)), to: Some(bound(to)), ..Bounds::default()};
+        Iter::new(self.root.as_deref(), N, bounds)
+    }
+}
+
+impl<const N: usize, T, P> HashmapE<N, T, P> {
+    /// Dictionary with the given root `Hashmap n X` cell, e.g. the partial tree of a Merkle
+    /// proof from [`HashmapE::prove`].
+    pub fn with_root(root: Option<Arc<Cell>>) -> Self {
+        HashmapE {root, _value: PhantomData}
+    }
+    
+    /// Merkle proof of the root cell keeping just the edges on the way to `key` and its leaf
+    /// with the value, pruning everything else; none for an empty dictionary. For a missing
+    /// key, the kept edges show where its path leaves the trie, proving it is not there.
+    pub fn prove<K: DictKey>(&self, key: &K) -> Result<Option<Cell>, DictError> {
+        const { assert!(K::BITS == N, "key width differs from the dictionary's") };
+        let Some(root) = self.root.as_deref() else { return Ok(None) };
+        let key = key.to_key_bits().ok_or(DictError::InvalidKey)?;
+        // indices of references on the way; the leaf is kept with its value references
+        let (mut cell, mut pos, mut path) = (root, 0, vec![]);
+        let found = loop {
+            let mut slice = edge_slice(cell)?;
+            let label = read_label(&mut slice, N - pos)?;
+            if label.iter().enumerate().any(|(i, &bit)| bit != key.bit(pos + i)) {
+                break false;
+            }
+            pos += label.len();
+            if pos == N {
+                break true;
+            }
+            let side = key.bit(pos);
+            cell = read_fork(&mut slice)?[side as usize].as_ref();
+            path.push(side as usize);
+            pos += 1;
+        };
+        Ok(Some(merkle_proof(root, |at, _| path.starts_with(at) || found && at.starts_with(&path))?))
+    }
+    
+    /// Removes `key`; tells whether it was there.
+    pub fn remove<K: DictKey>(&mut self, key: &K) -> Result<bool, DictError> {
+        const { assert!(K::BITS == N, "key width differs from the dictionary's") };
+        let (Some(root), Some(key)) = (&self.root, key.to_key_bits()) else { return Ok(false) };
+        let Some(root) = remove_edge(root, &bits(&key))? else { return Ok(false) };
+        self.root = root;
+        Ok(true)
+    }
+}
+
+impl<const N: usize, T: CellSerialize, P: ValuePlacement> HashmapE<N, T, P> {
+    /// Sets the value under `key`, adding the key if it is not there.
+    pub fn set<K: DictKey>(&mut self, key: &K, value: &T) -> Result<(), DictError> {
+        const { assert!(K::BITS == N, "key width differs from the dictionary's") };
+        let key = bits(&key.to_key_bits().ok_or(DictError::InvalidKey)?);
+        let store = |builder: &mut CellBuilder| P::store(value, builder);
+        self.root = Some(match &self.root {
+            None => edge(&key, N, store)?,
+            Some(root) => set_edge(root, &key, &store, false)?.expect("keys are added"),
+        });
+        Ok(())
+    }
+    
+    /// Sets the value under `key` only if the key is there; tells whether it was.
+    pub fn replace<K: DictKey>(&mut self, key: &K, value: &T) -> Result<bool, DictError> {
+        const { assert!(K::BITS == N, "key width differs from the dictionary's") };
+        let (Some(root), Some(key)) = (&self.root, key.to_key_bits()) else { return Ok(false) };
+        let store = |builder: &mut CellBuilder| P::store(value, builder);
+        let Some(root) = set_edge(root, &bits(&key), &store, true)? else { return Ok(false) };
+        self.root = Some(root);
+        Ok(true)
+    }
+}
+
+impl<const N: usize, T, P> Default for HashmapE<N, T, P> {
+    fn default() -> Self {
+        HashmapE {root: None, _value: PhantomData}
+    }
+}
+
+impl<const N: usize, T, P> CellSerialize for HashmapE<N, T, P> {
+    fn serialize(&self) -> Vec<String> {
+        match &self.root {
+            None => vec!["u 0 1bit".to_owned()],
+            Some(root) => {
+                let mut result = vec!["u 1 1bit".to_owned(), "ref {".to_owned()];
+                result.extend(root.serialize());
+                result.push("}".to_owned());
+                result
+            }
+        }
+    }
+    
+    fn store(&self, builder: &mut CellBuilder) -> Result<(), CellError> {
+        builder.store_bit(self.root.is_some())?;
+        if let Some(root) = &self.root {
+            builder.store_ref(Arc::clone(root))?;
+        }
+        Ok(())
+    }
+}
+
+impl<const N: usize, T, P> CellDeserialize for HashmapE<N, T, P> {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        let root = if slice.load_bit()? {Some(Arc::clone(slice.load_ref()?))} else {None};
+        Ok(HashmapE {root, _value: PhantomData})
+    }
+}
+
+/// Keys a scan is limited to.
+#[derive(Debug, Default, Clone)]
+struct Bounds {
+    prefix: Vec<bool>,
+    from: Option<Vec<bool>>,
+    to: Option<Vec<bool>>,
+}
+
+impl Bounds {
+    /// Whether some keys starting with `key` may be within bounds.
+    fn may_contain(&self, key: &[bool]) -> bool {
+        let common = key.len().min(self.prefix.len());
+        key[..common] == self.prefix[..common]
+            && self.from.as_ref().is_none_or(|from| key >= &from[..key.len()])
+            && self.to.as_ref().is_none_or(|to| key <= &to[..key.len()])
+    }
+    
+    /// Whether the whole key is within bounds.
+    fn contains(&self, key: &[bool]) -> bool {
+        self.may_contain(key) && self.to.as_deref() != Some(key)
+    }
+}
+
+/// Entries of a dictionary in order of keys, read as they are reached. Stops after the
+/// first malformed edge.
+#[derive(Debug, Clone)]
+pub struct Iter<'a, T, P = Inline> {
+    /// Edges yet to visit with key bits above them, the leftmost last.
+    stack: Vec<(&'a Cell, Vec<bool>)>,
+    key_len: usize,
+    bounds: Bounds,
+    _value: PhantomData<(T, P)>,
+}
+
+impl<'a, T, P> Iter<'a, T, P> {
+    fn new(root: Option<&'a Cell>, key_len: usize, bounds: Bounds) -> Self {
+        let stack = root.map(|root| (root, vec![])).into_iter().collect();
+        Iter {stack, key_len, bounds, _value: PhantomData}
+    }
+    
+    /// Visits the edge on top of the stack; gives its leaf if it is one within bounds.
+    fn visit(&mut self) -> Result<Option<(Vec<bool>, CellSlice<'a>)>, TlbError> {
+        let Some((cell, mut key)) = self.stack.pop() else { return Ok(None) };
+        let mut slice = edge_slice(cell)?;
+        key.extend(read_label(&mut slice, self.key_len - key.len())?);
+        if key.len() == self.key_len {
+            return Ok(self.bounds.contains(&key).then_some((key, slice)));
+        }
+        if self.bounds.may_contain(&key) {
+            let [left, right] = read_fork(&mut slice)?;
+            let mut right_key = key.clone();
+            right_key.push(true);
+            key.push(false);
+            self.stack.push((right.as_ref(), right_key));
+            self.stack.push((left.as_ref(), key));
+        }
+        Ok(None)
+    }
+}
+
+impl<T: CellDeserialize, P: ValuePlacement> Iterator for Iter<'_, T, P> {
+    type Item = Result<(RawBits, T), TlbError>;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.stack.is_empty() {
+            let leaf = self.visit().and_then(|leaf| {
+                leaf.map(|(key, slice)| Ok((key.into_iter().collect(), read_leaf::<T, P>(slice)?))).transpose()
+            });
+            match leaf {
+                Ok(None) => continue,
+                Ok(Some(entry)) => return Some(Ok(entry)),
+                Err(error) => {
+                    self.stack.clear();
+                    return Some(Err(error));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// `HashmapAugE n X Y`: `ahme_empty$0 extra:Y` or `ahme_root$1 root:^(HashmapAug n X Y)
+/// extra:Y`. Every fork and leaf of the trie keeps `Y` aggregated over the entries below it,
+/// like fees of all messages of a block; `extra` of the whole dictionary is read right away,
+/// entries are walked on access as in [`HashmapE`]. Values are in the rest of leaf cells,
+/// after their own `extra`.
+///
+/// Read-only: changing an entry needs the aggregates on its way recomputed, and the rule of
+/// aggregating differs from one `Y` to another.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashmapAugE<const N: usize, X, Y> {
+    root: Option<Arc<Cell>>,
+    pub extra: Y,
+    _value: PhantomData<X>,
+}
+
+impl<const N: usize, X, Y> HashmapAugE<N, X, Y> {
+    /// Root `HashmapAug n X Y` cell; none for an empty dictionary.
+    pub fn root(&self) -> Option<&Arc<Cell>> {
+        self.root.as_ref()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+}
+
+impl<const N: usize, X: CellDeserialize, Y: CellDeserialize> HashmapAugE<N, X, Y> {
+    /// Extra and value under `key`, found by following its bits from the root.
+    pub fn get<K: DictKey>(&self, key: &K) -> Result<Option<(Y, X)>, TlbError> {
+        const { assert!(K::BITS == N, "key width differs from the dictionary's") };
+        let (Some(mut cell), Some(key)) = (self.root.as_deref(), key.to_key_bits()) else { return Ok(None) };
+        let mut pos = 0;
+        loop {
+            let mut slice = edge_slice(cell)?;
+            let label = read_label(&mut slice, N - pos)?;
+            if label.iter().enumerate().any(|(i, &bit)| bit != key.bit(pos + i)) {
+                return Ok(None);
+            }
+            pos += label.len();
+            if pos == N {
+                return read_aug_leaf(slice).map(Some);
+            }
+            cell = read_aug_fork::<Y>(&mut slice)?[key.bit(pos) as usize].as_ref();
+            pos += 1;
+        }
+    }
+    
+    /// Entries with their extras in order of keys.
+    pub fn iter(&self) -> AugIter<'_, X, Y> {
+        let stack = self.root.as_deref().map(|root| (root, vec![])).into_iter().collect();
+        AugIter {stack, key_len: N, _value: PhantomData}
+    }
+}
+
+impl<const N: usize, X, Y: CellSerialize> CellSerialize for HashmapAugE<N, X, Y> {
+    fn serialize(&self) -> Vec<String> {
+        let mut result = match &self.root {
+            None => vec!["u 0 1bit".to_owned()],
+            Some(root) => {
+                let mut result = vec!["u 1 1bit".to_owned(), "ref {".to_owned()];
+                result.extend(root.serialize());
+                result.push("}".to_owned());
+                result
+            }
+        };
+        result.extend(self.extra.serialize());
+        result
+    }
+}
+
+impl<const N: usize, X, Y: CellDeserialize> CellDeserialize for HashmapAugE<N, X, Y> {
+    fn deserialize(slice: &mut CellSlice) -> Result<Self, TlbError> {
+        let root = if slice.load_bit()? {Some(Arc::clone(slice.load_ref()?))} else {None};
+        let extra = Y::deserialize(slice)?;
+        Ok(HashmapAugE {root, extra, _value: PhantomData})
+    }
+}
+
+/// Entries of [`HashmapAugE`] as keys, extras and values.
+pub struct AugIter<'a, X, Y> {
+    /// Edges yet to visit with key bits above them, the leftmost last.
+    stack: Vec<(&'a Cell, Vec<bool>)>,
+    key_len: usize,
+    _value: PhantomData<(X, Y)>,
+}
+
+impl<'a, X: CellDeserialize, Y: CellDeserialize> AugIter<'a, X, Y> {
+    fn visit(&mut self) -> Result<Option<(RawBits, Y, X)>, TlbError> {
+        let Some((cell, mut key)) = self.stack.pop() else { return Ok(None) };
+        let mut slice = edge_slice(cell)?;
+        key.extend(read_label(&mut slice, self.key_len - key.len())?);
+        if key.len() == self.key_len {
+            let (extra, value) = read_aug_leaf(slice)?;
+            return Ok(Some((key.into_iter().collect(), extra, value)));
+        }
+        let [left, right] = read_aug_fork::<Y>(&mut slice)?;
+        let mut right_key = key.clone();
+        right_key.push(true);
+        key.push(false);
+        self.stack.push((right.as_ref(), right_key));
+        self.stack.push((left.as_ref(), key));
+        Ok(None)
+    }
+}
+
+impl<X: CellDeserialize, Y: CellDeserialize> Iterator for AugIter<'_, X, Y> {
+    type Item = Result<(RawBits, Y, X), TlbError>;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.stack.is_empty() {
+            match self.visit() {
+                Ok(None) => continue,
+                Ok(Some(entry)) => return Some(Ok(entry)),
+                Err(error) => {
+                    self.stack.clear();
+                    return Some(Err(error));
+                }
+            }
+        }
+        None
+    }
+}
+
+fn bits(bits: &RawBits) -> Vec<bool> {
+    (0..bits.bit_len()).map(|i| bits.bit(i)).collect()
+}
+
+/// Slice of an edge cell; edges pruned from a Merkle proof cannot be walked.
+fn edge_slice(cell: &Cell) -> Result<CellSlice<'_>, TlbError> {
+    match cell.cell_type() {
+        CellType::PrunedBranch | CellType::Absent => Err(TlbError::Pruned),
+        _ => Ok(CellSlice::new(cell)),
+    }
+}
+
+/// Reads `hmn_leaf#_ value:X`, the rest of a leaf edge.
+fn read_leaf<T: CellDeserialize, P: ValuePlacement>(mut slice: CellSlice) -> Result<T, TlbError> {
+    let value = P::read(&mut slice)?;
+    if !slice.is_empty() {
+        return Err(TlbError::TrailingData);
+    }
+    Ok(value)
+}
+
+/// Reads `hmn_fork#_ left:^(Hashmap n X) right:^(Hashmap n X)`, the rest of a fork edge.
+fn read_fork<'a>(slice: &mut CellSlice<'a>) -> Result<[&'a Arc<Cell>; 2], TlbError> {
+    let fork = [slice.load_ref()?, slice.load_ref()?];
+    if !slice.is_empty() {
+        return Err(TlbError::TrailingData);
+    }
+    Ok(fork)
+}
+
+/// Reads `ahmn_leaf#_ extra:Y value:X`, the rest of a leaf edge of an augmented dictionary.
+fn read_aug_leaf<X: CellDeserialize, Y: CellDeserialize>(mut slice: CellSlice) -> Result<(Y, X), TlbError> {
+    let extra = Y::deserialize(&mut slice)?;
+    let value = X::deserialize(&mut slice)?;
+    if !slice.is_empty() {
+        return Err(TlbError::TrailingData);
+    }
+    Ok((extra, value))
+}
+
+/// Reads `ahmn_fork#_ left:^ right:^ extra:Y`; the extra is checked to be there and skipped.
+fn read_aug_fork<'a, Y: CellDeserialize>(slice: &mut CellSlice<'a>) -> Result<[&'a Arc<Cell>; 2], TlbError> {
+    let fork = [slice.load_ref()?, slice.load_ref()?];
+    Y::deserialize(slice)?;
+    if !slice.is_empty() {
+        return Err(TlbError::TrailingData);
+    }
+    Ok(fork)
+}
+
+/// Edge `cell` with `key` set to the value stored by `store`, `key` being the key bits below
+/// the edge. With `existing_only`, keys that are not there are left out, giving none.
+fn set_edge(cell: &Cell, key: &[bool], store: &dyn Fn(&mut CellBuilder) -> Result<(), CellError>,
+            existing_only: bool) -> Result<Option<Arc<Cell>>, DictError> {
+    let mut slice = edge_slice(cell)?;
+    let label = read_label(&mut slice, key.len())?;
+    let common = label.iter().zip(key).take_while(|(a, b)| a == b).count();
+    if common < label.len() {
+        if existing_only {
+            return Ok(None);
+        }
+        // keys part where the label ends: new fork there, the old edge keeps the rest of its label
+        let m = key.len() - common - 1;
+        let old = edge(&label[common + 1..], m, |builder| builder.store_remaining(&slice).map(|_| ()))?;
+        let new = edge(&key[common + 1..], m, store)?;
+        let [left, right] = if key[common] {[old, new]} else {[new, old]};
+        return Ok(Some(fork(&key[..common], key.len(), left, right)?));
+    }
+    if label.len() == key.len() {
+        return Ok(Some(edge(&label, key.len(), store)?));
+    }
+    let [left, right] = read_fork(&mut slice)?;
+    let side = key[label.len()];
+    let child = if side {right} else {left};
+    let Some(child) = set_edge(child, &key[label.len() + 1..], store, existing_only)? else { return Ok(None) };
+    let [left, right] = if side {[Arc::clone(left), child]} else {[child, Arc::clone(right)]};
+    Ok(Some(fork(&label, key.len(), left, right)?))
+}
+
+/// Edge `cell` without `key`, `key` being the key bits below the edge: none if the key is
+/// not there, otherwise the new edge, none if the key was the only one.
+fn remove_edge(cell: &Cell, key: &[bool]) -> Result<Option<Option<Arc<Cell>>>, DictError> {
+    let mut slice = edge_slice(cell)?;
+    let label = read_label(&mut slice, key.len())?;
+    if !key.starts_with(&label) {
+        return Ok(None);
+    }
+    if label.len() == key.len() {
+        return Ok(Some(None));
+    }
+    let [left, right] = read_fork(&mut slice)?;
+    let side = key[label.len()];
+    let (child, other) = if side {(right, left)} else {(left, right)};
+    let Some(child) = remove_edge(child, &key[label.len() + 1..])? else { return Ok(None) };
+    let edge = match child {
+        Some(child) => {
+            let [left, right] = if side {[Arc::clone(other), child]} else {[child, Arc::clone(other)]};
+            fork(&label, key.len(), left, right)?
+        }
+        None => {
+            // the fork is left with one branch, which is merged into this edge
+            let mut other = edge_slice(other)?;
+            let mut merged = label;
+            merged.push(!side);
+            merged.extend(read_label(&mut other, key.len() - merged.len())?);
+            edge(&merged, key.len(), |builder| builder.store_remaining(&other).map(|_| ()))?
+        }
+    };
+    Ok(Some(Some(edge)))
+}
+
+/// Edge labeled with `label`, with `m` key bits below its start, followed by what `rest`
+/// stores: a leaf value or fork references.
+fn edge(label: &[bool], m: usize, rest: impl FnOnce(&mut CellBuilder) -> Result<(), CellError>) -> Result<Arc<Cell>, CellError> {
+    let mut builder = CellBuilder::new();
+    write_label(&mut builder, label, m)?;
+    rest(&mut builder)?;
+    Ok(Arc::new(builder.build()))
+}
+
+fn fork(label: &[bool], m: usize, left: Arc<Cell>, right: Arc<Cell>) -> Result<Arc<Cell>, CellError> {
+    edge(label, m, |builder| builder.store_ref(left)?.store_ref(right).map(|_| ()))
+}
+
+/// Writes `HmLabel ~l m` in its shortest form, as TON nodes do: `hml_short$0` unless
+/// `hml_long$10` is shorter, and `hml_same$11` if it is shorter than both.
+fn write_label(builder: &mut CellBuilder, label: &[bool], m: usize) -> Result<(), CellError> {
+    let len_bits = (usize::BITS - m.leading_zeros()) as usize;
+    let (short, long, same) = (2 * label.len() + 2, 2 + len_bits + label.len(), 3 + len_bits);
+    if same < short.min(long) && label.iter().all(|&bit| bit == label[0]) {
+        builder.store_uint(0b11, 2)?.store_bit(label[0])?.store_uint(label.len() as u128, len_bits)?;
+        return Ok(());
+    }
+    if long < short {
+        builder.store_uint(0b10, 2)?.store_uint(label.len() as u128, len_bits)?;
+    } else {
+        builder.store_bit(false)?;
+        for _ in 0..label.len() {
+            builder.store_bit(true)?;
+        }
+        builder.store_bit(false)?;
+    }
+    for &bit in label {
+        builder.store_bit(bit)?;
+    }
+    Ok(())
+}
+
+/// Reads `HmLabel ~l m`: common bits of keys, at most `m` of them.
+fn read_label(slice: &mut CellSlice, m: usize) -> Result<Vec<bool>, TlbError> {
+    // `#<= m` takes as many bits as `m` itself
+    let len_bits = (usize::BITS - m.leading_zeros()) as usize;
+    let (len, same) = if !slice.load_bit()? {
+        // hml_short$0 len:(Unary ~n) s:(n * Bit)
+        let mut len = 0;
+        while slice.load_bit()? {
+            len += 1;
+        }
+        (len, None)
+    } else if !slice.load_bit()? {
+        // hml_long$10 n:(#<= m) s:(n * Bit)
+        (slice.load_uint(len_bits)? as usize, None)
+    } else {
+        // hml_same$11 v:Bit n:(#<= m)
+        let bit = slice.load_bit()?;
+        (slice.load_uint(len_bits)? as usize, Some(bit))
+    };
+    if len > m {
+        return Err(TlbError::InvalidValue);
+    }
+    match same {
+        Some(bit) => Ok(vec![bit; len]),
+        None => (0..len).map(|_| slice.load_bit()).collect(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::ton::cell::{Cell, CellBuilder};
+    use crate::ton::boc::to_boc;
+    use crate::ton::proof::proven_tree;
+    use crate::ton::{Address, Anycast};
+    
+    /// Cell of `(value, width)` pairs, then references.
+    fn cell(parts: &[(u128, usize)], refs: &[Cell]) -> Cell {
+        let mut builder = CellBuilder::new();
+        for &(value, width) in parts {
+            builder.store_uint(value, width).unwrap();
+        }
+        for child in refs {
+            builder.store_ref(Arc::new(child.clone())).unwrap();
+        }
+        builder.build()
+    }
+    
+    /// Trie of keys 0x01, 0x81 and 0x83 with values 0xaa, 0xbb and 0xcc.
+    fn three_keys() -> Cell {
+        // root: hml_short$0 of zero length, fork; left: hml_long$10 n=7 s=0000001, value 0xaa
+        let left = cell(&[(0b10, 2), (7, 3), (1, 7), (0xaa, 8)], &[]);
+        // right: hml_short$0 111110 s=00000, fork of hml_short$0 10 s=1 with values 0xbb and 0xcc
+        let forks = [cell(&[(0b0101, 4), (0xbb, 8)], &[]), cell(&[(0b0101, 4), (0xcc, 8)], &[])];
+        let right = cell(&[(0b0111110, 7), (0, 5)], &forks);
+        let root = cell(&[(0, 2)], &[left, right]);
+        cell(&[(1, 1)], &[root])
+    }
+    
+    fn keys(iter: Iter<u8>) -> Vec<u8> {
+        iter.map(|entry| entry.unwrap().0.data()[0]).collect()
+    }
+    
+    #[test]
+    fn reads_all_label_forms() {
+        // root: hml_short$0 of zero length, fork
+        // left: hml_long$10 n=7 s=0000001, value 0xaa; right: hml_short$0 1111111 0 s=0000001, value 0xbb
+        let left = cell(&[(0b10, 2), (7, 3), (1, 7), (0xaa, 8)], &[]);
+        let right = cell(&[(0, 1), (0b11111110, 8), (1, 7), (0xbb, 8)], &[]);
+        let root = cell(&[(0, 2)], &[left, right]);
+        let dict_cell = cell(&[(1, 1)], &[root]);
+        let dict = HashmapE::<8, u8>::from_cell(&dict_cell).unwrap();
+        assert_eq!(keys(dict.iter()), [0x01, 0x81]);
+        assert_eq!(dict.get(&0x81u8), Ok(Some(0xbb)));
+        
+        // hml_same$11 v=1 n=8: the only key is 0xff
+        let root = cell(&[(0b111, 3), (8, 4), (0xcc, 8)], &[]);
+        let dict = HashmapE::<8, u8>::from_cell(&cell(&[(1, 1)], &[root])).unwrap();
+        assert_eq!(dict.iter().collect::<Vec<_>>(), [Ok((RawBits::new(vec![0xff], 8), 0xcc))]);
+        
+        assert!(HashmapE::<8, u8>::from_cell(&cell(&[(0, 1)], &[])).unwrap().is_empty());
+    }
+    
+    #[test]
+    fn walks_keys_in_order() {
+        let dict = HashmapE::<8, u8>::from_cell(&three_keys()).unwrap();
+        assert_eq!(keys(dict.iter()), [0x01, 0x81, 0x83]);
+        assert_eq!(dict.get(&0x83u8), Ok(Some(0xcc)));
+        assert_eq!(dict.get(&0x82u8), Ok(None));
+        assert_eq!(dict.get(&0x03u8), Ok(None));
+        assert_eq!(dict.min_key::<u8>(), Ok(Some(0x01u8)));
+        assert_eq!(dict.max_key::<u8>(), Ok(Some(0x83u8)));
+        
+        assert_eq!(keys(dict.prefix(&RawBits::new(vec![0x80], 1))), [0x81, 0x83]);
+        assert_eq!(keys(dict.prefix(&RawBits::new(vec![0x82], 7))), [0x83]);
+        assert_eq!(keys(dict.prefix(&RawBits::new(vec![0x40], 2))), []);
+        assert_eq!(keys(dict.range(&0x01u8, &0x83u8)), [0x01, 0x81]);
+        assert_eq!(keys(dict.range(&0x02u8, &0xffu8)), [0x81, 0x83]);
+        assert_eq!(keys(dict.range(&0x82u8, &0x82u8)), []);
+        
+        let empty = HashmapE::<8, u8>::default();
+        assert_eq!((empty.min_key::<u8>(), empty.get(&0x01u8), empty.iter().count()), (Ok(None), Ok(None), 0));
+    }
+    
+    #[test]
+    fn typed_keys() {
+        assert_eq!((-1i32).to_key_bits(), Some(RawBits::new(vec![0xff; 4], 32)));
+        assert_eq!(i32::from_key_bits(&RawBits::new(vec![0xff, 0xff, 0xff, 0xfe], 32)), Ok(-2));
+        assert_eq!(Uint::<7>(5).to_key_bits(), Some(RawBits::new(vec![0x0a], 7)));
+        assert_eq!(Uint::<7>::from_key_bits(&RawBits::new(vec![0x0a], 7)), Ok(Uint(5)));
+        
+        // the only account, as hml_long$10 n=267
+        let address = MsgAddressInt::from(Address::new(-1, [0x33; 32]));
+        let bits = address.to_key_bits().unwrap();
+        assert_eq!(bits.bit_len(), 267);
+        assert_eq!(MsgAddressInt::from_key_bits(&bits), Ok(address.clone()));
+        let mut leaf = CellBuilder::new();
+        leaf.store_uint(0b10, 2).unwrap().store_uint(267, 9).unwrap();
+        leaf.store_bits(bits.data(), 267).unwrap().store_uint(0x55, 8).unwrap();
+        let dict = HashmapE::<267, u8>::from_cell(&cell(&[(1, 1)], &[leaf.build()])).unwrap();
+        assert_eq!(dict.get(&address), Ok(Some(0x55)));
+        assert_eq!(dict.min_key(), Ok(Some(address)));
+        
+        let anycast = MsgAddressInt::from(Address::new(0, [0x33; 32]).with_anycast(Anycast::new(RawBits::new(vec![0x80], 1))));
+        assert_eq!(anycast.to_key_bits(), None);
+        assert_eq!(dict.get(&anycast), Ok(None));
+    }
+    
+    #[test]
+    fn changes_rebuild_shortest_labels() {
+        let mut dict = HashmapE::<8, u8>::default();
+        for (key, value) in [(0x83u8, 0xcc), (0x01, 0xaa), (0x81, 0x00)] {
+            dict.set(&key, &value).unwrap();
+        }
+        assert_eq!(dict.replace(&0x81u8, &0xbb), Ok(true));
+        assert_eq!(dict.replace(&0x02u8, &0xbb), Ok(false));
+        // as `three_keys`, but the right edge is hml_same$11 v=0 n=5
+        let forks = [cell(&[(0b0101, 4), (0xbb, 8)], &[]), cell(&[(0b0101, 4), (0xcc, 8)], &[])];
+        let right = cell(&[(0b110, 3), (5, 3)], &forks);
+        let left = cell(&[(0b10, 2), (7, 3), (1, 7), (0xaa, 8)], &[]);
+        let expected = cell(&[(1, 1)], &[cell(&[(0, 2)], &[left, right])]);
+        assert_eq!(dict.to_cell(), Ok(expected.clone()));
+        let mut builder = CellBuilder::new();
+        builder.store_commands(&dict.serialize()).unwrap();
+        assert_eq!(builder.build(), expected);
+        assert_eq!(HashmapE::<8, u8>::from_cell(&expected), Ok(dict.clone()));
+        
+        // removing 0x01 merges the root into the right edge
+        assert_eq!(dict.remove(&0x01u8), Ok(true));
+        assert_eq!(dict.remove(&0x01u8), Ok(false));
+        let mut rest = HashmapE::<8, u8>::default();
+        rest.set(&0x81u8, &0xbb).unwrap();
+        rest.set(&0x83u8, &0xcc).unwrap();
+        assert_eq!(dict, rest);
+        assert_eq!(keys(dict.iter()), [0x81, 0x83]);
+        
+        for key in [0x81u8, 0x83] {
+            dict.remove(&key).unwrap();
+        }
+        assert!(dict.is_empty());
+        assert_eq!(dict.to_cell(), Ok(cell(&[(0, 1)], &[])));
+    }
+    
+    #[test]
+    fn changes_match_dictionaries_built_anew() {
+        let mut dict = HashmapE::<16, u32>::default();
+        let mut expected = std::collections::BTreeMap::new();
+        let mut seed = 12345u32;
+        for _ in 0..300 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let key = (seed >> 16) as u16 % 1000;
+            if seed.is_multiple_of(3) {
+                assert_eq!(dict.remove(&key), Ok(expected.remove(&key).is_some()));
+            } else {
+                dict.set(&key, &seed).unwrap();
+                expected.insert(key, seed);
+            }
+        }
+        let entries: Vec<_> = dict.iter().map(|entry| entry.unwrap()).collect();
+        let expected_entries: Vec<_> = expected.iter().map(|(key, value)| (key.to_key_bits().unwrap(), *value)).collect();
+        assert_eq!(entries, expected_entries);
+        
+        // tries are canonical: the same entries give the same cells whatever the history
+        let mut anew = HashmapE::<16, u32>::default();
+        for (key, value) in expected.iter().rev() {
+            anew.set(key, value).unwrap();
+        }
+        assert_eq!(dict.to_cell(), anew.to_cell());
+        let read = HashmapE::<16, u32>::from_cell(&dict.to_cell().unwrap()).unwrap();
+        assert_eq!(read.get(expected.keys().next().unwrap()), Ok(expected.values().next().copied()));
+    }
+    
+    #[test]
+    fn values_in_references() {
+        let mut dict = HashmapE::<8, u64, InRef>::default();
+        dict.set(&0xffu8, &0x1122).unwrap();
+        // hml_same$11 v=1 n=8, then ^uint64
+        let value = cell(&[(0x1122, 64)], &[]);
+        let expected = cell(&[(1, 1)], &[cell(&[(0b111, 3), (8, 4)], &[value])]);
+        assert_eq!(dict.to_cell(), Ok(expected.clone()));
+        
+        let read = HashmapE::<8, u64, InRef>::from_cell(&expected).unwrap();
+        assert_eq!(read.get(&0xffu8), Ok(Some(0x1122)));
+        // read inline, the leaf has a reference left over
+        assert_eq!(HashmapE::<8, u64>::from_cell(&expected).unwrap().get(&0xffu8), Err(TlbError::NotEnoughBits));
+    }
+    
+    #[test]
+    fn proofs_of_single_keys() {
+        let mut dict = HashmapE::<16, u64, InRef>::default();
+        for key in 0..200u16 {
+            dict.set(&(key * 3), &(key as u64 * 1000)).unwrap();
+        }
+        let root = dict.root().unwrap();
+        let proven = |proof: &Cell| {
+            let partial = proven_tree(proof, &root.repr_hash()).expect("proof of the dictionary");
+            HashmapE::<16, u64, InRef>::with_root(Some(Arc::new(partial.clone())))
+        };
+        
+        let proof = dict.prove(&30u16).unwrap().unwrap();
+        assert!(to_boc(&proof, false, false).len() < to_boc(root, false, false).len() / 10);
+        let partial = proven(&proof);
+        assert_eq!(partial.get(&30u16), Ok(Some(10_000)));
+        assert_eq!(partial.get(&33u16), Err(TlbError::Pruned));
+        assert_eq!(partial.iter().next(), Some(Err(TlbError::Pruned)));
+        
+        // 31 is not there: the proof holds the edge its path leaves the trie at
+        assert_eq!(proven(&dict.prove(&31u16).unwrap().unwrap()).get(&31u16), Ok(None));
+        assert_eq!(HashmapE::<16, u64, InRef>::default().prove(&31u16), Ok(None));
+    }
+    
+    #[test]
+    fn rejects_malformed_tries() {
+        // label longer than the key
+        let root = cell(&[(0b111, 3), (9, 4), (0xcc, 8)], &[]);
+        let dict = HashmapE::<8, u8>::from_cell(&cell(&[(1, 1)], &[root])).unwrap();
+        assert_eq!(dict.iter().collect::<Vec<_>>(), [Err(TlbError::InvalidValue)]);
+        // fork without its right subtree: only walks reaching it fail
+        let left = cell(&[(0b111, 3), (7, 3), (0xaa, 8)], &[]);
+        let root = cell(&[(0, 2)], &[left]);
+        let dict = HashmapE::<8, u8>::from_cell(&cell(&[(1, 1)], &[root])).unwrap();
+        assert_eq!(dict.get(&0x7fu8), Err(TlbError::NotEnoughRefs));
+        assert_eq!(dict.max_key::<u8>(), Err(TlbError::NotEnoughRefs));
+        assert_eq!(dict.clone().remove(&0x7fu8), Err(DictError::Malformed(TlbError::NotEnoughRefs)));
+        // leaf value followed by more data
+        let root = cell(&[(0b111, 3), (8, 4), (0xcc, 8), (1, 1)], &[]);
+        let dict = HashmapE::<8, u8>::from_cell(&cell(&[(1, 1)], &[root])).unwrap();
+        assert_eq!(dict.min_key::<u8>(), Ok(Some(0xffu8)));
+        assert_eq!(dict.get(&0xffu8), Err(TlbError::TrailingData));
+    }
+    
+    #[test]
+    fn augmented_dictionaries() {
+        // keys 0x01 and 0x81 with extras 3 and 5 and values 0xaa and 0xbb, summed up in forks
+        let left = cell(&[(0b10, 2), (7, 3), (1, 7), (3, 8), (0xaa, 8)], &[]);
+        let right = cell(&[(0b10, 2), (7, 3), (1, 7), (5, 8), (0xbb, 8)], &[]);
+        let root = cell(&[(0, 2), (8, 8)], &[left.clone(), right.clone()]);
+        let dict = HashmapAugE::<8, u8, u8>::from_cell(&cell(&[(1, 1), (8, 8)], &[root])).unwrap();
+        assert_eq!(dict.extra, 8);
+        assert_eq!(dict.iter().collect::<Vec<_>>(), [
+            Ok((RawBits::new(vec![0x01], 8), 3, 0xaa)),
+            Ok((RawBits::new(vec![0x81], 8), 5, 0xbb)),
+        ]);
+        assert_eq!(dict.get(&0x81u8), Ok(Some((5, 0xbb))));
+        assert_eq!(dict.get(&0x80u8), Ok(None));
+        assert_eq!(HashmapAugE::<8, u8, u8>::from_cell(&dict.to_cell().unwrap()), Ok(dict));
+        
+        let empty = HashmapAugE::<8, u8, u8>::from_cell(&cell(&[(0, 1), (0, 8)], &[])).unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(empty.iter().count(), 0);
+        
+        // a fork without its extra
+        let root = cell(&[(0, 2)], &[left, right]);
+        let dict = HashmapAugE::<8, u8, u8>::from_cell(&cell(&[(1, 1), (8, 8)], &[root])).unwrap();
+        assert_eq!(dict.iter().collect::<Vec<_>>(), [Err(TlbError::NotEnoughBits)]);
+    }
+}
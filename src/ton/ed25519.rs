@@ -0,0 +1,485 @@
+// (c) ProgramCrafter, 2024
+
+//! Ed25519 signature verification (RFC 8032), which wallets check external messages with.
+//!
+//! Only public data is processed, so arithmetic here is straightforward and not constant-time;
+//! it must not be reused for signing.
+
+
+/// Checks `signature` of `message` by `public_key`, as `CHECKSIGNU` of TVM does for 32-byte
+/// hashes. Keys that are not valid points and signatures with `S` not below the group
+/// order are rejected.
+pub fn verify(public_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool {
+    let Some(a) = Point::decode(public_key) else {
+        return false;
+    };
+    let (r, s) = signature.split_at(32);
+    let s = Scalar::from_le_bytes(s);
+    if !s.is_reduced() {
+        return false;
+    }
+    let mut hasher = Sha512::new();
+    hasher.update(r);
+    hasher.update(public_key);
+    hasher.update(message);
+    let k = Scalar::reduce(&hasher.finish());
+    
+    // [S]B - [k]A must be R
+    let check = Point::base().mul(&s).add(&a.negate().mul(&k));
+    check.encode() == r
+}
+
+
+/// Element of the field modulo `p = 2^255 - 19`, in five limbs of 51 bits. Limbs may exceed
+/// 51 bits a little between operations; [`Fe::to_bytes`] gives the canonical form.
+#[derive(Clone, Copy)]
+struct Fe([u64; 5]);
+
+const MASK: u64 = (1 << 51) - 1;
+
+impl Fe {
+    const ZERO: Fe = Fe([0; 5]);
+    const ONE: Fe = Fe([1, 0, 0, 0, 0]);
+    
+    /// Reads 255 bits, ignoring the top bit of the last byte.
+    fn from_bytes(bytes: &[u8; 32]) -> Fe {
+        let word = |i: usize| u64::from_le_bytes(bytes[8 * i..8 * i + 8].try_into().unwrap());
+        let (w0, w1, w2, w3) = (word(0), word(1), word(2), word(3));
+        Fe([
+            w0 & MASK,
+            (w0 >> 51 | w1 << 13) & MASK,
+            (w1 >> 38 | w2 << 26) & MASK,
+            (w2 >> 25 | w3 << 39) & MASK,
+            (w3 >> 12) & MASK,
+        ])
+    }
+    
+    fn to_bytes(self) -> [u8; 32] {
+        let mut t = self.carry().0;
+        // t < 2p now; subtract p if t + 19 reaches 2^255
+        let mut q = (t[0] + 19) >> 51;
+        for limb in &t[1..] {
+            q = (limb + q) >> 51;
+        }
+        t[0] += 19 * q;
+        for i in 0..4 {
+            t[i + 1] += t[i] >> 51;
+            t[i] &= MASK;
+        }
+        t[4] &= MASK;
+        
+        let words = [t[0] | t[1] << 51, t[1] >> 13 | t[2] << 38, t[2] >> 26 | t[3] << 25, t[3] >> 39 | t[4] << 12];
+        let mut bytes = [0u8; 32];
+        for (chunk, word) in bytes.chunks_exact_mut(8).zip(words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+    
+    /// Brings limbs back to 51 bits (the top carry wraps around multiplied by 19).
+    fn carry(self) -> Fe {
+        let mut t = self.0;
+        for i in 0..4 {
+            t[i + 1] += t[i] >> 51;
+            t[i] &= MASK;
+        }
+        t[0] += 19 * (t[4] >> 51);
+        t[4] &= MASK;
+        t[1] += t[0] >> 51;
+        t[0] &= MASK;
+        Fe(t)
+    }
+    
+    fn add(self, other: Fe) -> Fe {
+        Fe(std::array::from_fn(|i| self.0[i] + other.0[i])).carry()
+    }
+    
+    fn sub(self, other: Fe) -> Fe {
+        // 4p is added so that limbs do not underflow
+        const FOUR_P: [u64; 5] = [0x1fffffffffffb4, 0x1ffffffffffffc, 0x1ffffffffffffc, 0x1ffffffffffffc, 0x1ffffffffffffc];
+        Fe(std::array::from_fn(|i| self.0[i] + FOUR_P[i] - other.0[i])).carry()
+    }
+    
+    fn neg(self) -> Fe {
+        Fe::ZERO.sub(self)
+    }
+    
+    fn mul(self, other: Fe) -> Fe {
+        let [a0, a1, a2, a3, a4] = self.0.map(u128::from);
+        let [b0, b1, b2, b3, b4] = other.0.map(u128::from);
+        // 2^255 = 19, so limbs past the fifth come back multiplied by 19
+        let (b1_19, b2_19, b3_19, b4_19) = (b1 * 19, b2 * 19, b3 * 19, b4 * 19);
+        let r = [
+            a0 * b0 + a1 * b4_19 + a2 * b3_19 + a3 * b2_19 + a4 * b1_19,
+            a0 * b1 + a1 * b0 + a2 * b4_19 + a3 * b3_19 + a4 * b2_19,
+            a0 * b2 + a1 * b1 + a2 * b0 + a3 * b4_19 + a4 * b3_19,
+            a0 * b3 + a1 * b2 + a2 * b1 + a3 * b0 + a4 * b4_19,
+            a0 * b4 + a1 * b3 + a2 * b2 + a3 * b1 + a4 * b0,
+        ];
+        let mut t = [0u64; 5];
+        let mut carry = 0u128;
+        for i in 0..5 {
+            let value = r[i] + carry;
+            t[i] = value as u64 & MASK;
+            carry = value >> 51;
+        }
+        let folded = t[0] as u128 + carry * 19;
+        t[0] = folded as u64 & MASK;
+        t[1] += (folded >> 51) as u64;
+        Fe(t).carry()
+    }
+    
+    fn square(self) -> Fe {
+        self.mul(self)
+    }
+    
+    /// `self` to the power given in little-endian bytes.
+    fn pow(self, exponent: &[u8; 32]) -> Fe {
+        let mut result = Fe::ONE;
+        for bit in (0..256).rev() {
+            result = result.square();
+            if exponent[bit / 8] >> (bit % 8) & 1 == 1 {
+                result = result.mul(self);
+            }
+        }
+        result
+    }
+    
+    fn invert(self) -> Fe {
+        // p - 2 = 2^255 - 21
+        let mut exponent = [0xff; 32];
+        exponent[0] = 0xeb;
+        exponent[31] = 0x7f;
+        self.pow(&exponent)
+    }
+    
+    fn is_zero(self) -> bool {
+        self.to_bytes() == [0; 32]
+    }
+    
+    fn is_negative(self) -> bool {
+        self.to_bytes()[0] & 1 == 1
+    }
+    
+    fn equals(self, other: Fe) -> bool {
+        self.to_bytes() == other.to_bytes()
+    }
+    
+    /// `d = -121665 / 121666` of the curve.
+    const D: Fe = Fe([0x34dca135978a3, 0x1a8283b156ebd, 0x5e7a26001c029, 0x739c663a03cbb, 0x52036cee2b6ff]);
+    const D2: Fe = Fe([0x69b9426b2f159, 0x35050762add7a, 0x3cf44c0038052, 0x6738cc7407977, 0x2406d9dc56dff]);
+    /// Square root of -1: `2^((p - 1) / 4)`.
+    const SQRT_M1: Fe = Fe([0x61b274a0ea0b0, 0xd5a5fc8f189d, 0x7ef5e9cbd0c60, 0x78595a6804c9e, 0x2b8324804fc1d]);
+}
+
+
+/// Point of the twisted Edwards curve `-x^2 + y^2 = 1 + d x^2 y^2` in extended coordinates:
+/// `x = X/Z`, `y = Y/Z`, `x y = T/Z`.
+#[derive(Clone, Copy)]
+struct Point {
+    x: Fe,
+    y: Fe,
+    z: Fe,
+    t: Fe,
+}
+
+impl Point {
+    const IDENTITY: Point = Point {x: Fe::ZERO, y: Fe::ONE, z: Fe::ONE, t: Fe::ZERO};
+    
+    /// Base point, with `y = 4/5` and even `x`.
+    fn base() -> Point {
+        let mut encoded = [0x66; 32];
+        encoded[0] = 0x58;
+        Point::decode(&encoded).expect("base point is on the curve")
+    }
+    
+    /// Point from `y` with sign of `x` in the top bit (RFC 8032, 5.1.3).
+    fn decode(bytes: &[u8; 32]) -> Option<Point> {
+        let y = Fe::from_bytes(bytes);
+        let x_negative = bytes[31] >> 7 == 1;
+        let mut canonical = *bytes;
+        canonical[31] &= 0x7f;
+        if y.to_bytes() != canonical {
+            return None;
+        }
+        
+        // x^2 = u / v, x = u v^3 (u v^7)^((p - 5) / 8)
+        let y2 = y.square();
+        let u = y2.sub(Fe::ONE);
+        let v = Fe::D.mul(y2).add(Fe::ONE);
+        let v3 = v.square().mul(v);
+        let v7 = v3.square().mul(v);
+        let mut exponent = [0xff; 32];
+        exponent[0] = 0xfd;
+        exponent[31] = 0x0f;
+        let mut x = u.mul(v3).mul(u.mul(v7).pow(&exponent));
+        
+        let vx2 = v.mul(x.square());
+        if vx2.equals(u.neg()) {
+            x = x.mul(Fe::SQRT_M1);
+        } else if !vx2.equals(u) {
+            return None;
+        }
+        if x.is_zero() && x_negative {
+            return None;
+        }
+        if x.is_negative() != x_negative {
+            x = x.neg();
+        }
+        Some(Point {x, y, z: Fe::ONE, t: x.mul(y)})
+    }
+    
+    fn encode(&self) -> [u8; 32] {
+        let z_inv = self.z.invert();
+        let mut bytes = self.y.mul(z_inv).to_bytes();
+        bytes[31] |= (self.x.mul(z_inv).is_negative() as u8) << 7;
+        bytes
+    }
+    
+    /// Sum by unified formulas (`add-2008-hwcd-3`), also used for doubling.
+    fn add(&self, other: &Point) -> Point {
+        let a = self.y.sub(self.x).mul(other.y.sub(other.x));
+        let b = self.y.add(self.x).mul(other.y.add(other.x));
+        let c = self.t.mul(other.t).mul(Fe::D2);
+        let d = self.z.mul(other.z).add(self.z.mul(other.z));
+        let (e, f, g, h) = (b.sub(a), d.sub(c), d.add(c), b.add(a));
+        Point {x: e.mul(f), y: g.mul(h), z: f.mul(g), t: e.mul(h)}
+    }
+    
+    fn negate(&self) -> Point {
+        Point {x: self.x.neg(), t: self.t.neg(), ..*self}
+    }
+    
+    fn mul(&self, scalar: &Scalar) -> Point {
+        let mut result = Point::IDENTITY;
+        for bit in (0..256).rev() {
+            result = result.add(&result);
+            if scalar.0[bit / 64] >> (bit % 64) & 1 == 1 {
+                result = result.add(self);
+            }
+        }
+        result
+    }
+}
+
+
+/// Integer below 2^256 in little-endian 64-bit words, mostly reduced modulo the group order.
+struct Scalar([u64; 4]);
+
+/// Order of the base point, `2^252 + 27742317777372353535851937790883648493`.
+const ORDER: [u64; 4] = [0x5812631a5cf5d3ed, 0x14def9dea2f79cd6, 0, 0x1000000000000000];
+
+impl Scalar {
+    fn from_le_bytes(bytes: &[u8]) -> Scalar {
+        Scalar(std::array::from_fn(|i| u64::from_le_bytes(bytes[8 * i..8 * i + 8].try_into().unwrap())))
+    }
+    
+    fn is_reduced(&self) -> bool {
+        self.0.iter().rev().cmp(ORDER.iter().rev()) == std::cmp::Ordering::Less
+    }
+    
+    /// Little-endian 512-bit number modulo the group order, bit by bit.
+    fn reduce(bytes: &[u8; 64]) -> Scalar {
+        let mut r = Scalar([0; 4]);
+        for bit in (0..512).rev() {
+            // r < order < 2^253, so doubling does not overflow
+            let mut carry = (bytes[bit / 8] >> (bit % 8) & 1) as u64;
+            for word in &mut r.0 {
+                let next = *word >> 63;
+                *word = *word << 1 | carry;
+                carry = next;
+            }
+            if !r.is_reduced() {
+                let mut borrow = false;
+                for (word, order) in r.0.iter_mut().zip(ORDER) {
+                    let (value, b1) = word.overflowing_sub(order);
+                    let (value, b2) = value.overflowing_sub(borrow as u64);
+                    *word = value;
+                    borrow = b1 || b2;
+                }
+            }
+        }
+        r
+    }
+}
+
+
+const K: [u64; 80] = [
+    0x428a2f98d728ae22, 0x7137449123ef65cd, 0xb5c0fbcfec4d3b2f, 0xe9b5dba58189dbbc,
+    0x3956c25bf348b538, 0x59f111f1b605d019, 0x923f82a4af194f9b, 0xab1c5ed5da6d8118,
+    0xd807aa98a3030242, 0x12835b0145706fbe, 0x243185be4ee4b28c, 0x550c7dc3d5ffb4e2,
+    0x72be5d74f27b896f, 0x80deb1fe3b1696b1, 0x9bdc06a725c71235, 0xc19bf174cf692694,
+    0xe49b69c19ef14ad2, 0xefbe4786384f25e3, 0x0fc19dc68b8cd5b5, 0x240ca1cc77ac9c65,
+    0x2de92c6f592b0275, 0x4a7484aa6ea6e483, 0x5cb0a9dcbd41fbd4, 0x76f988da831153b5,
+    0x983e5152ee66dfab, 0xa831c66d2db43210, 0xb00327c898fb213f, 0xbf597fc7beef0ee4,
+    0xc6e00bf33da88fc2, 0xd5a79147930aa725, 0x06ca6351e003826f, 0x142929670a0e6e70,
+    0x27b70a8546d22ffc, 0x2e1b21385c26c926, 0x4d2c6dfc5ac42aed, 0x53380d139d95b3df,
+    0x650a73548baf63de, 0x766a0abb3c77b2a8, 0x81c2c92e47edaee6, 0x92722c851482353b,
+    0xa2bfe8a14cf10364, 0xa81a664bbc423001, 0xc24b8b70d0f89791, 0xc76c51a30654be30,
+    0xd192e819d6ef5218, 0xd69906245565a910, 0xf40e35855771202a, 0x106aa07032bbd1b8,
+    0x19a4c116b8d2d0c8, 0x1e376c085141ab53, 0x2748774cdf8eeb99, 0x34b0bcb5e19b48a8,
+    0x391c0cb3c5c95a63, 0x4ed8aa4ae3418acb, 0x5b9cca4f7763e373, 0x682e6ff3d6b2b8a3,
+    0x748f82ee5defb2fc, 0x78a5636f43172f60, 0x84c87814a1f0ab72, 0x8cc702081a6439ec,
+    0x90befffa23631e28, 0xa4506cebde82bde9, 0xbef9a3f7b2c67915, 0xc67178f2e372532b,
+    0xca273eceea26619c, 0xd186b8c721c0c207, 0xeada7dd6cde0eb1e, 0xf57d4f7fee6ed178,
+    0x06f067aa72176fba, 0x0a637dc5a2c898a6, 0x113f9804bef90dae, 0x1b710b35131c471b,
+    0x28db77f523047d84, 0x32caab7b40c72493, 0x3c9ebe0a15c9bebc, 0x431d67c49c100d4c,
+    0x4cc5d4becb3e42b6, 0x597f299cfc657e2a, 0x5fcb6fab3ad6faec, 0x6c44198c4a475817,
+];
+
+const INITIAL: [u64; 8] = [
+    0x6a09e667f3bcc908, 0xbb67ae8584caa73b, 0x3c6ef372fe94f82b, 0xa54ff53a5f1d36f1,
+    0x510e527fade682d1, 0x9b05688c2b3e6c1f, 0x1f83d9abfb41bd6b, 0x5be0cd19137e2179,
+];
+
+/// Incremental SHA-512 (FIPS 180-4), hashing `R`, key and message of a signature.
+struct Sha512 {
+    state: [u64; 8],
+    block: [u8; 128],
+    block_len: usize,
+    total_len: u128,
+}
+
+impl Sha512 {
+    fn new() -> Self {
+        Sha512 {state: INITIAL, block: [0; 128], block_len: 0, total_len: 0}
+    }
+    
+    fn update(&mut self, mut data: &[u8]) {
+        self.total_len += data.len() as u128;
+        while !data.is_empty() {
+            let taken = data.len().min(128 - self.block_len);
+            self.block[self.block_len..self.block_len + taken].copy_from_slice(&data[..taken]);
+            self.block_len += taken;
+            data = &data[taken..];
+            if self.block_len == 128 {
+                compress(&mut self.state, &self.block);
+                self.block_len = 0;
+            }
+        }
+    }
+    
+    fn finish(mut self) -> [u8; 64] {
+        let bit_len = self.total_len * 8;
+        // a 1 bit, zeros up to 112 bytes of the last block, then length in bits
+        self.update(&[0x80]);
+        while self.block_len != 112 {
+            self.update(&[0]);
+        }
+        self.update(&bit_len.to_be_bytes());
+        let mut hash = [0u8; 64];
+        for (chunk, word) in hash.chunks_exact_mut(8).zip(self.state) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        hash
+    }
+}
+
+fn compress(state: &mut [u64; 8], block: &[u8; 128]) {
+    let mut w = [0u64; 80];
+    for (word, chunk) in w.iter_mut().zip(block.chunks_exact(8)) {
+        *word = u64::from_be_bytes(chunk.try_into().unwrap());
+    }
+    for i in 16..80 {
+        let s0 = w[i - 15].rotate_right(1) ^ w[i - 15].rotate_right(8) ^ (w[i - 15] >> 7);
+        let s1 = w[i - 2].rotate_right(19) ^ w[i - 2].rotate_right(61) ^ (w[i - 2] >> 6);
+        w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+    }
+    
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for i in 0..80 {
+        let s1 = e.rotate_right(14) ^ e.rotate_right(18) ^ e.rotate_right(41);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(K[i]).wrapping_add(w[i]);
+        let s0 = a.rotate_right(28) ^ a.rotate_right(34) ^ a.rotate_right(39);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+        (h, g, f, e, d, c, b, a) = (g, f, e, d.wrapping_add(t1), c, b, a, t1.wrapping_add(t2));
+    }
+    for (word, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *word = word.wrapping_add(value);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn fe(value: u64) -> Fe {
+        Fe([value & MASK, value >> 51, 0, 0, 0])
+    }
+    
+    fn bytes(hex: &str) -> Vec<u8> {
+        (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+    }
+    
+    fn unhex<const N: usize>(hex: &str) -> [u8; N] {
+        bytes(hex).try_into().unwrap()
+    }
+    
+    #[test]
+    fn sha512_known_digests() {
+        let sha512 = |data: &[u8]| {
+            let mut hasher = Sha512::new();
+            for chunk in data.chunks(7) {
+                hasher.update(chunk);
+            }
+            hasher.finish()
+        };
+        assert_eq!(sha512(b"abc"), unhex("ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"));
+        assert_eq!(sha512(&[b'a'; 200]), unhex("4b11459c33f52a22ee8236782714c150a3b2c60994e9acee17fe68947a3e6789f31e7668394592da7bef827cddca88c4e6f86e4df7ed1ae6cba71f3e98faee9f"));
+    }
+    
+    #[test]
+    fn rfc8032_vectors() {
+        // TEST 1, 2 and 3 of RFC 8032, section 7.1
+        let vectors = [
+            ("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a", "",
+             "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b"),
+            ("3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c", "72",
+             "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00"),
+            ("fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025", "af82",
+             "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a"),
+        ];
+        for (key, message, signature) in vectors {
+            let (key, signature) = (unhex::<32>(key), unhex::<64>(signature));
+            let message = bytes(message);
+            assert!(verify(&key, &message, &signature), "{message:?}");
+            
+            let mut wrong_message = message.clone();
+            wrong_message.push(0);
+            assert!(!verify(&key, &wrong_message, &signature));
+            let mut wrong_signature = signature;
+            wrong_signature[5] ^= 1;
+            assert!(!verify(&key, &message, &wrong_signature));
+            // S + order is the same signature mathematically, but not canonical
+            let mut unreduced = signature;
+            let mut carry = 0u16;
+            for (byte, order) in unreduced[32..].iter_mut().zip(ORDER.iter().flat_map(|word| word.to_le_bytes())) {
+                let sum = *byte as u16 + order as u16 + carry;
+                *byte = sum as u8;
+                carry = sum >> 8;
+            }
+            assert!(!verify(&key, &message, &unreduced));
+        }
+    }
+    
+    #[test]
+    fn field_and_points() {
+        assert!(fe(7).mul(fe(7).invert()).equals(Fe::ONE));
+        assert!(Fe::SQRT_M1.square().equals(Fe::ONE.neg()));
+        assert!(Fe::D.mul(fe(121666)).equals(fe(121665).neg()));
+        assert!(Fe::D.add(Fe::D).equals(Fe::D2));
+        let base = Point::base();
+        let mut encoded = [0x66; 32];
+        encoded[0] = 0x58;
+        assert_eq!(base.encode(), encoded);
+        // [order]B is the identity
+        assert_eq!(base.mul(&Scalar(ORDER)).encode(), Point::IDENTITY.encode());
+        // y = 2 has no point on the curve
+        let mut not_on_curve = [0; 32];
+        not_on_curve[0] = 2;
+        assert!(Point::decode(&not_on_curve).is_none());
+    }
+}
@@ -0,0 +1,133 @@
+// (c) ProgramCrafter, 2024
+
+//! Merkle proofs: a tree with the parts of no interest replaced by pruned branches, proving
+//! the rest to anyone knowing the hash of the whole tree (e.g. a shard state root).
+
+use std::sync::Arc;
+
+use super::cell::{Cell, CellBuilder, CellError, CellType};
+
+
+/// Pruned branch standing for `cell` in a Merkle proof: keeps only its level-0 hash and depth.
+/// `cell` must not have pruned branches itself (level 0).
+pub fn prune(cell: &Cell) -> Result<Cell, CellError> {
+    if cell.level() != 0 {
+        return Err(CellError::InvalidExotic);
+    }
+    let mut builder = CellBuilder::new();
+    builder.store_uint(1, 8)?.store_uint(1, 8)?;
+    builder.store_bits(&cell.repr_hash(), 256)?.store_uint(cell.repr_depth() as u128, 16)?;
+    builder.build_exotic()
+}
+
+/// Merkle proof for `root`, keeping the cells for which `keep` returns true and pruning
+/// the others with their subtrees. `keep` gets each visited cell with its path: indices
+/// of references leading to it from the root. Subtrees of pruned cells are not visited.
+/// Cells to prune must have no pruned branches below, see [`prune`].
+pub fn merkle_proof(root: &Cell, mut keep: impl FnMut(&[usize], &Cell) -> bool) -> Result<Cell, CellError> {
+    fn visit(cell: &Cell, path: &mut Vec<usize>, keep: &mut impl FnMut(&[usize], &Cell) -> bool) -> Result<Cell, CellError> {
+        // absent cells have no data to keep, only hashes
+        if cell.cell_type() == CellType::Absent || !keep(path, cell) {
+            return prune(cell);
+        }
+        let mut refs = Vec::with_capacity(cell.refs().len());
+        for (index, child) in cell.refs().iter().enumerate() {
+            path.push(index);
+            refs.push(Arc::new(visit(child, path, keep)?));
+            path.pop();
+        }
+        let mut builder = CellBuilder::new();
+        builder.store_bits(cell.data(), cell.bit_len())?;
+        for child in refs {
+            builder.store_ref(child)?;
+        }
+        if cell.is_exotic() {builder.build_exotic()} else {Ok(builder.build())}
+    }
+    
+    let partial = visit(root, &mut vec![], &mut keep)?;
+    let mut builder = CellBuilder::new();
+    builder.store_uint(3, 8)?.store_bits(&partial.hash(0), 256)?.store_uint(partial.depth(0) as u128, 16)?;
+    builder.store_ref(Arc::new(partial))?;
+    builder.build_exotic()
+}
+
+/// Merkle proof keeping the cells along `paths` (indices of references from the root) and
+/// whole subtrees at their ends; see [`merkle_proof`].
+pub fn merkle_proof_of_paths(root: &Cell, paths: &[&[usize]]) -> Result<Cell, CellError> {
+    merkle_proof(root, |path, _| {
+        paths.iter().any(|keep| keep.starts_with(path) || path.starts_with(keep))
+    })
+}
+
+/// Partial tree of `proof`, if it is a Merkle proof of the tree with hash `root_hash`.
+/// Values read from the partial tree are then known to be in the original one; reading
+/// a pruned branch gives no data.
+pub fn proven_tree<'a>(proof: &'a Cell, root_hash: &[u8; 32]) -> Option<&'a Cell> {
+    // proofs are checked against their child when built, so only the hash is left
+    let partial = proof.refs().first()?;
+    (proof.cell_type() == CellType::MerkleProof && partial.hash(0) == *root_hash).then_some(partial)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ton::boc::{from_boc, to_boc};
+    
+    fn cell(value: u128, refs: &[&Arc<Cell>]) -> Arc<Cell> {
+        let mut builder = CellBuilder::new();
+        builder.store_uint(value, 16).unwrap();
+        for child in refs {
+            builder.store_ref(Arc::clone(child)).unwrap();
+        }
+        Arc::new(builder.build())
+    }
+    
+    /// Root with two forks of two leaves each.
+    fn tree() -> Arc<Cell> {
+        let forks: Vec<_> = (0..2).map(|i| {
+            cell(10 + i, &[&cell(100 + 2 * i, &[]), &cell(101 + 2 * i, &[])])
+        }).collect();
+        cell(1, &[&forks[0], &forks[1]])
+    }
+    
+    #[test]
+    fn proof_keeps_paths_and_prunes_the_rest() {
+        let root = tree();
+        let proof = merkle_proof_of_paths(&root, &[&[1, 0]]).unwrap();
+        assert_eq!(proof.cell_type(), CellType::MerkleProof);
+        assert_eq!(proof.level(), 0);
+        
+        let partial = proven_tree(&proof, &root.repr_hash()).unwrap();
+        assert_eq!(partial.level(), 1);
+        assert_eq!(partial.data(), root.data());
+        let [left, right] = partial.refs() else { panic!("root has two references") };
+        assert_eq!(left.cell_type(), CellType::PrunedBranch);
+        assert_eq!(left.hash(0), root.refs()[0].repr_hash());
+        assert_eq!(right.refs()[0].data(), root.refs()[1].refs()[0].data());
+        assert_eq!(right.refs()[1].cell_type(), CellType::PrunedBranch);
+        
+        assert_eq!(proven_tree(&proof, &root.refs()[0].repr_hash()), None);
+        assert_eq!(from_boc(&to_boc(&proof, false, true)), Ok(vec![proof]));
+    }
+    
+    #[test]
+    fn proof_by_predicate() {
+        let root = tree();
+        // odd leaves, and cells on the way to them
+        let proof = merkle_proof(&root, |path, cell| path.len() < 2 || cell.data()[1] % 2 == 1).unwrap();
+        let partial = proven_tree(&proof, &root.repr_hash()).unwrap();
+        for fork in partial.refs() {
+            assert_eq!(fork.refs()[0].cell_type(), CellType::PrunedBranch);
+            assert_eq!(fork.refs()[1].cell_type(), CellType::Ordinary);
+        }
+        
+        // everything pruned: the proof shows only the hash
+        let proof = merkle_proof(&root, |_, _| false).unwrap();
+        assert_eq!(proven_tree(&proof, &root.repr_hash()).unwrap().cell_type(), CellType::PrunedBranch);
+        
+        // partial trees cannot be pruned again
+        let partial = proven_tree(&proof, &root.repr_hash()).unwrap();
+        assert_eq!(prune(partial), Err(CellError::InvalidExotic));
+    }
+}
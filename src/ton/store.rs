@@ -0,0 +1,445 @@
+// (c) ProgramCrafter, 2024
+
+//! Storage of cells by representation hash, for indexers keeping many blocks: subtrees
+//! shared between them (accounts, configs, dictionaries) are stored once.
+//!
+//! Trees still in use are registered as roots; [`CellStore::sweep`] drops the rest, so that
+//! e.g. a window of recent blocks takes bounded space.
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use super::boc::{from_boc, to_boc, BocError};
+use super::cell::Cell;
+
+
+/// Reason why cells could not be stored or loaded.
+#[derive(Debug)]
+pub enum StoreError {
+    /// Backend failed to read or write.
+    Io(std::io::Error),
+    /// Stored cell is not a valid bag of cells.
+    Corrupted(BocError),
+    /// Stored cell refers to a cell that is not in the store, or has another hash than
+    /// it is stored under.
+    Inconsistent,
+}
+
+impl std::fmt::Display for StoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreError::Io(error) => write!(f, "cell store I/O failed: {error}"),
+            StoreError::Corrupted(error) => write!(f, "stored cell is corrupted: {error}"),
+            StoreError::Inconsistent => f.write_str("cell store misses cells or has them under wrong hashes"),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<std::io::Error> for StoreError {
+    fn from(error: std::io::Error) -> Self {
+        StoreError::Io(error)
+    }
+}
+
+
+/// Cells keyed by representation hash. Inserting a cell stores the whole tree below it;
+/// cells already in the store are kept and not stored again.
+pub trait CellStore {
+    /// Stores `cell` and every cell below it, returning its hash.
+    fn insert(&mut self, cell: &Cell) -> Result<[u8; 32], StoreError>;
+    
+    /// Cell with representation hash `hash`, with its whole tree.
+    fn get(&self, hash: &[u8; 32]) -> Result<Option<Arc<Cell>>, StoreError>;
+    
+    fn contains(&self, hash: &[u8; 32]) -> Result<bool, StoreError>;
+    
+    /// Keeps the stored tree of `hash` from being swept. Roots are counted: one added twice
+    /// stays until removed twice.
+    fn add_root(&mut self, hash: &[u8; 32]) -> Result<(), StoreError>;
+    
+    /// Undoes one [`CellStore::add_root`] of `hash`; returns whether it was a root.
+    fn remove_root(&mut self, hash: &[u8; 32]) -> Result<bool, StoreError>;
+    
+    /// Drops cells not reachable from any root, returning how many were dropped.
+    fn sweep(&mut self) -> Result<usize, StoreError>;
+}
+
+/// Adds one to the count of `hash`.
+fn count_root(roots: &mut HashMap<[u8; 32], usize>, hash: &[u8; 32]) {
+    *roots.entry(*hash).or_default() += 1;
+}
+
+/// Subtracts one from the count of `hash`, forgetting it at zero.
+fn uncount_root(roots: &mut HashMap<[u8; 32], usize>, hash: &[u8; 32]) -> bool {
+    let Some(count) = roots.get_mut(hash) else {
+        return false;
+    };
+    *count -= 1;
+    if *count == 0 {
+        roots.remove(hash);
+    }
+    true
+}
+
+/// Hashes of cells reachable from `roots`, with `children` listing hashes of references.
+fn mark(roots: impl IntoIterator<Item = [u8; 32]>,
+        mut children: impl FnMut(&[u8; 32]) -> Result<Vec<[u8; 32]>, StoreError>) -> Result<HashSet<[u8; 32]>, StoreError> {
+    let mut reachable = HashSet::new();
+    let mut pending: Vec<_> = roots.into_iter().collect();
+    while let Some(hash) = pending.pop() {
+        if reachable.insert(hash) {
+            pending.extend(children(&hash)?);
+        }
+    }
+    Ok(reachable)
+}
+
+
+/// Store keeping cells in memory. Trees inserted into it are rebuilt of cells already
+/// stored, so equal subtrees of different trees are shared through one [`Arc`].
+#[derive(Debug, Default)]
+pub struct MemoryCellStore {
+    cells: HashMap<[u8; 32], Arc<Cell>>,
+    roots: HashMap<[u8; 32], usize>,
+}
+
+impl MemoryCellStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+    
+    /// Stored copy of `cell`, inserting the cell (and its tree) if there is none. Cells built
+    /// through it, e.g. with [`CellBuilder::build_interned`], take memory only once for each
+    /// distinct subtree.
+    ///
+    /// [`CellBuilder::build_interned`]: super::cell::CellBuilder::build_interned
+    pub fn intern(&mut self, cell: Cell) -> Arc<Cell> {
+        self.intern_shared(&Arc::new(cell))
+    }
+    
+    fn intern_shared(&mut self, cell: &Arc<Cell>) -> Arc<Cell> {
+        if let Some(stored) = self.cells.get(&cell.repr_hash()) {
+            return stored.clone();
+        }
+        let refs: Vec<_> = cell.refs().iter().map(|child| self.intern_shared(child)).collect();
+        let stored = if refs.iter().zip(cell.refs()).all(|(stored, child)| Arc::ptr_eq(stored, child)) {
+            cell.clone()
+        } else {
+            Arc::new(cell.with_refs(refs))
+        };
+        self.cells.insert(cell.repr_hash(), stored.clone());
+        stored
+    }
+}
+
+impl CellStore for MemoryCellStore {
+    fn insert(&mut self, cell: &Cell) -> Result<[u8; 32], StoreError> {
+        if !self.cells.contains_key(&cell.repr_hash()) {
+            self.intern(cell.clone());
+        }
+        Ok(cell.repr_hash())
+    }
+    
+    fn get(&self, hash: &[u8; 32]) -> Result<Option<Arc<Cell>>, StoreError> {
+        Ok(self.cells.get(hash).cloned())
+    }
+    
+    fn contains(&self, hash: &[u8; 32]) -> Result<bool, StoreError> {
+        Ok(self.cells.contains_key(hash))
+    }
+    
+    fn add_root(&mut self, hash: &[u8; 32]) -> Result<(), StoreError> {
+        if !self.cells.contains_key(hash) {
+            return Err(StoreError::Inconsistent);
+        }
+        count_root(&mut self.roots, hash);
+        Ok(())
+    }
+    
+    fn remove_root(&mut self, hash: &[u8; 32]) -> Result<bool, StoreError> {
+        Ok(uncount_root(&mut self.roots, hash))
+    }
+    
+    fn sweep(&mut self) -> Result<usize, StoreError> {
+        let reachable = mark(self.roots.keys().copied(), |hash| {
+            Ok(self.cells[hash].refs().iter().map(|child| child.repr_hash()).collect())
+        })?;
+        let before = self.cells.len();
+        self.cells.retain(|hash, _| reachable.contains(hash));
+        Ok(before - self.cells.len())
+    }
+}
+
+
+/// Store keeping each cell in its own file of a directory, named by the hash in hex. A file
+/// holds bag of cells with the cell itself and its references as absent cells; cells are
+/// written after their children, so a stored cell always has its whole tree stored.
+///
+/// Nothing is cached: [`CellStore::get`] reads the tree from disk each time. Roots with
+/// their counts are kept in file `roots`, rewritten on each change.
+#[derive(Debug)]
+pub struct DiskCellStore {
+    dir: PathBuf,
+    roots: HashMap<[u8; 32], usize>,
+}
+
+impl DiskCellStore {
+    /// Opens store in `dir`, creating the directory if needed.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self, StoreError> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        let roots = match std::fs::read(dir.join("roots")) {
+            Ok(bytes) if bytes.len() % 40 == 0 => bytes.chunks(40).map(|entry| {
+                let (hash, count) = entry.split_at(32);
+                (hash.try_into().unwrap(), u64::from_be_bytes(count.try_into().unwrap()) as usize)
+            }).collect(),
+            Ok(_) => return Err(StoreError::Inconsistent),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(error) => return Err(error.into()),
+        };
+        Ok(DiskCellStore {dir, roots})
+    }
+    
+    fn path(&self, hash: &[u8; 32]) -> PathBuf {
+        self.dir.join(hash_to_hex(hash))
+    }
+    
+    /// Writes roots as 32-byte hashes, each followed by its count as 64-bit integer.
+    fn save_roots(&self) -> Result<(), StoreError> {
+        let bytes: Vec<u8> = self.roots.iter()
+            .flat_map(|(hash, &count)| hash.iter().copied().chain((count as u64).to_be_bytes()))
+            .collect();
+        let path = self.dir.join("roots");
+        std::fs::write(path.with_extension("partial"), bytes)?;
+        std::fs::rename(path.with_extension("partial"), path)?;
+        Ok(())
+    }
+    
+    /// Cell stored under `hash`, with its references as absent cells.
+    fn load_shallow(&self, hash: &[u8; 32]) -> Result<Option<Cell>, StoreError> {
+        let bytes = match std::fs::read(self.path(hash)) {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+        let cell = from_boc(&bytes).map_err(StoreError::Corrupted)?.pop().ok_or(StoreError::Inconsistent)?;
+        if cell.repr_hash() != *hash {
+            return Err(StoreError::Inconsistent);
+        }
+        Ok(Some(cell))
+    }
+}
+
+impl CellStore for DiskCellStore {
+    fn insert(&mut self, cell: &Cell) -> Result<[u8; 32], StoreError> {
+        let hash = cell.repr_hash();
+        if self.contains(&hash)? {
+            return Ok(hash);
+        }
+        for child in cell.refs() {
+            self.insert(child)?;
+        }
+        let shallow = cell.with_refs(cell.refs().iter().map(|child| Arc::new(child.to_absent())).collect());
+        // written under another name first, so that a file under the hash is always complete
+        let path = self.path(&hash);
+        let partial = path.with_extension("partial");
+        std::fs::write(&partial, to_boc(&shallow, false, true))?;
+        std::fs::rename(partial, path)?;
+        Ok(hash)
+    }
+    
+    fn get(&self, hash: &[u8; 32]) -> Result<Option<Arc<Cell>>, StoreError> {
+        let Some(shallow) = self.load_shallow(hash)? else {
+            return Ok(None);
+        };
+        let refs = shallow.refs().iter()
+            .map(|child| self.get(&child.repr_hash())?.ok_or(StoreError::Inconsistent))
+            .collect::<Result<_, _>>()?;
+        Ok(Some(Arc::new(shallow.with_refs(refs))))
+    }
+    
+    fn contains(&self, hash: &[u8; 32]) -> Result<bool, StoreError> {
+        Ok(self.path(hash).try_exists()?)
+    }
+    
+    fn add_root(&mut self, hash: &[u8; 32]) -> Result<(), StoreError> {
+        if !self.contains(hash)? {
+            return Err(StoreError::Inconsistent);
+        }
+        count_root(&mut self.roots, hash);
+        self.save_roots()
+    }
+    
+    fn remove_root(&mut self, hash: &[u8; 32]) -> Result<bool, StoreError> {
+        if !uncount_root(&mut self.roots, hash) {
+            return Ok(false);
+        }
+        self.save_roots()?;
+        Ok(true)
+    }
+    
+    fn sweep(&mut self) -> Result<usize, StoreError> {
+        let reachable = mark(self.roots.keys().copied(), |hash| {
+            let cell = self.load_shallow(hash)?.ok_or(StoreError::Inconsistent)?;
+            Ok(cell.refs().iter().map(|child| child.repr_hash()).collect())
+        })?;
+        let reachable: HashSet<String> = reachable.iter().map(hash_to_hex).collect();
+        let mut dropped = 0;
+        for entry in std::fs::read_dir(&self.dir)? {
+            let name = entry?.file_name().into_string().unwrap_or_default();
+            // cells only: `roots` and partially written files are not 64 hex digits
+            if name.len() == 64 && name.bytes().all(|c| c.is_ascii_hexdigit()) && !reachable.contains(&name) {
+                std::fs::remove_file(self.dir.join(name))?;
+                dropped += 1;
+            }
+        }
+        Ok(dropped)
+    }
+}
+
+fn hash_to_hex(hash: &[u8; 32]) -> String {
+    hash.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ton::cell::CellBuilder;
+    
+    /// Two trees sharing the subtree of `shared`.
+    fn trees(shared: u128) -> (Cell, Cell) {
+        let leaf = || {
+            let mut leaf = CellBuilder::new();
+            leaf.store_uint(shared, 32).unwrap();
+            Arc::new(leaf.build())
+        };
+        let mut first = CellBuilder::new();
+        first.store_uint(1, 8).unwrap().store_ref(leaf()).unwrap();
+        let mut second = CellBuilder::new();
+        second.store_uint(2, 8).unwrap().store_ref(leaf()).unwrap();
+        (first.build(), second.build())
+    }
+    
+    #[test]
+    fn memory_store_shares_subtrees() {
+        let (first, second) = trees(0xdead);
+        let mut store = MemoryCellStore::new();
+        let first_hash = store.insert(&first).unwrap();
+        let second_hash = store.insert(&second).unwrap();
+        assert_eq!(store.insert(&first).unwrap(), first_hash);
+        assert_eq!(store.len(), 3);
+        assert!(store.contains(&first.refs()[0].repr_hash()).unwrap());
+        
+        let (first, second) = (store.get(&first_hash).unwrap().unwrap(), store.get(&second_hash).unwrap().unwrap());
+        assert_eq!(first.repr_hash(), first_hash);
+        assert!(Arc::ptr_eq(&first.refs()[0], &second.refs()[0]));
+        assert_eq!(store.get(&[0; 32]).unwrap(), None);
+    }
+    
+    #[test]
+    fn builder_interns_equal_cells() {
+        let mut store = MemoryCellStore::new();
+        let leaf = |store: &mut MemoryCellStore, value| {
+            let mut leaf = CellBuilder::new();
+            leaf.store_uint(value, 8).unwrap();
+            leaf.build_interned(store)
+        };
+        let (first, second) = (leaf(&mut store, 7), leaf(&mut store, 7));
+        assert!(Arc::ptr_eq(&first, &second));
+        
+        // full binary tree of depth 4 over equal leaves: one distinct cell per level
+        let mut level = vec![first; 16];
+        while level.len() > 1 {
+            level = level.chunks(2).map(|pair| {
+                let mut fork = CellBuilder::new();
+                fork.store_ref(pair[0].clone()).unwrap().store_ref(pair[1].clone()).unwrap();
+                fork.build_interned(&mut store)
+            }).collect();
+        }
+        assert_eq!(store.len(), 5);
+        assert!(Arc::ptr_eq(&level[0].refs()[0], &level[0].refs()[1]));
+        assert_ne!(leaf(&mut store, 8), second);
+        assert_eq!(store.len(), 6);
+    }
+    
+    #[test]
+    fn disk_store_round_trip() {
+        let dir = std::env::temp_dir().join(format!("tlb-cell-store-{}", std::process::id()));
+        let (first, second) = trees(0xbeef);
+        let mut store = DiskCellStore::open(&dir).unwrap();
+        let first_hash = store.insert(&first).unwrap();
+        store.insert(&second).unwrap();
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+        
+        let reopened = DiskCellStore::open(&dir).unwrap();
+        assert_eq!(reopened.get(&first_hash).unwrap().as_deref(), Some(&first));
+        assert!(reopened.contains(&second.repr_hash()).unwrap());
+        assert!(reopened.get(&[0; 32]).unwrap().is_none());
+        
+        // leaf file replaced by another cell: parent cannot be read back
+        let leaf_hash = first.refs()[0].repr_hash();
+        std::fs::copy(reopened.path(&first_hash), reopened.path(&leaf_hash)).unwrap();
+        assert!(matches!(reopened.get(&first_hash), Err(StoreError::Inconsistent)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    /// Inserts both trees of [`trees`] into `store`, registers them as roots and drops them
+    /// one by one, checking stored cells in between.
+    fn sweep_keeps_cells_of_roots(store: &mut dyn CellStore) {
+        let (first, second) = trees(0xcafe);
+        let leaf = first.refs()[0].repr_hash();
+        let (first, second) = (store.insert(&first).unwrap(), store.insert(&second).unwrap());
+        assert!(matches!(store.add_root(&[0; 32]), Err(StoreError::Inconsistent)));
+        store.add_root(&first).unwrap();
+        store.add_root(&first).unwrap();
+        store.add_root(&second).unwrap();
+        assert_eq!(store.sweep().unwrap(), 0);
+        
+        assert!(store.remove_root(&second).unwrap());
+        assert!(!store.remove_root(&second).unwrap());
+        assert_eq!(store.sweep().unwrap(), 1);
+        assert!(!store.contains(&second).unwrap());
+        assert!(store.contains(&leaf).unwrap());
+        
+        // counted: the first root stays until removed twice
+        store.remove_root(&first).unwrap();
+        assert_eq!(store.sweep().unwrap(), 0);
+        store.remove_root(&first).unwrap();
+        assert_eq!(store.sweep().unwrap(), 2);
+        assert!(!store.contains(&leaf).unwrap());
+    }
+    
+    #[test]
+    fn sweep_in_memory() {
+        sweep_keeps_cells_of_roots(&mut MemoryCellStore::new());
+    }
+    
+    #[test]
+    fn sweep_on_disk() {
+        let dir = std::env::temp_dir().join(format!("tlb-cell-store-sweep-{}", std::process::id()));
+        let mut store = DiskCellStore::open(&dir).unwrap();
+        sweep_keeps_cells_of_roots(&mut store);
+        
+        // roots survive reopening
+        let (first, _) = trees(1);
+        let hash = store.insert(&first).unwrap();
+        store.add_root(&hash).unwrap();
+        let mut reopened = DiskCellStore::open(&dir).unwrap();
+        assert_eq!(reopened.sweep().unwrap(), 0);
+        assert!(reopened.remove_root(&hash).unwrap());
+        assert_eq!(reopened.sweep().unwrap(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
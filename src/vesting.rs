@@ -0,0 +1,85 @@
+// (c) ProgramCrafter, 2024
+
+//! Message bodies understood by the standard vesting wallet.
+
+use tlb_macro::*;
+use crate::ton::MsgAddressInt;
+use crate::ton::cell::Cell;
+
+
+/// `add_whitelist#7258a69b`, sent by the vesting sender to allow transfers to `address`
+/// while funds are still locked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = r#"
+    add_whitelist#7258a69b query_id:uint64 address:MsgAddressInt = InternalMsgBody;
+"#)]
+pub struct AddWhitelist {
+    pub query_id: u64,
+    pub address: MsgAddressInt,
+}
+
+/// `send#a7733acd`, sent by the owner to have the wallet send `message` (`MessageRelaxed`)
+/// with `send_mode`. Unless destination is whitelisted, only unlocked funds may be sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[tlb_deserializable]
+#[tlb_serializable(scheme = r#"
+    send#a7733acd query_id:uint64 send_mode:uint8 message:^Cell = InternalMsgBody;
+"#)]
+pub struct SendMessage {
+    pub query_id: u64,
+    pub send_mode: u8,
+    pub message: Cell,
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ton::{Address, CellDeserialize, CellSerialize, CommonMsgInfo, Coins};
+    use crate::ton::cell::CellBuilder;
+    
+    fn bits_of(cell: &Cell) -> String {
+        (0..cell.bit_len()).map(|i| if cell.bit(i) {'1'} else {'0'}).collect()
+    }
+    
+    fn hex_bits(hex: &str) -> String {
+        hex.chars().map(|c| format!("{:04b}", c.to_digit(16).unwrap())).collect()
+    }
+    
+    #[test]
+    fn add_whitelist_layout() {
+        let whitelist = AddWhitelist {query_id: 9, address: Address::new(-1, [0xa5; 32]).into()};
+        let cell = whitelist.to_cell().unwrap();
+        let expected = [
+            hex_bits("7258a69b"),
+            format!("{:064b}", 9),
+            "10".to_owned() + "0" + "11111111" + &"10100101".repeat(32),  // addr_std$10, no anycast, -1
+        ].concat();
+        assert_eq!(bits_of(&cell), expected);
+        assert!(cell.refs().is_empty());
+        assert_eq!(AddWhitelist::from_cell(&cell), Ok(whitelist));
+    }
+    
+    #[test]
+    fn send_message_keeps_message_in_ref() {
+        // int_msg_info with no state init and an empty inline body
+        let info = CommonMsgInfo::int_msg_info {
+            ihr_disabled: true, bounce: false, bounced: false,
+            src: Address::new(0, [0x11; 32]).into(), dest: Address::new(0, [0x22; 32]).into(),
+            value: Coins(1_000_000_000).into(), ihr_fee: Coins(0), fwd_fee: Coins(0),
+            created_lt: 0, created_at: 0
+        };
+        let mut builder = CellBuilder::new();
+        info.store(&mut builder).unwrap();
+        builder.store_uint(0b00, 2).unwrap();
+        let message = builder.build();
+        
+        let send = SendMessage {query_id: 0x1122334455667788, send_mode: 3, message: message.clone()};
+        let cell = send.to_cell().unwrap();
+        assert_eq!(bits_of(&cell), hex_bits("a7733acd") + &hex_bits("1122334455667788") + "00000011");
+        assert_eq!(cell.refs().len(), 1);
+        assert_eq!(*cell.refs()[0], message);
+        assert_eq!(SendMessage::from_cell(&cell), Ok(send));
+    }
+}
@@ -0,0 +1,138 @@
+// (c) ProgramCrafter, 2024
+
+//! Compile-time checking of bag-of-cells literals for `boc!()`.
+//!
+//! Only the generic `serialized_boc#b5ee9c72` format is accepted. Its structure is checked
+//! completely: header counts, root and child indices (children must follow their parent),
+//! cell descriptors, completion tags, total length and CRC32C if present.
+
+// the same implementation the library uses at runtime
+#[path = "../../src/ton/crc32c.rs"]
+mod crc32c;
+use crc32c::crc32c_software as crc32c;
+
+
+/// Decodes literal given as hex or base64 (standard or url-safe, padding optional).
+pub fn decode(text: &str) -> Result<Vec<u8>, String> {
+    let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if text.len().is_multiple_of(2) && text.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok((0..text.len()).step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+            .collect());
+    }
+    
+    let mut bytes = vec![];
+    let (mut acc, mut acc_bits) = (0u32, 0);
+    for c in text.trim_end_matches('=').chars() {
+        let sextet = match c {
+            'A'..='Z' => c as u32 - 'A' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 26,
+            '0'..='9' => c as u32 - '0' as u32 + 52,
+            '+' | '-' => 62,
+            '/' | '_' => 63,
+            _ => return Err(format!("BOC must be hex or base64, found `{c}`")),
+        };
+        acc = (acc << 6) | sextet;
+        acc_bits += 6;
+        if acc_bits >= 8 {
+            acc_bits -= 8;
+            bytes.push((acc >> acc_bits) as u8);
+            acc &= (1 << acc_bits) - 1;
+        }
+    }
+    Ok(bytes)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn bytes(&mut self, count: usize, what: &str) -> Result<&[u8], String> {
+        let end = self.pos.checked_add(count).filter(|&end| end <= self.data.len())
+            .ok_or_else(|| format!("BOC is truncated in {what}"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+    
+    fn uint(&mut self, size: usize, what: &str) -> Result<usize, String> {
+        Ok(self.bytes(size, what)?.iter().fold(0, |acc, &b| (acc << 8) | b as usize))
+    }
+}
+
+/// Checks that `data` is a well-formed bag of cells.
+pub fn validate(data: &[u8]) -> Result<(), String> {
+    let mut reader = Reader {data, pos: 0};
+    if reader.bytes(4, "magic")? != [0xb5, 0xee, 0x9c, 0x72] {
+        return Err("BOC must start with magic b5ee9c72".to_owned());
+    }
+    let flags = reader.uint(1, "header")?;
+    let (has_idx, has_crc32c) = (flags & 0x80 != 0, flags & 0x40 != 0);
+    let size = flags & 7;
+    if !(1..=4).contains(&size) {
+        return Err(format!("BOC reference size must be in 1..=4 bytes, not {size}"));
+    }
+    let off_bytes = reader.uint(1, "header")?;
+    if !(1..=8).contains(&off_bytes) {
+        return Err(format!("BOC offset size must be in 1..=8 bytes, not {off_bytes}"));
+    }
+    
+    let cells = reader.uint(size, "header")?;
+    let roots = reader.uint(size, "header")?;
+    let absent = reader.uint(size, "header")?;
+    let tot_cells_size = reader.uint(off_bytes, "header")?;
+    if roots == 0 || roots > cells {
+        return Err(format!("BOC with {cells} cells cannot have {roots} roots"));
+    }
+    if absent != 0 {
+        return Err("BOC with absent cells is not supported".to_owned());
+    }
+    for _ in 0..roots {
+        let root = reader.uint(size, "root list")?;
+        if root >= cells {
+            return Err(format!("root index {root} is out of {cells} cells"));
+        }
+    }
+    if has_idx {
+        reader.bytes(cells * off_bytes, "index")?;
+    }
+    
+    let cells_start = reader.pos;
+    for index in 0..cells {
+        let descriptor = reader.bytes(2, "cell descriptor")?;
+        let (d1, d2) = (descriptor[0] as usize, descriptor[1] as usize);
+        if d1 & 7 > 4 {
+            return Err(format!("cell {index} has more than 4 references"));
+        }
+        if d1 & 16 != 0 {
+            return Err(format!("cell {index} has stored hashes, which are not supported"));
+        }
+        let cell_data = reader.bytes(d2.div_ceil(2), "cell data")?;
+        if d2 % 2 == 1 && cell_data.last() == Some(&0) {
+            return Err(format!("cell {index} has no completion tag in its last byte"));
+        }
+        for _ in 0..d1 & 7 {
+            let child = reader.uint(size, "cell references")?;
+            if child <= index || child >= cells {
+                return Err(format!("cell {index} refers to cell {child}, which does not follow it"));
+            }
+        }
+    }
+    if reader.pos - cells_start != tot_cells_size {
+        return Err(format!("cells take {} bytes, but header says {tot_cells_size}", reader.pos - cells_start));
+    }
+    
+    if has_crc32c {
+        let expected = crc32c(&data[..reader.pos]);
+        let stored = u32::from_le_bytes(reader.bytes(4, "checksum")?.try_into().unwrap());
+        if stored != expected {
+            return Err(format!("BOC checksum is {stored:08x}, expected {expected:08x}"));
+        }
+    }
+    if reader.pos != data.len() {
+        return Err(format!("{} extra bytes after BOC", data.len() - reader.pos));
+    }
+    Ok(())
+}
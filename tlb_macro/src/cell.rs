@@ -0,0 +1,142 @@
+// (c) ProgramCrafter, 2024
+
+//! Parser and code generator of `cell!{}`: serialization of `kind: value` entries in order
+//! into a cell, without declaring a type for them.
+//!
+//! Entry kinds:
+//! - `uN: value` / `iN: value` - unsigned / signed integer in `N` bits, e.g. `u32: 0x5fcc3d14`;
+//!   value must have the narrowest Rust type holding `N` bits (`u32` for `u24`, `i128` for `i257`);
+//! - `coins: value` - amount in nanotons as `u128`, stored as `Coins`;
+//! - `Type: value` - any other type implementing `CellSerialize`, e.g. `ton::Address: dest`;
+//! - `ref: value` - any value implementing `CellSerialize`, stored in a child cell.
+
+use syn::{Expr, Ident, Token, Type};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use quote::{quote, quote_spanned};
+use proc_macro2::{Span, TokenStream};
+
+
+enum EntryKind {
+    Uint(usize, Span),
+    Int(usize, Span),
+    Coins(Span),
+    Ref(Span),
+    Other(Box<Type>),
+}
+
+pub struct CellEntry {
+    kind: EntryKind,
+    value: Expr,
+}
+
+pub struct CellEntries(Punctuated<CellEntry, Token![,]>);
+
+
+/// Recognizes `uN` and `iN` kinds, checking their width.
+fn integer_kind(name: &str, span: Span) -> syn::Result<Option<EntryKind>> {
+    let (signed, digits) = match name.split_at(1) {
+        ("u", digits) => (false, digits),
+        ("i", digits) => (true, digits),
+        _ => return Ok(None),
+    };
+    let Ok(bits) = digits.parse::<usize>() else {
+        return Ok(None);
+    };
+    
+    let max_bits = if signed {257} else {128};
+    if bits == 0 || bits > max_bits {
+        return Err(syn::Error::new(span, format!("integer width must be in 1..={max_bits} bits")));
+    }
+    Ok(Some(if signed {EntryKind::Int(bits, span)} else {EntryKind::Uint(bits, span)}))
+}
+
+impl Parse for CellEntry {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let kind = if input.peek(Token![ref]) {
+            EntryKind::Ref(input.parse::<Token![ref]>()?.span)
+        } else {
+            let ty: Type = input.parse()?;
+            match ty {
+                Type::Path(ref path) if path.qself.is_none() && path.path.get_ident().is_some() => {
+                    let ident = path.path.get_ident().unwrap();
+                    let name = ident.to_string();
+                    if name == "coins" {
+                        EntryKind::Coins(ident.span())
+                    } else {
+                        integer_kind(&name, ident.span())?.unwrap_or_else(|| EntryKind::Other(Box::new(ty)))
+                    }
+                },
+                _ => EntryKind::Other(Box::new(ty)),
+            }
+        };
+        input.parse::<Token![:]>()?;
+        let value = input.parse()?;
+        Ok(CellEntry {kind, value})
+    }
+}
+
+impl Parse for CellEntries {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Ok(CellEntries(Punctuated::parse_terminated(input)?))
+    }
+}
+
+
+/// Width of the narrowest Rust integer type holding `bits` bits, up to 128.
+fn rust_width(bits: usize) -> usize {
+    [8, 16, 32, 64, 128].into_iter().find(|width| bits <= *width).unwrap_or(128)
+}
+
+/// Generates expression evaluating to cell with serialized entries, `Result<Cell, CellError>`.
+pub fn create_cell_code(entries: &CellEntries) -> syn::Result<TokenStream> {
+    // not visible from entry values, which may have a `result` of their own
+    let result = Ident::new("result", Span::mixed_site());
+    let serializations = entries.0.iter().map(|CellEntry {kind, value}| match kind {
+        EntryKind::Uint(bits, span) => {
+            // value is bound to the narrowest Rust type holding `bits`, so literals get it too
+            let ty = Ident::new(&format!("u{}", rust_width(*bits)), *span);
+            let overflow = format!("value does not fit in {bits} bits");
+            let check = if *bits < rust_width(*bits) {
+                quote_spanned! {*span=> assert!((value as u128) >> #bits == 0, #overflow);}
+            } else {
+                quote_spanned! {*span=>}
+            };
+            Ok(quote_spanned! {*span=>{
+                let value: #ty = #value;
+                #check
+                #result.push(::std::format!("u {} {}bit", value, #bits));
+            }})
+        },
+        EntryKind::Int(bits, span) => {
+            let ty = Ident::new(&format!("i{}", rust_width(*bits)), *span);
+            Ok(quote_spanned! {*span=>{
+                let value: #ty = #value;
+                let mut s_field = crate::ton::CellSerialize::serialize(&crate::ton::Int::<#bits>(value as i128));
+                #result.append(&mut s_field);
+            }})
+        },
+        EntryKind::Coins(span) => Ok(quote_spanned! {*span=>{
+            let value: u128 = #value;
+            let mut s_field = crate::ton::CellSerialize::serialize(&crate::ton::Coins(value));
+            #result.append(&mut s_field);
+        }}),
+        EntryKind::Ref(span) => Ok(quote_spanned! {*span=>{
+            #result.push("ref {".to_owned());
+            let mut s_field = crate::ton::CellSerialize::serialize(&#value);
+            #result.append(&mut s_field);
+            #result.push("}".to_owned());
+        }}),
+        EntryKind::Other(ty) => Ok(quote! {{
+            let value: #ty = #value;
+            let mut s_field = crate::ton::CellSerialize::serialize(&value);
+            #result.append(&mut s_field);
+        }}),
+    }).collect::<syn::Result<Vec<_>>>()?;
+    
+    Ok(quote! {{
+        let mut #result : ::std::vec::Vec<::std::string::String> = ::std::vec![];
+        #(#serializations)*
+        crate::ton::cell::Cell::from_legacy_commands(&#result)
+    }})
+}
@@ -0,0 +1,102 @@
+// (c) ProgramCrafter, 2024
+
+//! JSON Schema description of annotated types, emitted as `JSON_SCHEMA` constants with
+//! `json-schema` feature. Integers, booleans and hand-written types of the crate (bit strings,
+//! cells, `VarAddress` etc.) are described inline; other field types are referenced as
+//! `#/$defs/TypeName`, so a complete document is assembled from constants of all annotated types.
+
+use syn::{Fields, GenericArgument, PathArguments, Type};
+use quote::ToTokens;
+use syn::ext::IdentExt;
+
+use crate::scheme::{generic_inner, option_inner, Scheme, SchemeItem};
+
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Schema of a bit string, written as `0`/`1` characters, of length in `min..=max`.
+fn bits_schema(min: usize, max: Option<usize>) -> String {
+    match max {
+        Some(max) => format!(r#"{{"type":"string","pattern":"^[01]*$","minLength":{min},"maxLength":{max}}}"#),
+        None => r#"{"type":"string","pattern":"^[01]*$"}"#.to_owned(),
+    }
+}
+
+/// Schema of a field value, chosen by the last segment of its Rust type.
+/// Integers that do not fit into JavaScript numbers are represented with decimal strings,
+/// bit strings with strings of `0`/`1`, cells with base64 of their bag of cells,
+/// and `Option<T>` (`Maybe T`) allows `null`.
+fn value_schema(ty: &Type) -> String {
+    let Type::Path(ref path) = ty else {
+        return "{}".to_owned();
+    };
+    if let Some(inner) = option_inner(ty) {
+        return format!(r#"{{"anyOf":[{},{{"type":"null"}}]}}"#, value_schema(inner));
+    }
+    if let Some(inner) = generic_inner(ty, "Box") {
+        return value_schema(inner);
+    }
+    let segment = path.path.segments.last().expect("empty type path");
+    let generic_arg = || match segment.arguments {
+        PathArguments::AngleBracketed(ref args) => args.args.first(),
+        _ => None,
+    };
+    let const_arg = || generic_arg().and_then(|arg| arg.to_token_stream().to_string().parse::<usize>().ok());
+    
+    let name = segment.ident.to_string();
+    match name.as_str() {
+        "bool" => r#"{"type":"boolean"}"#.to_owned(),
+        "u8" => r#"{"type":"integer","minimum":0,"maximum":255}"#.to_owned(),
+        "u16" => r#"{"type":"integer","minimum":0,"maximum":65535}"#.to_owned(),
+        "u32" => r#"{"type":"integer","minimum":0,"maximum":4294967295}"#.to_owned(),
+        "u64" | "u128" | "Uint" | "Coins" | "TokenAmount" | "Capabilities" => r#"{"type":"string","pattern":"^[0-9]+$"}"#.to_owned(),
+        "Int" => r#"{"type":"string","pattern":"^-?[0-9]+$"}"#.to_owned(),
+        "RawBits" => bits_schema(0, None),
+        "FixedBits" => const_arg().map_or_else(|| bits_schema(0, None), |bits| bits_schema(bits, Some(bits))),
+        "VarBits" => const_arg().filter(|bits| *bits < 16)
+            .map_or_else(|| bits_schema(0, None), |bits| bits_schema(0, Some((1 << bits) - 1))),
+        "Anycast" => bits_schema(1, Some(30)),
+        "VarAddress" => format!(r#"{{"type":"object","properties":{{"anycast":{{"anyOf":[{},{{"type":"null"}}]}},"workchain_id":{},"address":{}}},"required":["anycast","workchain_id","address"]}}"#,
+                                bits_schema(1, Some(30)), r#"{"type":"string","pattern":"^-?[0-9]+$"}"#, bits_schema(0, Some(511))),
+        "Cell" => r#"{"type":"string","contentEncoding":"base64","description":"bag of cells"}"#.to_owned(),
+        "Vec" => match generic_arg() {
+            Some(GenericArgument::Type(inner)) => format!(r#"{{"type":"array","items":{}}}"#, value_schema(inner)),
+            _ => r#"{"type":"array"}"#.to_owned(),
+        },
+        _ => format!(r##"{{"$ref":"#/$defs/{name}"}}"##),
+    }
+}
+
+/// Object schema of one constructor: fields from its scheme become required properties,
+/// and the TL-B-like declaration is kept under `x-tlb`.
+pub fn constructor_schema(title: &str, scheme: &Scheme, fields: &Fields, declaration: &str) -> String {
+    let declaration = escape(declaration);
+    let items = match scheme {
+        Scheme::Fundamental(_) => {
+            return format!(r#"{{"title":"{title}","type":"string","pattern":"^[0-9]+$","x-tlb":"{declaration}"}}"#);
+        },
+        Scheme::Items(items) => items,
+    };
+    
+    let names: Vec<String> = items.iter().filter_map(|item| match item {
+        SchemeItem::Field(name) | SchemeItem::Ref(name) => Some(name.unraw().to_string()),
+        SchemeItem::Constant {..} => None,
+    }).collect();
+    let properties: Vec<String> = names.iter().map(|name| {
+        let field = fields.iter()
+            .find(|field| field.ident.as_ref().is_some_and(|id| id.unraw() == name))
+            .expect("field existence is checked by serialization code");
+        format!(r#""{name}":{}"#, value_schema(&field.ty))
+    }).collect();
+    let required: Vec<String> = names.iter().map(|name| format!(r#""{name}""#)).collect();
+    
+    format!(r#"{{"title":"{title}","type":"object","properties":{{{}}},"required":[{}],"x-tlb":"{declaration}"}}"#,
+            properties.join(","), required.join(","))
+}
+
+/// Schema of enum: one of its constructors.
+pub fn enum_schema(title: &str, constructors: &[String]) -> String {
+    format!(r#"{{"title":"{title}","oneOf":[{}]}}"#, constructors.join(","))
+}
@@ -1,12 +1,20 @@
 // (c) ProgramCrafter, 2024
 
 extern crate proc_macro;
-use syn::{parse_macro_input, DeriveInput, Data, Expr, Fields, Ident, ItemEnum, Meta, MetaList, Lit, spanned::Spanned};
+use syn::{parse_macro_input, Attribute, DeriveInput, Data, Expr, Fields, Ident, ItemEnum, Meta, MetaList, Lit, LitInt, Variant, spanned::Spanned};
+use syn::{BinOp, ExprBinary, ExprGroup, ExprLit, ExprParen, ExprUnary, UnOp};
+use syn::ext::IdentExt;
 use quote::{quote_spanned, quote, ToTokens};
 use proc_macro2::Span;
 
 use std::collections::HashMap;
 
+mod boc;
+mod cell;
+mod json;
+mod scheme;
+use scheme::{check_field_types, describe_tag, original_text, parse_scheme, Scheme, SchemeItem};
+
 type OldTokenStream = proc_macro::TokenStream;
 type V2TokenStream = proc_macro2::TokenStream;
 
@@ -44,18 +52,18 @@ type V2TokenStream = proc_macro2::TokenStream;
 ///     result
 /// }
 /// ```
-fn create_serialization_code(attr: &str, struct_fields: &Fields, self_ref: bool) -> V2TokenStream {
-    match attr {
+fn create_serialization_code(scheme: &Scheme, struct_fields: &Fields, self_ref: bool) -> syn::Result<V2TokenStream> {
+    match scheme {
         // -------------------------------------------------------------
         // Processing `#[tlb_serializable(__fundamental_varuint16)]`
-        "__fundamental_varuint16" => {
+        Scheme::Fundamental(name) if name == "__fundamental_varuint16" => {
             let Fields::Unnamed(ref fields) = struct_fields else {
                 panic!("Fundamental VarUint16 struct must consist of unnamed fields");
             };
             assert!(fields.unnamed.len() == 1, "Fundamental VarUint16 struct must have exactly one field");
             
             assert!(self_ref);
-            return quote!{{
+            Ok(quote!{{
                 let value = self.0 as u128;
                 let bytes_required = 128 / 8 - value.leading_zeros() / 8;
                 assert!(bytes_required <= 15, "VarUint16 overflow");
@@ -63,64 +71,271 @@ fn create_serialization_code(attr: &str, struct_fields: &Fields, self_ref: bool)
                     ::std::format!("u {bytes_required} 4bit"), 
                     ::std::format!("u {value} {}bit", bytes_required * 8)
                 ];
-            }}
+            }})
+        },
+        Scheme::Fundamental(name) => {
+            Err(syn::Error::new(name.span(), format!("unknown fundamental type `{name}`")))
         },
         
         // -------------------------------------------------------------
         // Normal serialization - almost-TL-B for user-defined types.
-        _ => {
+        Scheme::Items(items) => {
             // Loading fields list, obtaining string->ident mapping with relevant spans
             let Fields::Named(ref fields) = struct_fields else {
                 panic!("For unambiguous parsing, normal structs must consist of named fields");
             };
             let mut field_spans: HashMap<String, (Ident, Span)> = HashMap::new();
             for field in fields.named.iter() {
-                let id = field.ident.clone().expect(&format!("unnamed field"));
-                field_spans.insert(id.to_string(), (id, field.span()));
+                let id = field.ident.clone().expect("unnamed field");
+                field_spans.insert(id.unraw().to_string(), (id, field.span()));
             }
             
             // ------------------------------------------------------------------------------------
             // Mapping each part of serialization TL-B to block of code that stores value into cell
-            let serializations = attr.split(",").map(|part_whitespaced| {
-                let part = part_whitespaced.trim();
-                if part.is_empty() {
-                    quote!{}
-                } else if part.starts_with("u ") {
-                    quote! { 
-                        result.push(#part.to_owned());
-                    }
-                } else {
-                    let (name, span) = &field_spans[part];
+            let serializations = items.iter().map(|item| match item {
+                SchemeItem::Constant {value, bits, span} => {
+                    let command = format!("u {value} {bits}bit");
+                    Ok(quote_spanned! {*span=>
+                        result.push(#command.to_owned());
+                    })
+                },
+                SchemeItem::Field(part) | SchemeItem::Ref(part) => {
+                    let Some((name, span)) = field_spans.get(&part.unraw().to_string()) else {
+                        return Err(syn::Error::new(part.span(), format!("no field `{}` to serialize", part.unraw())));
+                    };
                     
-                    if self_ref {
-                        quote_spanned! {span.clone()=>{
+                    if let SchemeItem::Ref(_) = item {
+                        let value = if self_ref {quote! {&self.#name}} else {quote! {#name}};
+                        let commands = create_ref_commands(value, is_option_field(struct_fields, name));
+                        return Ok(quote_spanned! {*span=>
+                            result.append(&mut #commands);
+                        });
+                    }
+                    let field = if self_ref {
+                        quote_spanned! {*span=>{
                             let mut s_field = crate::ton::CellSerialize::serialize(&self.#name);
                             result.append(&mut s_field);
                         }}
                     } else {
-                        quote_spanned! {span.clone()=>{
+                        quote_spanned! {*span=>{
                             let mut s_field = crate::ton::CellSerialize::serialize(#name);
                             result.append(&mut s_field);
                         }}
-                    }
-                }
-            });
+                    };
+                    Ok(field)
+                },
+            }).collect::<syn::Result<Vec<_>>>()?;
             
             // ------------------------------------------------------------------------------------
             // Constructing function of all those code chunks
-            quote!{{
+            Ok(quote!{{
                 #(#serializations)*
-            }}
+            }})
         }
     }
 }
 
-fn create_serialization_code_struct(attr: &str, struct_wrap: &Data) -> V2TokenStream {
+/// Whether field `name` is an `Option`, which `^name` stores as `Maybe ^X`.
+fn is_option_field(struct_fields: &Fields, name: &Ident) -> bool {
+    struct_fields.iter()
+        .find(|field| field.ident.as_ref().is_some_and(|id| id.unraw() == name.unraw()))
+        .is_some_and(|field| scheme::option_inner(&field.ty).is_some())
+}
+
+/// Generates expression with commands of `^field`: the value enclosed in `ref {` ... `}`.
+/// For `Option` fields (`Maybe ^X`) presence bit goes first, and stays in the cell itself.
+fn create_ref_commands(value: V2TokenStream, maybe: bool) -> V2TokenStream {
+    let wrap = |value: V2TokenStream| quote! {{
+        let mut commands = ::std::vec!["ref {".to_owned()];
+        commands.append(&mut crate::ton::CellSerialize::serialize(#value));
+        commands.push("}".to_owned());
+        commands
+    }};
+    if !maybe {
+        return wrap(value);
+    }
+    let wrapped = wrap(quote! {value});
+    quote! {
+        match #value {
+            ::std::option::Option::None => ::std::vec!["u 0 1bit".to_owned()],
+            ::std::option::Option::Some(value) => {
+                let mut commands = ::std::vec!["u 1 1bit".to_owned()];
+                commands.append(&mut #wrapped);
+                commands
+            },
+        }
+    }
+}
+
+/// Generates code filling `report: SizeReport` with bits and cells taken by each top-level scheme item:
+/// every item is stored into `builder: &mut CellBuilder` and measured by what it added.
+/// Fields are not checked here, so this must run after `create_serialization_code` succeeded.
+fn create_size_report_code(scheme: &Scheme, struct_fields: &Fields, self_ref: bool) -> V2TokenStream {
+    let items = match scheme {
+        Scheme::Fundamental(_) => {
+            let part = scheme.describe(struct_fields);
+            let store = create_store_code(scheme, struct_fields, self_ref);
+            return quote! {
+                #store
+                report.push(#part, builder);
+            };
+        },
+        Scheme::Items(items) => items,
+    };
+    
+    let parts = items.iter().map(|item| {
+        let part = match item {
+            SchemeItem::Constant {value, bits, ..} => describe_tag(*value, *bits),
+            SchemeItem::Field(part) => part.unraw().to_string(),
+            SchemeItem::Ref(part) => format!("^{}", part.unraw()),
+        };
+        let store = create_item_store_code(item, struct_fields, self_ref);
+        quote! {
+            #store
+            report.push(#part, builder);
+        }
+    });
+    quote! {
+        #(#parts)*
+    }
+}
+
+/// Generates code storing value into `builder: &mut CellBuilder`, for `CellSerialize::store`.
+/// Fields are not checked here, so this must run after `create_serialization_code` succeeded.
+fn create_store_code(scheme: &Scheme, struct_fields: &Fields, self_ref: bool) -> V2TokenStream {
+    let items = match scheme {
+        Scheme::Fundamental(_) => {
+            // the only one, `__fundamental_varuint16`
+            return quote! {{
+                let value = self.0 as u128;
+                let bytes_required = 128 / 8 - value.leading_zeros() / 8;
+                assert!(bytes_required <= 15, "VarUint16 overflow");
+                builder.store_uint(bytes_required as u128, 4)?;
+                builder.store_uint(value, bytes_required as usize * 8)?;
+            }};
+        },
+        Scheme::Items(items) => items,
+    };
+    
+    let stores = items.iter().map(|item| create_item_store_code(item, struct_fields, self_ref));
+    quote! {
+        #(#stores)*
+    }
+}
+
+/// Generates expression for `CellSerialize::bit_len_hint`: bits of constants, hints of fields
+/// stored in place and presence bits of `Maybe ^X` fields, with the number of references the
+/// scheme stores at most. Fields are not checked here either.
+fn create_bit_len_hint_code(scheme: &Scheme, struct_fields: &Fields) -> (V2TokenStream, usize) {
+    let items = match scheme {
+        Scheme::Fundamental(_) => {
+            // `__fundamental_varuint16`: length in 4 bits, then the value in that many bytes
+            return (quote! {
+                4 + (128 / 8 - (self.0 as u128).leading_zeros() as usize / 8) * 8
+            }, 0);
+        },
+        Scheme::Items(items) => items,
+    };
+    
+    let mut refs = 0;
+    let bits = items.iter().map(|item| match item {
+        SchemeItem::Constant {bits, ..} => quote! {#bits},
+        SchemeItem::Field(part) => {
+            let name = struct_fields.iter()
+                .filter_map(|field| field.ident.as_ref())
+                .find(|id| id.unraw() == part.unraw())
+                .expect("field existence is checked by serialization code");
+            quote! {crate::ton::CellSerialize::bit_len_hint(&self.#name)}
+        },
+        SchemeItem::Ref(part) => {
+            refs += 1;
+            let name = struct_fields.iter()
+                .filter_map(|field| field.ident.as_ref())
+                .find(|id| id.unraw() == part.unraw())
+                .expect("field existence is checked by serialization code");
+            if is_option_field(struct_fields, name) {quote! {1}} else {quote! {0}}
+        },
+    }).collect::<Vec<_>>();
+    (quote! {0 #(+ #bits)*}, refs)
+}
+
+/// Generates code storing one scheme item into `builder`, see `create_store_code`.
+fn create_item_store_code(item: &SchemeItem, struct_fields: &Fields, self_ref: bool) -> V2TokenStream {
+    match item {
+        SchemeItem::Constant {value, bits, span} => quote_spanned! {*span=>
+            builder.store_uint(#value, #bits)?;
+        },
+        SchemeItem::Field(part) | SchemeItem::Ref(part) => {
+            let name = struct_fields.iter()
+                .filter_map(|field| field.ident.as_ref())
+                .find(|id| id.unraw() == part.unraw())
+                .expect("field existence is checked by serialization code");
+            let value = if self_ref {quote! {&self.#name}} else {quote! {#name}};
+            if let SchemeItem::Ref(_) = item {
+                if is_option_field(struct_fields, name) {
+                    quote! {
+                        builder.store_bit(::std::option::Option::is_some(#value))?;
+                        if let ::std::option::Option::Some(value) = #value {
+                            builder.store_ref(::std::sync::Arc::new(crate::ton::CellSerialize::to_cell(value)?))?;
+                        }
+                    }
+                } else {
+                    quote! {
+                        builder.store_ref(::std::sync::Arc::new(crate::ton::CellSerialize::to_cell(#value)?))?;
+                    }
+                }
+            } else {
+                quote! {
+                    crate::ton::CellSerialize::store(#value, builder)?;
+                }
+            }
+        },
+    }
+}
+
+fn create_serialization_code_struct(scheme: &Scheme, struct_wrap: &Data) -> syn::Result<V2TokenStream> {
     match *struct_wrap {
         Data::Union(_) => unimplemented!("union serialization is not defined yet"),
         Data::Enum(_) => panic!("please use #[tlb_enum_serializable] for enums"),
         Data::Struct(ref data) => {
-            create_serialization_code(attr, &data.fields, true)
+            create_serialization_code(scheme, &data.fields, true)
+        }
+    }
+}
+
+
+/// Builds TL-B-like declaration line: `constructor fields = Type;`.
+fn create_declaration(constructor: &str, description: &str, name: &Ident) -> String {
+    if description.is_empty() {
+        format!("{constructor} = {name};")
+    } else {
+        format!("{constructor} {description} = {name};")
+    }
+}
+
+/// Generates `TLB_SCHEME` constant holding the declaration(s) type was built from: TL-B text
+/// of `scheme = "..."` form as written, or declaration rendered from the scheme otherwise.
+fn create_scheme_const(name: &Ident, declarations: &[String]) -> V2TokenStream {
+    let scheme = declarations.join("\n");
+    quote! {
+        #[allow(dead_code)]
+        impl #name {
+            /// TL-B-like declaration this type is serialized by.
+            pub const TLB_SCHEME: &str = #scheme;
+        }
+    }
+}
+
+/// Generates `JSON_SCHEMA` constant with JSON Schema of the type, if `json-schema` feature is enabled.
+fn create_json_schema_const(name: &Ident, schema: String) -> V2TokenStream {
+    if !cfg!(feature = "json-schema") {
+        return quote! {};
+    }
+    quote! {
+        #[allow(dead_code)]
+        impl #name {
+            /// JSON Schema of this type; field types are referenced as `#/$defs/TypeName`.
+            pub const JSON_SCHEMA: &str = #schema;
         }
     }
 }
@@ -128,6 +343,11 @@ fn create_serialization_code_struct(attr: &str, struct_wrap: &Data) -> V2TokenSt
 
 /// Creates impl of crate::ton::CellSerialize for struct the attribute is attached to.
 /// Uses [create_serialization_code](fn.create_serialization_code.html) internally.
+/// Also emits `TLB_SCHEME` constant with TL-B-like declaration of the type, `size_report()`
+/// method listing bits and cells taken by each field, and `JSON_SCHEMA` constant when `json-schema`
+/// feature is enabled. Schemes starting with a constant of up to 64 bits also get `TAG` and
+/// `TAG_BITS` constants with it. Generated `store` reserves builder capacity for the bits and
+/// references of the value up front, from `bit_len_hint()` of its fields.
 ///
 /// # Examples
 /// 
@@ -140,6 +360,13 @@ fn create_serialization_code_struct(attr: &str, struct_wrap: &Data) -> V2TokenSt
 /// pub struct Address {
 ///     workchain: u8,      hash_high: u128,      hash_low: u128
 /// }
+/// 
+/// #[tlb_serializable(scheme = r#"
+///     add_whitelist#7258a69b query_id:uint64 address:MsgAddressInt = InternalMsgBody;
+/// "#)]
+/// pub struct AddWhitelist {
+///     query_id: u64,      address: MsgAddressInt
+/// }
 /// ```
 #[proc_macro_attribute]
 pub fn tlb_serializable(attr: OldTokenStream, mut item: OldTokenStream) -> OldTokenStream {
@@ -147,25 +374,492 @@ pub fn tlb_serializable(attr: OldTokenStream, mut item: OldTokenStream) -> OldTo
     let input: DeriveInput = parse_macro_input!(struct_item);
     let name = input.ident;
     
-    let serializers = create_serialization_code_struct(&attr.to_string(), &input.data);
-    item.extend(OldTokenStream::from(quote! {
-        impl crate::ton::CellSerialize for #name {
-            fn serialize(&self) -> ::std::vec::Vec<::std::string::String> {
-                let mut result : ::std::vec::Vec<::std::string::String> = ::std::vec![];
-                #serializers
-                result
+    let attr = V2TokenStream::from(attr);
+    let generated = parse_scheme(attr.clone()).and_then(|scheme| {
+        let serializers = create_serialization_code_struct(&scheme, &input.data)?;
+        let Data::Struct(ref data) = input.data else {unreachable!()};
+        check_field_types(attr.clone(), &data.fields)?;
+        let report = create_size_report_code(&scheme, &data.fields, true);
+        let store = create_store_code(&scheme, &data.fields, true);
+        let (bit_len_hint, refs) = create_bit_len_hint_code(&scheme, &data.fields);
+        let declaration = original_text(attr)
+            .unwrap_or_else(|| create_declaration("_", &scheme.describe(&data.fields), &name));
+        let json = json::constructor_schema(&name.to_string(), &scheme, &data.fields, &declaration);
+        let json_const = create_json_schema_const(&name, json);
+        let scheme_const = create_scheme_const(&name, &[declaration]);
+        let tag_consts = match leading_tag(&scheme) {
+            Some((tag, bits)) => quote! {
+                #[allow(dead_code)]
+                impl #name {
+                    pub const TAG: u64 = #tag;
+                    pub const TAG_BITS: usize = #bits;
+                }
+            },
+            None => quote! {},
+        };
+        Ok(quote! {
+            impl crate::ton::CellSerialize for #name {
+                fn serialize(&self) -> ::std::vec::Vec<::std::string::String> {
+                    let mut result : ::std::vec::Vec<::std::string::String> = ::std::vec![];
+                    #serializers
+                    result
+                }
+                
+                fn store(&self, builder: &mut crate::ton::cell::CellBuilder)
+                        -> ::std::result::Result<(), crate::ton::cell::CellError> {
+                    builder.reserve(crate::ton::CellSerialize::bit_len_hint(self), #refs);
+                    #store
+                    Ok(())
+                }
+                
+                fn bit_len_hint(&self) -> usize {
+                    #bit_len_hint
+                }
+            }
+            #[allow(dead_code)]
+            impl #name {
+                /// Bits and cells taken by each top-level part of serialized value.
+                pub fn size_report(&self) -> ::std::result::Result<crate::ton::SizeReport, crate::ton::cell::CellError> {
+                    let mut report = crate::ton::SizeReport::default();
+                    let builder = &mut crate::ton::cell::CellBuilder::new();
+                    #report
+                    Ok(report)
+                }
             }
+            #scheme_const
+            #json_const
+            #tag_consts
+        })
+    });
+    item.extend(OldTokenStream::from(generated.unwrap_or_else(syn::Error::into_compile_error)));
+    
+    item
+}
+
+
+/// Generates body of `CellDeserialize::deserialize`: reads scheme items in order, checks
+/// constants and builds value with `constructor` (`Self` or `Self::Variant`). Fields not
+/// mentioned in scheme are not stored, so they get their `Default` values.
+fn create_deserialization_code(scheme: &Scheme, struct_fields: &Fields, constructor: V2TokenStream) -> syn::Result<V2TokenStream> {
+    let items = match scheme {
+        Scheme::Fundamental(name) if name == "__fundamental_varuint16" => {
+            return Ok(quote! {
+                let bytes_required = slice.load_uint(4)? as usize;
+                let value = slice.load_uint(bytes_required * 8)?;
+                let value = ::std::convert::TryFrom::try_from(value)
+                    .map_err(|_| crate::ton::cell::TlbError::InvalidValue)?;
+                Ok(#constructor(value))
+            });
+        },
+        Scheme::Fundamental(name) => {
+            return Err(syn::Error::new(name.span(), format!("unknown fundamental type `{name}`")));
+        },
+        Scheme::Items(items) => items,
+    };
+    let Fields::Named(ref fields) = struct_fields else {
+        panic!("For unambiguous parsing, normal structs must consist of named fields");
+    };
+    
+    let mut loaded: Vec<String> = vec![];
+    let loads = items.iter().map(|item| match item {
+        SchemeItem::Constant {value, bits, span} => Ok(quote_spanned! {*span=>
+            if slice.load_uint(#bits)? != #value {
+                return Err(crate::ton::cell::TlbError::TagMismatch);
+            }
+        }),
+        SchemeItem::Field(part) | SchemeItem::Ref(part) => {
+            let Some(field) = fields.named.iter().find(|field| field.ident.as_ref().unwrap().unraw() == part.unraw()) else {
+                return Err(syn::Error::new(part.span(), format!("no field `{}` to deserialize", part.unraw())));
+            };
+            let (name, ty) = (field.ident.as_ref().unwrap(), &field.ty);
+            loaded.push(name.unraw().to_string());
+            if let SchemeItem::Ref(_) = item {
+                // child cell must hold exactly the field
+                if scheme::option_inner(ty).is_some() {
+                    Ok(quote_spanned! {field.span()=>
+                        let #name: #ty = if slice.load_bit()? {
+                            ::std::option::Option::Some(crate::ton::CellDeserialize::from_ref(slice)?)
+                        } else {
+                            ::std::option::Option::None
+                        };
+                    })
+                } else {
+                    Ok(quote_spanned! {field.span()=>
+                        let #name: #ty = crate::ton::CellDeserialize::from_ref(slice)?;
+                    })
+                }
+            } else {
+                Ok(quote_spanned! {field.span()=>
+                    let #name: #ty = crate::ton::CellDeserialize::deserialize(slice)?;
+                })
+            }
+        },
+    }).collect::<syn::Result<Vec<_>>>()?;
+    
+    let inits = fields.named.iter().map(|field| {
+        let name = field.ident.as_ref().unwrap();
+        if loaded.contains(&name.unraw().to_string()) {
+            quote! { #name, }
+        } else {
+            quote! { #name: ::std::default::Default::default(), }
         }
-    }));
+    });
+    Ok(quote! {
+        #(#loads)*
+        Ok(#constructor {#(#inits)*})
+    })
+}
+
+/// Generates expression telling, without consuming `slice`, whether it starts with constants
+/// the scheme begins with, followed by a prefix accepted by the type of the first field.
+/// Checking stops at the first field, so its errors are left to `deserialize`.
+fn create_prefix_check(scheme: &Scheme, struct_fields: &Fields) -> V2TokenStream {
+    let Scheme::Items(items) = scheme else {
+        return quote! {true};
+    };
+    
+    let mut constants = vec![];
+    let mut first_field = None;
+    for item in items {
+        match item {
+            SchemeItem::Constant {value, bits, ..} => constants.push(quote! {
+                probe.load_uint(#bits) == ::std::result::Result::Ok(#value)
+            }),
+            SchemeItem::Field(part) => {
+                let field = struct_fields.iter()
+                    .find(|field| field.ident.as_ref().is_some_and(|id| id.unraw() == part.unraw()))
+                    .expect("field existence is checked by deserialization code");
+                first_field = Some(&field.ty);
+                break;
+            },
+            SchemeItem::Ref(_) => break,
+        }
+    }
+    
+    match (constants.is_empty(), first_field) {
+        (true, None) => quote! {true},
+        (true, Some(ty)) => quote! {
+            <#ty as crate::ton::CellDeserialize>::prefix_matches(slice)
+        },
+        (false, None) => quote! {{
+            let mut probe = crate::ton::cell::CellSlice::clone(slice);
+            #(#constants)&&*
+        }},
+        (false, Some(ty)) => quote! {{
+            let mut probe = crate::ton::cell::CellSlice::clone(slice);
+            #(#constants)&&* && <#ty as crate::ton::CellDeserialize>::prefix_matches(&probe)
+        }},
+    }
+}
+
+/// Removes `#[tlb_lenient]` from attributes of the type, generating `PARSE_MODE` override
+/// if it was there.
+fn take_parse_mode(attrs: &mut Vec<Attribute>) -> V2TokenStream {
+    let before = attrs.len();
+    attrs.retain(|attr| !attr.path().is_ident("tlb_lenient"));
+    if attrs.len() == before {
+        return quote! {};
+    }
+    quote! {
+        const PARSE_MODE: crate::ton::ParseMode = crate::ton::ParseMode::Lenient;
+    }
+}
+
+/// Constant the scheme starts with, if it fits in 64 bits: constructor tag of the type.
+fn leading_tag(scheme: &Scheme) -> Option<(u64, usize)> {
+    match scheme {
+        Scheme::Items(items) => match items.first() {
+            Some(SchemeItem::Constant {value, bits, ..}) if *bits <= 64 => Some((*value as u64, *bits)),
+            _ => None,
+        },
+        Scheme::Fundamental(_) => None,
+    }
+}
+
+/// Generates `peek_tag()` reading `bits`-bit constructor tag without consuming the slice.
+fn create_peek_tag(name: &Ident, bits: usize) -> V2TokenStream {
+    quote! {
+        #[allow(dead_code)]
+        impl #name {
+            /// Constructor tag the slice starts with, read without consuming it; the slice
+            /// may hold another type with a tag of the same width.
+            pub fn peek_tag(slice: &crate::ton::cell::CellSlice) -> ::std::result::Result<u64, crate::ton::cell::TlbError> {
+                Ok(slice.peek_uint(#bits)? as u64)
+            }
+        }
+    }
+}
+
+/// Creates impl of crate::ton::CellDeserialize for struct the attribute is attached to,
+/// reading fields by the same scheme language as `#[tlb_serializable]`.
+///
+/// Without arguments, scheme is taken from `#[tlb_serializable(...)]` attribute following
+/// this one, so it is not repeated. Types whose scheme starts with a constant get `peek_tag()`
+/// reading it without consuming the slice.
+///
+/// `#[tlb_lenient]` placed after this attribute makes `from_cell` ignore data left after the
+/// value, for types that newer contract versions may extend.
+///
+/// # Examples
+///
+/// ```no_run
+/// #[tlb_deserializable]
+/// #[tlb_serializable(u 2 2bit, anycast, workchain, hash_high, hash_low)]
+/// pub struct Address {
+///     anycast: Option<Anycast>,      workchain: u8,      hash_high: u128,      hash_low: u128
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn tlb_deserializable(attr: OldTokenStream, mut item: OldTokenStream) -> OldTokenStream {
+    let struct_item = item.clone();
+    let mut input: DeriveInput = parse_macro_input!(struct_item);
+    let parse_mode = take_parse_mode(&mut input.attrs);
+    item = input.to_token_stream().into();
+    let name = input.ident;
+    let Data::Struct(ref data) = input.data else {
+        panic!("please use #[tlb_enum_deserializable] for enums");
+    };
+    
+    let tokens: V2TokenStream = if attr.is_empty() {
+        let serializable = input.attrs.iter().find(|attr| attr.path().is_ident("tlb_serializable"));
+        match serializable.map(|attr| &attr.meta) {
+            Some(Meta::List(MetaList {tokens, ..})) => tokens.clone(),
+            _ => panic!("#[tlb_deserializable] needs scheme, either as argument or in following #[tlb_serializable(...)]"),
+        }
+    } else {
+        attr.into()
+    };
+    
+    let generated = parse_scheme(tokens).and_then(|scheme| {
+        let body = create_deserialization_code(&scheme, &data.fields, quote! {Self})?;
+        let prefix_check = create_prefix_check(&scheme, &data.fields);
+        let peek_tag = match leading_tag(&scheme) {
+            Some((_, bits)) => create_peek_tag(&name, bits),
+            None => quote! {},
+        };
+        Ok(quote! {
+            impl crate::ton::CellDeserialize for #name {
+                fn deserialize(slice: &mut crate::ton::cell::CellSlice)
+                        -> ::std::result::Result<Self, crate::ton::cell::TlbError> {
+                    #body
+                }
+                
+                fn prefix_matches(slice: &crate::ton::cell::CellSlice) -> bool {
+                    #prefix_check
+                }
+                
+                #parse_mode
+            }
+            #peek_tag
+        })
+    });
+    item.extend(OldTokenStream::from(generated.unwrap_or_else(syn::Error::into_compile_error)));
     
     item
 }
 
+/// Creates both crate::ton::CellSerialize and crate::ton::CellDeserialize impls from one scheme:
+/// on structs, the same as `#[tlb_deserializable]` followed by `#[tlb_serializable(...)]` with
+/// the arguments of this attribute; on enums, without arguments, the same as
+/// `#[tlb_enum_deserializable]` followed by `#[tlb_enum_serializable]`. Other attributes,
+/// e.g. `#[tlb_lenient]` or `#[tlb_tag_bits(...)]`, are placed after it.
+///
+/// # Examples
+///
+/// ```no_run
+/// #[tlb(scheme = r#"
+///     add_whitelist#7258a69b query_id:uint64 address:MsgAddressInt = InternalMsgBody;
+/// "#)]
+/// pub struct AddWhitelist {
+///     query_id: u64,      address: MsgAddressInt
+/// }
+///
+/// #[tlb]
+/// #[tlb_tag_bits(1)]
+/// pub enum SwapKind {
+///     #[tlb_item_serializable()] GivenIn{},
+///     #[tlb_item_serializable()] GivenOut{},
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn tlb(attr: OldTokenStream, item: OldTokenStream) -> OldTokenStream {
+    let input: DeriveInput = parse_macro_input!(item);
+    let attr = V2TokenStream::from(attr);
+    let generated = match input.data {
+        Data::Enum(_) if attr.is_empty() => quote! {
+            #[::tlb_macro::tlb_enum_deserializable]
+            #[::tlb_macro::tlb_enum_serializable]
+            #input
+        },
+        Data::Enum(_) => syn::Error::new(attr.span(), "#[tlb] takes schemes of enum variants from #[tlb_item_serializable(...)]")
+            .into_compile_error(),
+        _ if attr.is_empty() => syn::Error::new(input.ident.span(), "#[tlb(...)] needs scheme of the struct")
+            .into_compile_error(),
+        _ => quote! {
+            #[::tlb_macro::tlb_deserializable(#attr)]
+            #[::tlb_macro::tlb_serializable(#attr)]
+            #input
+        },
+    };
+    generated.into()
+}
 
-#[derive(Debug)] enum TlbPrefix {Wanted(String), NotWanted}
+
+/// Converts variant name to prefix for its constants: `int_msg_info` and `IntMsgInfo` become `INT_MSG_INFO`.
+fn screaming_snake_case(ident: &Ident) -> String {
+    let mut result = String::new();
+    let mut prev_lower = false;
+    for c in ident.to_string().chars() {
+        if c.is_uppercase() && prev_lower {
+            result.push('_');
+        }
+        prev_lower = c.is_lowercase() || c.is_ascii_digit();
+        result.extend(c.to_uppercase());
+    }
+    result
+}
+
+
+/// Computes enum discriminant given as integer literal, possibly negated or combined
+/// with other literals by arithmetic and bitwise operators.
+fn evaluate_discriminant(expr: &Expr) -> syn::Result<i128> {
+    let overflow = || syn::Error::new(expr.span(), "enum discriminant overflows");
+    match expr {
+        Expr::Lit(ExprLit {lit: Lit::Int(ref lit), ..}) => lit.base10_parse::<i128>(),
+        Expr::Paren(ExprParen {expr: ref inner, ..}) | Expr::Group(ExprGroup {expr: ref inner, ..}) => {
+            evaluate_discriminant(inner)
+        },
+        Expr::Unary(ExprUnary {op: UnOp::Neg(_), expr: ref inner, ..}) => {
+            evaluate_discriminant(inner)?.checked_neg().ok_or_else(overflow)
+        },
+        Expr::Binary(ExprBinary {ref left, ref op, ref right, ..}) => {
+            let (left, right) = (evaluate_discriminant(left)?, evaluate_discriminant(right)?);
+            let shift = || u32::try_from(right).ok().filter(|shift| *shift < 128).ok_or_else(overflow);
+            match op {
+                BinOp::Add(_) => left.checked_add(right).ok_or_else(overflow),
+                BinOp::Sub(_) => left.checked_sub(right).ok_or_else(overflow),
+                BinOp::Mul(_) => left.checked_mul(right).ok_or_else(overflow),
+                BinOp::Div(_) => left.checked_div(right).ok_or_else(overflow),
+                BinOp::Rem(_) => left.checked_rem(right).ok_or_else(overflow),
+                BinOp::Shl(_) => left.checked_shl(shift()?).ok_or_else(overflow),
+                BinOp::Shr(_) => left.checked_shr(shift()?).ok_or_else(overflow),
+                BinOp::BitAnd(_) => Ok(left & right),
+                BinOp::BitOr(_) => Ok(left | right),
+                BinOp::BitXor(_) => Ok(left ^ right),
+                _ => Err(syn::Error::new_spanned(op, "operator is not supported in enum discriminant")),
+            }
+        },
+        _ => Err(syn::Error::new_spanned(expr,
+            "enum tag must be computable at macro expansion: use integer literals and operators only")),
+    }
+}
+
+
+#[derive(Debug)] enum TlbPrefix {Wanted(usize), NotWanted}
+
+/// Finds how constructors of enum are distinguished: either #[tlb_tag_bits(N)], #[repr(uN)] or
+/// #[tlb_assert_unsafe(items_prefixes_nonoverlap)] is required.
+fn enum_prefix(attrs: &[Attribute]) -> TlbPrefix {
+    let mut prefixes_nonoverlap = false;
+    let mut repr_type: Option<String> = None;
+    let mut tag_bits: Option<usize> = None;
+    for attr in attrs {
+        if attr.path().is_ident("tlb_assert_unsafe") {
+            let Meta::List(MetaList {tokens: ref tokens_assert, ..}) = attr.meta else {
+                panic!("#[tlb_assert_unsafe] attribute must have argument with the specific assertion");
+            };
+            let assertion = tokens_assert.to_string();
+            if assertion == "items_prefixes_nonoverlap" {
+                assert!(!prefixes_nonoverlap);
+                prefixes_nonoverlap = true;
+            } else {
+                println!("Unknown assertion {assertion:?}");
+            }
+        } else if attr.path().is_ident("tlb_tag_bits") {
+            assert!(tag_bits.is_none(), "Two #[tlb_tag_bits] attributes on enum are not supported");
+            let bits: LitInt = attr.parse_args().expect("#[tlb_tag_bits] attribute must have tag width as argument");
+            let bits: usize = bits.base10_parse().expect("#[tlb_tag_bits] attribute must have tag width as argument");
+            assert!((1..=64).contains(&bits), "#[tlb_tag_bits] tag width must be in 1..=64 bits");
+            tag_bits = Some(bits);
+        } else if attr.path().is_ident("repr") {
+            assert!(repr_type.is_none(), "Two #[repr] attributes on enum are not supported");
+            let Meta::List(MetaList {tokens: ref tokens_type, ..}) = attr.meta else {
+                panic!("#[repr] attribute must have argument specifying the type");
+            };
+            repr_type = Some(tokens_type.to_string());
+        }
+    }
+    match (prefixes_nonoverlap, tag_bits, repr_type) {
+        (true, None, None) => TlbPrefix::NotWanted,
+        (true, _, _) => panic!("Tag width must not be specified for enum with non-overlapping item prefixes"),
+        (false, Some(bits), _) => TlbPrefix::Wanted(bits),
+        (false, None, Some(t)) => match t.as_str() {
+            "u8" => TlbPrefix::Wanted(8),
+            "u16" => TlbPrefix::Wanted(16),
+            "u32" => TlbPrefix::Wanted(32),
+            "u64" => TlbPrefix::Wanted(64),
+            _ => panic!("#[repr({t})] does not define tag width, please add #[tlb_tag_bits(N)]"),
+        },
+        (false, None, None) => panic!("Don't know how to differentiate tags of the enum"),
+    }
+}
+
+/// Whether variant is marked `#[tlb(other)]`, catching values no other variant matches.
+fn is_fallback(variant: &Variant) -> bool {
+    variant.attrs.iter().any(|attr| matches!(attr.meta, Meta::List(MetaList {ref path, ref tokens, ..})
+        if path.is_ident("tlb") && tokens.to_string() == "other"))
+}
+
+/// Computes tag of each variant from enum discriminants; negative values are stored as two's
+/// complement of tag width. Enums without tags, and the fallback variant, get zeroes. Tags
+/// must fit the width and differ, or no value could be read as the later variant.
+fn enum_tags(input: &ItemEnum, need_prefix: &TlbPrefix) -> syn::Result<Vec<u64>> {
+    let TlbPrefix::Wanted(bits) = *need_prefix else {
+        return Ok(vec![0; input.variants.len()]);
+    };
+    let mut variant_index: i128 = 0;
+    let mut used: HashMap<u64, &Ident> = HashMap::new();
+    input.variants.iter().map(|variant| {
+        if is_fallback(variant) {
+            return Ok(0);
+        }
+        if let Some((_, ref expr)) = variant.discriminant {
+            variant_index = evaluate_discriminant(expr)?;
+        }
+        let fits = if variant_index < 0 {
+            variant_index >= -(1i128 << (bits - 1))
+        } else {
+            variant_index >> bits == 0
+        };
+        if !fits {
+            let vident = &variant.ident;
+            let span = variant.discriminant.as_ref().map_or(vident.span(), |(_, expr)| expr.span());
+            return Err(syn::Error::new(span, format!(
+                "tag {variant_index} of variant `{vident}` does not fit in {bits}-bit enum tag")));
+        }
+        let tag = (variant_index as u128 & (u128::MAX >> (128 - bits))) as u64;
+        if let Some(previous) = used.insert(tag, &variant.ident) {
+            let vident = &variant.ident;
+            let span = variant.discriminant.as_ref().map_or(vident.span(), |(_, expr)| expr.span());
+            return Err(syn::Error::new(span, format!(
+                "tag {variant_index} of variant `{vident}` is already used by variant `{previous}`")));
+        }
+        variant_index += 1;
+        Ok(tag)
+    }).collect()
+}
 
 /// Creates impl of crate::ton::CellSerialize for struct the attribute is attached to.
 /// Uses [create_serialization_code](fn.create_serialization_code.html) internally.
+/// Also emits `TLB_SCHEME` constant with TL-B-like declaration of the type, and a pair of
+/// `{VARIANT}_TAG` / `{VARIANT}_TAG_BITS` constants per variant: its tag for enums with tags,
+/// or the constant its scheme starts with (if up to 64 bits) for non-overlapping prefixes.
+/// `size_report()` lists bits and cells taken by the tag and each field of the stored variant.
+///
+/// Tag width is taken from `#[repr(uN)]`, or set explicitly with `#[tlb_tag_bits(N)]` for widths
+/// that have no Rust integer type. Without either, `#[tlb_assert_unsafe(items_prefixes_nonoverlap)]`
+/// states that variants store distinguishing prefixes themselves.
+/// With `json-schema` feature, `JSON_SCHEMA` constant describes the enum as `oneOf` its variants.
 ///
 /// # Examples
 /// 
@@ -196,107 +890,407 @@ pub fn tlb_serializable(attr: OldTokenStream, mut item: OldTokenStream) -> OldTo
 ///     #[tlb_item_serializable()] Empty{}  = 0,
 ///     #[tlb_item_serializable()] Normal{} = 0xb5eec792,
 /// }
+/// 
+/// #[tlb_enum_serializable]
+/// #[tlb_tag_bits(2)]
+/// enum AccountStatus {
+///     #[tlb_item_serializable()] Uninit{},
+///     #[tlb_item_serializable()] Frozen{},
+///     #[tlb_item_serializable()] Active{},
+///     #[tlb_item_serializable()] NonExist{},
+/// }
 /// ```
 #[proc_macro_attribute]
 pub fn tlb_enum_serializable(_: OldTokenStream, item: OldTokenStream) -> OldTokenStream {
     let mut input: ItemEnum = parse_macro_input!(item);
     
-    // For prefix definition, either #[repr(u?)] or #[tlb_assert_unsafe(items_prefixes_nonoverlap)] is required.
-    let mut need_prefix: Option<TlbPrefix> = None;
+    let need_prefix = enum_prefix(&input.attrs);
+    // #[repr] is retained for use with Rust
     input.attrs.retain(|attr| {
-        if attr.path().is_ident("tlb_assert_unsafe") {
-            let Meta::List(MetaList {tokens: ref tokens_assert, ..}) = attr.meta else {
-                panic!("#[tlb_assert_unsafe] attribute must have argument with the specific assertion");
-            };
-            let assertion = tokens_assert.to_string();
-            if assertion == "items_prefixes_nonoverlap" {
-                assert!(need_prefix.is_none());
-                need_prefix = Some(TlbPrefix::NotWanted);
-                false
-            } else {
-                println!("Unknown assertion {assertion:?}");
-                true
-            }
-        } else if attr.path().is_ident("repr") {
-            assert!(need_prefix.is_none(), "Two #[repr] attributes on enum are not supported");
-            let Meta::List(MetaList {tokens: ref tokens_type, ..}) = attr.meta else {
-                panic!("#[repr] attribute must have argument specifying the type");
-            };
-            need_prefix = Some(TlbPrefix::Wanted(tokens_type.to_string()));
-            true    // we retain #[repr] attribute for use with Rust
-        } else {
-            true
-        }
+        let known_assertion = matches!(attr.meta, Meta::List(MetaList {ref path, ref tokens, ..})
+            if path.is_ident("tlb_assert_unsafe") && tokens.to_string() == "items_prefixes_nonoverlap");
+        !known_assertion && !attr.path().is_ident("tlb_tag_bits")
     });
-    let need_prefix: TlbPrefix = need_prefix.expect("Don't know how to differentiate tags of the enum");
     let name = input.ident.clone();
     
     
-    // Generating code for each variant of the enum
-    let mut variant_index = 0;
-    let variant_generators: Vec<V2TokenStream> = input.variants.iter_mut().map(|variant| {
-        let mut store = None;
+    // Extracting serialization scheme of each variant; attributes are removed from all
+    // variants first, so that errors in one of them do not leave others unprocessed
+    let mut variant_schemes: Vec<V2TokenStream> = vec![];
+    let mut fallbacks: Vec<bool> = vec![];
+    for variant in input.variants.iter_mut() {
+        fallbacks.push(is_fallback(variant));
+        let mut tokens = None;
         variant.attrs.retain(|attr| {
             if !attr.path().is_ident("tlb_item_serializable") {return true;}
             let Meta::List(MetaList {tokens: ref tokens_tlb, ..}) = attr.meta else {
                 panic!("#[tlb_item_serializable] attribute must have argument with the specific serialization");
             };
-            let tlb = tokens_tlb.to_string();
             
-            assert!(store.is_none(), "multiple serialization definitions found");
-            store = Some(create_serialization_code(&tlb, &variant.fields, false));
+            assert!(tokens.is_none(), "multiple serialization definitions found");
+            tokens = Some(tokens_tlb.clone());
             false
         });
-        let store = store.expect(&format!("serialization definition for variant {} is required", variant.ident));
-        
-        // Enum discriminant
-        if let Some((_, Expr::Lit(ref idx))) = variant.discriminant {
-            if let Lit::Int(ref discriminant) = idx.lit {
-                variant_index = discriminant.base10_parse::<u64>().unwrap();
+        variant_schemes.push(tokens.unwrap_or_else(|| {
+            panic!("serialization definition for variant {} is required", variant.ident)
+        }));
+    }
+    
+    
+    // Generating code for each variant of the enum
+    let mut declarations: Vec<String> = vec![];
+    let mut tag_consts: Vec<V2TokenStream> = vec![];
+    let mut constructor_schemas: Vec<String> = vec![];
+    let variant_generators = enum_tags(&input, &need_prefix).and_then(|tags| {
+        input.variants.iter().zip(variant_schemes).zip(tags).zip(fallbacks).map(|(((variant, tokens), tag), fallback)| {
+            let scheme = parse_scheme(tokens.clone())?;
+            let store = create_serialization_code(&scheme, &variant.fields, false)?;
+            check_field_types(tokens.clone(), &variant.fields)?;
+            let report = create_size_report_code(&scheme, &variant.fields, false);
+            let store_cell = create_store_code(&scheme, &variant.fields, false);
+            let vident = &variant.ident;
+            
+            let fields_unpacker: Vec<_> = variant.fields.iter().map(|field| {
+                let id = field.ident.clone().expect("unnamed field in enum");
+                quote!{ #id, }
+            }).collect();
+            
+            let prefix = screaming_snake_case(vident);
+            let tag_name = Ident::new(&format!("{prefix}_TAG"), vident.span());
+            let bits_name = Ident::new(&format!("{prefix}_TAG_BITS"), vident.span());
+            let (store_tag, report_tag, store_cell_tag) = match need_prefix {
+                // fallback variant keeps the tag it was read with in its own fields
+                _ if fallback => (quote! {}, quote! {}, quote! {}),
+                TlbPrefix::NotWanted => {
+                    // variant's own leading constant is its tag
+                    if let Scheme::Items(ref items) = scheme {
+                        if let Some(SchemeItem::Constant {value, bits, ..}) = items.first() {
+                            if *bits <= 64 {
+                                let value = *value as u64;
+                                tag_consts.push(quote! {
+                                    pub const #tag_name: u64 = #value;
+                                    pub const #bits_name: usize = #bits;
+                                });
+                            }
+                        }
+                    }
+                    (quote! {}, quote! {}, quote! {})     // ^^^ result: Vec<String>
+                },
+                TlbPrefix::Wanted(bits) => {
+                    tag_consts.push(quote! {
+                        pub const #tag_name: u64 = #tag;
+                        pub const #bits_name: usize = #bits;
+                    });
+                    let command = format!("u {tag} {bits}bit");
+                    let part = describe_tag(tag as u128, bits);
+                    let tag = tag as u128;
+                    (quote! {
+                        result.push(#command.to_owned());
+                    }, quote! {
+                        builder.store_uint(#tag, #bits)?;
+                        report.push(#part, builder);
+                    }, quote! {
+                        builder.store_uint(#tag, #bits)?;
+                    })
+                },
+            };
+            
+            let tag = match need_prefix {
+                TlbPrefix::NotWanted => String::new(),
+                TlbPrefix::Wanted(bits) => describe_tag(tag as u128, bits),
+            };
+            let declaration = original_text(tokens).unwrap_or_else(|| {
+                create_declaration(&format!("{vident}{tag}"), &scheme.describe(&variant.fields), &name)
+            });
+            if !fallback {
+                constructor_schemas.push(json::constructor_schema(&vident.to_string(), &scheme, &variant.fields, &declaration));
+                declarations.push(declaration);
             }
-        };
-        let vident = &variant.ident;
-        
-        let fields_unpacker: Vec<_> = variant.fields.iter().map(|field| {
-            let id = field.ident.clone().expect("unnamed field in enum");
-            quote!{ #id, }
-        }).collect();
-        
-        let store_tag = match need_prefix {
-            TlbPrefix::NotWanted => quote! {},     // ^^^ result: Vec<String>
-            TlbPrefix::Wanted(ref t) => {
-                let s = &t[1..];
-                quote! {
-                    result.push(::std::format!("u {} {}bit", #variant_index, #s));
+            
+            Ok((quote! {
+                #name::#vident {#(#fields_unpacker)*} => {
+                    #store_tag
+                    #store
                 }
-            },
-        };
-        
-        variant_index += 1;
-        
-        quote! {
-            #name::#vident {#(#fields_unpacker)*} => {
-                #store_tag
-                #store
-            }
-        }
-    }).collect();
+            }, quote! {
+                #name::#vident {#(#fields_unpacker)*} => {
+                    #report_tag
+                    #report
+                }
+            }, quote! {
+                #name::#vident {#(#fields_unpacker)*} => {
+                    #store_cell_tag
+                    #store_cell
+                }
+            }))
+        }).collect::<syn::Result<Vec<(V2TokenStream, V2TokenStream, V2TokenStream)>>>()
+    });
     
     
+    // #[tlb(other)] is only dropped now, since tags are computed skipping the marked variant
+    for variant in input.variants.iter_mut() {
+        variant.attrs.retain(|attr| !attr.path().is_ident("tlb"));
+    }
     let mut result: OldTokenStream = input.to_token_stream().into();
+    let variant_generators = match variant_generators {
+        Ok(generators) => generators,
+        Err(err) => {
+            result.extend(OldTokenStream::from(err.into_compile_error()));
+            return result;
+        }
+    };
+    let variant_serializers = variant_generators.iter().map(|(serializer, _, _)| serializer);
+    let variant_reports = variant_generators.iter().map(|(_, report, _)| report);
+    let variant_stores = variant_generators.iter().map(|(_, _, store)| store);
     result.extend(OldTokenStream::from(quote! {
         impl crate::ton::CellSerialize for #name {
             fn serialize(&self) -> ::std::vec::Vec<::std::string::String> {
                 let mut result = ::std::vec![];
                 match &self {
-                    #(#variant_generators)*
+                    #(#variant_serializers)*
                 }
                 result
             }
+            
+            fn store(&self, builder: &mut crate::ton::cell::CellBuilder)
+                    -> ::std::result::Result<(), crate::ton::cell::CellError> {
+                match &self {
+                    #(#variant_stores)*
+                }
+                Ok(())
+            }
+        }
+        #[allow(dead_code)]
+        impl #name {
+            /// Bits and cells taken by each top-level part of serialized value, starting with enum tag.
+            pub fn size_report(&self) -> ::std::result::Result<crate::ton::SizeReport, crate::ton::cell::CellError> {
+                let mut report = crate::ton::SizeReport::default();
+                let builder = &mut crate::ton::cell::CellBuilder::new();
+                match &self {
+                    #(#variant_reports)*
+                }
+                Ok(report)
+            }
         }
     }));
+    result.extend(OldTokenStream::from(create_scheme_const(&name, &declarations)));
+    let json = json::enum_schema(&name.to_string(), &constructor_schemas);
+    result.extend(OldTokenStream::from(create_json_schema_const(&name, json)));
+    if !tag_consts.is_empty() {
+        result.extend(OldTokenStream::from(quote! {
+            #[allow(dead_code)]
+            impl #name {
+                #(#tag_consts)*
+            }
+        }));
+    }
     
     // println!("{}", result.to_string());
     
     result
 }
+
+
+/// Creates impl of crate::ton::CellDeserialize for enum, reading variants by the schemes of
+/// `#[tlb_enum_serializable]` that must follow this attribute.
+///
+/// For enums with tags, the tag is loaded and selects the variant. Enums with
+/// `#[tlb_assert_unsafe(items_prefixes_nonoverlap)]` pick the first variant whose leading
+/// constants (and prefix of the first field after them, see `CellDeserialize::prefix_matches`)
+/// match the slice; errors in the rest of the chosen variant are returned as they are.
+/// If no variant matches, `TlbError::UnknownTag` is returned, unless one variant is marked
+/// `#[tlb(other)]`: it is read then from the same position, tag included, and is written
+/// without tag, so its fields should capture the unknown data (e.g. `tag: Uint<8>, rest: Cell`).
+/// It does not take a tag of its own, nor appears in `TLB_SCHEME`. Enums with tags also get
+/// `peek_tag()` reading the tag without consuming the slice. `#[tlb_lenient]` works as
+/// for `#[tlb_deserializable]`.
+///
+/// # Examples
+///
+/// ```no_run
+/// #[tlb_enum_deserializable]
+/// #[tlb_enum_serializable]
+/// #[tlb_tag_bits(2)]
+/// pub enum MsgAddressExt {
+///     #[tlb_item_serializable()] AddrNone{},
+///     #[tlb_item_serializable(external_address)] AddrExtern{external_address: VarBits<9>},
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn tlb_enum_deserializable(_: OldTokenStream, mut item: OldTokenStream) -> OldTokenStream {
+    let enum_item = item.clone();
+    let mut input: ItemEnum = parse_macro_input!(enum_item);
+    let parse_mode = take_parse_mode(&mut input.attrs);
+    item = input.to_token_stream().into();
+    // `#[tlb]` refers to the attribute by its full path
+    assert!(input.attrs.iter().any(|attr| attr.path().segments.last().is_some_and(|segment| segment.ident == "tlb_enum_serializable")),
+            "#[tlb_enum_deserializable] must be followed by #[tlb_enum_serializable]");
+    let need_prefix = enum_prefix(&input.attrs);
+    let name = &input.ident;
+    let type_name = name.to_string();
+    
+    let generated = enum_tags(&input, &need_prefix).and_then(|tags| {
+        let mut variant_parsers: Vec<(u128, V2TokenStream)> = vec![];
+        let mut prefix_checks = vec![];
+        let mut fallback: Option<(V2TokenStream, V2TokenStream)> = None;
+        for (variant, tag) in input.variants.iter().zip(&tags) {
+            let tokens = variant.attrs.iter().find_map(|attr| match attr.meta {
+                Meta::List(MetaList {ref path, ref tokens, ..}) if path.is_ident("tlb_item_serializable") => Some(tokens.clone()),
+                _ => None,
+            }).unwrap_or_else(|| panic!("serialization definition for variant {} is required", variant.ident));
+            let vident = &variant.ident;
+            let scheme = parse_scheme(tokens)?;
+            let body = create_deserialization_code(&scheme, &variant.fields, quote! {Self::#vident})?;
+            
+            if is_fallback(variant) {
+                if fallback.is_some() {
+                    return Err(syn::Error::new(vident.span(), "only one variant may be marked #[tlb(other)]"));
+                }
+                fallback = Some((body, create_prefix_check(&scheme, &variant.fields)));
+                continue;
+            }
+            variant_parsers.push((*tag as u128, body));
+            prefix_checks.push(create_prefix_check(&scheme, &variant.fields));
+        }
+        let bodies = variant_parsers.iter().map(|(_, body)| body);
+        
+        let (dispatch, prefix_matches) = match (&need_prefix, fallback) {
+            // the tag is only consumed once it is known, so that fallback variant reads it too
+            (TlbPrefix::Wanted(bits), Some((fallback, fallback_check))) => {
+                let tags: Vec<u128> = variant_parsers.iter().map(|(tag, _)| *tag).collect();
+                (quote! {
+                    match slice.peek_uint(#bits)? {
+                        #(#tags => {slice.skip_bits(#bits)?; #bodies},)*
+                        _ => {#fallback},
+                    }
+                }, quote! {
+                    matches!(slice.peek_uint(#bits), Ok(#(#tags)|*)) || #fallback_check
+                })
+            },
+            (TlbPrefix::Wanted(bits), None) => {
+                let tags: Vec<u128> = variant_parsers.iter().map(|(tag, _)| *tag).collect();
+                (quote! {
+                    match slice.load_uint(#bits)? {
+                        #(#tags => {#bodies},)*
+                        _ => Err(crate::ton::cell::TlbError::UnknownTag {type_name: #type_name}),
+                    }
+                }, quote! {
+                    matches!(slice.peek_uint(#bits), Ok(#(#tags)|*))
+                })
+            },
+            // variant is chosen by its own prefix (or prefix of its first field); errors
+            // found further inside it are returned as they are
+            (TlbPrefix::NotWanted, Some((fallback, fallback_check))) => (quote! {
+                #(if #prefix_checks {#bodies} else)*
+                {#fallback}
+            }, quote! {
+                #((#prefix_checks))||* || #fallback_check
+            }),
+            (TlbPrefix::NotWanted, None) => (quote! {
+                #(if #prefix_checks {#bodies} else)*
+                {Err(crate::ton::cell::TlbError::UnknownTag {type_name: #type_name})}
+            }, quote! {
+                #((#prefix_checks))||*
+            }),
+        };
+        let peek_tag = match need_prefix {
+            TlbPrefix::Wanted(bits) => create_peek_tag(name, bits),
+            TlbPrefix::NotWanted => quote! {},
+        };
+        Ok(quote! {
+            impl crate::ton::CellDeserialize for #name {
+                fn deserialize(slice: &mut crate::ton::cell::CellSlice)
+                        -> ::std::result::Result<Self, crate::ton::cell::TlbError> {
+                    #dispatch
+                }
+                
+                fn prefix_matches(slice: &crate::ton::cell::CellSlice) -> bool {
+                    #prefix_matches
+                }
+                
+                #parse_mode
+            }
+            #peek_tag
+        })
+    });
+    item.extend(OldTokenStream::from(generated.unwrap_or_else(syn::Error::into_compile_error)));
+    
+    item
+}
+
+
+/// Serializes a list of `kind: value` entries in order, for one-off cells that do not deserve
+/// a struct. Evaluates to `Result<Cell, CellError>`, failing if entries do not fit into a cell.
+///
+/// Kinds are `uN` / `iN` for integers of `N` bits, `coins` for nanoton amounts, and any other
+/// type implementing `CellSerialize`. `ref` stores the value in a child cell instead.
+///
+/// # Example
+///
+/// ```no_run
+/// let body = cell! {
+///     u32: 0x0f8a7ea5,
+///     u64: query_id,
+///     coins: 1_000_000_000,
+///     ton::Address: destination,
+/// }?;
+/// ```
+#[proc_macro]
+pub fn cell(input: OldTokenStream) -> OldTokenStream {
+    let entries = parse_macro_input!(input as cell::CellEntries);
+    cell::create_cell_code(&entries).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+
+/// Embeds bag of cells given as hex or base64 string literal, checking its structure at
+/// compile time. Evaluates to `&'static [u8]`.
+///
+/// # Example
+///
+/// ```no_run
+/// const EMPTY_CELL: &[u8] = boc!("b5ee9c724101010100020000004cacb9cd");
+/// ```
+#[proc_macro]
+pub fn boc(input: OldTokenStream) -> OldTokenStream {
+    let literal = parse_macro_input!(input as syn::LitStr);
+    let checked = boc::decode(&literal.value()).and_then(|bytes| {
+        boc::validate(&bytes)?;
+        Ok(bytes)
+    });
+    match checked {
+        Ok(bytes) => {
+            let bytes = syn::LitByteStr::new(&bytes, literal.span());
+            quote! {{
+                let boc: &'static [u8] = #bytes;
+                boc
+            }}
+        },
+        Err(message) => syn::Error::new(literal.span(), message).into_compile_error(),
+    }.into()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn enum_tags_must_fit_and_differ() {
+        let input: ItemEnum = syn::parse_quote! {
+            enum Op {A = 1, B, C = -1}
+        };
+        assert_eq!(enum_tags(&input, &TlbPrefix::Wanted(4)).unwrap(), [1, 2, 15]);
+        
+        let input: ItemEnum = syn::parse_quote! {
+            enum Op {A = 16}
+        };
+        let err = enum_tags(&input, &TlbPrefix::Wanted(4)).unwrap_err();
+        assert_eq!(err.to_string(), "tag 16 of variant `A` does not fit in 4-bit enum tag");
+        
+        let input: ItemEnum = syn::parse_quote! {
+            enum Op {A = 2, B = 1, C}
+        };
+        let err = enum_tags(&input, &TlbPrefix::Wanted(4)).unwrap_err();
+        assert_eq!(err.to_string(), "tag 2 of variant `C` is already used by variant `A`");
+    }
+}
@@ -0,0 +1,507 @@
+// (c) ProgramCrafter, 2024
+
+//! Parser of the scheme mini-language used in `#[tlb_serializable(...)]` and
+//! `#[tlb_item_serializable(...)]` attributes.
+//!
+//! Scheme is a comma-separated list of items, stored in order:
+//! - `u VALUE WIDTHbit` - constant bits, e.g. `u 4 3bit` or `u 0x25938561 32bit`;
+//! - `field` - field of the type, stored using its own `CellSerialize` impl;
+//! - `^field` - field stored in a child cell; for `Option` fields this is `Maybe ^X`, with
+//!   presence bit in the cell itself and the value, if any, in a child cell.
+//!
+//! A single `__fundamental_*` item instead selects encoding built into the macro.
+//!
+//! Items may be spread over several lines and annotated with comments, including doc
+//! comments (`///`), which are ignored.
+//!
+//! Alternatively, scheme may be given as real TL-B text pasted from block.tlb:
+//! `scheme = r#"add_whitelist#7258a69b query_id:uint64 address:MsgAddressInt = InternalMsgBody;"#`.
+//! Constructor tag becomes a constant, `name:Type` refers to field `name` (its Rust type decides
+//! how it is stored), `name:^Type` stores the field in a child cell and `name:(Maybe ^Type)`
+//! stores `Option` field the same way as `^name` does. Rust type of the field must agree with
+//! `Type`, e.g. `uint64` needs `u64` and `MsgAddressInt` a type of the same name; mismatch is
+//! a compile error.
+
+use syn::{Fields, GenericArgument, Ident, LitInt, LitStr, Meta, PathArguments, Token, Type};
+use syn::ext::IdentExt;
+use syn::parse::{Parse, ParseStream, Parser};
+use quote::ToTokens;
+use proc_macro2::{Span, TokenStream};
+
+use std::collections::HashMap;
+
+
+pub enum SchemeItem {
+    Constant {value: u128, bits: usize, span: Span},
+    Field(Ident),
+    Ref(Ident),
+}
+
+pub enum Scheme {
+    Fundamental(Ident),
+    Items(Vec<SchemeItem>),
+}
+
+
+impl Parse for SchemeItem {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(Token![^]) {
+            input.parse::<Token![^]>()?;
+            return Ok(SchemeItem::Ref(input.call(Ident::parse_any)?));
+        }
+        
+        let name = input.call(Ident::parse_any).map_err(|err| {
+            syn::Error::new(err.span(), "expected constant `u VALUE WIDTHbit` or field name")
+        })?;
+        if name != "u" || !input.peek(LitInt) {
+            return Ok(SchemeItem::Field(name));
+        }
+        
+        let value_lit: LitInt = input.parse()?;
+        let width_lit: LitInt = input.parse().map_err(|err| {
+            syn::Error::new(err.span(), "expected constant width, like `32bit`")
+        })?;
+        if width_lit.suffix() != "bit" {
+            return Err(syn::Error::new(width_lit.span(), "constant width must be written as `<N>bit`"));
+        }
+        
+        let bits: usize = width_lit.base10_parse()?;
+        if bits > 128 {
+            return Err(syn::Error::new(width_lit.span(), "constants wider than 128 bits are not supported"));
+        }
+        let value: u128 = value_lit.base10_parse()?;
+        if bits < 128 && value >> bits != 0 {
+            return Err(syn::Error::new(value_lit.span(), format!("constant {value} does not fit in {bits} bits")));
+        }
+        
+        let span = name.span().join(width_lit.span()).unwrap_or_else(|| name.span());
+        Ok(SchemeItem::Constant {value, bits, span})
+    }
+}
+
+/// Skips doc comments, which reach the macro as `#[doc = "..."]` or `#![doc = "..."]` attributes.
+fn skip_doc_comments(input: ParseStream) -> syn::Result<()> {
+    while input.peek(Token![#]) {
+        input.parse::<Token![#]>()?;
+        if input.peek(Token![!]) {
+            input.parse::<Token![!]>()?;
+        }
+        let content;
+        syn::bracketed!(content in input);
+        let meta: Meta = content.parse()?;
+        if !meta.path().is_ident("doc") {
+            return Err(syn::Error::new_spanned(meta, "attributes are not allowed inside scheme"));
+        }
+    }
+    Ok(())
+}
+
+fn parse_items(input: ParseStream) -> syn::Result<Vec<SchemeItem>> {
+    let mut items = vec![];
+    loop {
+        skip_doc_comments(input)?;
+        if input.is_empty() {break;}
+        items.push(input.parse()?);
+        
+        skip_doc_comments(input)?;
+        if input.is_empty() {break;}
+        input.parse::<Token![,]>()?;
+    }
+    Ok(items)
+}
+
+/// Cursor over TL-B text given in `scheme = "..."` form.
+struct TlbText<'a> {
+    text: &'a str,
+    pos: usize,
+    span: Span,
+    /// Field names with positions of their types in `text`, for checking against Rust types.
+    field_types: Vec<(String, usize, usize)>,
+}
+
+impl TlbText<'_> {
+    /// Error pointing at the string literal, with location of the problem inside it.
+    fn error(&self, at: usize, message: &str) -> syn::Error {
+        let before = &self.text[..at];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+        syn::Error::new(self.span, format!("{message} (line {line}, column {column} of scheme)"))
+    }
+    
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+    
+    fn skip_space(&mut self) {
+        loop {
+            let rest = &self.text[self.pos..];
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            if trimmed.starts_with("//") {
+                self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else if trimmed.starts_with("/*") {
+                self.pos += trimmed.find("*/").map_or(trimmed.len(), |i| i + 2);
+            } else {
+                break;
+            }
+        }
+    }
+    
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> &str {
+        let start = self.pos;
+        while self.peek().is_some_and(&pred) {
+            self.pos += 1;
+        }
+        &self.text[start..self.pos]
+    }
+    
+    fn word(&mut self) -> &str {
+        self.take_while(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+    
+    /// Parses constructor tag following its name: `$0101`, `#7258a69b`, or empty `$_` / `#_`.
+    fn tag(&mut self, constructor: &str) -> syn::Result<Option<SchemeItem>> {
+        let start = self.pos;
+        let (radix, bits_per_digit) = match self.peek() {
+            Some('$') => (2, 1),
+            Some('#') => (16, 4),
+            _ if constructor == "_" => return Ok(None),
+            _ => return Err(self.error(start, &format!(
+                "constructor `{constructor}` needs explicit tag: `$bits`, `#hex`, or `$_` for none"))),
+        };
+        self.pos += 1;
+        if self.peek() == Some('_') {
+            self.pos += 1;
+            return Ok(None);
+        }
+        
+        let digits = self.take_while(|c| c.is_digit(radix)).to_owned();
+        if digits.is_empty() {
+            return Err(self.error(self.pos, "expected constructor tag digits"));
+        }
+        let bits = digits.len() * bits_per_digit;
+        if bits > 128 {
+            return Err(self.error(start, "constructor tags wider than 128 bits are not supported"));
+        }
+        let value = u128::from_str_radix(&digits, radix).unwrap();
+        Ok(Some(SchemeItem::Constant {value, bits, span: self.span}))
+    }
+    
+    /// Skips field type, returning whether the field is stored in a child cell
+    /// (`^Type`, or `(Maybe ^Type)` for `Option` fields).
+    fn field_type(&mut self) -> syn::Result<bool> {
+        self.skip_space();
+        let start = self.pos;
+        match self.peek() {
+            Some('^') => {
+                self.pos += 1;
+                self.skip_space();
+                if self.text[self.pos..].starts_with("(Maybe") {
+                    return Err(self.error(start, "child cell holding `Maybe X` is not supported, did you mean `(Maybe ^X)`?"));
+                }
+                self.field_type()?;
+                return Ok(true);
+            },
+            Some('(') => {
+                let mut depth = 0;
+                loop {
+                    match self.peek() {
+                        Some('(') => depth += 1,
+                        Some(')') => depth -= 1,
+                        None => return Err(self.error(start, "unclosed parenthesis in field type")),
+                        _ => {},
+                    }
+                    self.pos += self.peek().unwrap().len_utf8();
+                    if depth == 0 {break;}
+                }
+                let compound = &self.text[start + 1..self.pos - 1];
+                if let Some(inner) = compound.trim_start().strip_prefix("Maybe").and_then(|rest| rest.trim_start().strip_prefix('^')) {
+                    if !inner.contains('^') {
+                        return Ok(true);
+                    }
+                }
+                if compound.contains('^') {
+                    return Err(self.error(start, "child cells inside compound types other than `(Maybe ^X)` are not supported"));
+                }
+            },
+            _ => {
+                if self.take_while(|c| c.is_ascii_alphanumeric() || c == '_' || c == '#').is_empty() {
+                    return Err(self.error(start, "expected field type"));
+                }
+            },
+        }
+        if matches!(self.peek(), Some('.' | '?')) {
+            return Err(self.error(start, "conditional fields are not supported"));
+        }
+        Ok(false)
+    }
+    
+    /// Parses `constructor$tag field:Type ... = Type;` into scheme items.
+    fn declaration(&mut self) -> syn::Result<Vec<SchemeItem>> {
+        self.skip_space();
+        let constructor = self.word().to_owned();
+        if constructor.is_empty() {
+            return Err(self.error(self.pos, "expected constructor name"));
+        }
+        let mut items: Vec<SchemeItem> = self.tag(&constructor)?.into_iter().collect();
+        
+        loop {
+            self.skip_space();
+            let start = self.pos;
+            match self.peek() {
+                None => return Err(self.error(start, "expected `=` ending the declaration")),
+                Some('=') => break,
+                Some('{') => return Err(self.error(start, "implicit fields are not supported")),
+                _ => {},
+            }
+            
+            let name = self.word().to_owned();
+            if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+                return Err(self.error(start, "expected field `name:Type`"));
+            }
+            if name == "_" {
+                return Err(self.error(start, "anonymous fields are not supported, please name the field"));
+            }
+            self.skip_space();
+            if self.peek() != Some(':') {
+                return Err(self.error(self.pos, "expected `:` after field name"));
+            }
+            self.pos += 1;
+            
+            self.skip_space();
+            let type_start = self.pos;
+            let is_ref = self.field_type()?;
+            self.field_types.push((name.clone(), type_start, self.pos));
+            let name = Ident::new(&name, self.span);
+            items.push(if is_ref {SchemeItem::Ref(name)} else {SchemeItem::Field(name)});
+        }
+        Ok(items)
+    }
+}
+
+/// String literal of `scheme = "..."` form, if attribute contents are in this form.
+fn scheme_literal(tokens: TokenStream) -> Option<LitStr> {
+    let text_form = |input: ParseStream| {
+        let key: Ident = input.parse()?;
+        if key != "scheme" {
+            return Err(syn::Error::new(key.span(), "expected `scheme`"));
+        }
+        input.parse::<Token![=]>()?;
+        input.parse::<LitStr>()
+    };
+    text_form.parse2(tokens).ok()
+}
+
+/// TL-B text of `scheme = "..."` form without comments, on a single line, as it should be
+/// shown in `TLB_SCHEME`: `revoke#6f89f5e3 query_id:uint64 = InternalMsgBody;`.
+pub fn original_text(tokens: TokenStream) -> Option<String> {
+    let mut text = scheme_literal(tokens)?.value();
+    while let Some(start) = text.find("/*") {
+        let end = text[start..].find("*/").map_or(text.len(), |i| start + i + 2);
+        text.replace_range(start..end, " ");
+    }
+    let words: Vec<&str> = text.lines()
+        .flat_map(|line| line.split_once("//").map_or(line, |(code, _)| code).split_whitespace())
+        .collect();
+    Some(words.join(" "))
+}
+
+/// Parses attribute contents into a scheme, reporting errors at the offending tokens.
+pub fn parse_scheme(tokens: TokenStream) -> syn::Result<Scheme> {
+    if let Some(lit) = scheme_literal(tokens.clone()) {
+        let text = lit.value();
+        let mut cursor = TlbText {text: &text, pos: 0, span: lit.span(), field_types: vec![]};
+        return Ok(Scheme::Items(cursor.declaration()?));
+    }
+    
+    let items = parse_items.parse2(tokens)?;
+    
+    if let [SchemeItem::Field(ref name)] = items[..] {
+        if name.to_string().starts_with("__fundamental_") {
+            return Ok(Scheme::Fundamental(name.clone()));
+        }
+    }
+    Ok(Scheme::Items(items))
+}
+
+
+/// Last segment of Rust type path with its generic arguments, like `("Uint", ["7"])`.
+fn type_name(ty: &Type) -> Option<(String, Vec<String>)> {
+    let Type::Path(ref path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    let args = match segment.arguments {
+        PathArguments::AngleBracketed(ref args) => {
+            args.args.iter().map(|arg| arg.to_token_stream().to_string().replace(' ', "")).collect()
+        },
+        _ => vec![],
+    };
+    Some((segment.ident.to_string(), args))
+}
+
+/// Rust types whose TL-B encoding is known, so they must match the declared type exactly.
+/// Other types (e.g. `Capabilities` stored as `uint64`) are trusted to encode the declared type.
+const KNOWN_TYPES: [&str; 19] = [
+    "u8", "u16", "u32", "u64", "u128", "i8", "i16", "i32", "i64", "i128", "bool",
+    "Uint", "Int", "Coins", "TokenAmount", "FixedBits", "VarBits", "Address", "Option",
+];
+
+/// Whether field of Rust type `ty` is stored as TL-B type `tlb`, as far as this can be seen
+/// from type names. `Cell` and `RawBits` hold any data.
+fn type_matches(tlb: &str, ty: &Type) -> bool {
+    let tlb = tlb.trim();
+    if let Some(inner) = tlb.strip_prefix('(').and_then(|inner| inner.strip_suffix(')')) {
+        return type_matches(inner, ty);
+    }
+    if let Some(inner) = tlb.strip_prefix('^') {
+        return type_matches(inner, ty);
+    }
+    if let Some(inner) = generic_inner(ty, "Box") {
+        return type_matches(tlb, inner);
+    }
+    let Some((rust, args)) = type_name(ty) else {
+        return false;
+    };
+    if rust == "Cell" || rust == "RawBits" {
+        return true;
+    }
+    
+    let (head, rest) = tlb.split_once(char::is_whitespace).unwrap_or((tlb, ""));
+    let rest = rest.trim();
+    let known = KNOWN_TYPES.contains(&rust.as_str());
+    let width_matches = |rust_width: Option<usize>, width: &str| match rust_width {
+        Some(rust_width) => width.parse() == Ok(rust_width),
+        None => args.first().is_some_and(|arg| arg == width),
+    };
+    let uint_matches = |width: &str| match rust.as_str() {
+        "u8" => width_matches(Some(8), width),
+        "u16" => width_matches(Some(16), width),
+        "u32" => width_matches(Some(32), width),
+        "u64" => width_matches(Some(64), width),
+        "u128" => width_matches(Some(128), width),
+        "Uint" => width_matches(None, width),
+        // numbers wider than `u128`, like `item_id:uint256`, are kept as their bits
+        "FixedBits" => width_matches(None, width),
+        _ => !known,
+    };
+    
+    match head {
+        "Maybe" => option_inner(ty).is_some_and(|inner| type_matches(rest, inner)),
+        _ if rust == "Option" => false,
+        "Bool" => rust == "bool" || !known,
+        "Coins" | "Grams" => matches!(rust.as_str(), "Coins" | "TokenAmount") || !known,
+        "VarUInteger" if rest == "16" => matches!(rust.as_str(), "Coins" | "TokenAmount") || !known,
+        "#" => uint_matches("32"),
+        "##" => uint_matches(rest),
+        _ if head.starts_with('#') => rust == "Uint" || !known,
+        _ if head.strip_prefix("uint").is_some_and(|width| width.parse::<usize>().is_ok()) => uint_matches(&head[4..]),
+        _ if head.strip_prefix("int").is_some_and(|width| width.parse::<usize>().is_ok()) => {
+            if rust == "Int" {width_matches(None, &head[3..])} else {!known}
+        },
+        _ if head.strip_prefix("bits").is_some_and(|width| width.parse::<usize>().is_ok()) => {
+            if rust == "FixedBits" {width_matches(None, &head[4..])} else {!known}
+        },
+        // named type, like `MsgAddressInt` or `(BinTree ShardDescr)`
+        _ if head.starts_with(|c: char| c.is_ascii_uppercase()) => rust == head,
+        _ => true,
+    }
+}
+
+/// Checks that fields of `scheme = "..."` form have Rust types storing their declared TL-B
+/// types: `uint64` needs `u64`, `Bool` needs `bool`, `Coins` needs `Coins`, `MsgAddressInt`
+/// needs a type named `MsgAddressInt`, etc. Token form has no types to check.
+pub fn check_field_types(tokens: TokenStream, struct_fields: &Fields) -> syn::Result<()> {
+    let Some(lit) = scheme_literal(tokens) else {
+        return Ok(());
+    };
+    let text = lit.value();
+    let mut cursor = TlbText {text: &text, pos: 0, span: lit.span(), field_types: vec![]};
+    cursor.declaration()?;
+    
+    for (name, start, end) in &cursor.field_types {
+        let Some(field) = struct_fields.iter().find(|field| field.ident.as_ref().is_some_and(|id| id.unraw() == name)) else {
+            continue;       // reported by serialization code
+        };
+        let tlb = &text[*start..*end];
+        if !type_matches(tlb, &field.ty) {
+            let rust = field.ty.to_token_stream().to_string().replace(' ', "");
+            return Err(cursor.error(*start, &format!("field `{name}:{tlb}` cannot be stored by Rust type `{rust}`")));
+        }
+    }
+    Ok(())
+}
+
+
+/// Renders a bit constant in TL-B notation: `#hex` when width is a multiple of 4, `$bits` otherwise.
+pub fn describe_tag(value: u128, bits: usize) -> String {
+    if bits == 0 {
+        "$_".to_owned()
+    } else if bits.is_multiple_of(4) {
+        format!("#{value:0width$x}", width = bits / 4)
+    } else {
+        format!("${value:0width$b}", width = bits)
+    }
+}
+
+/// Type wrapped into `Option<T>`, if field has such a type.
+pub fn option_inner(ty: &Type) -> Option<&Type> {
+    generic_inner(ty, "Option")
+}
+
+/// Type `T` wrapped as `wrapper<T>`, like `Box<T>`.
+pub fn generic_inner<'t>(ty: &'t Type, wrapper: &str) -> Option<&'t Type> {
+    let Type::Path(ref path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    match (segment.ident == wrapper, &segment.arguments) {
+        (true, PathArguments::AngleBracketed(ref args)) => match args.args.first() {
+            Some(GenericArgument::Type(inner)) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Renders Rust type of a field, writing `Option<T>` as TL-B `(Maybe T)`, or `(Maybe ^T)`
+/// when the value is stored in a child cell.
+fn describe_type(ty: &str, is_ref: bool) -> String {
+    match ty.strip_prefix("Option<").and_then(|inner| inner.strip_suffix('>')) {
+        Some(inner) if is_ref => format!("(Maybe ^{})", describe_type(inner, false)),
+        Some(inner) => format!("(Maybe {})", describe_type(inner, false)),
+        None if is_ref => format!("^{ty}"),
+        None => ty.to_owned(),
+    }
+}
+
+impl Scheme {
+    /// Renders scheme in TL-B-like notation, used for `TLB_SCHEME` constants.
+    ///
+    /// For `#[tlb_serializable(u 4 3bit, workchain, hash_high, hash_low)]` the result is
+    /// `$100 workchain:u8 hash_high:u128 hash_low:u128`.
+    pub fn describe(&self, struct_fields: &Fields) -> String {
+        let items = match self {
+            Scheme::Fundamental(name) if name == "__fundamental_varuint16" => return "(VarUInteger 16)".to_owned(),
+            Scheme::Fundamental(name) => return name.to_string(),
+            Scheme::Items(items) => items,
+        };
+        
+        let field_types: HashMap<String, String> = struct_fields.iter().filter_map(|field| {
+            let id = field.ident.as_ref()?;
+            Some((id.unraw().to_string(), field.ty.to_token_stream().to_string().replace(' ', "")))
+        }).collect();
+        
+        items.iter().map(|item| match item {
+            SchemeItem::Constant {value, bits, ..} => describe_tag(*value, *bits),
+            SchemeItem::Field(name) => {
+                let name = name.unraw().to_string();
+                format!("{name}:{}", describe_type(&field_types[&name], false))
+            },
+            SchemeItem::Ref(name) => {
+                let name = name.unraw().to_string();
+                format!("{name}:{}", describe_type(&field_types[&name], true))
+            },
+        }).collect::<Vec<_>>().join(" ")
+    }
+}